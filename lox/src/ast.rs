@@ -0,0 +1,153 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::token::Token;
+
+/// Index of an `Expr` stored in an `Ast` arena, replacing what used to be a
+/// `Box<Expr>` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExprId(u32);
+
+/// Index of a `Stmt` stored in an `Ast` arena, replacing what used to be a
+/// `Box<Stmt>` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StmtId(u32);
+
+/// Flat arena backing the parsed tree. Child nodes that used to be
+/// heap-allocated one at a time behind a `Box` now live together in
+/// `exprs`/`stmts`, addressed by the lightweight `ExprId`/`StmtId` indices
+/// above, which keeps related nodes close in memory and lets the whole tree
+/// be dropped in one step instead of walking a pointer per node.
+#[derive(Debug, Default)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl Ast {
+    pub fn new() -> Ast {
+        Ast {
+            exprs: Vec::new(),
+            stmts: Vec::new(),
+        }
+    }
+
+    pub fn push_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId((self.exprs.len() - 1) as u32)
+    }
+
+    pub fn push_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId((self.stmts.len() - 1) as u32)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn expr_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.0 as usize]
+    }
+
+    pub fn stmt_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id.0 as usize]
+    }
+
+    #[inline]
+    pub fn binary(&mut self, left: Expr, operator: Token, right: Expr) -> Expr {
+        let left = self.push_expr(left);
+        let right = self.push_expr(right);
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        }
+    }
+
+    #[inline]
+    pub fn grouping(&mut self, expression: Expr) -> Expr {
+        let expression = self.push_expr(expression);
+        Expr::Grouping { expression }
+    }
+
+    #[inline]
+    pub fn unary(&mut self, operator: Token, right: Expr) -> Expr {
+        let right = self.push_expr(right);
+        Expr::Unary { operator, right }
+    }
+
+    #[inline]
+    pub fn assign(&mut self, name: Token, value: Expr) -> Expr {
+        let value = self.push_expr(value);
+        Expr::Assign {
+            name,
+            value,
+            depth: None,
+        }
+    }
+
+    #[inline]
+    pub fn logical(&mut self, left: Expr, operator: Token, right: Expr) -> Expr {
+        let left = self.push_expr(left);
+        let right = self.push_expr(right);
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        }
+    }
+
+    #[inline]
+    pub fn call(&mut self, callee: Expr, paren: Token, arguments: Vec<Expr>) -> Expr {
+        let callee = self.push_expr(callee);
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        }
+    }
+
+    #[inline]
+    pub fn var(&mut self, name: Token, initializer: Expr) -> Stmt {
+        let initializer = self.push_expr(initializer);
+        Stmt::Var { name, initializer }
+    }
+
+    #[inline]
+    pub fn ifstmt(&mut self, condition: Expr, then_branch: Stmt, else_branch: Stmt) -> Stmt {
+        let then_branch = self.push_stmt(then_branch);
+        let else_branch = self.push_stmt(else_branch);
+        Stmt::IfStmt {
+            condition,
+            then_branch,
+            else_branch,
+        }
+    }
+
+    #[inline]
+    pub fn whilestmt(&mut self, condition: Expr, body: Stmt, increment: Expr) -> Stmt {
+        let body = self.push_stmt(body);
+        let increment = self.push_expr(increment);
+        Stmt::WhileStmt {
+            condition,
+            body,
+            increment,
+        }
+    }
+
+    #[inline]
+    pub fn function(&mut self, name: Token, params: Vec<Token>, body: Stmt) -> Stmt {
+        let body = self.push_stmt(body);
+        Stmt::Function { name, params, body }
+    }
+
+    #[inline]
+    pub fn returnstmt(&mut self, keyword: Token, value: Expr) -> Stmt {
+        let value = self.push_expr(value);
+        Stmt::ReturnStmt { keyword, value }
+    }
+}
@@ -1,84 +1,245 @@
-use crate::expr::{Expr, Visitor};
-use crate::token::{Literal, Token, TokenType};
+use crate::ast::{Ast, ExprId};
+use crate::expr::{self as ex, Expr};
+use crate::stmt::{self, Stmt};
 
-#[derive(Clone, Copy)]
-pub struct ASTPrinter {}
+/// Renders a parsed tree as a parenthesized s-expression, one line per
+/// top-level statement - the same shape as the classic "Crafting
+/// Interpreters" `AstPrinter` exercise. Used by the `--emit=ast` flag and
+/// by the golden-file test harness under `tests/parser/ok`, where a stable
+/// textual form is what actually gets diffed against an `.expected`
+/// snapshot.
+pub struct AstPrinter;
 
-impl ASTPrinter {
-    pub fn new() -> ASTPrinter {
-        ASTPrinter {}
+impl AstPrinter {
+    pub fn new() -> AstPrinter {
+        AstPrinter
     }
 
-    pub fn print(&self, expr: Expr) -> String {
-        expr.accept(Box::new(*self))
+    fn print_expr_id(&mut self, id: ExprId, ast: &mut Ast) -> String {
+        let node = ast.expr(id).clone();
+        node.accept(self, ast)
     }
+}
+
+/// Renders every statement in `statements`, in order, one s-expression per
+/// line.
+pub fn print(statements: &[Stmt], ast: &mut Ast) -> String {
+    let mut printer = AstPrinter::new();
+    statements
+        .iter()
+        .map(|s| s.clone().accept(&mut printer, ast))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    fn parenthesize(&self, name: String, exprs: Vec<Expr>) -> String {
-        let mut string = String::new();
-        string.push('(');
-        string.push_str(&name);
-        for expr in exprs {
-            string.push(' ');
-            string.push_str(&expr.accept(Box::new(*self)));
+impl ex::Visitor<String> for AstPrinter {
+    fn visit_binary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
+        match expr {
+            Expr::Binary { left, operator, right } => format!(
+                "({} {} {})",
+                operator.lexeme,
+                self.print_expr_id(*left, ast),
+                self.print_expr_id(*right, ast)
+            ),
+            _ => panic!("Unexpected value in printing binary expression."), // should be unreachable
         }
-        string.push(')');
+    }
 
-        string
+    fn visit_grouping_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
+        match expr {
+            Expr::Grouping { expression } => {
+                format!("(group {})", self.print_expr_id(*expression, ast))
+            }
+            _ => panic!("Unexpected value in printing grouping expression."), // should be unreachable
+        }
     }
-}
 
-impl Visitor<String> for ASTPrinter {
-    fn visit_binary_expr(&self, expr: &Expr) -> String {
+    fn visit_literal_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> String {
         match expr {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => self.parenthesize(operator.lexeme.clone(), vec![*left.clone(), *right.clone()]),
-            _ => String::new(),
+            Expr::Literal { value } => format!("{}", value),
+            _ => panic!("Unexpected value in printing literal expression."), // should be unreachable
         }
     }
-    fn visit_grouping_expr(&self, expr: &Expr) -> String {
+
+    fn visit_unary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
         match expr {
-            Expr::Grouping { expression } => {
-                self.parenthesize("group".to_string(), vec![*expression.clone()])
+            Expr::Unary { operator, right } => {
+                format!("({} {})", operator.lexeme, self.print_expr_id(*right, ast))
             }
-            _ => String::new(),
+            _ => panic!("Unexpected value in printing unary expression."), // should be unreachable
         }
     }
-    fn visit_literal_expr(&self, expr: &Expr) -> String {
+
+    fn visit_variable_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> String {
         match expr {
-            Expr::Literal { value } => {
-                if *value == Literal::Null {
-                    String::from("Nil")
-                } else {
-                    value.to_string()
-                }
+            Expr::Variable { name, .. } => name.lexeme.clone(),
+            _ => panic!("Unexpected value in printing variable expression."), // should be unreachable
+        }
+    }
+
+    fn visit_assign_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                format!("(= {} {})", name.lexeme, self.print_expr_id(*value, ast))
             }
-            _ => String::new(),
+            _ => panic!("Unexpected value in printing assign expression."), // should be unreachable
         }
     }
-    fn visit_unary_expr(&self, expr: &Expr) -> String {
+
+    fn visit_logical_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
         match expr {
-            Expr::Unary { operator, right } => {
-                self.parenthesize(operator.lexeme.clone(), vec![*right.clone()])
+            Expr::Logical { left, operator, right } => format!(
+                "({} {} {})",
+                operator.lexeme,
+                self.print_expr_id(*left, ast),
+                self.print_expr_id(*right, ast)
+            ),
+            _ => panic!("Unexpected value in printing logical expression."), // should be unreachable
+        }
+    }
+
+    fn visit_call_expr(&mut self, expr: &Expr, ast: &mut Ast) -> String {
+        match expr {
+            Expr::Call { callee, arguments, .. } => {
+                let callee = self.print_expr_id(*callee, ast);
+                let args = arguments
+                    .iter()
+                    .map(|arg| arg.clone().accept(self, ast))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if args.is_empty() {
+                    format!("(call {})", callee)
+                } else {
+                    format!("(call {} {})", callee, args)
+                }
             }
-            _ => String::new(),
+            _ => panic!("Unexpected value in printing call expression."), // should be unreachable
         }
     }
 }
 
-pub fn test() {
-    let expr = Expr::binary(
-        Box::new(Expr::unary(
-            Token::new(TokenType::Minus, 1, "-".to_string(), Literal::Symbol),
-            Box::new(Expr::literal(Literal::Number(123.0))),
-        )),
-        Token::new(TokenType::Star, 1, "*".to_string(), Literal::Symbol),
-        Box::new(Expr::grouping(Box::new(Expr::literal(Literal::Number(
-            45.67,
-        ))))),
-    );
-    let ast = ASTPrinter::new();
-    println!("{}", ast.print(expr));
+impl stmt::Visitor<String> for AstPrinter {
+    fn visit_expression_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Expression { expr } => format!("(; {})", expr.clone().accept(self, ast)),
+            _ => panic!("Unexpected value in printing expression statement."), // should be unreachable
+        }
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Print { expr } => format!("(print {})", expr.clone().accept(self, ast)),
+            _ => panic!("Unexpected value in printing print statement."), // should be unreachable
+        }
+    }
+
+    fn visit_repl_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Repl { expr } => format!("(repl {})", expr.clone().accept(self, ast)),
+            _ => panic!("Unexpected value in printing repl statement."), // should be unreachable
+        }
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Var { name, initializer } => {
+                if *ast.expr(*initializer) == Expr::Null {
+                    format!("(var {})", name.lexeme)
+                } else {
+                    format!("(var {} {})", name.lexeme, self.print_expr_id(*initializer, ast))
+                }
+            }
+            _ => panic!("Unexpected value in printing var statement."), // should be unreachable
+        }
+    }
+
+    fn visit_block_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Block { statements } => {
+                let body = statements
+                    .iter()
+                    .map(|s| s.clone().accept(self, ast))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("(block {})", body)
+            }
+            _ => panic!("Unexpected value in printing block statement."), // should be unreachable
+        }
+    }
+
+    fn visit_ifstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::IfStmt { condition, then_branch, else_branch } => {
+                let condition = condition.clone().accept(self, ast);
+                let then_branch = ast.stmt(*then_branch).clone().accept(self, ast);
+                if *ast.stmt(*else_branch) == Stmt::Null {
+                    format!("(if {} {})", condition, then_branch)
+                } else {
+                    let else_branch = ast.stmt(*else_branch).clone().accept(self, ast);
+                    format!("(if {} {} {})", condition, then_branch, else_branch)
+                }
+            }
+            _ => panic!("Unexpected value in printing if statement."), // should be unreachable
+        }
+    }
+
+    fn visit_whilestmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::WhileStmt { condition, body, .. } => {
+                let condition = condition.clone().accept(self, ast);
+                let body = ast.stmt(*body).clone().accept(self, ast);
+                format!("(while {} {})", condition, body)
+            }
+            _ => panic!("Unexpected value in printing while statement."), // should be unreachable
+        }
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Function { name, params, body } => {
+                let params = params
+                    .iter()
+                    .map(|p| p.lexeme.clone())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let body = ast.stmt(*body).clone().accept(self, ast);
+                format!("(fun {} ({}) {})", name.lexeme, params, body)
+            }
+            _ => panic!("Unexpected value in printing function statement."), // should be unreachable
+        }
+    }
+
+    fn visit_returnstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::ReturnStmt { value, .. } => {
+                if *ast.expr(*value) == Expr::Null {
+                    "(return)".to_string()
+                } else {
+                    format!("(return {})", self.print_expr_id(*value, ast))
+                }
+            }
+            _ => panic!("Unexpected value in printing return statement."), // should be unreachable
+        }
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Break { .. } => "(break)".to_string(),
+            _ => panic!("Unexpected value in printing break statement."), // should be unreachable
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Continue { .. } => "(continue)".to_string(),
+            _ => panic!("Unexpected value in printing continue statement."), // should be unreachable
+        }
+    }
+
+    fn visit_import_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) -> String {
+        match stmt {
+            Stmt::Import { path, .. } => format!("(import \"{}\")", path.lexeme),
+            _ => panic!("Unexpected value in printing import statement."), // should be unreachable
+        }
+    }
 }
@@ -1,21 +1,71 @@
 // use lox::environment::Environment;
 // use lox::interpreter;
 // use lox::token::{LoxTypes, Literal};
-use lox::*;
-use std::{cmp::Ordering, env};
 use lox::function::Function;
+use lox::*;
+use std::{cmp::Ordering, env, fs, process::exit};
 
 fn main() {
-    let args = env::args().collect::<Vec<String>>();
-    let mut globals = environment::Environment::new();
-    globals.define(
-        "clock".to_string(),
-        token::LoxTypes::Object(token::Literal::Function(Function::clock())),
-    );
-    let mut interpreter = interpreter::Interpreter::new_with_env(LoxError::NoError, globals);
+    let mut args = env::args().collect::<Vec<String>>();
+    let engine = if let Some(pos) = args.iter().position(|arg| arg == "--bytecode") {
+        args.remove(pos);
+        Engine::Bytecode
+    } else {
+        Engine::TreeWalk
+    };
+    let time = if let Some(pos) = args.iter().position(|arg| arg == "--time") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+    let emit_kind = args
+        .iter()
+        .position(|arg| arg.starts_with("--emit="))
+        .map(|pos| {
+            let flag = args.remove(pos);
+            match flag.trim_start_matches("--emit=") {
+                "tokens" => EmitKind::Tokens,
+                "ast" => EmitKind::Ast,
+                other => {
+                    eprintln!("Unknown --emit kind '{}', expected 'tokens' or 'ast'.", other);
+                    exit(1);
+                }
+            }
+        });
+
+    let interner = interner::Interner::new();
+    let globals = environment::Environment::new();
+    Function::register_standard_library(&globals, &interner);
+    let mut interpreter =
+        interpreter::Interpreter::new_with_env(LoxError::NoError, globals, interner);
+
+    if let Some(kind) = emit_kind {
+        let filename = args.get(1).expect("Needs one argument, that is file name");
+        let source = fs::read_to_string(filename).expect("File not found");
+        let error = emit(source, kind, &mut interpreter);
+        exit(if error == LoxError::NoError { 0 } else { 1 });
+    }
+
+    add_prelude(&mut interpreter, engine);
     match args.len().cmp(&2) {
         Ordering::Greater => panic!("Needs one argument, that is file name, or no arguments"),
-        Ordering::Equal => run_file(&args[1], &mut interpreter),
-        Ordering::Less => prompt(&mut interpreter),
+        Ordering::Equal if time => match run_file_timed(&args[1], &mut interpreter, engine) {
+            Ok(timings) => eprintln!(
+                "scanning: {:?}, parsing: {:?}, interpreting: {:?}",
+                timings.scanning, timings.parsing, timings.interpreting
+            ),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        },
+        Ordering::Equal => {
+            if let Err(err) = run_file(&args[1], &mut interpreter, engine) {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+        Ordering::Less => prompt(&mut interpreter, engine),
     }
 }
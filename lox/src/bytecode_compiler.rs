@@ -0,0 +1,792 @@
+use crate::chunk::{BytecodeFunction, Chunk, OpCode, Value};
+use crate::token::{Literal, Token, TokenType};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Binding power of an expression form, lowest to highest. Mirrors the
+/// tree-walking grammar's precedence climbing, but expressed as a table the
+/// bytecode compiler's Pratt parser can look up by token type.
+#[derive(Copy, Clone, PartialEq, Debug, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment, // =
+    Or,         // or
+    And,        // and
+    Equality,   // == !=
+    Comparison, // < > <= >=
+    Term,       // + -
+    Factor,     // * /
+    Unary,      // ! -
+    Call,       // . ()
+    Primary,
+}
+
+impl Precedence {
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call => Precedence::Primary,
+            Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+type ParseFn = fn(&mut Compiler, can_assign: bool);
+
+#[derive(Clone)]
+struct ParseRule {
+    prefix: Option<ParseFn>,
+    infix: Option<ParseFn>,
+    precedence: Precedence,
+}
+
+/// A local variable binding within a `FunctionScope`. `depth == -1` means
+/// "declared but not yet initialized" - reading it in that state is the
+/// `var a = a;` self-reference the resolver also rejects in the tree-walker.
+struct Local {
+    name: String,
+    depth: i32,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum FunctionKind {
+    Script,
+    Function,
+}
+
+/// Compilation state for one function body (or the top-level script, which
+/// is just a function with no parameters and an empty name). Slot 0 of
+/// `locals` is reserved for the function value itself, matching how the VM
+/// lays out a call frame's stack window.
+struct FunctionScope {
+    chunk: Chunk,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    name: String,
+    arity: usize,
+    kind: FunctionKind,
+}
+
+impl FunctionScope {
+    fn new(name: String, kind: FunctionKind) -> FunctionScope {
+        FunctionScope {
+            chunk: Chunk::new(),
+            locals: vec![Local {
+                name: String::new(),
+                depth: 0,
+            }],
+            scope_depth: 0,
+            name,
+            arity: 0,
+            kind,
+        }
+    }
+}
+
+/// Single-pass compiler turning the flat `Vec<Token>` produced by
+/// `Scanner::scan_tokens` directly into a `BytecodeFunction`, without
+/// building an AST. `scopes` holds one `FunctionScope` per function
+/// currently being compiled, innermost last, so nested `fun` declarations
+/// compile their body into their own chunk. A nested function cannot read
+/// locals declared by an enclosing function: this backend has no upvalues,
+/// so such a reference is a compile error (see `resolves_as_enclosing_local`).
+pub struct Compiler {
+    tokens: Vec<Token>,
+    current: usize,
+    had_error: bool,
+    parse_rules: HashMap<TokenType, ParseRule>,
+    scopes: Vec<FunctionScope>,
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>) -> Compiler {
+        let mut parse_rules = HashMap::new();
+        let mut rule = |kind, prefix, infix, precedence| {
+            parse_rules.insert(
+                kind,
+                ParseRule {
+                    prefix,
+                    infix,
+                    precedence,
+                },
+            )
+        };
+
+        use TokenType::*;
+        rule(
+            LeftParen,
+            Some(Compiler::grouping as ParseFn),
+            Some(Compiler::call as ParseFn),
+            Precedence::Call,
+        );
+        rule(RightParen, None, None, Precedence::None);
+        rule(LeftBrace, None, None, Precedence::None);
+        rule(RightBrace, None, None, Precedence::None);
+        rule(Comma, None, None, Precedence::None);
+        rule(Dot, None, None, Precedence::None);
+        rule(
+            Minus,
+            Some(Compiler::unary as ParseFn),
+            Some(Compiler::binary as ParseFn),
+            Precedence::Term,
+        );
+        rule(Plus, None, Some(Compiler::binary as ParseFn), Precedence::Term);
+        rule(Semicolon, None, None, Precedence::None);
+        rule(Slash, None, Some(Compiler::binary as ParseFn), Precedence::Factor);
+        rule(Star, None, Some(Compiler::binary as ParseFn), Precedence::Factor);
+        rule(Bang, Some(Compiler::unary as ParseFn), None, Precedence::None);
+        rule(
+            BangEqual,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Equality,
+        );
+        rule(Equal, None, None, Precedence::None);
+        rule(
+            EqualEqual,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Equality,
+        );
+        rule(
+            Greater,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Comparison,
+        );
+        rule(
+            GreaterEqual,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Comparison,
+        );
+        rule(
+            Less,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Comparison,
+        );
+        rule(
+            LessEqual,
+            None,
+            Some(Compiler::binary as ParseFn),
+            Precedence::Comparison,
+        );
+        rule(
+            Identifier,
+            Some(Compiler::variable as ParseFn),
+            None,
+            Precedence::None,
+        );
+        rule(TString, Some(Compiler::string as ParseFn), None, Precedence::None);
+        rule(Number, Some(Compiler::number as ParseFn), None, Precedence::None);
+        rule(And, None, Some(Compiler::and_op as ParseFn), Precedence::And);
+        rule(Class, None, None, Precedence::None);
+        rule(Else, None, None, Precedence::None);
+        rule(False, Some(Compiler::literal as ParseFn), None, Precedence::None);
+        rule(For, None, None, Precedence::None);
+        rule(Fun, None, None, Precedence::None);
+        rule(If, None, None, Precedence::None);
+        rule(Nil, Some(Compiler::literal as ParseFn), None, Precedence::None);
+        rule(Or, None, Some(Compiler::or_op as ParseFn), Precedence::Or);
+        rule(Print, None, None, Precedence::None);
+        rule(Return, None, None, Precedence::None);
+        rule(Super, None, None, Precedence::None);
+        rule(This, None, None, Precedence::None);
+        rule(True, Some(Compiler::literal as ParseFn), None, Precedence::None);
+        rule(Var, None, None, Precedence::None);
+        rule(While, None, None, Precedence::None);
+        rule(Break, None, None, Precedence::None);
+        rule(Continue, None, None, Precedence::None);
+        rule(InterpolationStart, None, None, Precedence::None);
+        rule(InterpolationEnd, None, None, Precedence::None);
+        rule(Eof, None, None, Precedence::None);
+
+        Compiler {
+            tokens,
+            current: 0,
+            had_error: false,
+            parse_rules,
+            scopes: vec![FunctionScope::new(String::new(), FunctionKind::Script)],
+        }
+    }
+
+    pub fn compile(tokens: Vec<Token>) -> Result<BytecodeFunction, ()> {
+        let mut compiler = Compiler::new(tokens);
+
+        while !compiler.check(TokenType::Eof) {
+            compiler.declaration();
+        }
+
+        compiler.emit(OpCode::Nil);
+        compiler.emit(OpCode::Return);
+        if compiler.had_error {
+            Err(())
+        } else {
+            let script = compiler.scopes.pop().unwrap();
+            Ok(BytecodeFunction {
+                name: script.name,
+                arity: script.arity,
+                chunk: script.chunk,
+            })
+        }
+    }
+
+    fn declaration(&mut self) {
+        if self.match_token(TokenType::Fun) {
+            self.fun_declaration();
+        } else if self.match_token(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+    }
+
+    fn fun_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "expect function name.");
+        let name = self.previous().clone();
+
+        if self.scope_depth() > 0 {
+            self.declare_local(name.lexeme.clone());
+            self.mark_initialized();
+            self.function(name.lexeme);
+            return;
+        }
+
+        let global = self.identifier_constant(name.clone());
+        self.function(name.lexeme);
+        self.emit_operand(OpCode::DefineGlobal, global);
+    }
+
+    /// Compiles a function's parameter list and body into its own
+    /// `FunctionScope`, then emits it as a constant in the *enclosing*
+    /// scope's chunk - the scope this call pushed is popped before that
+    /// emit, so `self.scopes.last()` is the enclosing function again.
+    fn function(&mut self, name: String) {
+        self.scopes.push(FunctionScope::new(name.clone(), FunctionKind::Function));
+        self.begin_scope();
+
+        self.consume(TokenType::LeftParen, "expect '(' after function name.");
+        if !self.check(TokenType::RightParen) {
+            loop {
+                let arity_exceeded = {
+                    let scope = self.scopes.last_mut().unwrap();
+                    scope.arity += 1;
+                    scope.arity > 255
+                };
+                if arity_exceeded {
+                    self.error("can't have more than 255 parameters.");
+                }
+                self.consume(TokenType::Identifier, "expect parameter name.");
+                let param = self.previous().lexeme.clone();
+                self.declare_local(param);
+                self.mark_initialized();
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "expect ')' after parameters.");
+        self.consume(TokenType::LeftBrace, "expect '{' before function body.");
+        self.block();
+        self.emit(OpCode::Nil);
+        self.emit(OpCode::Return);
+
+        let finished = self.scopes.pop().unwrap();
+        let function = BytecodeFunction {
+            name: finished.name,
+            arity: finished.arity,
+            chunk: finished.chunk,
+        };
+        self.emit_constant(Value::Function(Rc::new(function)));
+    }
+
+    fn var_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "expect variable name.");
+        let name = self.previous().clone();
+
+        let global = if self.scope_depth() == 0 {
+            Some(self.identifier_constant(name.clone()))
+        } else {
+            self.declare_local(name.lexeme.clone());
+            None
+        };
+
+        if self.match_token(TokenType::Equal) {
+            self.expression();
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "expect ';' after variable declaration.",
+        );
+
+        match global {
+            Some(global) => self.emit_operand(OpCode::DefineGlobal, global),
+            None => self.mark_initialized(),
+        }
+    }
+
+    fn statement(&mut self) {
+        if self.match_token(TokenType::Print) {
+            self.print_statement();
+        } else if self.match_token(TokenType::LeftBrace) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else if self.match_token(TokenType::If) {
+            self.if_statement();
+        } else if self.match_token(TokenType::While) {
+            self.while_statement();
+        } else if self.match_token(TokenType::Return) {
+            self.return_statement();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "expect ';' after value.");
+        self.emit(OpCode::Print);
+    }
+
+    fn return_statement(&mut self) {
+        if self.scopes.last().unwrap().kind == FunctionKind::Script {
+            self.error("can't return from top-level code.");
+        }
+
+        if self.match_token(TokenType::Semicolon) {
+            self.emit(OpCode::Nil);
+            self.emit(OpCode::Return);
+        } else {
+            self.expression();
+            self.consume(TokenType::Semicolon, "expect ';' after return value.");
+            self.emit(OpCode::Return);
+        }
+    }
+
+    fn block(&mut self) {
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(TokenType::RightBrace, "expect '}' after block.");
+    }
+
+    fn if_statement(&mut self) {
+        self.consume(TokenType::LeftParen, "expect '(' after 'if'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "expect ')' after condition.");
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.statement();
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+
+        if self.match_token(TokenType::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn while_statement(&mut self) {
+        let loop_start = self.current_chunk_ref().code.len();
+        self.consume(TokenType::LeftParen, "expect '(' after 'while'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "expect ')' after condition.");
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.statement();
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "expect ';' after expression.");
+        self.emit(OpCode::Pop);
+    }
+
+    fn expression(&mut self) {
+        self.parse_precedence(Precedence::Assignment);
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        self.advance();
+        let prefix_rule = match self.get_rule(self.previous().token_type.clone()).prefix {
+            Some(rule) => rule,
+            None => {
+                self.error("expect expression.");
+                return;
+            }
+        };
+
+        let can_assign = precedence <= Precedence::Assignment;
+        prefix_rule(self, can_assign);
+
+        while precedence <= self.get_rule(self.peek().token_type.clone()).precedence {
+            self.advance();
+            let infix_rule = self
+                .get_rule(self.previous().token_type.clone())
+                .infix
+                .expect("infix rule must exist for an operator token");
+            infix_rule(self, can_assign);
+        }
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.error("invalid assignment target.");
+        }
+    }
+
+    fn number(&mut self, _can_assign: bool) {
+        match &self.previous().literal {
+            Literal::Number(n) => {
+                let value = *n;
+                self.emit_constant(Literal::Number(value).into());
+            }
+            _ => self.error("expect number literal."),
+        }
+    }
+
+    fn string(&mut self, _can_assign: bool) {
+        match self.previous().literal.clone() {
+            Literal::LString(s) => self.emit_constant(Literal::LString(s).into()),
+            _ => self.error("expect string literal."),
+        }
+    }
+
+    fn literal(&mut self, _can_assign: bool) {
+        match self.previous().token_type {
+            TokenType::False => self.emit(OpCode::False),
+            TokenType::True => self.emit(OpCode::True),
+            TokenType::Nil => self.emit(OpCode::Nil),
+            _ => (), // unreachable
+        }
+    }
+
+    fn grouping(&mut self, _can_assign: bool) {
+        self.expression();
+        self.consume(TokenType::RightParen, "expect ')' after expression.");
+    }
+
+    fn call(&mut self, _can_assign: bool) {
+        let arg_count = self.argument_list();
+        self.emit_operand(OpCode::Call, arg_count);
+    }
+
+    fn argument_list(&mut self) -> usize {
+        let mut count = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression();
+                count += 1;
+                if count > 255 {
+                    self.error("can't have more than 255 arguments.");
+                }
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "expect ')' after arguments.");
+        count
+    }
+
+    fn unary(&mut self, _can_assign: bool) {
+        let op_type = self.previous().token_type.clone();
+        self.parse_precedence(Precedence::Unary);
+        match op_type {
+            TokenType::Minus => self.emit(OpCode::Negate),
+            TokenType::Bang => self.emit(OpCode::Not),
+            _ => (), // unreachable
+        }
+    }
+
+    fn binary(&mut self, _can_assign: bool) {
+        let op_type = self.previous().token_type.clone();
+        let rule = self.get_rule(op_type.clone());
+        self.parse_precedence(rule.precedence.next());
+        match op_type {
+            TokenType::Plus => self.emit(OpCode::Add),
+            TokenType::Minus => self.emit(OpCode::Subtract),
+            TokenType::Star => self.emit(OpCode::Multiply),
+            TokenType::Slash => self.emit(OpCode::Divide),
+            TokenType::EqualEqual => self.emit(OpCode::Equal),
+            TokenType::BangEqual => {
+                self.emit(OpCode::Equal);
+                self.emit(OpCode::Not);
+            }
+            TokenType::Greater => self.emit(OpCode::Greater),
+            TokenType::GreaterEqual => {
+                self.emit(OpCode::Less);
+                self.emit(OpCode::Not);
+            }
+            TokenType::Less => self.emit(OpCode::Less),
+            TokenType::LessEqual => {
+                self.emit(OpCode::Greater);
+                self.emit(OpCode::Not);
+            }
+            _ => (), // unreachable
+        }
+    }
+
+    fn and_op(&mut self, _can_assign: bool) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end_jump);
+    }
+
+    fn or_op(&mut self, _can_assign: bool) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(else_jump);
+        self.emit(OpCode::Pop);
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end_jump);
+    }
+
+    fn variable(&mut self, can_assign: bool) {
+        let name = self.previous().clone();
+
+        if let Some(slot) = self.resolve_local(&name.lexeme) {
+            if can_assign && self.match_token(TokenType::Equal) {
+                self.expression();
+                self.emit_operand(OpCode::SetLocal, slot);
+            } else {
+                self.emit_operand(OpCode::GetLocal, slot);
+            }
+            return;
+        }
+
+        if self.resolves_as_enclosing_local(&name.lexeme) {
+            self.error(&format!(
+                "can't close over '{}': this backend has no upvalues, so a nested function may only read its own locals and globals.",
+                name.lexeme
+            ));
+            return;
+        }
+
+        let arg = self.identifier_constant(name);
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.expression();
+            self.emit_operand(OpCode::SetGlobal, arg);
+        } else {
+            self.emit_operand(OpCode::GetGlobal, arg);
+        }
+    }
+
+    fn identifier_constant(&mut self, name: Token) -> usize {
+        self.current_chunk_mut()
+            .add_constant(Value::Literal(Literal::LString(name.lexeme)))
+    }
+
+    // scope handling
+
+    fn scope_depth(&self) -> usize {
+        self.scopes.last().unwrap().scope_depth
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.last_mut().unwrap().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let new_depth = self.scope_depth() - 1;
+        self.scopes.last_mut().unwrap().scope_depth = new_depth;
+
+        loop {
+            let still_in_scope = matches!(
+                self.scopes.last().unwrap().locals.last(),
+                Some(local) if local.depth > new_depth as i32
+            );
+            if !still_in_scope {
+                break;
+            }
+            self.scopes.last_mut().unwrap().locals.pop();
+            self.emit(OpCode::Pop);
+        }
+    }
+
+    /// Pushes a new, not-yet-initialized local into the innermost function
+    /// scope. Rejects redeclaring a name already bound in the same block.
+    fn declare_local(&mut self, name: String) {
+        let depth = self.scope_depth() as i32;
+        let duplicate = {
+            let locals = &self.scopes.last().unwrap().locals;
+            let mut duplicate = false;
+            for local in locals.iter().rev() {
+                if local.depth != -1 && local.depth < depth {
+                    break;
+                }
+                if local.name == name {
+                    duplicate = true;
+                    break;
+                }
+            }
+            duplicate
+        };
+        if duplicate {
+            self.error("already a variable with this name in this scope.");
+            return;
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .locals
+            .push(Local { name, depth: -1 });
+    }
+
+    fn mark_initialized(&mut self) {
+        let depth = self.scope_depth() as i32;
+        if let Some(local) = self.scopes.last_mut().unwrap().locals.last_mut() {
+            local.depth = depth;
+        }
+    }
+
+    /// Scans the innermost function's locals from the end, so shadowing a
+    /// name in a nested block resolves to the most recent declaration.
+    fn resolve_local(&mut self, name: &str) -> Option<usize> {
+        let locals = &self.scopes.last().unwrap().locals;
+        let mut found = None;
+        let mut uninitialized = false;
+        for (index, local) in locals.iter().enumerate().rev() {
+            if local.name == name {
+                found = Some(index);
+                uninitialized = local.depth == -1;
+                break;
+            }
+        }
+        if uninitialized {
+            self.error("can't read local variable in its own initializer.");
+        }
+        found
+    }
+
+    /// Whether `name` is bound as a local in some *enclosing* `FunctionScope`
+    /// - i.e. the reference `resolve_local` just failed on would, in the
+    /// tree-walking interpreter, have resolved through a captured closure.
+    /// This backend has no upvalues (see the `Compiler` doc comment and
+    /// `variable`), so such a reference is rejected at compile time instead
+    /// of silently falling through to `GetGlobal` and failing at runtime
+    /// with a misleading "undefined variable" error.
+    fn resolves_as_enclosing_local(&self, name: &str) -> bool {
+        self.scopes[..self.scopes.len() - 1]
+            .iter()
+            .any(|scope| scope.locals.iter().any(|local| local.name == name))
+    }
+
+    // token stream helpers
+
+    fn advance(&mut self) {
+        if !self.check(TokenType::Eof) {
+            self.current += 1;
+        }
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek().token_type == token_type
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> bool {
+        if !self.check(token_type) {
+            false
+        } else {
+            self.advance();
+            true
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) {
+        if self.check(token_type) {
+            self.advance();
+        } else {
+            self.error(message);
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    fn get_rule(&self, token_type: TokenType) -> ParseRule {
+        self.parse_rules
+            .get(&token_type)
+            .cloned()
+            .expect("every token type must have a parse rule")
+    }
+
+    // chunk emission
+
+    fn current_chunk_mut(&mut self) -> &mut Chunk {
+        &mut self.scopes.last_mut().unwrap().chunk
+    }
+
+    fn current_chunk_ref(&self) -> &Chunk {
+        &self.scopes.last().unwrap().chunk
+    }
+
+    fn emit(&mut self, opcode: OpCode) {
+        let line = self.previous().line;
+        self.current_chunk_mut().write(opcode, line);
+    }
+
+    fn emit_operand(&mut self, opcode: OpCode, operand: usize) {
+        let line = self.previous().line;
+        self.current_chunk_mut().write(opcode, line);
+        self.current_chunk_mut().write_byte(operand as u8, line);
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let index = self.current_chunk_mut().add_constant(value);
+        self.emit_operand(OpCode::Constant, index);
+    }
+
+    fn emit_jump(&mut self, opcode: OpCode) -> usize {
+        let line = self.previous().line;
+        self.current_chunk_mut().write_jump(opcode, line)
+    }
+
+    fn patch_jump(&mut self, placeholder: usize) {
+        if let Err(message) = self.current_chunk_mut().patch_jump(placeholder) {
+            self.error(&message);
+        }
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        let line = self.previous().line;
+        if let Err(message) = self.current_chunk_mut().write_loop(loop_start, line) {
+            self.error(&message);
+        }
+    }
+
+    // error handling
+
+    fn error(&mut self, message: &str) {
+        eprintln!("[line {}] compile error: {}", self.previous().line, message);
+        self.had_error = true;
+    }
+}
+
+pub fn compile(tokens: Vec<Token>) -> Result<BytecodeFunction, ()> {
+    Compiler::compile(tokens)
+}
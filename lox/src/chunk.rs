@@ -0,0 +1,216 @@
+use crate::token::Literal;
+use std::fmt;
+use std::rc::Rc;
+
+/// Bytecode operations for the stack-based VM backend (see `vm.rs`). Covers
+/// constant loads, arithmetic, comparisons, jumps, globals, locals and calls.
+/// Operand-carrying opcodes are always followed by a single index byte,
+/// except `Jump`/`JumpIfFalse`/`Loop`, which are followed by a 2-byte
+/// big-endian distance.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Return,
+}
+
+impl OpCode {
+    const TABLE: [OpCode; 25] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::DefineGlobal,
+        OpCode::GetGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::Less,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Return,
+    ];
+
+    pub fn from_u8(byte: u8) -> OpCode {
+        Self::TABLE[byte as usize]
+    }
+}
+
+/// A compiled unit of bytecode: a flat byte stream, the constant pool it
+/// indexes into, and a line table so runtime errors can point back at source.
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    lines: Vec<usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            code: Vec::new(),
+            constants: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Appends a single opcode byte, returning its offset.
+    pub fn write(&mut self, opcode: OpCode, line: usize) -> usize {
+        self.write_byte(opcode as u8, line)
+    }
+
+    pub fn write_byte(&mut self, byte: u8, line: usize) -> usize {
+        let offset = self.code.len();
+        self.code.push(byte);
+        self.lines.push(line);
+        offset
+    }
+
+    /// Appends a jump opcode with a placeholder 2-byte distance, returning
+    /// the offset of that placeholder for `patch_jump` to fill in later.
+    pub fn write_jump(&mut self, opcode: OpCode, line: usize) -> usize {
+        self.write(opcode, line);
+        self.write_byte(0xff, line);
+        self.write_byte(0xff, line);
+        self.code.len() - 2
+    }
+
+    /// Backpatches a jump placeholder emitted by `write_jump` with the byte
+    /// distance from just past the placeholder to the current end of chunk.
+    pub fn patch_jump(&mut self, placeholder: usize) -> Result<(), String> {
+        let jump = self.code.len() - placeholder - 2;
+        if jump > u16::MAX as usize {
+            return Err("too much code to jump over.".to_string());
+        }
+        self.code[placeholder] = ((jump >> 8) & 0xff) as u8;
+        self.code[placeholder + 1] = (jump & 0xff) as u8;
+        Ok(())
+    }
+
+    /// Appends an `OP_LOOP` jumping back to `loop_start`.
+    pub fn write_loop(&mut self, loop_start: usize, line: usize) -> Result<(), String> {
+        self.write(OpCode::Loop, line);
+        let jump = self.code.len() + 2 - loop_start;
+        if jump > u16::MAX as usize {
+            return Err("loop body too large.".to_string());
+        }
+        self.write_byte(((jump >> 8) & 0xff) as u8, line);
+        self.write_byte((jump & 0xff) as u8, line);
+        Ok(())
+    }
+
+    pub fn get_line(&self, offset: usize) -> usize {
+        self.lines[offset]
+    }
+
+    /// Adds `value` to the constant pool, returning its index. Unlike the
+    /// tree-walking interpreter, the bytecode VM never mutates constants in
+    /// place, so no interning/dedup is needed here.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    pub fn get_constant(&self, index: usize) -> Value {
+        self.constants[index].clone()
+    }
+}
+
+/// A function compiled to its own `Chunk`, callable from any chunk that
+/// holds it as a constant. `name` is empty for the implicit top-level script
+/// function the VM wraps every program in.
+#[derive(Debug)]
+pub struct BytecodeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+}
+
+impl fmt::Display for BytecodeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
+
+/// Runtime value for the bytecode backend. Reuses the tree-walker's
+/// `Literal` for primitives so there's no second number/string/bool
+/// representation to keep in sync, and adds `Function` for values the
+/// tree-walker has no equivalent of: a callable compiled `Chunk`.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Literal(Literal),
+    Function(Rc<BytecodeFunction>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Literal(literal) => !matches!(literal, Literal::Boolean(false) | Literal::Null),
+            Value::Function(_) => true,
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Value {
+        Value::Literal(literal)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Literal(a), Value::Literal(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Literal(literal) => write!(f, "{}", literal),
+            Value::Function(function) => write!(f, "{}", function),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::token::Token;
+use std::fmt;
+
+/// How serious a `Diagnostic` is. Only `Error` exists today since nothing in
+/// `lox` yet produces warnings, but keeping this as its own type (rather than
+/// baking "error" into `Diagnostic` itself) leaves room for one later without
+/// another breaking change to every call site.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single reported problem, carrying enough source position to underline
+/// it later instead of just naming a line number. `code` is a short,
+/// grep-able tag (`"scan"`, `"parse"`, `"resolve"`, `"runtime"`) identifying
+/// which phase raised it.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub line: usize,
+    pub column: usize,
+    pub span: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(
+        code: &'static str,
+        line: usize,
+        column: usize,
+        span: usize,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            line,
+            column,
+            span: span.max(1),
+            message,
+        }
+    }
+
+    /// Builds an error `Diagnostic` pointing at `token`, the way almost every
+    /// scanning/parsing/resolving/runtime error does - the token's own
+    /// `lexeme` length becomes the underlined span.
+    pub fn error_at(code: &'static str, token: &Token, message: String) -> Diagnostic {
+        Diagnostic::error(code, token.line, token.column, token.lexeme.len(), message)
+    }
+}
+
+/// The full set of problems found while running a phase (or a whole
+/// program), collected instead of bailing at the first one so a user sees
+/// every mistake in a file at once. `Interpreter::import_module` merges a
+/// module's own `Diagnostics` into the importer's via `extend`.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn had_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.items.extend(other.items);
+    }
+
+    /// Renders every diagnostic as a line number, the offending source line,
+    /// and a caret underline spanning it - `source` is the same string the
+    /// diagnostics were collected against, so line numbers line up.
+    pub fn render(&self, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut out = String::new();
+        for diagnostic in &self.items {
+            out.push_str(&format!(
+                "[line {}] {} [{}]: {}\n",
+                diagnostic.line, diagnostic.severity, diagnostic.code, diagnostic.message
+            ));
+            if let Some(source_line) = lines.get(diagnostic.line.saturating_sub(1)) {
+                out.push_str(source_line);
+                out.push('\n');
+                let indent = " ".repeat(diagnostic.column.saturating_sub(1));
+                let underline = "^".repeat(diagnostic.span);
+                out.push_str(&format!("{}{}\n", indent, underline));
+            }
+        }
+        out
+    }
+}
@@ -1,82 +1,136 @@
-use crate::interpreter::{InterpreterError, LoxRuntime};
-use crate::token::{Literal, LoxTypes, Token};
-use std::collections::HashMap;
-
-#[derive(Debug, Clone)]
-pub struct Environment {
-    values: HashMap<String, LoxTypes>,
-    enclosing: Option<Box<Environment>>,
-}
-
-impl Environment {
-    pub fn new() -> Environment {
-        Environment {
-            values: HashMap::new(),
-            enclosing: None,
-        }
-    }
-
-    pub fn new_with_enclosing(enclosing: Box<Environment>) -> Environment {
-        Environment {
-            values: HashMap::new(),
-            enclosing: Some(enclosing),
-        }
-    }
-
-    pub fn values(&self) -> HashMap<String, LoxTypes> {
-        self.values.clone()
-    }
-
-    pub fn enclosing(&self) -> HashMap<String, LoxTypes> {
-        match &self.enclosing {
-            Some(encl) => encl.values(),
-            None => panic!("No enclosing environment found."),
-        }
-    }
-
-    pub fn upper_env(self) -> Environment {
-        match self.enclosing {
-            Some(encl) => *encl,
-            None => self,
-        }
-    }
-
-    pub fn define(&mut self, name: String, value: LoxTypes) {
-        self.values.insert(name, value);
-    }
-
-    pub fn get(&self, name: Token) -> LoxRuntime {
-        match self.values.get(&name.lexeme) {
-            Some(value) => Ok(value.clone()),
-            None => match &self.enclosing {
-                Some(encl) => encl.get(name),
-                None => InterpreterError::error(
-                    name.clone(),
-                    format!("undefined variable '{}'.", name.lexeme),
-                ),
-            },
-        }
-    }
-
-    pub fn get_global(&self, name: Token) -> LoxRuntime {
-        match &self.enclosing {
-            Some(encl) => encl.get_global(name),
-            None => self.get(name)
-        }
-    }
-
-    pub fn assign(&mut self, name: Token, value: LoxTypes) -> LoxRuntime {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
-            Ok(LoxTypes::Object(Literal::Null))
-        } else {
-            match &mut self.enclosing {
-                Some(encl) => encl.assign(name, value),
-                None => InterpreterError::error(
-                    name.clone(),
-                    format!("undefined variable '{}'.", name.lexeme),
-                ),
-            }
-        }
-    }
-}
+use crate::interner::Symbol;
+use crate::interpreter::{InterpreterError, LoxRuntime};
+use crate::token::{Literal, LoxTypes, Token};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A shared, interior-mutable handle to an `Environment`. Cloning an `EnvRef`
+/// is cheap (a refcount bump) and every clone sees the same live scope, so a
+/// closure that captures one observes later mutations instead of a frozen
+/// snapshot.
+pub type EnvRef = Rc<RefCell<Environment>>;
+
+/// Every binding an `Environment` ever sees came from a scanned `Identifier`
+/// token (or, for natively-defined globals, was interned through the same
+/// `InternerRef` up front), so it always carries a `Symbol`.
+fn symbol_of(name: &Token) -> Symbol {
+    name.symbol
+        .expect("identifier token passed to Environment must carry an interned symbol")
+}
+
+#[derive(Debug)]
+pub struct Environment {
+    values: HashMap<Symbol, LoxTypes>,
+    enclosing: Option<EnvRef>,
+}
+
+impl Environment {
+    pub fn new() -> EnvRef {
+        Rc::new(RefCell::new(Environment {
+            values: HashMap::new(),
+            enclosing: None,
+        }))
+    }
+
+    pub fn extend(enclosing: EnvRef) -> EnvRef {
+        Rc::new(RefCell::new(Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }))
+    }
+
+    pub fn define(&mut self, symbol: Symbol, value: LoxTypes) {
+        self.values.insert(symbol, value);
+    }
+
+    /// This environment's own bindings, not `enclosing`'s - used to splice an
+    /// imported module's top-level functions/vars into the importing scope.
+    pub fn own_bindings(&self) -> Vec<(Symbol, LoxTypes)> {
+        self.values
+            .iter()
+            .map(|(&symbol, value)| (symbol, value.clone()))
+            .collect()
+    }
+
+    pub fn get(&self, name: Token) -> LoxRuntime {
+        match self.values.get(&symbol_of(&name)) {
+            Some(value) => Ok(value.clone()),
+            None => match &self.enclosing {
+                Some(encl) => encl.borrow().get(name),
+                None => InterpreterError::error(
+                    name.clone(),
+                    format!("undefined variable '{}'.", name.lexeme),
+                ),
+            },
+        }
+    }
+
+    pub fn get_global(&self, name: Token) -> LoxRuntime {
+        match &self.enclosing {
+            Some(encl) => encl.borrow().get_global(name),
+            None => self.get(name),
+        }
+    }
+
+    pub fn assign(&mut self, name: Token, value: LoxTypes) -> LoxRuntime {
+        let symbol = symbol_of(&name);
+        if self.values.contains_key(&symbol) {
+            self.values.insert(symbol, value);
+            Ok(LoxTypes::Object(Literal::Null))
+        } else {
+            match &self.enclosing {
+                Some(encl) => encl.borrow_mut().assign(name, value),
+                None => InterpreterError::error(
+                    name.clone(),
+                    format!("undefined variable '{}'.", name.lexeme),
+                ),
+            }
+        }
+    }
+
+    /// Follows `enclosing` exactly `distance` times, as computed by the
+    /// resolver. Panics on an out-of-range distance since that would mean the
+    /// resolver itself is wrong, not a user-facing error.
+    fn ancestor(env: &EnvRef, distance: usize) -> EnvRef {
+        let mut current = Rc::clone(env);
+        for _ in 0..distance {
+            let parent = Rc::clone(
+                current
+                    .borrow()
+                    .enclosing
+                    .as_ref()
+                    .expect("resolver distance exceeds environment chain length."),
+            );
+            current = parent;
+        }
+        current
+    }
+
+    /// Reads `name` directly out of the scope `distance` hops up `env`, as
+    /// resolved statically, instead of walking the chain by name.
+    pub fn get_at(env: &EnvRef, distance: usize, name: &Token) -> LoxRuntime {
+        match Environment::ancestor(env, distance)
+            .borrow()
+            .values
+            .get(&symbol_of(name))
+        {
+            Some(value) => Ok(value.clone()),
+            None => InterpreterError::error(
+                name.clone(),
+                format!("undefined variable '{}'.", name.lexeme),
+            ),
+        }
+    }
+
+    /// Writes `value` directly into the scope `distance` hops up `env`, as
+    /// resolved statically, instead of walking the chain by name.
+    pub fn assign_at(env: &EnvRef, distance: usize, name: Token, value: LoxTypes) -> LoxRuntime {
+        let symbol = symbol_of(&name);
+        Environment::ancestor(env, distance)
+            .borrow_mut()
+            .values
+            .insert(symbol, value);
+        Ok(LoxTypes::Object(Literal::Null))
+    }
+}
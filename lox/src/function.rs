@@ -1,20 +1,273 @@
-use std::fmt;
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum Function {
-    Clock {},
-}
-
-impl Function {
-    pub fn clock() -> Function {
-        Function::Clock {}
-    }
-}
-
-impl fmt::Display for Function {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Function::Clock {} => write!(f, "<native function>"),
-        }
-    }
-}
+use crate::environment::EnvRef;
+use crate::interner::InternerRef;
+use crate::interpreter::{Interpreter, InterpreterError, LoxRuntime};
+use crate::stmt::Stmt;
+use crate::token::{Literal, LoxTypes, Token};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum Function {
+    Clock {},
+    /// A builtin implemented in Rust instead of Lox: `name` is how source
+    /// code calls it, `arity` is checked the same way as a `User` function's
+    /// parameter count, and `function` does the actual work. `paren` is
+    /// threaded through to `function` so it can report a properly located
+    /// `InterpreterError` on bad input, the same way a user-defined function
+    /// body would.
+    NativeFn {
+        name: &'static str,
+        arity: usize,
+        function: fn(&Interpreter, Vec<LoxTypes>, Token) -> LoxRuntime,
+    },
+    User {
+        name: Token,
+        params: Vec<Token>,
+        body: Box<Stmt>,
+        /// The environment live when this function was declared, captured
+        /// by reference so the call it makes later sees the same scope a
+        /// nested `fun` would close over, not a frozen snapshot of it.
+        closure: EnvRef,
+    },
+}
+
+impl Function {
+    pub fn clock() -> Function {
+        Function::Clock {}
+    }
+
+    pub fn native(
+        name: &'static str,
+        arity: usize,
+        function: fn(&Interpreter, Vec<LoxTypes>, Token) -> LoxRuntime,
+    ) -> Function {
+        Function::NativeFn {
+            name,
+            arity,
+            function,
+        }
+    }
+
+    pub fn user(name: Token, params: Vec<Token>, body: Box<Stmt>, closure: EnvRef) -> Function {
+        Function::User {
+            name,
+            params,
+            body,
+            closure,
+        }
+    }
+
+    /// The name globals are registered under: the token lexeme for a `User`
+    /// function, the fixed name given at construction for everything else.
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Clock {} => "clock",
+            Function::NativeFn { name, .. } => name,
+            Function::User { name, .. } => &name.lexeme,
+        }
+    }
+
+    /// The standard set of natives seeded into every fresh global scope:
+    /// `len`/`str`/`num`/`typeof` for converting between Lox's handful of
+    /// value kinds, `print`/`read_line` for simple IO, and `sqrt`/`floor`
+    /// for arithmetic the language has no operator for. `clock` is included
+    /// here too so it goes through the same registration path as everything
+    /// else instead of being special-cased by callers.
+    fn standard_library() -> Vec<Function> {
+        vec![
+            Function::clock(),
+            Function::native("len", 1, native::len),
+            Function::native("str", 1, native::str),
+            Function::native("num", 1, native::num),
+            Function::native("typeof", 1, native::type_of),
+            Function::native("print", 1, native::print),
+            Function::native("read_line", 0, native::read_line),
+            Function::native("sqrt", 1, native::sqrt),
+            Function::native("floor", 1, native::floor),
+            Function::native("rational", 2, native::rational),
+            Function::native("complex", 2, native::complex),
+        ]
+    }
+
+    /// Interns each standard-library function's name and defines it in
+    /// `globals`, so source code can call `len(...)`, `sqrt(...)`, etc.
+    /// without the caller having to register them one at a time. This is
+    /// already the registrable-builtin mechanism: `Function::native` is a
+    /// host function plus its name/arity, `Vec<Function>` is the registry,
+    /// and this call seeds it into `globals` as ordinary callable values
+    /// that flow through `visit_call_expr`'s arity check and `LoxCallable`
+    /// dispatch exactly like a `User` function - a separate `Builtin` trait
+    /// would just be a second way to express the same three fields. See
+    /// `tests/run/ok/natives.lox`, which calls several of these the same way
+    /// user code calls any other function.
+    pub fn register_standard_library(globals: &EnvRef, interner: &InternerRef) {
+        for function in Self::standard_library() {
+            let symbol = interner.borrow_mut().intern(function.name());
+            globals.borrow_mut().define(
+                symbol,
+                LoxTypes::Object(Literal::Function(Box::new(function))),
+            );
+        }
+    }
+}
+
+impl PartialEq for Function {
+    /// Compares `NativeFn`s by name and arity rather than function-pointer
+    /// address, since pointer equality isn't guaranteed meaningful and two
+    /// natives are the same Lox-visible function iff they were registered
+    /// under the same name.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Function::Clock {}, Function::Clock {}) => true,
+            (
+                Function::NativeFn {
+                    name: n1,
+                    arity: a1,
+                    ..
+                },
+                Function::NativeFn {
+                    name: n2,
+                    arity: a2,
+                    ..
+                },
+            ) => n1 == n2 && a1 == a2,
+            (
+                Function::User {
+                    name: n1,
+                    params: p1,
+                    body: b1,
+                    ..
+                },
+                Function::User {
+                    name: n2,
+                    params: p2,
+                    body: b2,
+                    ..
+                },
+            ) => n1 == n2 && p1 == p2 && b1 == b2,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Function::Clock {} => write!(f, "<native function>"),
+            Function::NativeFn { name, .. } => write!(f, "<native function {}>", name),
+            Function::User { name, .. } => write!(f, "<fn {}>", name.lexeme),
+        }
+    }
+}
+
+/// Implementations of the functions `Function::standard_library` registers.
+/// Each takes the already-evaluated arguments (already checked for arity by
+/// `LoxCallable::fcall`) and the call-site `paren` token to report errors
+/// against.
+mod native {
+    use super::{Interpreter, InterpreterError, Literal, LoxRuntime, LoxTypes, Token};
+
+    pub fn len(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match &args[0] {
+            LoxTypes::Object(Literal::LString(s)) => {
+                Ok(LoxTypes::Object(Literal::Number(s.chars().count() as f64)))
+            }
+            other => {
+                InterpreterError::error(paren, format!("len expects a string, found {}.", other))
+            }
+        }
+    }
+
+    pub fn str(_interpreter: &Interpreter, args: Vec<LoxTypes>, _paren: Token) -> LoxRuntime {
+        let LoxTypes::Object(literal) = &args[0];
+        Ok(LoxTypes::Object(Literal::LString(literal.to_plain_string())))
+    }
+
+    pub fn num(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match &args[0] {
+            LoxTypes::Object(Literal::Number(n)) => Ok(LoxTypes::Object(Literal::Number(*n))),
+            LoxTypes::Object(Literal::LString(s)) => match s.trim().parse() {
+                Ok(n) => Ok(LoxTypes::Object(Literal::Number(n))),
+                Err(_) => {
+                    InterpreterError::error(paren, format!("couldn't parse '{}' as a number.", s))
+                }
+            },
+            other => InterpreterError::error(
+                paren,
+                format!("num expects a string or number, found {}.", other),
+            ),
+        }
+    }
+
+    pub fn type_of(_interpreter: &Interpreter, args: Vec<LoxTypes>, _paren: Token) -> LoxRuntime {
+        let LoxTypes::Object(literal) = &args[0];
+        Ok(LoxTypes::Object(Literal::LString(literal.literal_type())))
+    }
+
+    pub fn print(_interpreter: &Interpreter, args: Vec<LoxTypes>, _paren: Token) -> LoxRuntime {
+        println!("{}", args[0]);
+        Ok(LoxTypes::Object(Literal::Null))
+    }
+
+    pub fn read_line(_interpreter: &Interpreter, _args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(_) => Ok(LoxTypes::Object(Literal::LString(
+                line.trim_end().to_owned(),
+            ))),
+            Err(e) => InterpreterError::error(paren, format!("couldn't read from stdin: {}.", e)),
+        }
+    }
+
+    pub fn sqrt(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match &args[0] {
+            LoxTypes::Object(Literal::Number(n)) => Ok(LoxTypes::Object(Literal::Number(n.sqrt()))),
+            other => {
+                InterpreterError::error(paren, format!("sqrt expects a number, found {}.", other))
+            }
+        }
+    }
+
+    pub fn floor(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match &args[0] {
+            LoxTypes::Object(Literal::Number(n)) => {
+                Ok(LoxTypes::Object(Literal::Number(n.floor())))
+            }
+            other => {
+                InterpreterError::error(paren, format!("floor expects a number, found {}.", other))
+            }
+        }
+    }
+
+    /// The only way Lox source can construct a `Literal::Rational` - there's
+    /// no literal syntax for it, so it's reached the same way `sqrt`/`floor`
+    /// reach their results: through a native call. `n`/`d` are truncated
+    /// toward zero to `i64` (an `as` cast) before the fraction is reduced.
+    pub fn rational(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match (&args[0], &args[1]) {
+            (LoxTypes::Object(Literal::Number(n)), LoxTypes::Object(Literal::Number(d))) => {
+                match Literal::rational(*n as i64, *d as i64) {
+                    Ok(value) => Ok(LoxTypes::Object(value)),
+                    Err(e) => InterpreterError::error(paren, e),
+                }
+            }
+            (n, d) => InterpreterError::error(
+                paren,
+                format!("rational expects two numbers, found {} and {}.", n, d),
+            ),
+        }
+    }
+
+    /// The only way Lox source can construct a `Literal::Complex` - see
+    /// `rational` above.
+    pub fn complex(_interpreter: &Interpreter, args: Vec<LoxTypes>, paren: Token) -> LoxRuntime {
+        match (&args[0], &args[1]) {
+            (LoxTypes::Object(Literal::Number(re)), LoxTypes::Object(Literal::Number(im))) => {
+                Ok(LoxTypes::Object(Literal::Complex(*re, *im)))
+            }
+            (re, im) => InterpreterError::error(
+                paren,
+                format!("complex expects two numbers, found {} and {}.", re, im),
+            ),
+        }
+    }
+}
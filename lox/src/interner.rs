@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A small integer id for an interned lexeme. Two identical identifiers
+/// scanned anywhere in the source resolve to the same `Symbol`, so code
+/// holding one can compare/hash it as a `u32` instead of the original
+/// `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Shared handle to an `Interner`, so the `Scanner` (which mints symbols as
+/// it scans identifiers) and anything built outside of scanning (native
+/// globals defined straight from Rust string literals) can agree on the
+/// same ids across an entire REPL session, not just one `Scanner::new` call.
+pub type InternerRef = Rc<RefCell<Interner>>;
+
+/// Bidirectional lexeme <-> `Symbol` table: `strings` holds the canonical
+/// `String` for each id, `ids` the reverse lookup used to dedupe repeats.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: Vec<String>,
+    ids: HashMap<String, Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> InternerRef {
+        Rc::new(RefCell::new(Interner {
+            strings: Vec::new(),
+            ids: HashMap::new(),
+        }))
+    }
+
+    pub fn intern(&mut self, lexeme: &str) -> Symbol {
+        if let Some(&symbol) = self.ids.get(lexeme) {
+            return symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(lexeme.to_string());
+        self.ids.insert(lexeme.to_string(), symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+}
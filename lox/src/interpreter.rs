@@ -1,13 +1,49 @@
-use crate::environment::Environment;
+use crate::ast::Ast;
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::environment::{EnvRef, Environment};
 use crate::expr::{self as ex, Expr};
+use crate::function::Function;
+use crate::interner::{Interner, InternerRef};
+use crate::optimizer;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
 use crate::stmt::{self, Stmt};
 use crate::token::{Literal, LoxTypes, Token, TokenType::*};
 use crate::traits::LoxCallable;
 use crate::LoxError;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 // use lazy_static::lazy_static;
 
 // 10.2.1
 
+/// Where a `print` statement's value goes: a real session writes straight to
+/// stdout, but the golden-file test harness under `tests/run/ok` swaps in a
+/// shared buffer (via `Interpreter::capture_output`) so a program's full
+/// output can be diffed against its `.expected` file without touching the
+/// real stdout.
+#[derive(Clone, Debug)]
+enum Output {
+    Stdout,
+    Buffer(Rc<RefCell<String>>),
+}
+
+impl Output {
+    fn write_line(&self, line: &str) {
+        match self {
+            Output::Stdout => println!("{}", line),
+            Output::Buffer(buffer) => {
+                let mut buffer = buffer.borrow_mut();
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct InterpreterError {
     operator: Token,
@@ -35,10 +71,66 @@ impl InterpreterError {
 
 pub type LoxRuntime = Result<LoxTypes, InterpreterError>;
 
+/// Short-circuiting control-flow signal raised by `break`/`continue`, mirrored
+/// after how `self.error` already short-circuits statement execution on a
+/// runtime error: set by `visit_break_stmt`/`visit_continue_stmt`, it makes
+/// `execute`'s callers stop running the rest of the current statement list
+/// instead of unwinding the Rust call stack, and is consumed by the nearest
+/// enclosing `visit_whilestmt_stmt`. This is the same `Flow`-style signal a
+/// `Break`/`Continue`/`Normal` return enum would carry, just threaded through
+/// a field `execute`/`execute_block` poll after each statement rather than
+/// through their return type - and a `break`/`continue` outside any loop is
+/// already rejected earlier and harder than a runtime check: at parse time,
+/// by `Parser::loop_depth` (see `fun_declaration`/`break_statement`). See
+/// `tests/run/ok/loops.lox`, which breaks out of a `for` loop and skips an
+/// iteration with `continue` in the same run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LoopSignal {
+    Break,
+    Continue,
+}
+
 #[derive(Clone, Debug)]
 pub struct Interpreter {
     error: LoxError,
-    environment: Environment,
+    environment: EnvRef,
+    interner: InternerRef,
+    loop_signal: Option<LoopSignal>,
+    /// Value most recently handed to `return`, not yet claimed by the call
+    /// whose body is running it - the same side-channel idea as
+    /// `loop_signal`: set by `visit_returnstmt_stmt`, checked (but not
+    /// consumed) by `execute_block`/`visit_whilestmt_stmt` so it keeps
+    /// propagating out of nested blocks and loops instead of just the
+    /// innermost one, and finally drained by `call_function` at the
+    /// function-call boundary to become that call's result. Loops are the
+    /// one place this deliberately does NOT behave like `loop_signal`:
+    /// `visit_whilestmt_stmt` breaks its own `loop {}` as soon as this is
+    /// set but leaves the value in place instead of taking it, so it keeps
+    /// rising through every enclosing block/loop all the way to the call
+    /// frame that's waiting on it. See `tests/run/ok/return_propagation.lox`,
+    /// which returns from a block nested inside an `if` inside a `for` loop.
+    return_value: Option<LoxTypes>,
+    /// The value of the most recently interpreted `Stmt::Repl`, handed back
+    /// to `interpret`'s caller for display; `None` for a file/block that
+    /// never ran one. Also what `_` is bound to in the global environment.
+    repl_value: Option<LoxTypes>,
+    /// Absolute path of the file currently being interpreted, used to resolve
+    /// a relative `import` path against the importing file's own directory
+    /// instead of the process's working directory. `None` in a REPL session
+    /// or any session that never called `set_current_file`.
+    current_file: Option<PathBuf>,
+    /// Canonicalized paths already pulled in by `import`, so a module that's
+    /// imported twice (or imports itself, directly or through a cycle) only
+    /// runs once.
+    imported: HashSet<PathBuf>,
+    /// Every runtime error raised so far, collected instead of stopping at
+    /// the first one - mirrors `Scanner`/`Parser`/`Resolver`'s own
+    /// `diagnostics`, and gains entries from an imported module's via
+    /// `import_module`.
+    diagnostics: Diagnostics,
+    /// Where `print` output is written - `Output::Stdout` for every real
+    /// session.
+    output: Output,
 }
 
 impl Interpreter {
@@ -46,32 +138,97 @@ impl Interpreter {
         Interpreter {
             error,
             environment: Environment::new(),
+            interner: Interner::new(),
+            loop_signal: None,
+            return_value: None,
+            repl_value: None,
+            current_file: None,
+            imported: HashSet::new(),
+            diagnostics: Diagnostics::new(),
+            output: Output::Stdout,
+        }
+    }
+
+    pub fn new_with_env(error: LoxError, environment: EnvRef, interner: InternerRef) -> Interpreter {
+        Interpreter {
+            error,
+            environment,
+            interner,
+            loop_signal: None,
+            return_value: None,
+            repl_value: None,
+            current_file: None,
+            imported: HashSet::new(),
+            diagnostics: Diagnostics::new(),
+            output: Output::Stdout,
         }
     }
 
-    pub fn new_with_env(error: LoxError, environment: Environment) -> Interpreter {
-        Interpreter { error, environment }
+    /// Hands out the `Interner` the scanner should keep interning into for
+    /// the rest of this session, so a REPL line reusing a name gets back the
+    /// same `Symbol` it got the first time.
+    pub fn interner(&self) -> InternerRef {
+        Rc::clone(&self.interner)
+    }
+
+    /// Records the absolute path of the file about to be run, so a relative
+    /// `import` path inside it resolves against its directory. Called by
+    /// `run_file` before handing the source off to `run`.
+    pub fn set_current_file(&mut self, path: PathBuf) {
+        self.current_file = Some(path);
     }
 
-    pub fn interpret(&mut self, stmts: Vec<Stmt>) {
+    /// Redirects every later `print` statement's output into a shared
+    /// buffer instead of stdout, handing back the buffer so the caller (the
+    /// golden-file test harness, via `lib::run_buffered`) can read it once
+    /// the run finishes.
+    pub fn capture_output(&mut self) -> Rc<RefCell<String>> {
+        let buffer = Rc::new(RefCell::new(String::new()));
+        self.output = Output::Buffer(Rc::clone(&buffer));
+        buffer
+    }
+
+    /// Runs `stmts` and, if the last one executed was a `Stmt::Repl` (a bare
+    /// expression typed at the prompt), returns the value it produced so
+    /// `prompt` can print it - distinct from a file's statements, which never
+    /// produce one.
+    pub fn interpret(&mut self, stmts: Vec<Stmt>, ast: &mut Ast) -> Option<LoxTypes> {
+        self.repl_value = None;
         for stmt in stmts {
-            self.execute(stmt);
-            if self.error == LoxError::RuntimeError {
+            self.execute(stmt, ast);
+            if self.error == LoxError::RuntimeError
+                || self.loop_signal.is_some()
+                || self.return_value.is_some()
+            {
                 break;
             }
         }
+        self.repl_value.clone()
     }
 
     pub fn had_error(&self) -> bool {
         self.error == LoxError::RuntimeError
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> LoxRuntime {
-        expr.accept(self)
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
     }
 
-    fn execute(&mut self, stmt: Stmt) {
-        stmt.accept(self);
+    /// Records a runtime error as a `Diagnostic` and flips the short-circuit
+    /// flag every `execute`/`execute_block` loop already checks, replacing
+    /// the old eprintln-on-construct `LoxError::runtime_error`.
+    fn report_runtime_error(&mut self, err: InterpreterError) {
+        self.diagnostics
+            .push(Diagnostic::error_at("runtime", &err.operator(), err.message()));
+        self.error = LoxError::RuntimeError;
+    }
+
+    fn evaluate(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
+        expr.accept(self, ast)
+    }
+
+    fn execute(&mut self, stmt: Stmt, ast: &mut Ast) {
+        stmt.accept(self, ast);
     }
 
     fn is_true(obj: Literal) -> bool {
@@ -96,39 +253,200 @@ impl Interpreter {
         }
     }
 
-    fn execute_block(&mut self, stmts: Vec<Stmt>, env: Environment) {
-        let previous = self.environment.clone();
+    fn execute_block(&mut self, stmts: Vec<Stmt>, env: EnvRef, ast: &mut Ast) {
+        let previous = Rc::clone(&self.environment);
         self.environment = env;
         for stmt in stmts {
-            self.execute(stmt);
-            if self.error == LoxError::RuntimeError {
+            self.execute(stmt, ast);
+            if self.error == LoxError::RuntimeError
+                || self.loop_signal.is_some()
+                || self.return_value.is_some()
+            {
                 break;
             }
         }
-        self.environment.enclosing().extend(previous.values());
-        self.environment = self.environment.clone().upper_env();
+        self.environment = previous;
+    }
+
+    /// Runs a user-defined function's body in a fresh environment extending
+    /// its captured `closure` (not the caller's environment - that's what
+    /// makes it a closure rather than dynamic scoping), with `params` bound
+    /// to `arguments`. Drains `return_value` once the body stops running so
+    /// it becomes this call's result (nil if the body fell off the end
+    /// without a `return`) and doesn't leak past this call's boundary.
+    pub(crate) fn call_function(
+        &mut self,
+        params: &[Token],
+        body: &Stmt,
+        closure: &EnvRef,
+        arguments: Vec<LoxTypes>,
+        ast: &mut Ast,
+    ) -> LoxRuntime {
+        let call_env = Environment::extend(Rc::clone(closure));
+        for (param, arg) in params.iter().zip(arguments) {
+            call_env.borrow_mut().define(
+                param
+                    .symbol
+                    .expect("parameter token must carry an interned symbol"),
+                arg,
+            );
+        }
+
+        let statements = match body {
+            Stmt::Block { statements } => statements.clone(),
+            _ => panic!("function body must be a block"), // should be unreachable
+        };
+        self.execute_block(statements, call_env, ast);
+        Ok(self
+            .return_value
+            .take()
+            .unwrap_or(LoxTypes::Object(Literal::Null)))
+    }
+
+    /// Evaluates a binary arithmetic op across the `Rational -> Number ->
+    /// Complex` numeric tower, promoting both operands to the narrowest
+    /// shared level before applying the matching closure - mirrors `rlox`'s
+    /// `tower_binop` (see `rlox/src/vm.rs`). Returns `None` when either
+    /// operand is not numeric, so callers fall through to it for their
+    /// existing type-error message.
+    fn numeric_tower_binop(
+        l: &Literal,
+        r: &Literal,
+        rational_op: impl Fn(i64, i64, i64, i64) -> Result<Literal, String>,
+        float_op: impl Fn(f64, f64) -> f64,
+        complex_op: impl Fn((f64, f64), (f64, f64)) -> (f64, f64),
+    ) -> Option<Result<Literal, String>> {
+        match (l, r) {
+            (Literal::Number(a), Literal::Number(b)) => {
+                Some(Ok(Literal::Number(float_op(*a, *b))))
+            }
+            (Literal::Rational(an, ad), Literal::Rational(bn, bd)) => {
+                Some(rational_op(*an, *ad, *bn, *bd))
+            }
+            (Literal::Complex(_, _), _) | (_, Literal::Complex(_, _)) => {
+                let (a, b) = (l.as_complex()?, r.as_complex()?);
+                let (re, im) = complex_op(a, b);
+                Some(Ok(Literal::Complex(re, im)))
+            }
+            (Literal::Rational(_, _), _) | (_, Literal::Rational(_, _)) => {
+                let (a, b) = (l.as_real()?, r.as_real()?);
+                Some(Ok(Literal::Number(float_op(a, b))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves an `import` path relative to the directory of
+    /// `current_file`, the way a `#include` or a relative JS `import` would,
+    /// falling back to the process's working directory when run without a
+    /// backing file (e.g. from the REPL).
+    fn resolve_import_path(&self, path: &str) -> PathBuf {
+        let candidate = Path::new(path);
+        if candidate.is_absolute() {
+            return candidate.to_path_buf();
+        }
+        let base_dir = self
+            .current_file
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("."));
+        base_dir.join(candidate)
+    }
+
+    /// Scans, parses, resolves and runs the module at `path` (resolved
+    /// relative to the importing file) in a fresh scope nested under the
+    /// current environment, then splices its top-level functions/vars into
+    /// the importing scope. Already-imported modules, tracked by
+    /// canonicalized path, are silently skipped the second time - including
+    /// when a cycle would otherwise re-enter one that's still loading.
+    fn import_module(&mut self, keyword: Token, path: &str) {
+        let target = self.resolve_import_path(path);
+        let canonical = match target.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(e) => {
+                self.report_runtime_error(InterpreterError::new(
+                    keyword,
+                    format!("couldn't find module '{}': {}.", path, e),
+                ));
+                return;
+            }
+        };
+
+        if !self.imported.insert(canonical.clone()) {
+            return;
+        }
+
+        let source = match std::fs::read_to_string(&canonical) {
+            Ok(source) => source,
+            Err(e) => {
+                self.report_runtime_error(InterpreterError::new(
+                    keyword,
+                    format!("couldn't read module '{}': {}.", path, e),
+                ));
+                return;
+            }
+        };
+
+        let mut scanner = Scanner::new(LoxError::NoError, source, self.interner());
+        let tokens = scanner.scan_tokens();
+        if scanner.had_error() {
+            self.diagnostics.extend(scanner.diagnostics().clone());
+            self.error = LoxError::RuntimeError;
+            return;
+        }
+
+        let mut parser = Parser::new(tokens, LoxError::NoError);
+        let mut stmts = parser.parse();
+        if parser.had_error() {
+            self.diagnostics.extend(parser.diagnostics().clone());
+            self.error = LoxError::RuntimeError;
+            return;
+        }
+        let mut module_ast = parser.take_ast();
+        optimizer::optimize_program(&mut stmts, &mut module_ast);
+
+        let mut resolver = Resolver::new(LoxError::NoError);
+        resolver.resolve(&mut stmts, &mut module_ast);
+        if resolver.had_error() {
+            self.diagnostics.extend(resolver.diagnostics().clone());
+            self.error = LoxError::RuntimeError;
+            return;
+        }
+
+        let previous_file = self.current_file.replace(canonical);
+        let module_env = Environment::extend(Rc::clone(&self.environment));
+        self.execute_block(stmts, Rc::clone(&module_env), &mut module_ast);
+        self.current_file = previous_file;
+
+        for (symbol, value) in module_env.borrow().own_bindings() {
+            self.environment.borrow_mut().define(symbol, value);
+        }
     }
 }
 
 impl ex::Visitor<LoxRuntime> for Interpreter {
-    fn visit_literal_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_literal_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> LoxRuntime {
         match expr {
             Expr::Literal { value } => Ok(LoxTypes::Object(value.clone())),
             _ => panic!("Unexpected value in interpreting literal expression."), // should be unreachable
         }
     }
 
-    fn visit_grouping_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_grouping_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
-            Expr::Grouping { expression } => self.evaluate(expression),
+            Expr::Grouping { expression } => {
+                let inner = ast.expr(*expression).clone();
+                self.evaluate(&inner, ast)
+            }
             _ => panic!("Unexpected value in interpreting grouping expression."), // should be unreachable
         }
     }
 
-    fn visit_unary_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_unary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
             Expr::Unary { operator, right } => {
-                let LoxTypes::Object(r) = self.evaluate(right)?;
+                let right_expr = ast.expr(*right).clone();
+                let LoxTypes::Object(r) = self.evaluate(&right_expr, ast)?;
                 match operator.token_type {
                     Minus => {
                         if let Literal::Number(n) = r {
@@ -148,92 +466,79 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
         }
     }
 
-    fn visit_binary_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_binary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let LoxTypes::Object(l) = self.evaluate(left)?;
-                let LoxTypes::Object(r) = self.evaluate(right)?;
+                let left_expr = ast.expr(*left).clone();
+                let right_expr = ast.expr(*right).clone();
+                let LoxTypes::Object(l) = self.evaluate(&left_expr, ast)?;
+                let LoxTypes::Object(r) = self.evaluate(&right_expr, ast)?;
 
                 match operator.token_type {
-                    Minus => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Number(ln - rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be numbers, instead found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
-                            InterpreterError::error(
-                                operator.clone(),
-                                format!(
-                                    "both operands must be numbers, instead found {} and {}.",
-                                    l.literal_type(),
-                                    r.literal_type()
-                                ),
-                            )
-                        }
-                    }
-                    Slash => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Number(ln / rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be numbers, instead found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
-                            InterpreterError::error(
-                                operator.clone(),
-                                format!(
-                                    "both operands must be numbers, instead found {} and {}.",
-                                    l.literal_type(),
-                                    r.literal_type()
-                                ),
-                            )
-                        }
-                    }
-                    Star => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Number(ln * rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be numbers, instead found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
-                            InterpreterError::error(
-                                operator.clone(),
-                                format!(
-                                    "both operands must be numbers, instead found {} and {}.",
-                                    l.literal_type(),
-                                    r.literal_type()
-                                ),
+                    Minus => match Interpreter::numeric_tower_binop(
+                        &l,
+                        &r,
+                        |an, ad, bn, bd| Literal::rational(an * bd - bn * ad, ad * bd),
+                        |a, b| a - b,
+                        |(are, aim), (bre, bim)| (are - bre, aim - bim),
+                    ) {
+                        Some(Ok(value)) => Ok(LoxTypes::Object(value)),
+                        Some(Err(e)) => InterpreterError::error(operator.clone(), e),
+                        None => InterpreterError::error(
+                            operator.clone(),
+                            format!(
+                                "both operands must be numbers, instead found {} and {}.",
+                                l.literal_type(),
+                                r.literal_type()
+                            ),
+                        ),
+                    },
+                    Slash => match Interpreter::numeric_tower_binop(
+                        &l,
+                        &r,
+                        |an, ad, bn, bd| Literal::rational(an * bd, ad * bn),
+                        |a, b| a / b,
+                        |(are, aim), (bre, bim)| {
+                            let denom = bre * bre + bim * bim;
+                            (
+                                (are * bre + aim * bim) / denom,
+                                (aim * bre - are * bim) / denom,
                             )
-                        }
-                    }
+                        },
+                    ) {
+                        Some(Ok(value)) => Ok(LoxTypes::Object(value)),
+                        Some(Err(e)) => InterpreterError::error(operator.clone(), e),
+                        None => InterpreterError::error(
+                            operator.clone(),
+                            format!(
+                                "both operands must be numbers, instead found {} and {}.",
+                                l.literal_type(),
+                                r.literal_type()
+                            ),
+                        ),
+                    },
+                    Star => match Interpreter::numeric_tower_binop(
+                        &l,
+                        &r,
+                        |an, ad, bn, bd| Literal::rational(an * bn, ad * bd),
+                        |a, b| a * b,
+                        |(are, aim), (bre, bim)| (are * bre - aim * bim, are * bim + aim * bre),
+                    ) {
+                        Some(Ok(value)) => Ok(LoxTypes::Object(value)),
+                        Some(Err(e)) => InterpreterError::error(operator.clone(), e),
+                        None => InterpreterError::error(
+                            operator.clone(),
+                            format!(
+                                "both operands must be numbers, instead found {} and {}.",
+                                l.literal_type(),
+                                r.literal_type()
+                            ),
+                        ),
+                    },
                     Plus => {
                         if let Literal::Number(ln) = l {
                             if let Literal::Number(rn) = r {
@@ -266,44 +571,34 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
                                 )
                             }
                         } else {
-                            InterpreterError::error(
-                                operator.clone(),
-                                format!(
-                                    "plus sign operands must be numbers or strings, found {} on left and {} on right.",
-                                    l.literal_type(),
-                                    r.literal_type()
-                                ),
-                            )
-                        }
-                    }
-                    Greater => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ln > rn)))
-                            } else {
-                                InterpreterError::error(
+                            match Interpreter::numeric_tower_binop(
+                                &l,
+                                &r,
+                                |an, ad, bn, bd| Literal::rational(an * bd + bn * ad, ad * bd),
+                                |a, b| a + b,
+                                |(are, aim), (bre, bim)| (are + bre, aim + bim),
+                            ) {
+                                Some(Ok(value)) => Ok(LoxTypes::Object(value)),
+                                Some(Err(e)) => InterpreterError::error(operator.clone(), e),
+                                None => InterpreterError::error(
                                     operator.clone(),
                                     format!(
-                                        "both operands must be of the same type, found {} and {}.",
+                                        "plus sign operands must be numbers or strings, found {} on left and {} on right.",
                                         l.literal_type(),
                                         r.literal_type()
                                     ),
-                                )
+                                ),
                             }
-                        } else if let Literal::LString(ls) = l.clone() {
-                            if let Literal::LString(rs) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ls > rs)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
+                        }
+                    }
+                    Greater => match (l.as_real(), r.as_real()) {
+                        (Some(ln), Some(rn)) => Ok(LoxTypes::Object(Literal::Boolean(ln > rn))),
+                        _ => {
+                            if let Literal::LString(ls) = l.clone() {
+                                if let Literal::LString(rs) = r.clone() {
+                                    return Ok(LoxTypes::Object(Literal::Boolean(ls > rs)));
+                                }
                             }
-                        } else {
                             InterpreterError::error(
                                 operator.clone(),
                                 format!(
@@ -313,35 +608,15 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
                                 ),
                             )
                         }
-                    }
-                    Less => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ln < rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
+                    },
+                    Less => match (l.as_real(), r.as_real()) {
+                        (Some(ln), Some(rn)) => Ok(LoxTypes::Object(Literal::Boolean(ln < rn))),
+                        _ => {
+                            if let Literal::LString(ls) = l.clone() {
+                                if let Literal::LString(rs) = r.clone() {
+                                    return Ok(LoxTypes::Object(Literal::Boolean(ls < rs)));
+                                }
                             }
-                        } else if let Literal::LString(ls) = l.clone() {
-                            if let Literal::LString(rs) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ls < rs)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
                             InterpreterError::error(
                                 operator.clone(),
                                 format!(
@@ -351,35 +626,15 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
                                 ),
                             )
                         }
-                    }
-                    GreaterEqual => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ln >= rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
+                    },
+                    GreaterEqual => match (l.as_real(), r.as_real()) {
+                        (Some(ln), Some(rn)) => Ok(LoxTypes::Object(Literal::Boolean(ln >= rn))),
+                        _ => {
+                            if let Literal::LString(ls) = l.clone() {
+                                if let Literal::LString(rs) = r.clone() {
+                                    return Ok(LoxTypes::Object(Literal::Boolean(ls >= rs)));
+                                }
                             }
-                        } else if let Literal::LString(ls) = l.clone() {
-                            if let Literal::LString(rs) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ls >= rs)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
                             InterpreterError::error(
                                 operator.clone(),
                                 format!(
@@ -389,35 +644,15 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
                                 ),
                             )
                         }
-                    }
-                    LessEqual => {
-                        if let Literal::Number(ln) = l {
-                            if let Literal::Number(rn) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ln <= rn)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
+                    },
+                    LessEqual => match (l.as_real(), r.as_real()) {
+                        (Some(ln), Some(rn)) => Ok(LoxTypes::Object(Literal::Boolean(ln <= rn))),
+                        _ => {
+                            if let Literal::LString(ls) = l.clone() {
+                                if let Literal::LString(rs) = r.clone() {
+                                    return Ok(LoxTypes::Object(Literal::Boolean(ls <= rs)));
+                                }
                             }
-                        } else if let Literal::LString(ls) = l.clone() {
-                            if let Literal::LString(rs) = r {
-                                Ok(LoxTypes::Object(Literal::Boolean(ls <= rs)))
-                            } else {
-                                InterpreterError::error(
-                                    operator.clone(),
-                                    format!(
-                                        "both operands must be of the same type, found {} and {}.",
-                                        l.literal_type(),
-                                        r.literal_type()
-                                    ),
-                                )
-                            }
-                        } else {
                             InterpreterError::error(
                                 operator.clone(),
                                 format!(
@@ -427,7 +662,7 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
                                 ),
                             )
                         }
-                    }
+                    },
                     BangEqual => Ok(LoxTypes::Object(Literal::Boolean(l != r))),
                     EqualEqual => Ok(LoxTypes::Object(Literal::Boolean(l == r))),
                     _ => panic!("Non-binary operator found in binary expression."), // should be unreachable
@@ -437,70 +672,88 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
         }
     }
 
-    fn visit_variable_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_variable_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> LoxRuntime {
         match expr {
-            Expr::Variable { name } => self.environment.get(name.clone()),
+            Expr::Variable { name, depth } => match depth {
+                Some(distance) => Environment::get_at(&self.environment, *distance, name),
+                None => self.environment.borrow().get_global(name.clone()),
+            },
             _ => panic!("Unexpected value in interpreting variable expression."), // should be unreachable
         }
     }
 
-    fn visit_assign_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_assign_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
-            Expr::Assign { name, value } => {
-                let new_value = self.evaluate(value)?;
-                self.environment.assign(name.clone(), new_value.clone())?;
+            Expr::Assign { name, value, depth } => {
+                let value_expr = ast.expr(*value).clone();
+                let new_value = self.evaluate(&value_expr, ast)?;
+                match depth {
+                    Some(distance) => {
+                        Environment::assign_at(
+                            &self.environment,
+                            *distance,
+                            name.clone(),
+                            new_value.clone(),
+                        )?;
+                    }
+                    None => {
+                        self.environment
+                            .borrow_mut()
+                            .assign(name.clone(), new_value.clone())?;
+                    }
+                }
                 Ok(new_value)
             }
             _ => panic!("Unexpected value in interpreting assign expression."), // should be unreachable
         }
     }
 
-    fn visit_logical_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_logical_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
             Expr::Logical {
                 left,
                 operator,
                 right,
             } => {
-                let l = self.evaluate(left)?;
+                let left_expr = ast.expr(*left).clone();
+                let l = self.evaluate(&left_expr, ast)?;
                 if (operator.token_type == Or && Interpreter::is_true_object(l.clone()))
                     || !Interpreter::is_true_object(l.clone())
                 {
                     return Ok(l);
                 }
-                self.evaluate(right)
+                let right_expr = ast.expr(*right).clone();
+                self.evaluate(&right_expr, ast)
             }
             _ => panic!("Unexpected value in interpreting logical expression."), // should be unreachable
         }
     }
 
-    fn visit_call_expr(&mut self, expr: &Expr) -> LoxRuntime {
+    fn visit_call_expr(&mut self, expr: &Expr, ast: &mut Ast) -> LoxRuntime {
         match expr {
             Expr::Call {
                 callee,
                 paren,
                 arguments,
             } => {
-                let call = self.evaluate(callee)?;
+                let callee_expr = ast.expr(*callee).clone();
+                let call = self.evaluate(&callee_expr, ast)?;
 
                 let mut args: Vec<LoxTypes> = Vec::new();
 
                 for arg in arguments {
-                    args.push(self.evaluate(arg)?);
+                    args.push(self.evaluate(arg, ast)?);
                 }
 
-                if args.len() != callee.arity(paren.clone())? {
+                let arity = call.arity(paren.clone())?;
+                if args.len() != arity {
                     return InterpreterError::error(
                         paren.clone(),
-                        format!(
-                            "expected {} arguments, found {}.",
-                            callee.arity(paren.clone())?,
-                            args.len()
-                        ),
+                        format!("expected {} arguments, found {}.", arity, args.len()),
                     );
                 }
 
-                callee.fcall(self, args, paren.clone())
+                call.fcall(self, args, paren.clone(), ast)
             }
             _ => panic!("Unexpected value in interpreting call expression."), // should be unreachable
         }
@@ -508,104 +761,223 @@ impl ex::Visitor<LoxRuntime> for Interpreter {
 }
 
 impl stmt::Visitor<()> for Interpreter {
-    fn visit_expression_stmt(&mut self, stmt: &Stmt) {
+    fn visit_expression_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
-            Stmt::Expression { expr } => match self.evaluate(expr) {
+            Stmt::Expression { expr } => match self.evaluate(expr, ast) {
                 Ok(_) => (),
                 Err(err) => {
-                    LoxError::runtime_error(err);
-                    self.error = LoxError::RuntimeError;
+                    self.report_runtime_error(err);
                 }
             },
             _ => panic!("Unexpected value in evaluating expression statement."), // should be unreachable
         }
     }
 
-    fn visit_print_stmt(&mut self, stmt: &Stmt) {
+    fn visit_print_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
-            Stmt::Print { expr } => match self.evaluate(expr) {
-                Ok(value) => println!("{}", value),
+            Stmt::Print { expr } => match self.evaluate(expr, ast) {
+                Ok(value) => self.output.write_line(&value.to_string()),
                 Err(err) => {
-                    LoxError::runtime_error(err);
-                    self.error = LoxError::RuntimeError;
+                    self.report_runtime_error(err);
                 }
             },
             _ => panic!("Unexpected value in evaluating print statement."), // should be unreachable
         }
     }
 
-    fn visit_var_stmt(&mut self, stmt: &Stmt) {
+    fn visit_repl_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
+        match stmt {
+            Stmt::Repl { expr } => match self.evaluate(expr, ast) {
+                Ok(value) => {
+                    let underscore = self.interner.borrow_mut().intern("_");
+                    self.environment
+                        .borrow_mut()
+                        .define(underscore, value.clone());
+                    self.repl_value = Some(value);
+                }
+                Err(err) => {
+                    self.report_runtime_error(err);
+                }
+            },
+            _ => panic!("Unexpected value in evaluating repl statement."), // should be unreachable
+        }
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
             Stmt::Var { name, initializer } => {
                 let mut value = LoxTypes::Object(Literal::Null);
-                if **initializer != Expr::Null {
-                    match self.evaluate(initializer) {
+                if *ast.expr(*initializer) != Expr::Null {
+                    let init_expr = ast.expr(*initializer).clone();
+                    match self.evaluate(&init_expr, ast) {
                         Ok(val) => value = val,
                         Err(err) => {
-                            LoxError::runtime_error(err);
-                            self.error = LoxError::RuntimeError;
+                            self.report_runtime_error(err);
                         }
                     }
                 }
 
-                self.environment.define(name.lexeme.clone(), value);
+                self.environment.borrow_mut().define(
+                    name.symbol
+                        .expect("identifier token must carry an interned symbol"),
+                    value,
+                );
             }
             _ => panic!("Unexpected value in evaluating var statement."), // should be unreachable
         }
     }
 
-    fn visit_block_stmt(&mut self, stmt: &Stmt) {
+    fn visit_block_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
             Stmt::Block { statements } => {
                 self.execute_block(
                     statements.clone(),
-                    Environment::new_with_enclosing(Box::new(self.environment.clone())),
+                    Environment::extend(Rc::clone(&self.environment)),
+                    ast,
                 );
             }
             _ => panic!("Unexpected value in evaluating var statement."), // should be unreachable
         }
     }
 
-    fn visit_ifstmt_stmt(&mut self, stmt: &Stmt) {
+    fn visit_ifstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
             Stmt::IfStmt {
                 condition,
                 then_branch,
                 else_branch,
-            } => match self.evaluate(condition) {
+            } => match self.evaluate(condition, ast) {
                 Ok(val) => {
                     if Interpreter::is_true_object(val) {
-                        self.execute(*then_branch.clone());
-                    } else if **else_branch != Stmt::Null {
-                        self.execute(*else_branch.clone());
+                        let then_stmt = ast.stmt(*then_branch).clone();
+                        self.execute(then_stmt, ast);
+                    } else if *ast.stmt(*else_branch) != Stmt::Null {
+                        let else_stmt = ast.stmt(*else_branch).clone();
+                        self.execute(else_stmt, ast);
                     }
                 }
                 Err(err) => {
-                    LoxError::runtime_error(err);
-                    self.error = LoxError::RuntimeError;
+                    self.report_runtime_error(err);
                 }
             },
             _ => panic!("Unexpected value in evaluating if statement."), // should be unreachable
         }
     }
 
-    fn visit_whilestmt_stmt(&mut self, stmt: &Stmt) {
+    fn visit_whilestmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
         match stmt {
-            Stmt::WhileStmt { condition, body } => loop {
-                match self.evaluate(condition) {
+            Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => loop {
+                match self.evaluate(condition, ast) {
                     Ok(val) => {
                         if !Interpreter::is_true_object(val) {
                             break;
                         }
-                        self.execute(*body.clone())
+                        let body_stmt = ast.stmt(*body).clone();
+                        self.execute(body_stmt, ast);
+                        if self.error == LoxError::RuntimeError || self.return_value.is_some() {
+                            break;
+                        }
+
+                        // `continue` still needs to run a `for` loop's
+                        // increment before the next condition check, so it's
+                        // evaluated here rather than as part of `body` (which
+                        // `continue` just cut short).
+                        let stop = self.loop_signal.take() == Some(LoopSignal::Break);
+                        if *ast.expr(*increment) != Expr::Null {
+                            let increment_expr = ast.expr(*increment).clone();
+                            if let Err(err) = self.evaluate(&increment_expr, ast) {
+                                self.report_runtime_error(err);
+                                break;
+                            }
+                        }
+                        if stop {
+                            break;
+                        }
                     }
                     Err(err) => {
-                        LoxError::runtime_error(err);
-                        self.error = LoxError::RuntimeError;
+                        // Without this `break`, a failing condition (e.g. a
+                        // type error) would report once and then spin
+                        // forever re-evaluating the exact same failure
+                        // instead of stopping the loop like every other
+                        // runtime error already does.
+                        self.report_runtime_error(err);
+                        break;
                     }
                 }
             },
             _ => panic!("Unexpected value in evaluating while statement."), // should be unreachable
         }
     }
+
+    fn visit_function_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
+        match stmt {
+            Stmt::Function { name, params, body } => {
+                let body = Box::new(ast.stmt(*body).clone());
+                let function = Function::user(
+                    name.clone(),
+                    params.clone(),
+                    body,
+                    Rc::clone(&self.environment),
+                );
+                self.environment.borrow_mut().define(
+                    name.symbol
+                        .expect("identifier token must carry an interned symbol"),
+                    LoxTypes::Object(Literal::Function(Box::new(function))),
+                );
+            }
+            _ => panic!("Unexpected value in evaluating function statement."), // should be unreachable
+        }
+    }
+
+    fn visit_returnstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) {
+        match stmt {
+            Stmt::ReturnStmt { value, .. } => {
+                let result = if *ast.expr(*value) != Expr::Null {
+                    let value_expr = ast.expr(*value).clone();
+                    match self.evaluate(&value_expr, ast) {
+                        Ok(val) => val,
+                        Err(err) => {
+                            self.report_runtime_error(err);
+                            return;
+                        }
+                    }
+                } else {
+                    LoxTypes::Object(Literal::Null)
+                };
+                self.return_value = Some(result);
+            }
+            _ => panic!("Unexpected value in evaluating return statement."), // should be unreachable
+        }
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) {
+        match stmt {
+            Stmt::Break { .. } => self.loop_signal = Some(LoopSignal::Break),
+            _ => panic!("Unexpected value in evaluating break statement."), // should be unreachable
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) {
+        match stmt {
+            Stmt::Continue { .. } => self.loop_signal = Some(LoopSignal::Continue),
+            _ => panic!("Unexpected value in evaluating continue statement."), // should be unreachable
+        }
+    }
+
+    fn visit_import_stmt(&mut self, stmt: &Stmt, _ast: &mut Ast) {
+        match stmt {
+            Stmt::Import { keyword, path } => match &path.literal {
+                Literal::LString(module_path) => {
+                    let module_path = module_path.clone();
+                    self.import_module(keyword.clone(), &module_path);
+                }
+                _ => panic!("import path token didn't carry a string literal."), // should be unreachable
+            },
+            _ => panic!("Unexpected value in evaluating import statement."), // should be unreachable
+        }
+    }
 }
@@ -5,20 +5,32 @@
 // #![allow()]
 
 pub mod traits;
+pub mod ast;
+pub mod ast_printer;
+pub mod bytecode_compiler;
+pub mod chunk;
+pub mod diagnostics;
 pub mod environment;
 pub mod expr;
+pub mod interner;
 pub mod interpreter;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
 pub mod stmt;
 pub mod token;
 pub mod function;
+pub mod vm;
 
-use interpreter::{Interpreter, InterpreterError};
-use parser::{Parser, ParserError};
+use interpreter::Interpreter;
+use parser::Parser;
+use resolver::Resolver;
 use rustyline::Editor;
-use scanner::{Scanner, ScannerError};
-use std::fs;
+use scanner::Scanner;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs, io};
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum LoxError {
@@ -26,48 +38,185 @@ pub enum LoxError {
     Error,
     ScanningError,
     ParsingError,
+    ResolvingError,
     RuntimeError,
 }
 
-impl LoxError {
-    pub fn scanning_error(err: ScannerError) {
-        eprintln!("[line {}]\nScanning error: {}", err.line(), err.message());
-    }
+/// Which backend `run` should drive the scanned tokens through: the
+/// tree-walking `Interpreter`, or the bytecode `Compiler`/`VM` pair.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Engine {
+    TreeWalk,
+    Bytecode,
+}
 
-    pub fn parsing_error(err: ParserError) {
-        eprintln!(
-            "[line {}]\nParsing error on {:?}: {}",
-            err.token().line,
-            err.token().token_type,
-            err.message()
-        );
-    }
+/// What `emit` should dump instead of actually running the program - one
+/// non-executing analysis mode per pipeline stage that has stable pretty-
+/// printing (see `scanner::dump_tokens` / `ast_printer::print`).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EmitKind {
+    Tokens,
+    Ast,
+}
 
-    pub fn runtime_error(err: InterpreterError) {
-        eprintln!(
-            "[line {}]\nRuntime error on operand {:?}: {}",
-            err.operator().line,
-            err.operator().token_type,
-            err.message()
-        );
-    }
+/// Wall-clock time spent in each phase of a `run_timed` call. A phase that
+/// was never reached because an earlier one failed keeps its `Duration`
+/// default of zero.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PhaseTimings {
+    pub scanning: Duration,
+    pub parsing: Duration,
+    pub interpreting: Duration,
 }
 
-pub fn run_file(filename: &str, interpreter: &mut Interpreter) {
-    let error = run(
-        fs::read_to_string(filename).expect("File not found"),
-        interpreter,
-    );
+/// Prints the user-facing message for a top-level `LoxError`, the same way
+/// `run_file` always has - factored out so `run_file_timed` can report
+/// identically without duplicating the match.
+fn report_top_level_error(error: LoxError) {
     match error {
         LoxError::Error => eprintln!("\nGeneric error while executing the program"),
         LoxError::ScanningError => eprintln!("\nError while scanning the program"),
         LoxError::ParsingError => eprintln!("\nError while parsing the program"),
+        LoxError::ResolvingError => eprintln!("\nError while resolving the program"),
         LoxError::RuntimeError => eprintln!("\nError while running the program"),
         _ => (),
     }
 }
 
-pub fn prompt(interpreter: &mut Interpreter) {
+/// Standard-library helpers (see `std.lox`), embedded in the binary so a
+/// session always has them even without network or filesystem access to
+/// fetch them separately.
+const PRELUDE: &str = include_str!("std.lox");
+
+/// Why loading a source file for `run_file`/`run_file_timed` failed -
+/// narrowed down from `std::io::Error`'s full `ErrorKind` to the handful of
+/// cases an embedder actually needs to branch on, plus the invalid-UTF-8
+/// case `fs::read_to_string` itself can hit.
+#[derive(Debug)]
+pub enum LoxLoadError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    InvalidData(PathBuf),
+    Other(PathBuf, io::Error),
+}
+
+impl fmt::Display for LoxLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxLoadError::NotFound(path) => write!(f, "file not found: {}", path.display()),
+            LoxLoadError::PermissionDenied(path) => {
+                write!(f, "permission denied reading {}", path.display())
+            }
+            LoxLoadError::InvalidData(path) => write!(f, "{} is not valid UTF-8", path.display()),
+            LoxLoadError::Other(path, err) => write!(f, "can't read {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Reads `filename` into a `String`, turning the handful of I/O failures a
+/// source file can hit into a `LoxLoadError` instead of panicking.
+fn load_source(filename: &str) -> Result<String, LoxLoadError> {
+    let path = PathBuf::from(filename);
+    fs::read_to_string(filename).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => LoxLoadError::NotFound(path),
+        io::ErrorKind::PermissionDenied => LoxLoadError::PermissionDenied(path),
+        io::ErrorKind::InvalidData => LoxLoadError::InvalidData(path),
+        _ => LoxLoadError::Other(path, err),
+    })
+}
+
+pub fn run_file(filename: &str, interpreter: &mut Interpreter, engine: Engine) -> Result<LoxError, LoxLoadError> {
+    let source = load_source(filename)?;
+    if let Ok(path) = fs::canonicalize(filename) {
+        interpreter.set_current_file(path);
+    }
+    let error = run(source, interpreter, false, engine);
+    report_top_level_error(error);
+    Ok(error)
+}
+
+/// Same as `run_file`, but measures wall-clock time spent in each phase
+/// instead of just running the program, for profiling which phase dominates
+/// on large programs.
+pub fn run_file_timed(
+    filename: &str,
+    interpreter: &mut Interpreter,
+    engine: Engine,
+) -> Result<PhaseTimings, LoxLoadError> {
+    let source = load_source(filename)?;
+    if let Ok(path) = fs::canonicalize(filename) {
+        interpreter.set_current_file(path);
+    }
+    let (error, timings) = run_timed(source, interpreter, engine);
+    report_top_level_error(error);
+    Ok(timings)
+}
+
+/// Runs in-memory `source` through `interpreter` the same way `run_file`
+/// does, but without touching the filesystem - for embedders that already
+/// have their program text (e.g. fetched over a network, or generated) and
+/// have no file for `run_file` to load.
+pub fn run_source(source: impl Into<String>, interpreter: &mut Interpreter, engine: Engine) -> LoxError {
+    run(source.into(), interpreter, false, engine)
+}
+
+/// Scans (and, for `EmitKind::Ast`, parses) `source` and prints the
+/// resulting token stream or AST instead of running the program - the
+/// non-executing analysis mode behind the CLI's `--emit` flag.
+pub fn emit(source: String, kind: EmitKind, interpreter: &mut Interpreter) -> LoxError {
+    let mut scanner = Scanner::new(LoxError::NoError, source.clone(), interpreter.interner());
+    let tokens = scanner.scan_tokens();
+
+    if scanner.had_error() {
+        eprint!("{}", scanner.diagnostics().render(&source));
+        return LoxError::ScanningError;
+    }
+
+    match kind {
+        EmitKind::Tokens => {
+            println!("{}", scanner::dump_tokens(&tokens));
+            LoxError::NoError
+        }
+        EmitKind::Ast => {
+            let mut parser = Parser::new(tokens, LoxError::NoError);
+            let statements = parser.parse();
+            if parser.had_error() {
+                eprint!("{}", parser.diagnostics().render(&source));
+                return LoxError::ParsingError;
+            }
+            let mut ast = parser.take_ast();
+            println!("{}", ast_printer::print(&statements, &mut ast));
+            LoxError::NoError
+        }
+    }
+}
+
+/// Runs the standard library through `interpreter` before any user code, so
+/// both `run_file` and `prompt` sessions start with `std.lox`'s helpers
+/// already defined. Prefers an on-disk `std.lox` found on a `LOX_PATH`
+/// search path (handy for iterating on the standard library itself) over
+/// the copy embedded in the binary at compile time. Under `Engine::Bytecode`
+/// every `run` builds a fresh `vm::VM`, so the prelude's definitions are not
+/// visible to code run afterwards.
+pub fn add_prelude(interpreter: &mut Interpreter, engine: Engine) {
+    let source = find_prelude_override().unwrap_or_else(|| PRELUDE.to_string());
+    let error = run(source, interpreter, false, engine);
+    if error != LoxError::NoError {
+        report_top_level_error(error);
+        panic!("the prelude failed to load ({:?}) - every session needs it, so this is fatal", error);
+    }
+}
+
+/// Looks for a `std.lox` inside each directory listed in `LOX_PATH` (colon-
+/// separated, like `PATH`), returning its contents if one is readable.
+fn find_prelude_override() -> Option<String> {
+    let search_path = env::var("LOX_PATH").ok()?;
+    env::split_paths(&search_path)
+        .map(|dir| dir.join("std.lox"))
+        .find_map(|candidate| fs::read_to_string(candidate).ok())
+}
+
+pub fn prompt(interpreter: &mut Interpreter, engine: Engine) {
     let mut rl = Editor::<()>::new();
     if rl.load_history("history.txt").is_err() {}
     loop {
@@ -75,7 +224,7 @@ pub fn prompt(interpreter: &mut Interpreter) {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
-                run(line, interpreter);
+                run(line, interpreter, true, engine);
             }
             Err(err) => {
                 println!("{:?}", err);
@@ -86,24 +235,111 @@ pub fn prompt(interpreter: &mut Interpreter) {
     rl.save_history("history.txt").unwrap();
 }
 
-fn run(to_execute: String, interpreter: &mut Interpreter) -> LoxError {
-    let mut scanner = Scanner::new(LoxError::NoError, to_execute);
+/// Runs `source` through `interpreter` the same way `run_file` does, but
+/// captures everything the program `print`s into a `String` instead of
+/// writing it to stdout - used by the golden-file test harness under
+/// `tests/run/ok` to diff a program's full output against its `.expected`
+/// file.
+pub fn run_buffered(source: String, interpreter: &mut Interpreter, engine: Engine) -> (String, LoxError) {
+    let buffer = interpreter.capture_output();
+    let error = run(source, interpreter, false, engine);
+    let output = buffer.borrow().clone();
+    (output, error)
+}
+
+/// Runs `source` through `interpreter` the same way `run` does, but also
+/// measures wall-clock time spent scanning, parsing, and interpreting -
+/// reported back as structured `PhaseTimings` instead of only a `LoxError`,
+/// so callers can profile which phase dominates on a large program.
+pub fn run_timed(source: String, interpreter: &mut Interpreter, engine: Engine) -> (LoxError, PhaseTimings) {
+    let mut timings = PhaseTimings::default();
+    let error = run_inner(source, interpreter, false, engine, Some(&mut timings));
+    (error, timings)
+}
+
+/// Drives `to_execute` through the scan/parse/resolve/interpret (or
+/// bytecode-compile/run) pipeline, rendering every diagnostic a phase
+/// collected - not just the first - before bailing out of that phase.
+fn run(to_execute: String, interpreter: &mut Interpreter, repl: bool, engine: Engine) -> LoxError {
+    run_inner(to_execute, interpreter, repl, engine, None)
+}
+
+/// Shared implementation behind `run` and `run_timed` - takes an optional
+/// `PhaseTimings` to fill in so the untimed path pays nothing for the
+/// `Instant::now()` calls it doesn't need.
+fn run_inner(
+    to_execute: String,
+    interpreter: &mut Interpreter,
+    repl: bool,
+    engine: Engine,
+    mut timings: Option<&mut PhaseTimings>,
+) -> LoxError {
+    let source = to_execute.clone();
+    let scan_start = Instant::now();
+    let mut scanner = Scanner::new(LoxError::NoError, to_execute, interpreter.interner());
     let tokens = scanner.scan_tokens();
+    if let Some(timings) = timings.as_deref_mut() {
+        timings.scanning = scan_start.elapsed();
+    }
 
     if scanner.had_error() {
-        LoxError::ScanningError
-    } else {
-        let mut parser = Parser::new(tokens, LoxError::NoError);
-        let exp = parser.parse();
-
-        if parser.had_error() {
-            LoxError::ParsingError
-        } else {
-            interpreter.interpret(exp);
-            if interpreter.had_error() {
-                LoxError::RuntimeError
+        eprint!("{}", scanner.diagnostics().render(&source));
+        return LoxError::ScanningError;
+    }
+
+    match engine {
+        Engine::Bytecode => match bytecode_compiler::compile(tokens) {
+            Ok(script) => {
+                let mut vm = vm::VM::new();
+                if vm.interpret(script).is_ok() {
+                    LoxError::NoError
+                } else {
+                    LoxError::RuntimeError
+                }
+            }
+            Err(()) => LoxError::ParsingError,
+        },
+        Engine::TreeWalk => {
+            let parse_start = Instant::now();
+            let mut parser = if repl {
+                Parser::new_repl(tokens, LoxError::NoError)
             } else {
-                LoxError::NoError
+                Parser::new(tokens, LoxError::NoError)
+            };
+            let mut exp = parser.parse();
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.parsing = parse_start.elapsed();
+            }
+
+            if parser.had_error() {
+                eprint!("{}", parser.diagnostics().render(&source));
+                LoxError::ParsingError
+            } else {
+                let mut ast = parser.take_ast();
+                optimizer::optimize_program(&mut exp, &mut ast);
+
+                let mut resolver = Resolver::new(LoxError::NoError);
+                resolver.resolve(&mut exp, &mut ast);
+
+                if resolver.had_error() {
+                    eprint!("{}", resolver.diagnostics().render(&source));
+                    LoxError::ResolvingError
+                } else {
+                    let interpret_start = Instant::now();
+                    let result = interpreter.interpret(exp, &mut ast);
+                    if let Some(timings) = timings.as_deref_mut() {
+                        timings.interpreting = interpret_start.elapsed();
+                    }
+                    if let Some(value) = result {
+                        println!("{}", value);
+                    }
+                    if interpreter.had_error() {
+                        eprint!("{}", interpreter.diagnostics().render(&source));
+                        LoxError::RuntimeError
+                    } else {
+                        LoxError::NoError
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,235 @@
+use crate::ast::{Ast, ExprId, StmtId};
+use crate::expr::{Expr, Visitor};
+use crate::stmt::Stmt;
+use crate::token::{Literal, Token, TokenType::*};
+
+/// Constant-folding pass built on the existing `Visitor` mechanism: it walks
+/// an `Expr` tree bottom-up and collapses any subtree whose operands have
+/// already reduced to `Literal`s, shrinking the tree before it reaches the
+/// interpreter.
+pub struct Optimizer {}
+
+impl Optimizer {
+    pub fn new() -> Optimizer {
+        Optimizer {}
+    }
+}
+
+pub fn optimize(expr: Expr, ast: &mut Ast) -> Expr {
+    expr.accept(&mut Optimizer::new(), ast)
+}
+
+/// Runs the constant-folding pass over every expression reachable from
+/// `statements`, mutating them (and the arena backing their children) in
+/// place. Runs after parsing and before resolving, so the resolver and the
+/// interpreter both see the folded tree.
+pub fn optimize_program(statements: &mut [Stmt], ast: &mut Ast) {
+    for stmt in statements {
+        optimize_stmt(stmt, ast);
+    }
+}
+
+fn optimize_expr_id(id: ExprId, ast: &mut Ast) {
+    let folded = optimize(ast.expr(id).clone(), ast);
+    *ast.expr_mut(id) = folded;
+}
+
+fn optimize_stmt_id(id: StmtId, ast: &mut Ast) {
+    let mut node = std::mem::replace(ast.stmt_mut(id), Stmt::Null);
+    optimize_stmt(&mut node, ast);
+    *ast.stmt_mut(id) = node;
+}
+
+fn optimize_stmt(stmt: &mut Stmt, ast: &mut Ast) {
+    match stmt {
+        Stmt::Expression { expr } | Stmt::Print { expr } | Stmt::Repl { expr } => {
+            *expr = optimize(expr.clone(), ast);
+        }
+        Stmt::Var { initializer, .. } => {
+            if *ast.expr(*initializer) != Expr::Null {
+                optimize_expr_id(*initializer, ast);
+            }
+        }
+        Stmt::Block { statements } => {
+            for s in statements {
+                optimize_stmt(s, ast);
+            }
+        }
+        Stmt::IfStmt {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            *condition = optimize(condition.clone(), ast);
+            optimize_stmt_id(*then_branch, ast);
+            if *ast.stmt(*else_branch) != Stmt::Null {
+                optimize_stmt_id(*else_branch, ast);
+            }
+        }
+        Stmt::WhileStmt {
+            condition,
+            body,
+            increment,
+        } => {
+            *condition = optimize(condition.clone(), ast);
+            optimize_stmt_id(*body, ast);
+            if *ast.expr(*increment) != Expr::Null {
+                optimize_expr_id(*increment, ast);
+            }
+        }
+        Stmt::Function { body, .. } => optimize_stmt_id(*body, ast),
+        Stmt::ReturnStmt { value, .. } => {
+            if *ast.expr(*value) != Expr::Null {
+                optimize_expr_id(*value, ast);
+            }
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Import { .. } => (),
+        Stmt::Null => (),
+    }
+}
+
+fn is_truthy(value: &Literal) -> bool {
+    match value {
+        Literal::Boolean(b) => *b,
+        Literal::Null => false,
+        _ => true,
+    }
+}
+
+fn fold_unary(operator: &Token, right: Expr, ast: &mut Ast) -> Expr {
+    if let Expr::Literal { value } = &right {
+        match (&operator.token_type, value) {
+            (Minus, Literal::Number(n)) => return Expr::literal(Literal::Number(-n)),
+            (Bang, _) => return Expr::literal(Literal::Boolean(!is_truthy(value))),
+            _ => (),
+        }
+    }
+    ast.unary(operator.clone(), right)
+}
+
+fn fold_binary(left: Expr, operator: &Token, right: Expr, ast: &mut Ast) -> Expr {
+    if let (Expr::Literal { value: lv }, Expr::Literal { value: rv }) = (&left, &right) {
+        if let Some(folded) = fold_literals(lv, operator, rv) {
+            return Expr::literal(folded);
+        }
+    }
+    ast.binary(left, operator.clone(), right)
+}
+
+fn fold_literals(left: &Literal, operator: &Token, right: &Literal) -> Option<Literal> {
+    match (left, right) {
+        (Literal::Number(ln), Literal::Number(rn)) => match &operator.token_type {
+            Plus => Some(Literal::Number(ln + rn)),
+            Minus => Some(Literal::Number(ln - rn)),
+            Star => Some(Literal::Number(ln * rn)),
+            Slash if *rn != 0.0 => Some(Literal::Number(ln / rn)),
+            Greater => Some(Literal::Boolean(ln > rn)),
+            GreaterEqual => Some(Literal::Boolean(ln >= rn)),
+            Less => Some(Literal::Boolean(ln < rn)),
+            LessEqual => Some(Literal::Boolean(ln <= rn)),
+            _ => None,
+        },
+        (Literal::LString(ls), Literal::LString(rs)) => match &operator.token_type {
+            Plus => Some(Literal::LString(format!("{}{}", ls, rs))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Visitor<Expr> for Optimizer {
+    fn visit_binary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = ast.expr(*left).clone().accept(self, ast);
+                let right = ast.expr(*right).clone().accept(self, ast);
+                fold_binary(left, operator, right, ast)
+            }
+            _ => panic!("Unexpected value in optimizing binary expression."), // should be unreachable
+        }
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Grouping { expression } => {
+                let inner = ast.expr(*expression).clone().accept(self, ast);
+                if matches!(inner, Expr::Literal { .. }) {
+                    inner
+                } else {
+                    ast.grouping(inner)
+                }
+            }
+            _ => panic!("Unexpected value in optimizing grouping expression."), // should be unreachable
+        }
+    }
+
+    fn visit_literal_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> Expr {
+        expr.clone()
+    }
+
+    fn visit_unary_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Unary { operator, right } => {
+                let right = ast.expr(*right).clone().accept(self, ast);
+                fold_unary(operator, right, ast)
+            }
+            _ => panic!("Unexpected value in optimizing unary expression."), // should be unreachable
+        }
+    }
+
+    fn visit_variable_expr(&mut self, expr: &Expr, _ast: &mut Ast) -> Expr {
+        expr.clone()
+    }
+
+    fn visit_assign_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                let value = ast.expr(*value).clone().accept(self, ast);
+                ast.assign(name.clone(), value)
+            }
+            _ => panic!("Unexpected value in optimizing assign expression."), // should be unreachable
+        }
+    }
+
+    fn visit_logical_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = ast.expr(*left).clone().accept(self, ast);
+                let right = ast.expr(*right).clone().accept(self, ast);
+
+                if let Expr::Literal { value } = &left {
+                    let truthy = is_truthy(value);
+                    let short_circuits =
+                        (operator.token_type == Or && truthy) || (operator.token_type == And && !truthy);
+                    return if short_circuits { left } else { right };
+                }
+
+                ast.logical(left, operator.clone(), right)
+            }
+            _ => panic!("Unexpected value in optimizing logical expression."), // should be unreachable
+        }
+    }
+
+    fn visit_call_expr(&mut self, expr: &Expr, ast: &mut Ast) -> Expr {
+        match expr {
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = ast.expr(*callee).clone().accept(self, ast);
+                let arguments = arguments.iter().map(|arg| arg.clone().accept(self, ast)).collect();
+                ast.call(callee, paren.clone(), arguments)
+            }
+            _ => panic!("Unexpected value in optimizing call expression."), // should be unreachable
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::ast::Ast;
+use crate::diagnostics::{Diagnostic, Diagnostics};
 use crate::expr::Expr;
 use crate::stmt::Stmt;
 use crate::token::{Literal, Token, TokenType, TokenType::*};
@@ -5,22 +7,30 @@ use crate::LoxError;
 
 /** Full grammar
  * program        → declaration* EOF ;
- * declaration    → varDecl
+ * declaration    → funDecl
+ *                | varDecl
  *                | statement ;
+ * funDecl        → "fun" function ;
+ * function       → IDENTIFIER "(" parameters? ")" block ;
+ * parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
  * varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
  * statement      → ifStmt
  *                | block
  *                | printStmt
  *                | whileStmt
  *                | forStmt
+ *                | returnStmt
+ *                | importStmt
  *                | exprStmt ;
  * ifStmt         → "if" "(" expression ")" statement
  *                ( "else" statement )? ;
  * block          → "{" declaration* "}" ;
  * exprStmt       → expression ";" ;
  * printStmt      → "print" expression ";" ;
+ * importStmt     → "import" STRING ";" ;
  * whileStmt      → "while" "(" expression ")" statement ;
  * forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) ")" expression? ";" expression? ")" statement ;
+ * returnStmt     → "return" expression? ";" ;
  *
  * expression     → assignment ;
  * assignment     → IDENTIFIER "=" assignment
@@ -49,9 +59,7 @@ pub struct ParserError {
 
 impl ParserError {
     pub fn new(token: Token, message: String) -> ParserError {
-        let err = ParserError { token, message };
-        LoxError::parsing_error(err.clone());
-        err
+        ParserError { token, message }
     }
 
     pub fn message(&self) -> String {
@@ -72,6 +80,11 @@ pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     error: LoxError,
+    repl: bool,
+    errors: Vec<ParserError>,
+    loop_depth: usize,
+    ast: Ast,
+    diagnostics: Diagnostics,
 }
 
 impl Parser {
@@ -80,6 +93,24 @@ impl Parser {
             tokens,
             current: 0,
             error,
+            repl: false,
+            errors: Vec::new(),
+            loop_depth: 0,
+            ast: Ast::new(),
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    pub fn new_repl(tokens: Vec<Token>, error: LoxError) -> Parser {
+        Parser {
+            tokens,
+            current: 0,
+            error,
+            repl: true,
+            errors: Vec::new(),
+            loop_depth: 0,
+            ast: Ast::new(),
+            diagnostics: Diagnostics::new(),
         }
     }
 
@@ -89,9 +120,9 @@ impl Parser {
         while !self.is_at_end() {
             match self.declaration() {
                 Ok(stmt) => statements.push(stmt),
-                Err(_) => {
-                    // LoxError::parsing_error(err);
-                    break;
+                Err(err) => {
+                    self.report(err);
+                    self.synchronize();
                 }
             }
         }
@@ -100,18 +131,46 @@ impl Parser {
     }
 
     pub fn had_error(&self) -> bool {
-        self.error == LoxError::ParsingError
+        !self.errors.is_empty()
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Records a parsing error as a `Diagnostic` and pushes it to `errors`
+    /// (what `had_error`/`errors` report on), replacing the old
+    /// eprintln-on-construct `LoxError::parsing_error`. Used both by `parse`'s
+    /// top-level catch and by `declaration`'s inner one, so a statement-level
+    /// syntax error is never silently dropped.
+    fn report(&mut self, err: ParserError) {
+        self.diagnostics
+            .push(Diagnostic::error_at("parse", &err.token(), err.message()));
+        self.errors.push(err);
+    }
+
+    pub fn errors(&self) -> &[ParserError] {
+        &self.errors
+    }
+
+    /// Hands over the arena of child `Expr`/`Stmt` nodes built up while
+    /// parsing. Call once `parse` has returned the top-level statements.
+    pub fn take_ast(self) -> Ast {
+        self.ast
     }
 
     // grammar rules
 
     fn declaration(&mut self) -> StmtError {
-        if self.match_token(Var) {
+        if self.match_token(Fun) {
+            self.fun_declaration("function")
+        } else if self.match_token(Var) {
             self.var_declaration()
         } else {
             match self.statement() {
                 id @ Ok(_) => id,
-                Err(_) => {
+                Err(err) => {
+                    self.report(err);
                     self.synchronize();
                     Ok(Stmt::Null)
                 }
@@ -119,6 +178,40 @@ impl Parser {
         }
     }
 
+    fn fun_declaration(&mut self, kind: &str) -> StmtError {
+        let name = self.consume(Identifier, format!("expected {} name.", kind))?;
+        self.consume(LeftParen, format!("expected '(' after {} name.", kind))?;
+
+        let mut params: Vec<Token> = Vec::new();
+        if !self.check(RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(ParserError::new(
+                        self.peek(),
+                        "can't have more than 255 parameters.".to_string(),
+                    ));
+                }
+                params.push(self.consume(Identifier, "expected parameter name.".to_string())?);
+                if !self.match_token(Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(RightParen, "expected ')' after parameters.".to_string())?;
+
+        self.consume(LeftBrace, format!("expected '{{' before {} body.", kind))?;
+        // A function body starts its own loop context: a `break`/`continue`
+        // can't reach across the function boundary to a loop enclosing the
+        // `fun` declaration, so `loop_depth` is reset here and restored once
+        // the body's done, the same way `self.repl` is never touched by this.
+        let enclosing_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = enclosing_loop_depth;
+        let body = body?;
+
+        Ok(self.ast.function(name, params, Stmt::block(body)))
+    }
+
     fn var_declaration(&mut self) -> StmtError {
         let name = self.consume(Identifier, "expected identifier.".to_string())?;
         let initializer;
@@ -130,7 +223,7 @@ impl Parser {
 
         self.consume(Semicolon, "expected ';' after the value.".to_string())?;
 
-        Ok(Stmt::var(name, Box::new(initializer)))
+        Ok(self.ast.var(name, initializer))
     }
 
     fn statement(&mut self) -> StmtError {
@@ -145,11 +238,75 @@ impl Parser {
             self.while_statement()
         } else if self.match_token(For) {
             self.for_statement()
+        } else if self.match_token(Return) {
+            self.return_statement()
+        } else if self.match_token(Break) {
+            self.break_statement()
+        } else if self.match_token(Continue) {
+            self.continue_statement()
+        } else if self.match_token(Import) {
+            self.import_statement()
         } else {
             self.expression_statement()
         }
     }
 
+    /// A `break`/`continue` outside of any loop is caught right here, at
+    /// parse time, instead of being allowed to reach the interpreter and
+    /// escape as an unhandled `loop_signal` - `loop_depth` tracks the nesting
+    /// the same way `self.repl`/`self.errors` track other parser state, and
+    /// `fun_declaration` resets it across a function boundary so a nested
+    /// `fun` doesn't inherit its enclosing loop's context.
+    fn break_statement(&mut self) -> StmtError {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            return Err(ParserError::new(
+                keyword,
+                "can't use 'break' outside of a loop.".to_string(),
+            ));
+        }
+        self.consume(Semicolon, "expected ';' after 'break'.".to_string())?;
+
+        Ok(Stmt::breakstmt(keyword))
+    }
+
+    fn continue_statement(&mut self) -> StmtError {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            return Err(ParserError::new(
+                keyword,
+                "can't use 'continue' outside of a loop.".to_string(),
+            ));
+        }
+        self.consume(Semicolon, "expected ';' after 'continue'.".to_string())?;
+
+        Ok(Stmt::continuestmt(keyword))
+    }
+
+    fn import_statement(&mut self) -> StmtError {
+        let keyword = self.previous();
+        let path = self.consume(
+            TString,
+            "expected a module path string after 'import'.".to_string(),
+        )?;
+        self.consume(Semicolon, "expected ';' after import path.".to_string())?;
+
+        Ok(Stmt::import(keyword, path))
+    }
+
+    fn return_statement(&mut self) -> StmtError {
+        let keyword = self.previous();
+        let value = if !self.check(Semicolon) {
+            self.expression()?
+        } else {
+            Expr::Null
+        };
+
+        self.consume(Semicolon, "expected ';' after return value.".to_string())?;
+
+        Ok(self.ast.returnstmt(keyword, value))
+    }
+
     fn print_statement(&mut self) -> StmtError {
         let value = self.expression()?;
         self.consume(Semicolon, "expected ';' after the value.".to_string())?;
@@ -159,6 +316,11 @@ impl Parser {
 
     fn expression_statement(&mut self) -> StmtError {
         let value = self.expression()?;
+
+        if self.repl && !self.check(Semicolon) && self.is_at_end() {
+            return Ok(Stmt::repl(value));
+        }
+
         self.consume(Semicolon, "expected ';' after the value.".to_string())?;
         // Ok(Stmt::expression(Box::new(value)))
         Ok(Stmt::expression(value))
@@ -190,11 +352,7 @@ impl Parser {
             else_branch = Stmt::Null;
         }
 
-        Ok(Stmt::ifstmt(
-            condition,
-            Box::new(then_branch),
-            Box::new(else_branch),
-        ))
+        Ok(self.ast.ifstmt(condition, then_branch, else_branch))
     }
 
     fn while_statement(&mut self) -> StmtError {
@@ -205,11 +363,20 @@ impl Parser {
             "expected ')' after while condition.".to_string(),
         )?;
 
+        self.loop_depth += 1;
         let body = self.statement()?;
+        self.loop_depth -= 1;
 
-        Ok(Stmt::whilestmt(condition, Box::new(body)))
+        Ok(self.ast.whilestmt(condition, body, Expr::Null))
     }
 
+    // Desugars into the `WhileStmt`/`Block` nodes `visit_whilestmt_stmt`/
+    // `execute_block` already run, rather than adding a dedicated interpreter
+    // path: the increment is carried as a field on `WhileStmt` instead of
+    // appended to `body` precisely so `continue` - which cuts `body` short -
+    // still reaches it before the next condition check. See
+    // `tests/run/ok/loops.lox`, which `continue`s past one iteration and
+    // still lands on the total a working increment would produce.
     fn for_statement(&mut self) -> StmtError {
         // converts a for loop in a while loop
         self.consume(LeftParen, "expected '(' after 'for'.".to_string())?;
@@ -242,13 +409,15 @@ impl Parser {
         }
         self.consume(RightParen, "expected ')' after for clauses.".to_string())?;
 
-        let mut body = self.statement()?;
-
-        if increment != Expr::Null {
-            body = Stmt::block(vec![body, Stmt::expression(increment)]);
-        }
+        self.loop_depth += 1;
+        let body = self.statement()?;
+        self.loop_depth -= 1;
 
-        body = Stmt::whilestmt(condition, Box::new(body));
+        // The increment is threaded into the `WhileStmt` itself, rather than
+        // appended to `body` as a trailing statement, so `continue` (which
+        // stops the rest of `body` early) still lets the interpreter run it
+        // before jumping back to the condition.
+        let mut body = self.ast.whilestmt(condition, body, increment);
 
         if initializer != Stmt::Null {
             body = Stmt::block(vec![initializer, body]);
@@ -268,11 +437,11 @@ impl Parser {
             let equals = self.previous();
             let value = self.assignment()?;
             match exp {
-                Expr::Variable { name } => {
-                    return Ok(Expr::assign(name, Box::new(value)));
+                Expr::Variable { name, .. } => {
+                    return Ok(self.ast.assign(name, value));
                 }
                 _ => {
-                    ParserError::new(equals, "invalid assign target.".to_string());
+                    self.report(ParserError::new(equals, "invalid assign target.".to_string()));
                 }
             }
         }
@@ -285,7 +454,7 @@ impl Parser {
         while self.match_token(Or) {
             let or = self.previous();
             let rest = self.logic_and()?;
-            and = Expr::logical(Box::new(and), or, Box::new(rest));
+            and = self.ast.logical(and, or, rest);
         }
 
         Ok(and)
@@ -297,7 +466,7 @@ impl Parser {
         while self.match_token(And) {
             let and = self.previous();
             let rest = self.equality()?;
-            eq = Expr::logical(Box::new(eq), and, Box::new(rest));
+            eq = self.ast.logical(eq, and, rest);
         }
 
         Ok(eq)
@@ -310,7 +479,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.comparison()?;
 
-            expr = Expr::binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -323,7 +492,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.term()?;
 
-            expr = Expr::binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -336,7 +505,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.factor()?;
 
-            expr = Expr::binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -349,7 +518,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.unary()?;
 
-            expr = Expr::binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -360,7 +529,7 @@ impl Parser {
             let operator = self.previous();
             let right = self.unary()?;
 
-            Ok(Expr::unary(operator, Box::new(right)))
+            Ok(self.ast.unary(operator, right))
         } else {
             self.call()
         }
@@ -392,7 +561,7 @@ impl Parser {
                     ));
                 }
                 args.push(self.expression()?);
-                if self.match_token(Comma) {
+                if !self.match_token(Comma) {
                     break;
                 }
             }
@@ -400,7 +569,7 @@ impl Parser {
 
         let paren = self.consume(RightParen, "expected ')' after arguments.".to_string())?;
 
-        Ok(Expr::call(Box::new(callee), paren, args))
+        Ok(self.ast.call(callee, paren, args))
     }
 
     fn primary(&mut self) -> ExprError {
@@ -411,11 +580,34 @@ impl Parser {
         } else if self.match_token(Nil) {
             Ok(Expr::literal(Literal::Null))
         } else if self.match_tokens(vec![Number, TString]) {
-            Ok(Expr::literal(self.previous().literal))
+            let mut expr = Expr::literal(self.previous().literal);
+
+            // An interpolated string scans as TString, (InterpolationStart
+            // expr InterpolationEnd TString)*; splice each piece onto the
+            // running expression as a `+` concatenation.
+            while self.match_token(InterpolationStart) {
+                let open = self.concat_operator();
+                let inner = self.expression()?;
+                self.consume(
+                    InterpolationEnd,
+                    "expected '}' to close interpolated expression.".to_string(),
+                )?;
+                let close = self.concat_operator();
+
+                expr = self.ast.binary(expr, open, inner);
+
+                self.consume(
+                    TString,
+                    "expected string text after interpolated expression.".to_string(),
+                )?;
+                expr = self.ast.binary(expr, close, Expr::literal(self.previous().literal));
+            }
+
+            Ok(expr)
         } else if self.match_token(LeftParen) {
             let expr = self.expression()?;
             self.consume(RightParen, "expected ')' after expression;".to_string())?;
-            Ok(Expr::grouping(Box::new(expr)))
+            Ok(self.ast.grouping(expr))
         } else if self.match_token(Identifier) {
             Ok(Expr::variable(self.previous()))
         } else {
@@ -429,6 +621,18 @@ impl Parser {
 
     // aux
 
+    /// A synthetic `+` token, for splicing interpolated string pieces
+    /// together without a `+` actually appearing in the source.
+    fn concat_operator(&self) -> Token {
+        Token::new(
+            Plus,
+            self.previous().line,
+            self.previous().column,
+            "+".to_string(),
+            Literal::Symbol,
+        )
+    }
+
     fn match_token(&mut self, token_type: TokenType) -> bool {
         if self.check(token_type) {
             self.advance();
@@ -494,7 +698,7 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                Class | Fun | Var | For | If | While | Print | Return => return,
+                Class | Fun | Var | For | If | While | Print | Return | Import => return,
                 _ => {
                     self.advance();
                 }
@@ -0,0 +1,243 @@
+use crate::ast::{Ast, ExprId, StmtId};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use crate::LoxError;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct ResolverError {
+    token: Token,
+    message: String,
+}
+
+impl ResolverError {
+    pub fn new(token: Token, message: String) -> ResolverError {
+        ResolverError { token, message }
+    }
+
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    pub fn token(&self) -> Token {
+        self.token.clone()
+    }
+}
+
+/// Static pass that walks the AST produced by `Parser::parse` and stamps
+/// `Expr::Variable`/`Expr::Assign` with the number of scope hops to their
+/// declaration, so a later interpreter can look variables up in O(1)
+/// instead of walking the environment chain at runtime. The distance is
+/// stored directly on the node rather than in a side table keyed by a
+/// node id - the AST arena already gives every node a stable identity via
+/// its `ExprId`/`StmtId`, so a parallel map would only duplicate that.
+///
+/// `Environment` is an `Rc<RefCell<...>>` (`EnvRef`), so
+/// `Environment::extend`/`Function::User.closure` share the same scope
+/// instance rather than deep-cloning it, and `Environment::get_at`/
+/// `assign_at` walk exactly the hop count this pass recorded instead of
+/// searching dynamically - but that's only correct if this pass pushes a
+/// scope at exactly the points the interpreter creates a runtime
+/// `Environment`. `resolve_function` is the one place that isn't automatic:
+/// see its own comment, and `tests/run/ok/closures.lox`, which closes over
+/// an enclosing function's local and would resolve one hop too deep if the
+/// two ever drifted apart again.
+#[derive(Debug)]
+pub struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    error: LoxError,
+    diagnostics: Diagnostics,
+}
+
+impl Resolver {
+    pub fn new(error: LoxError) -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            error,
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    pub fn had_error(&self) -> bool {
+        self.error == LoxError::ResolvingError
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Records a resolving error as a `Diagnostic` and flips the
+    /// short-circuit flag `had_error` checks, replacing the old
+    /// eprintln-on-construct `LoxError::resolving_error`.
+    fn report(&mut self, err: ResolverError) {
+        self.diagnostics
+            .push(Diagnostic::error_at("resolve", &err.token(), err.message()));
+        self.error = LoxError::ResolvingError;
+    }
+
+    pub fn resolve(&mut self, statements: &mut [Stmt], ast: &mut Ast) {
+        for stmt in statements {
+            self.resolve_stmt_inline(stmt, ast);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), false);
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(name))
+    }
+
+    fn resolve_stmt(&mut self, id: StmtId, ast: &mut Ast) {
+        let mut node = std::mem::replace(ast.stmt_mut(id), Stmt::Null);
+        self.resolve_stmt_inline(&mut node, ast);
+        *ast.stmt_mut(id) = node;
+    }
+
+    fn resolve_stmt_inline(&mut self, stmt: &mut Stmt, ast: &mut Ast) {
+        match stmt {
+            Stmt::Expression { expr } => self.resolve_expr_inline(expr, ast),
+            Stmt::Print { expr } => self.resolve_expr_inline(expr, ast),
+            Stmt::Repl { expr } => self.resolve_expr_inline(expr, ast),
+            Stmt::Var { name, initializer } => {
+                self.declare(name);
+                if *ast.expr(*initializer) != Expr::Null {
+                    self.resolve_expr(*initializer, ast);
+                }
+                self.define(name);
+            }
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.resolve(statements, ast);
+                self.end_scope();
+            }
+            Stmt::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr_inline(condition, ast);
+                self.resolve_stmt(*then_branch, ast);
+                if *ast.stmt(*else_branch) != Stmt::Null {
+                    self.resolve_stmt(*else_branch, ast);
+                }
+            }
+            Stmt::WhileStmt {
+                condition,
+                body,
+                increment,
+            } => {
+                self.resolve_expr_inline(condition, ast);
+                self.resolve_stmt(*body, ast);
+                if *ast.expr(*increment) != Expr::Null {
+                    self.resolve_expr(*increment, ast);
+                }
+            }
+            Stmt::Function { name, params, body } => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, *body, ast);
+            }
+            Stmt::ReturnStmt { value, .. } => {
+                if *ast.expr(*value) != Expr::Null {
+                    self.resolve_expr(*value, ast);
+                }
+            }
+            Stmt::Break { .. } => (),
+            Stmt::Continue { .. } => (),
+            Stmt::Import { .. } => (),
+            Stmt::Null => (),
+        }
+    }
+
+    fn resolve_function(&mut self, params: &[Token], body: StmtId, ast: &mut Ast) {
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        // `Interpreter::call_function` runs a function body's statements
+        // directly in the same runtime `Environment` as its parameters -
+        // it unwraps the body's `Stmt::Block` rather than recursing through
+        // `execute_block` a second time. Resolving has to match that: going
+        // through `resolve_stmt` here would hit the `Stmt::Block` arm and
+        // push a *second* scope for the body, one more than the interpreter
+        // ever creates, so every reference reaching into an enclosing
+        // function's locals would resolve one hop too deep.
+        let mut node = std::mem::replace(ast.stmt_mut(body), Stmt::Null);
+        match &mut node {
+            Stmt::Block { statements } => self.resolve(statements, ast),
+            _ => panic!("function body must be a block"), // should be unreachable
+        }
+        *ast.stmt_mut(body) = node;
+        self.end_scope();
+    }
+
+    fn resolve_expr(&mut self, id: ExprId, ast: &mut Ast) {
+        let mut node = std::mem::replace(ast.expr_mut(id), Expr::Null);
+        self.resolve_expr_inline(&mut node, ast);
+        *ast.expr_mut(id) = node;
+    }
+
+    fn resolve_expr_inline(&mut self, expr: &mut Expr, ast: &mut Ast) {
+        match expr {
+            Expr::Variable { name, depth } => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(&name.lexeme) == Some(&false) {
+                        self.report(ResolverError::new(
+                            name.clone(),
+                            "can't read local variable in its own initializer.".to_string(),
+                        ));
+                    }
+                }
+                *depth = self.resolve_local(&name.lexeme);
+            }
+            Expr::Assign { name, value, depth } => {
+                self.resolve_expr(*value, ast);
+                *depth = self.resolve_local(&name.lexeme);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(*left, ast);
+                self.resolve_expr(*right, ast);
+            }
+            Expr::Grouping { expression } => self.resolve_expr(*expression, ast),
+            Expr::Literal { .. } => (),
+            Expr::Unary { right, .. } => self.resolve_expr(*right, ast),
+            Expr::Logical { left, right, .. } => {
+                self.resolve_expr(*left, ast);
+                self.resolve_expr(*right, ast);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(*callee, ast);
+                for arg in arguments {
+                    self.resolve_expr_inline(arg, ast);
+                }
+            }
+            Expr::Null => (),
+        }
+    }
+}
@@ -1,55 +1,77 @@
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::interner::InternerRef;
 use crate::token::{Literal, Token, TokenType};
 use crate::LoxError;
 
-pub struct ScannerError {
-    line: usize,
-    message: String,
-}
-
-impl ScannerError {
-    pub fn new(line: usize, message: String) -> ScannerError {
-        ScannerError { line, message }
-    }
-
-    pub fn message(&self) -> String {
-        self.message.clone()
-    }
-
-    pub fn line(&self) -> usize {
-        self.line
-    }
+/// Renders a token stream one line per token, the same `{:?} 'lexeme' (line
+/// N)` shape rlox's own `:ast` REPL command already prints tokens in - used
+/// by the `--emit=tokens` flag and the scanner golden-file tests under
+/// `tests/scanner/ok`.
+pub fn dump_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| format!("{:?} '{}' (line {})", token.token_type, token.lexeme, token.line))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
+/// Scans `source` one `char` at a time via a pre-collected buffer, so every
+/// lookup is O(1) regardless of how many multibyte characters precede it.
+/// `start`/`current` index into that buffer (char positions), while
+/// `start_byte`/`current_byte` track the matching byte offsets so lexemes can
+/// still be sliced out of the original `String` in O(1). `column`/
+/// `start_column` mirror `current`/`start` but reset to 1 on every `\n`, so
+/// each token also carries where it begins on its line. `interner` is shared
+/// with the rest of a REPL session (not recreated per `Scanner`), so the same
+/// identifier always gets the same `Symbol` across lines.
 pub struct Scanner {
     source: String,
+    chars: Vec<char>,
     token_list: Vec<Token>,
     start: usize,
     current: usize,
+    start_byte: usize,
+    current_byte: usize,
     line: usize,
+    column: usize,
+    start_column: usize,
     error: LoxError,
+    interner: InternerRef,
+    diagnostics: Diagnostics,
 }
 
 impl Scanner {
-    pub fn new(error: LoxError, source: String) -> Scanner {
+    pub fn new(error: LoxError, source: String, interner: InternerRef) -> Scanner {
+        let chars = source.chars().collect();
         Scanner {
             source,
+            chars,
             token_list: Vec::new(),
             start: 0,
             current: 0,
+            start_byte: 0,
+            current_byte: 0,
             line: 1,
+            column: 1,
+            start_column: 1,
             error,
+            interner,
+            diagnostics: Diagnostics::new(),
         }
     }
 
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_byte = self.current_byte;
+            self.start_column = self.column;
             self.scan_token();
         }
 
         self.token_list.push(Token::new(
             TokenType::Eof,
             self.line,
+            self.column,
             String::new(),
             Literal::Eof,
         ));
@@ -60,8 +82,12 @@ impl Scanner {
         self.error == LoxError::ScanningError
     }
 
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.current >= self.chars.len()
     }
 
     fn scan_token(&mut self) {
@@ -111,11 +137,7 @@ impl Scanner {
                         self.advance();
                     }
                 } else if self.match_char('*') {
-                    while self.peek() != '*' && self.peek_next() != '/' && !self.is_at_end() {
-                        self.advance();
-                    }
-                    self.advance();
-                    self.advance();
+                    self.block_comment();
                 } else {
                     self.add_token(TokenType::Slash, Literal::Symbol)
                 }
@@ -133,35 +155,49 @@ impl Scanner {
                 } else if Scanner::is_alpha(ch) {
                     self.identifier();
                 } else {
-                    LoxError::scanning_error(ScannerError::new(
-                        self.line,
-                        String::from("Unexpected character"),
-                    ));
-                    self.error = LoxError::ScanningError;
+                    self.error_token("Unexpected character");
                 }
             }
         }
     }
 
+    fn error_token(&mut self, message: &str) {
+        let span = (self.current - self.start).max(1);
+        self.diagnostics.push(Diagnostic::error(
+            "scan",
+            self.line,
+            self.start_column,
+            span,
+            message.to_string(),
+        ));
+        self.error = LoxError::ScanningError;
+    }
+
     fn advance(&mut self) -> char {
+        let ch = self.chars[self.current];
         self.current += 1;
-        self.source
-            .chars()
-            .nth(self.current - 1)
-            .expect("Error advancing on character")
+        self.current_byte += ch.len_utf8();
+        if ch == '\n' {
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        ch
     }
 
     fn add_token(&mut self, typ: TokenType, literal: Literal) {
-        let st: String = self.source[self.start..self.current].to_string();
+        let st: String = self.source[self.start_byte..self.current_byte].to_string();
         self.token_list
-            .push(Token::new(typ, self.line, st, literal));
+            .push(Token::new(typ, self.line, self.start_column, st, literal));
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() || self.source.chars().nth(self.current).unwrap() != expected {
+        if self.is_at_end() || self.chars[self.current] != expected {
             false
         } else {
             self.current += 1;
+            self.current_byte += expected.len_utf8();
+            self.column += 1;
             true
         }
     }
@@ -170,38 +206,190 @@ impl Scanner {
         if self.is_at_end() {
             '\0'
         } else {
-            self.source.chars().nth(self.current).unwrap()
+            self.chars[self.current]
         }
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
+        if self.current + 1 >= self.chars.len() {
             '\0'
         } else {
-            self.source.chars().nth(self.current + 1).unwrap()
+            self.chars[self.current + 1]
         }
     }
 
-    fn string(&mut self) {
-        while !self.is_at_end() && self.peek() != '"' {
+    /// Scans the body of a `/* ... */` comment, the opening `/*` already
+    /// consumed by the caller. Tracks nesting depth so an inner `/*` requires
+    /// its own `*/` before the outer comment closes, and bumps `line` on
+    /// embedded newlines since `advance` itself doesn't.
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                self.error_token("Unterminated block comment");
+                return;
+            }
+
             if self.peek() == '\n' {
+                self.advance();
                 self.line += 1;
+            } else if self.peek() == '/' && self.peek_next() == '*' {
+                self.advance();
+                self.advance();
+                depth += 1;
+            } else if self.peek() == '*' && self.peek_next() == '/' {
+                self.advance();
+                self.advance();
+                depth -= 1;
+            } else {
+                self.advance();
             }
-            self.advance();
         }
+    }
+
+    /// Scans a string literal, decoding backslash escapes and splitting on
+    /// `${expr}` interpolations as it goes. A plain string (no interpolation)
+    /// still produces a single `TString` token, decoded value included. An
+    /// interpolated string instead emits `TString`, `InterpolationStart`, the
+    /// inner expression's own tokens, `InterpolationEnd`, `TString`, ... so
+    /// the parser can splice the pieces back into a concatenation.
+    fn string(&mut self) {
+        let mut value = String::new();
+
+        loop {
+            if self.is_at_end() {
+                self.error_token("Unterminated string");
+                return;
+            }
+
+            match self.peek() {
+                '"' => break,
+                '\\' => {
+                    self.advance();
+                    match self.decode_escape() {
+                        Some(ch) => value.push(ch),
+                        None => return,
+                    }
+                }
+                '$' if self.peek_next() == '{' => {
+                    let part = std::mem::take(&mut value);
+                    self.add_token(TokenType::TString, Literal::LString(part));
+
+                    self.start = self.current;
+                    self.start_byte = self.current_byte;
+                    self.start_column = self.column;
+                    self.advance(); // '$'
+                    self.advance(); // '{'
+                    self.add_token(TokenType::InterpolationStart, Literal::Symbol);
 
+                    if !self.scan_interpolation() {
+                        return;
+                    }
+
+                    self.start = self.current;
+                    self.start_byte = self.current_byte;
+                    self.start_column = self.column;
+                }
+                c => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    value.push(c);
+                    self.advance();
+                }
+            }
+        }
+
+        self.advance(); // closing quote
+        self.add_token(TokenType::TString, Literal::LString(value));
+    }
+
+    /// Scans the tokens of an interpolated expression until the `}` that
+    /// closes it, tracking brace depth so a nested `{`/`}` pair inside the
+    /// expression doesn't end the interpolation early.
+    fn scan_interpolation(&mut self) -> bool {
+        let mut depth = 0;
+
+        loop {
+            if self.is_at_end() {
+                self.error_token("Unterminated interpolation");
+                return false;
+            }
+
+            self.start = self.current;
+            self.start_byte = self.current_byte;
+            self.start_column = self.column;
+
+            match self.peek() {
+                '}' if depth == 0 => {
+                    self.advance();
+                    self.add_token(TokenType::InterpolationEnd, Literal::Symbol);
+                    return true;
+                }
+                '{' => {
+                    depth += 1;
+                    self.scan_token();
+                }
+                '}' => {
+                    depth -= 1;
+                    self.scan_token();
+                }
+                _ => self.scan_token(),
+            }
+        }
+    }
+
+    /// Decodes the escape sequence following a `\` already consumed by the
+    /// caller, reporting an `error_token` (and returning `None`) for an
+    /// unterminated string or an unrecognised escape.
+    fn decode_escape(&mut self) -> Option<char> {
         if self.is_at_end() {
-            LoxError::scanning_error(ScannerError::new(
-                self.line,
-                String::from("Unterminated string"),
-            ));
-            self.error = LoxError::ScanningError;
-        } else {
-            self.advance();
-            self.add_token(
-                TokenType::TString,
-                Literal::LString(self.source[self.start + 1..self.current - 1].to_string()),
-            );
+            self.error_token("Unterminated string");
+            return None;
+        }
+
+        match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            '$' => Some('$'),
+            'u' => self.decode_unicode_escape(),
+            other => {
+                self.error_token(&format!("invalid escape sequence '\\{}'", other));
+                None
+            }
+        }
+    }
+
+    /// Decodes a `\u{XXXX}` escape, the `u` already consumed by the caller.
+    fn decode_unicode_escape(&mut self) -> Option<char> {
+        if self.peek() != '{' {
+            self.error_token("expected '{' after '\\u'");
+            return None;
+        }
+        self.advance();
+
+        let mut digits = String::new();
+        while self.peek() != '}' {
+            if self.is_at_end() || self.peek() == '"' {
+                self.error_token("unterminated '\\u{...}' escape");
+                return None;
+            }
+            digits.push(self.advance());
+        }
+        self.advance(); // closing '}'
+
+        match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+            Some(ch) => Some(ch),
+            None => {
+                self.error_token(&format!("invalid unicode escape '\\u{{{}}}'", digits));
+                None
+            }
         }
     }
 
@@ -209,34 +397,112 @@ impl Scanner {
         ('0'..='9').contains(&c)
     }
 
+    /// Consumes a run of digits accepted by `is_digit`, treating `_` as a
+    /// separator that must sit directly between two digits (never leading,
+    /// trailing, or doubled). Returns the digit count on success, or `None`
+    /// if a misplaced separator raised a scanning error.
+    fn consume_digit_run(
+        &mut self,
+        is_digit: impl Fn(char) -> bool,
+        mut has_preceding_digit: bool,
+    ) -> Option<usize> {
+        let mut count = 0;
+        loop {
+            let c = self.peek();
+            if is_digit(c) {
+                self.advance();
+                count += 1;
+                has_preceding_digit = true;
+            } else if c == '_' {
+                if !has_preceding_digit || !is_digit(self.peek_next()) {
+                    self.advance();
+                    self.error_token("misplaced digit separator '_' in numeric literal");
+                    return None;
+                }
+                self.advance();
+                has_preceding_digit = false;
+            } else {
+                break;
+            }
+        }
+        Some(count)
+    }
+
+    fn is_hex_digit(c: char) -> bool {
+        c.is_ascii_hexdigit()
+    }
+
+    fn is_octal_digit(c: char) -> bool {
+        ('0'..='7').contains(&c)
+    }
+
+    fn is_binary_digit(c: char) -> bool {
+        c == '0' || c == '1'
+    }
+
+    /// Parses a validated radix literal (`0x.../0o.../0b...`, underscores
+    /// already accepted by `consume_digit_run`) into its numeric value, or
+    /// `None` if it's too big for a `u64` (e.g. `0xffffffffffffffff` plus
+    /// one more digit) - the caller reports that as a scanning error rather
+    /// than letting the overflow panic the process.
+    fn parse_radix_number(lexeme: &str) -> Option<f64> {
+        let cleaned: String = lexeme.chars().filter(|&c| c != '_').collect();
+        let (radix, digits) = match &cleaned[..2] {
+            "0x" | "0X" => (16, &cleaned[2..]),
+            "0o" | "0O" => (8, &cleaned[2..]),
+            "0b" | "0B" => (2, &cleaned[2..]),
+            _ => unreachable!("number() only takes this path after matching a base prefix"),
+        };
+        u64::from_str_radix(digits, radix).ok().map(|n| n as f64)
+    }
+
     fn number(&mut self) {
-        while Scanner::is_digit(self.peek()) {
-            self.advance();
+        if self.chars[self.start] == '0' && matches!(self.peek(), 'x' | 'X' | 'o' | 'O' | 'b' | 'B')
+        {
+            let is_digit: fn(char) -> bool = match self.peek() {
+                'x' | 'X' => Scanner::is_hex_digit,
+                'o' | 'O' => Scanner::is_octal_digit,
+                _ => Scanner::is_binary_digit,
+            };
+            self.advance(); // consume the base sigil
+
+            let digit_count = match self.consume_digit_run(is_digit, false) {
+                Some(count) => count,
+                None => return,
+            };
+            if digit_count == 0 {
+                self.error_token("numeric literal has no digits after its base prefix");
+                return;
+            }
+
+            match Scanner::parse_radix_number(&self.source[self.start_byte..self.current_byte]) {
+                Some(value) => self.add_token(TokenType::Number, Literal::Number(value)),
+                None => self.error_token("numeric literal out of range"),
+            }
+            return;
+        }
+
+        if self.consume_digit_run(Scanner::is_digit, true).is_none() {
+            return;
         }
 
         if self.peek() == '.' && Scanner::is_digit(self.peek_next()) {
             self.advance();
-            while Scanner::is_digit(self.peek()) {
-                self.advance();
+            if self.consume_digit_run(Scanner::is_digit, false).is_none() {
+                return;
             }
         }
 
-        self.add_token(
-            TokenType::Number,
-            Literal::Number(
-                self.source[self.start..self.current]
-                    .parse::<f64>()
-                    .unwrap(),
-            ),
-        )
+        let lexeme = self.source[self.start_byte..self.current_byte].replace('_', "");
+        self.add_token(TokenType::Number, Literal::Number(lexeme.parse::<f64>().unwrap()))
     }
 
     fn is_alpha(c: char) -> bool {
-        ('a'..='z').contains(&c) || ('A'..='Z').contains(&c) || c == '_'
+        c.is_alphabetic() || c == '_'
     }
 
     fn is_alphanumeric(c: char) -> bool {
-        Scanner::is_alpha(c) || Scanner::is_digit(c)
+        c.is_alphanumeric() || c == '_'
     }
 
     // fn identifier(&mut self) {
@@ -257,23 +523,34 @@ impl Scanner {
             self.advance();
         }
 
-        let text = &self.source[self.start..self.current];
-        if let Some(t) = Scanner::match_keyword(text) {
+        let text = self.source[self.start_byte..self.current_byte].to_string();
+        if let Some(t) = Scanner::match_keyword(&text) {
             self.add_token(t, Literal::Keyword);
         } else {
-            self.add_token(TokenType::Identifier, Literal::Identifier);
+            let symbol = self.interner.borrow_mut().intern(&text);
+            self.token_list.push(Token::with_symbol(
+                TokenType::Identifier,
+                self.line,
+                self.start_column,
+                text,
+                Literal::Identifier,
+                symbol,
+            ));
         }
     }
 
     fn match_keyword(s: &str) -> Option<TokenType> {
         match s {
             "and" => Some(TokenType::And),
+            "break" => Some(TokenType::Break),
             "class" => Some(TokenType::Class),
+            "continue" => Some(TokenType::Continue),
             "else" => Some(TokenType::Else),
             "false" => Some(TokenType::False),
             "for" => Some(TokenType::For),
             "fun" => Some(TokenType::Fun),
             "if" => Some(TokenType::If),
+            "import" => Some(TokenType::Import),
             "nil" => Some(TokenType::Nil),
             "or" => Some(TokenType::Or),
             "print" => Some(TokenType::Print),
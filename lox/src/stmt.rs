@@ -1,3 +1,4 @@
+use crate::ast::{Ast, ExprId, StmtId};
 use crate::expr::Expr;
 use crate::token::Token;
 
@@ -9,43 +10,89 @@ pub enum Stmt {
     Print {
         expr: Expr,
     },
+    /// A bare expression typed at the REPL prompt (see `Parser::repl` /
+    /// `expression_statement`) - unlike `Print`, its value isn't just written
+    /// to stdout: it's also handed back to `Interpreter::interpret`'s caller
+    /// for display and bound to the `_` global so a following line can refer
+    /// to it.
+    Repl {
+        expr: Expr,
+    },
     Var {
         name: Token,
-        initializer: Box<Expr>,
+        initializer: ExprId,
     },
     Block {
         statements: Vec<Stmt>,
     },
     IfStmt {
         condition: Expr,
-        then_branch: Box<Stmt>,
-        else_branch: Box<Stmt>,
+        then_branch: StmtId,
+        else_branch: StmtId,
     },
     WhileStmt {
         condition: Expr,
-        body: Box<Stmt>,
+        body: StmtId,
+        /// The `for` loop's increment clause, run after `body` on every
+        /// iteration including one ended early by `continue` — `Expr::Null`
+        /// for a plain `while` (or a `for` with no increment clause).
+        increment: ExprId,
+    },
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: StmtId,
+    },
+    ReturnStmt {
+        keyword: Token,
+        value: ExprId,
+    },
+    Break {
+        keyword: Token,
+    },
+    Continue {
+        keyword: Token,
+    },
+    /// `import "path.lox";` - `path` is the scanned string token (its
+    /// `Literal::LString` is the module path), `keyword` is kept for error
+    /// reporting the same way `ReturnStmt`/`Break`/`Continue` keep theirs.
+    Import {
+        keyword: Token,
+        path: Token,
     },
     Null,
 }
 
 pub trait Visitor<T> {
-    fn visit_expression_stmt(&mut self, stmt: &Stmt) -> T;
-    fn visit_print_stmt(&mut self, stmt: &Stmt) -> T;
-    fn visit_var_stmt(&mut self, stmt: &Stmt) -> T;
-    fn visit_block_stmt(&mut self, stmt: &Stmt) -> T;
-    fn visit_ifstmt_stmt(&mut self, stmt: &Stmt) -> T;
-    fn visit_whilestmt_stmt(&mut self, stmt: &Stmt) -> T;
+    fn visit_expression_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_print_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_repl_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_var_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_block_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_ifstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_whilestmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_function_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_returnstmt_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_break_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_continue_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
+    fn visit_import_stmt(&mut self, stmt: &Stmt, ast: &mut Ast) -> T;
 }
 
 impl Stmt {
-    pub fn accept<T>(&self, visitor: &mut impl Visitor<T>) -> T {
+    pub fn accept<T>(&self, visitor: &mut impl Visitor<T>, ast: &mut Ast) -> T {
         match self {
-            Stmt::Expression { .. } => visitor.visit_expression_stmt(self),
-            Stmt::Print { .. } => visitor.visit_print_stmt(self),
-            Stmt::Var { .. } => visitor.visit_var_stmt(self),
-            Stmt::Block { .. } => visitor.visit_block_stmt(self),
-            Stmt::IfStmt { .. } => visitor.visit_ifstmt_stmt(self),
-            Stmt::WhileStmt { .. } => visitor.visit_whilestmt_stmt(self),
+            Stmt::Expression { .. } => visitor.visit_expression_stmt(self, ast),
+            Stmt::Print { .. } => visitor.visit_print_stmt(self, ast),
+            Stmt::Repl { .. } => visitor.visit_repl_stmt(self, ast),
+            Stmt::Var { .. } => visitor.visit_var_stmt(self, ast),
+            Stmt::Block { .. } => visitor.visit_block_stmt(self, ast),
+            Stmt::IfStmt { .. } => visitor.visit_ifstmt_stmt(self, ast),
+            Stmt::WhileStmt { .. } => visitor.visit_whilestmt_stmt(self, ast),
+            Stmt::Function { .. } => visitor.visit_function_stmt(self, ast),
+            Stmt::ReturnStmt { .. } => visitor.visit_returnstmt_stmt(self, ast),
+            Stmt::Break { .. } => visitor.visit_break_stmt(self, ast),
+            Stmt::Continue { .. } => visitor.visit_continue_stmt(self, ast),
+            Stmt::Import { .. } => visitor.visit_import_stmt(self, ast),
             Stmt::Null => panic!("calling visit on Stmt::Null"),
         }
     }
@@ -58,23 +105,23 @@ impl Stmt {
         Stmt::Print { expr }
     }
     #[inline]
-    pub fn var(name: Token, initializer: Box<Expr>) -> Stmt {
-        Stmt::Var { name, initializer }
+    pub fn repl(expr: Expr) -> Stmt {
+        Stmt::Repl { expr }
     }
     #[inline]
     pub fn block(statements: Vec<Stmt>) -> Stmt {
         Stmt::Block { statements }
     }
     #[inline]
-    pub fn ifstmt(condition: Expr, then_branch: Box<Stmt>, else_branch: Box<Stmt>) -> Stmt {
-        Stmt::IfStmt {
-            condition,
-            then_branch,
-            else_branch,
-        }
+    pub fn breakstmt(keyword: Token) -> Stmt {
+        Stmt::Break { keyword }
+    }
+    #[inline]
+    pub fn continuestmt(keyword: Token) -> Stmt {
+        Stmt::Continue { keyword }
     }
     #[inline]
-    pub fn whilestmt(condition: Expr, body: Box<Stmt>) -> Stmt {
-        Stmt::WhileStmt { condition, body }
+    pub fn import(keyword: Token, path: Token) -> Stmt {
+        Stmt::Import { keyword, path }
     }
 }
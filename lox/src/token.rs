@@ -1,7 +1,8 @@
 use std::fmt;
 use crate::function;
+use crate::interner::Symbol;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TokenType {
     LeftParen,
     RightParen,
@@ -30,6 +31,11 @@ pub enum TokenType {
     TString,
     Number,
 
+    // String interpolation markers: bracket the tokens of an embedded
+    // `${expr}` so the parser can splice it into a concatenation.
+    InterpolationStart,
+    InterpolationEnd,
+
     // Keywords.
     And,
     Or,
@@ -47,16 +53,27 @@ pub enum TokenType {
     Super,
     This,
     Var,
+    Break,
+    Continue,
+    Import,
 
     Eof,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Literal {
     Number(f64),
+    /// A real/imaginary `f64` pair, top of the numeric tower - always
+    /// stored as given, no reduction. See `Literal::as_complex`.
+    Complex(f64, f64),
+    /// A reduced `i64` numerator/denominator pair, bottom of the numeric
+    /// tower - always built through `Literal::rational`, which divides by
+    /// the gcd and keeps the denominator positive, so every
+    /// `Literal::Rational` in play is already in lowest terms.
+    Rational(i64, i64),
     LString(String),
     Boolean(bool),
-    Function(function::Function),
+    Function(Box<function::Function>),
     Identifier,
     Keyword,
     Eof,
@@ -75,9 +92,71 @@ impl Literal {
         Literal::Boolean(false)
     }
 
+    /// Builds a reduced `Literal::Rational`, dividing `n`/`d` by their gcd
+    /// and normalizing the sign so the denominator is always positive -
+    /// mirrors `rlox`'s `Value::rational` (see `rlox/src/types.rs`). Errors
+    /// on a zero denominator instead of producing a value that would divide
+    /// by zero the moment it's used.
+    pub fn rational(n: i64, d: i64) -> Result<Literal, String> {
+        if d == 0 {
+            return Err("rational denominator cannot be zero.".to_string());
+        }
+        let (n, d) = if d < 0 { (-n, -d) } else { (n, d) };
+        let divisor = gcd(n.unsigned_abs(), d.unsigned_abs()).max(1) as i64;
+        Ok(Literal::Rational(n / divisor, d / divisor))
+    }
+
+    /// This value's position on the `Rational -> Number -> Complex` numeric
+    /// tower expressed as a real/imaginary pair, or `None` for a
+    /// non-numeric `Literal`. Used by equality, which compares across the
+    /// tower by value.
+    pub fn as_complex(&self) -> Option<(f64, f64)> {
+        match self {
+            Literal::Number(n) => Some((*n, 0.0)),
+            Literal::Rational(n, d) => Some((*n as f64 / *d as f64, 0.0)),
+            Literal::Complex(re, im) => Some((*re, *im)),
+            _ => None,
+        }
+    }
+
+    /// This value as a real `f64`, or `None` for a `Complex` (or
+    /// non-numeric) `Literal` - unlike `as_complex`, never projects a
+    /// `Complex` onto its real part, since ordering comparisons need to
+    /// reject it rather than silently compare one.
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Literal::Number(n) => Some(*n),
+            Literal::Rational(n, d) => Some(*n as f64 / *d as f64),
+            _ => None,
+        }
+    }
+
+    /// Bare scalar text for this value - `42`, `hello`, `true` - unlike
+    /// `Display`, which wraps values in their variant name for diagnostics
+    /// (`(Number 42)`, `(LString "hello")`). Used by the `str()` native so
+    /// `str(42)` yields `"42"` rather than `"(Number 42)"`.
+    pub fn to_plain_string(&self) -> String {
+        match self {
+            Literal::Number(n) => n.to_string(),
+            Literal::Complex(re, im) if *im < 0.0 => format!("{}-{}i", re, -im),
+            Literal::Complex(re, im) => format!("{}+{}i", re, im),
+            Literal::Rational(n, d) => format!("{}/{}", n, d),
+            Literal::LString(s) => s.clone(),
+            Literal::Boolean(b) => b.to_string(),
+            Literal::Function(fun) => fun.to_string(),
+            Literal::Identifier => "Identifier".to_string(),
+            Literal::Keyword => "Keyword".to_string(),
+            Literal::Eof => "EOF".to_string(),
+            Literal::Symbol => "Symbol".to_string(),
+            Literal::Null => "nil".to_string(),
+        }
+    }
+
     pub fn literal_type(&self) -> String {
         match self {
             Literal::Number(_) => "number".to_string(),
+            Literal::Complex(_, _) => "complex".to_string(),
+            Literal::Rational(_, _) => "rational".to_string(),
             Literal::LString(_) => "string".to_string(),
             Literal::Boolean(_) => "boolean".to_string(),
             Literal::Function(_) => "function".to_string(),
@@ -90,10 +169,46 @@ impl Literal {
     }
 }
 
+/// Euclid's algorithm, used by `Literal::rational` to keep every rational
+/// it builds in lowest terms.
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl PartialEq for Literal {
+    /// Numeric variants compare by value across the whole
+    /// `Rational -> Number -> Complex` tower (so `2 == 4/2` is true)
+    /// instead of requiring the same variant on both sides; everything
+    /// else falls back to ordinary per-variant structural equality.
+    fn eq(&self, other: &Literal) -> bool {
+        if let (Some(a), Some(b)) = (self.as_complex(), other.as_complex()) {
+            return a == b;
+        }
+        match (self, other) {
+            (Literal::LString(a), Literal::LString(b)) => a == b,
+            (Literal::Boolean(a), Literal::Boolean(b)) => a == b,
+            (Literal::Function(a), Literal::Function(b)) => a == b,
+            (Literal::Identifier, Literal::Identifier) => true,
+            (Literal::Keyword, Literal::Keyword) => true,
+            (Literal::Eof, Literal::Eof) => true,
+            (Literal::Symbol, Literal::Symbol) => true,
+            (Literal::Null, Literal::Null) => true,
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Literal::Number(n) => write!(f, "(Number {})", n),
+            Literal::Complex(re, im) if *im < 0.0 => write!(f, "(Complex {}-{}i)", re, -im),
+            Literal::Complex(re, im) => write!(f, "(Complex {}+{}i)", re, im),
+            Literal::Rational(n, d) => write!(f, "(Rational {}/{})", n, d),
             Literal::LString(s) => write!(f, "(LString \"{}\")", s),
             Literal::Boolean(b) => write!(f, "(Boolean {})", b),
             Literal::Function(fun) => write!(f, "(Function {})", fun),
@@ -110,17 +225,48 @@ impl fmt::Display for Literal {
 pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
+    pub column: usize,
     pub lexeme: String,
     pub literal: Literal,
+    /// Interned id for `Identifier` tokens, so `Environment` can key its
+    /// bindings on an integer instead of re-hashing `lexeme`. `None` for
+    /// every other token kind.
+    pub symbol: Option<Symbol>,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, line: usize, lexeme: String, literal: Literal) -> Token {
+    pub fn new(
+        token_type: TokenType,
+        line: usize,
+        column: usize,
+        lexeme: String,
+        literal: Literal,
+    ) -> Token {
+        Token {
+            token_type,
+            line,
+            column,
+            lexeme,
+            literal,
+            symbol: None,
+        }
+    }
+
+    pub fn with_symbol(
+        token_type: TokenType,
+        line: usize,
+        column: usize,
+        lexeme: String,
+        literal: Literal,
+        symbol: Symbol,
+    ) -> Token {
         Token {
             token_type,
             line,
+            column,
             lexeme,
             literal,
+            symbol: Some(symbol),
         }
     }
 }
@@ -1,70 +1,91 @@
-use crate::expr::Expr;
-use crate::function::Function;
-use crate::interpreter::{Interpreter, InterpreterError, LoxRuntime};
-use crate::token::{Literal, LoxTypes, Token};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-pub trait LoxCallable {
-    fn fcall(
-        &self,
-        interpreter: &Interpreter,
-        arguments: Vec<LoxTypes>,
-        paren: Token,
-    ) -> LoxRuntime;
-    fn arity(&self, paren: Token) -> Result<usize, InterpreterError>;
-}
-
-impl LoxCallable for Expr {
-    fn fcall(
-        &self,
-        interpreter: &Interpreter,
-        arguments: Vec<LoxTypes>,
-        paren: Token,
-    ) -> LoxRuntime {
-        match self {
-            _ => InterpreterError::error(paren, "can only call functions and classes.".to_string()),
-        }
-    }
-
-    fn arity(&self, paren: Token) -> Result<usize, InterpreterError> {
-        match self {
-            _ => Err(InterpreterError::new(
-                paren,
-                "can only call functions and classes.".to_string(),
-            )),
-        }
-    }
-}
-
-impl LoxCallable for Function {
-    fn fcall(
-        &self,
-        interpreter: &Interpreter,
-        arguments: Vec<LoxTypes>,
-        paren: Token,
-    ) -> LoxRuntime {
-        match self {
-            Function::Clock {} => Ok(LoxTypes::Object(Literal::Number(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_millis() as f64
-                    / 1000.0,
-            ))),
-            // _ => Err(InterpreterError::new(
-            //     paren,
-            //     "can only call functions and classes.".to_string(),
-            // )),
-        }
-    }
-
-    fn arity(&self, paren: Token) -> Result<usize, InterpreterError> {
-        match self {
-            Function::Clock {} => Ok(0),
-            // _ => Err(InterpreterError::new(
-            //     paren,
-            //     "can only call functions and classes.".to_string(),
-            // )),
-        }
-    }
-}
+use crate::ast::Ast;
+use crate::function::Function;
+use crate::interpreter::{Interpreter, InterpreterError, LoxRuntime};
+use crate::token::{Literal, LoxTypes, Token};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait LoxCallable {
+    fn fcall(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LoxTypes>,
+        paren: Token,
+        ast: &mut Ast,
+    ) -> LoxRuntime;
+    fn arity(&self, paren: Token) -> Result<usize, InterpreterError>;
+}
+
+/// Dispatches a call through whatever the callee expression evaluated to,
+/// rather than the callee expression itself - a bare `Expr` (e.g. a
+/// `Variable` node naming a function) carries no information about what it
+/// evaluates to, so only the value it produced can possibly be callable.
+impl LoxCallable for LoxTypes {
+    fn fcall(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LoxTypes>,
+        paren: Token,
+        ast: &mut Ast,
+    ) -> LoxRuntime {
+        match self {
+            LoxTypes::Object(Literal::Function(function)) => {
+                function.fcall(interpreter, arguments, paren, ast)
+            }
+            LoxTypes::Object(_) => {
+                InterpreterError::error(paren, "can only call functions and classes.".to_string())
+            }
+        }
+    }
+
+    fn arity(&self, paren: Token) -> Result<usize, InterpreterError> {
+        match self {
+            LoxTypes::Object(Literal::Function(function)) => function.arity(paren),
+            LoxTypes::Object(_) => Err(InterpreterError::new(
+                paren,
+                "can only call functions and classes.".to_string(),
+            )),
+        }
+    }
+}
+
+impl LoxCallable for Function {
+    fn fcall(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LoxTypes>,
+        paren: Token,
+        ast: &mut Ast,
+    ) -> LoxRuntime {
+        let arity = self.arity(paren.clone())?;
+        if arguments.len() != arity {
+            return InterpreterError::error(
+                paren,
+                format!("expected {} arguments, found {}.", arity, arguments.len()),
+            );
+        }
+        match self {
+            Function::Clock {} => Ok(LoxTypes::Object(Literal::Number(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards")
+                    .as_millis() as f64
+                    / 1000.0,
+            ))),
+            Function::NativeFn { function, .. } => function(interpreter, arguments, paren),
+            Function::User {
+                params,
+                body,
+                closure,
+                ..
+            } => interpreter.call_function(params, body, closure, arguments, ast),
+        }
+    }
+
+    fn arity(&self, paren: Token) -> Result<usize, InterpreterError> {
+        match self {
+            Function::Clock {} => Ok(0),
+            Function::NativeFn { arity, .. } => Ok(*arity),
+            Function::User { params, .. } => Ok(params.len()),
+        }
+    }
+}
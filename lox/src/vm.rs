@@ -0,0 +1,273 @@
+use crate::chunk::{BytecodeFunction, Chunk, OpCode, Value};
+use crate::token::Literal;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// One call's view of the stack: which function is running, where its
+/// bytecode instruction pointer is, and where its stack window begins.
+/// Slot 0 of that window holds the called function itself (unused by user
+/// code, but keeping it reserved lines local indices up with `Compiler`'s).
+struct CallFrame {
+    function: Rc<BytecodeFunction>,
+    ip: usize,
+    slot_base: usize,
+}
+
+/// Stack-based interpreter for the bytecode emitted by `bytecode_compiler`.
+/// Values are the same `Literal` the tree-walking interpreter already uses
+/// (wrapped in `Value`), so there's no second number/string/bool
+/// representation to keep in sync. A call pushes a `CallFrame` rather than
+/// recursing into `interpret`, so deep Lox call chains don't grow the Rust
+/// stack.
+pub struct VM {
+    stack: Vec<Value>,
+    globals: HashMap<String, Value>,
+    frames: Vec<CallFrame>,
+}
+
+impl VM {
+    pub fn new() -> VM {
+        VM {
+            stack: Vec::new(),
+            globals: HashMap::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn interpret(&mut self, script: BytecodeFunction) -> Result<(), ()> {
+        let script = Rc::new(script);
+        self.push(Value::Function(Rc::clone(&script)));
+        self.frames.push(CallFrame {
+            function: script,
+            ip: 0,
+            slot_base: 0,
+        });
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<(), ()> {
+        loop {
+            let opcode = OpCode::from_u8(self.read_byte());
+
+            match opcode {
+                OpCode::Constant => {
+                    let index = self.read_byte() as usize;
+                    let value = self.current_chunk().get_constant(index);
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Literal::Null.into()),
+                OpCode::True => self.push(Literal::lit_true().into()),
+                OpCode::False => self.push(Literal::lit_false().into()),
+                OpCode::Pop => {
+                    self.pop()?;
+                }
+                OpCode::DefineGlobal => {
+                    let index = self.read_byte() as usize;
+                    let name = self.global_name(index)?;
+                    let value = self.pop()?;
+                    self.globals.insert(name, value);
+                }
+                OpCode::GetGlobal => {
+                    let index = self.read_byte() as usize;
+                    let name = self.global_name(index)?;
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(value.clone()),
+                        None => return self.runtime_error(&format!("undefined variable '{}'.", name)),
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let index = self.read_byte() as usize;
+                    let name = self.global_name(index)?;
+                    let value = self.peek()?.clone();
+                    if !self.globals.contains_key(&name) {
+                        return self.runtime_error(&format!("undefined variable '{}'.", name));
+                    }
+                    self.globals.insert(name, value);
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let base = self.frames.last().unwrap().slot_base;
+                    let value = self.stack[base + slot].clone();
+                    self.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let base = self.frames.last().unwrap().slot_base;
+                    let value = self.peek()?.clone();
+                    self.stack[base + slot] = value;
+                }
+                OpCode::Equal => {
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    self.push(Literal::Boolean(a == b).into());
+                }
+                OpCode::Greater => self.number_comparison(|a, b| a > b)?,
+                OpCode::Less => self.number_comparison(|a, b| a < b)?,
+                OpCode::Add => {
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    match (a, b) {
+                        (Value::Literal(Literal::Number(a)), Value::Literal(Literal::Number(b))) => {
+                            self.push(Literal::Number(a + b).into())
+                        }
+                        (Value::Literal(Literal::LString(a)), Value::Literal(Literal::LString(b))) => {
+                            self.push(Literal::LString(a + &b).into())
+                        }
+                        (Value::Literal(Literal::LString(a)), Value::Literal(Literal::Number(b))) => {
+                            self.push(Literal::LString(format!("{}{}", a, b)).into())
+                        }
+                        _ => {
+                            return self.runtime_error("operands must be two numbers or two strings.")
+                        }
+                    }
+                }
+                OpCode::Subtract => self.number_binary(|a, b| a - b)?,
+                OpCode::Multiply => self.number_binary(|a, b| a * b)?,
+                OpCode::Divide => self.number_binary(|a, b| a / b)?,
+                OpCode::Not => {
+                    let value = self.pop()?;
+                    self.push(Literal::Boolean(!value.is_truthy()).into());
+                }
+                OpCode::Negate => match self.pop()? {
+                    Value::Literal(Literal::Number(n)) => self.push(Literal::Number(-n).into()),
+                    _ => return self.runtime_error("operand must be a number."),
+                },
+                OpCode::Print => {
+                    println!("{}", self.pop()?);
+                }
+                OpCode::Jump => {
+                    let offset = self.read_jump();
+                    self.frames.last_mut().unwrap().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_jump();
+                    if !self.peek()?.is_truthy() {
+                        self.frames.last_mut().unwrap().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_jump();
+                    self.frames.last_mut().unwrap().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(arg_count)?;
+                }
+                OpCode::Return => {
+                    let result = self.pop()?;
+                    let frame = self.frames.pop().unwrap();
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.truncate(frame.slot_base);
+                    self.push(result);
+                }
+            }
+        }
+    }
+
+    /// Leaves `arg_count` arguments and the callee beneath them on the stack
+    /// and pushes a new `CallFrame` reusing that same stack window as the
+    /// callee's locals - slot 0 is the function value, slots 1.. are the
+    /// arguments.
+    fn call_value(&mut self, arg_count: usize) -> Result<(), ()> {
+        let callee_index = self.stack.len() - 1 - arg_count;
+        let callee = self.stack[callee_index].clone();
+        match callee {
+            Value::Function(function) => {
+                if function.arity != arg_count {
+                    return self.runtime_error(&format!(
+                        "expected {} arguments but got {}.",
+                        function.arity, arg_count
+                    ));
+                }
+                self.frames.push(CallFrame {
+                    function,
+                    ip: 0,
+                    slot_base: callee_index,
+                });
+                Ok(())
+            }
+            other => self.runtime_error(&format!("can only call functions, found {}.", other)),
+        }
+    }
+
+    fn current_chunk(&self) -> &Chunk {
+        &self.frames.last().unwrap().function.chunk
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_jump(&mut self) -> usize {
+        let high = self.read_byte() as usize;
+        let low = self.read_byte() as usize;
+        (high << 8) | low
+    }
+
+    fn global_name(&self, index: usize) -> Result<String, ()> {
+        match self.current_chunk().get_constant(index) {
+            Value::Literal(Literal::LString(name)) => Ok(name),
+            _ => Err(()),
+        }
+    }
+
+    fn number_binary(&mut self, op: fn(f64, f64) -> f64) -> Result<(), ()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        match (a, b) {
+            (Value::Literal(Literal::Number(a)), Value::Literal(Literal::Number(b))) => {
+                self.push(Literal::Number(op(a, b)).into());
+                Ok(())
+            }
+            _ => self.runtime_error("operands must be numbers."),
+        }
+    }
+
+    fn number_comparison(&mut self, op: fn(f64, f64) -> bool) -> Result<(), ()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        match (a, b) {
+            (Value::Literal(Literal::Number(a)), Value::Literal(Literal::Number(b))) => {
+                self.push(Literal::Boolean(op(a, b)).into());
+                Ok(())
+            }
+            _ => self.runtime_error("operands must be numbers."),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Result<Value, ()> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => self.runtime_error("stack underflow."),
+        }
+    }
+
+    fn peek(&self) -> Result<&Value, ()> {
+        match self.stack.last() {
+            Some(value) => Ok(value),
+            None => self.runtime_error("stack underflow."),
+        }
+    }
+
+    fn runtime_error<T>(&self, message: &str) -> Result<T, ()> {
+        let frame = self.frames.last().unwrap();
+        let line = frame.function.chunk.get_line(frame.ip.saturating_sub(1));
+        eprintln!("[line {}] runtime error: {}", line, message);
+        Err(())
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
+}
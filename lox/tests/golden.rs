@@ -0,0 +1,134 @@
+//! Golden-file tests: each fixture under `tests/<phase>/ok/<name>.lox` is run
+//! through that phase and diffed against the sibling `<name>.expected`. Set
+//! `UPDATE_EXPECT=1` to (re)write the `.expected` files from the current
+//! output instead of asserting against them.
+
+use lox::ast_printer;
+use lox::environment::Environment;
+use lox::function::Function;
+use lox::interner::Interner;
+use lox::interpreter::Interpreter;
+use lox::parser::Parser;
+use lox::scanner::{self, Scanner};
+use lox::{Engine, LoxError};
+use std::fs;
+
+fn run_golden(dir: &str, produce: fn(&str) -> String) {
+    let update = std::env::var("UPDATE_EXPECT").as_deref() == Ok("1");
+    let mut ran_any = false;
+    let mut failures = Vec::new();
+
+    for entry in fs::read_dir(dir).unwrap_or_else(|err| panic!("can't read {}: {}", dir, err)) {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("lox") {
+            continue;
+        }
+        ran_any = true;
+
+        let source = fs::read_to_string(&path).unwrap();
+        let actual = produce(&source);
+        let expected_path = path.with_extension("expected");
+
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = match fs::read_to_string(&expected_path) {
+            Ok(expected) => expected,
+            Err(_) => {
+                failures.push(format!(
+                    "missing {} - rerun with UPDATE_EXPECT=1 to generate it",
+                    expected_path.display()
+                ));
+                continue;
+            }
+        };
+        if actual != expected {
+            failures.push(format!("mismatch for {}", path.display()));
+        }
+    }
+
+    assert!(ran_any, "no .lox fixtures found under {}", dir);
+    assert!(
+        failures.is_empty(),
+        "{} fixture(s) failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+fn dump_scanner(source: &str) -> String {
+    let interner = Interner::new();
+    let mut scanner = Scanner::new(LoxError::NoError, source.to_string(), interner);
+    let tokens = scanner.scan_tokens();
+    scanner::dump_tokens(&tokens)
+}
+
+fn dump_parser(source: &str) -> String {
+    let interner = Interner::new();
+    let mut scanner = Scanner::new(LoxError::NoError, source.to_string(), interner);
+    let tokens = scanner.scan_tokens();
+    let mut parser = Parser::new(tokens, LoxError::NoError);
+    let statements = parser.parse();
+    assert!(
+        !parser.had_error(),
+        "{}",
+        parser.diagnostics().render(source)
+    );
+    let mut ast = parser.take_ast();
+    ast_printer::print(&statements, &mut ast)
+}
+
+fn dump_run(source: &str) -> String {
+    let interner = Interner::new();
+    let globals = Environment::new();
+    Function::register_standard_library(&globals, &interner);
+    let mut interpreter = Interpreter::new_with_env(LoxError::NoError, globals, interner);
+    let (output, error) = lox::run_buffered(source.to_string(), &mut interpreter, Engine::TreeWalk);
+    assert_eq!(error, LoxError::NoError, "program did not run cleanly");
+    output
+}
+
+#[test]
+fn scanner_golden_files() {
+    run_golden("tests/scanner/ok", dump_scanner);
+}
+
+#[test]
+fn parser_golden_files() {
+    run_golden("tests/parser/ok", dump_parser);
+}
+
+#[test]
+fn run_golden_files() {
+    run_golden("tests/run/ok", dump_run);
+}
+
+/// The bytecode backend's `OpCode::Print` writes straight to real stdout
+/// (see `vm.rs`) with no `Interpreter::capture_output`-style redirection, so
+/// there's no way to diff its printed output against an `.expected` file the
+/// way `run_golden_files` does for the tree-walker. `tests/run/bytecode_ok`
+/// fixtures are instead just a smoke check that the bytecode compiler/VM
+/// runs each program to completion without erroring.
+#[test]
+fn bytecode_golden_files() {
+    let dir = "tests/run/bytecode_ok";
+    let mut ran_any = false;
+    for entry in fs::read_dir(dir).unwrap_or_else(|err| panic!("can't read {}: {}", dir, err)) {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("lox") {
+            continue;
+        }
+        ran_any = true;
+
+        let source = fs::read_to_string(&path).unwrap();
+        let interner = Interner::new();
+        let globals = Environment::new();
+        Function::register_standard_library(&globals, &interner);
+        let mut interpreter = Interpreter::new_with_env(LoxError::NoError, globals, interner);
+        let error = lox::run_source(source, &mut interpreter, Engine::Bytecode);
+        assert_eq!(error, LoxError::NoError, "{} did not run cleanly", path.display());
+    }
+    assert!(ran_any, "no .lox fixtures found under {}", dir);
+}
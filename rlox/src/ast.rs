@@ -0,0 +1,571 @@
+//! A second, independent frontend for rlox: a recursive-descent parser that
+//! builds an explicit [`Ast`] instead of emitting bytecode as it goes, plus a
+//! textual [`dump`] of the result. This exists purely for inspection/tooling
+//! (`--dump-ast`) and does not replace [`crate::compiler::compile`], which
+//! remains the single-pass parser actually used to run programs - rewriting
+//! that one to lower from this AST would mean re-deriving its entire feature
+//! surface (classes, closures, try/catch, pipes, arrays, rationals/complex
+//! numbers...) against a second code path, which isn't worth the risk for a
+//! debugging facility. Coverage here is deliberately the "core" expression
+//! and statement grammar: literals, unary/binary/logical/conditional
+//! expressions, calls, variables and assignment, and the `print`/`var`/
+//! block/`if`/`while`/`return` statement forms.
+use crate::scanner::{Position, Scanner, Token, TokenType};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Number(f64),
+    Str(String),
+    Bool(bool),
+    Nil,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Literal, Position),
+    Variable(String, Position),
+    Assign(String, Box<Expr>, Position),
+    Unary(TokenType, Box<Expr>, Position),
+    Binary(Box<Expr>, TokenType, Box<Expr>, Position),
+    Logical(Box<Expr>, TokenType, Box<Expr>, Position),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>, Position),
+    Grouping(Box<Expr>, Position),
+    Call(Box<Expr>, Vec<Expr>, Position),
+}
+
+impl Expr {
+    pub fn position(&self) -> Position {
+        match self {
+            Expr::Literal(_, position)
+            | Expr::Variable(_, position)
+            | Expr::Assign(_, _, position)
+            | Expr::Unary(_, _, position)
+            | Expr::Binary(_, _, _, position)
+            | Expr::Logical(_, _, _, position)
+            | Expr::Conditional(_, _, _, position)
+            | Expr::Grouping(_, position)
+            | Expr::Call(_, _, position) => *position,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    Expression(Expr),
+    Print(Expr),
+    Var(String, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Return(Option<Expr>),
+}
+
+/// A whole program: every top-level statement, in source order.
+pub type Ast = Vec<Stmt>;
+
+/// Parses `code` into an [`Ast`], or every `"[line N] Error...: message"`
+/// collected along the way if parsing failed - mirroring the format
+/// `Parser::error_at` in `compiler.rs` writes to stderr, since this frontend
+/// predates the structured `Diagnostic` type.
+pub fn parse(code: &str) -> Result<Ast, Vec<String>> {
+    let mut parser = AstParser::new(code);
+    let ast = parser.parse_program();
+    if parser.errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+/// Renders `ast` as an indented, parenthesized tree - one statement per line.
+pub fn dump(ast: &Ast) -> String {
+    let mut out = String::new();
+    for stmt in ast {
+        dump_stmt(stmt, 0, &mut out);
+    }
+    out
+}
+
+fn indent(depth: usize, out: &mut String) {
+    out.push_str(&"  ".repeat(depth));
+}
+
+fn dump_stmt(stmt: &Stmt, depth: usize, out: &mut String) {
+    indent(depth, out);
+    match stmt {
+        Stmt::Expression(expr) => {
+            out.push_str("(expr-stmt\n");
+            dump_expr(expr, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Stmt::Print(expr) => {
+            out.push_str("(print\n");
+            dump_expr(expr, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Stmt::Var(name, init) => {
+            out.push_str(&format!("(var {}", name));
+            if let Some(init) = init {
+                out.push('\n');
+                dump_expr(init, depth + 1, out);
+                out.push('\n');
+                indent(depth, out);
+            }
+            out.push(')');
+        }
+        Stmt::Block(statements) => {
+            out.push_str("(block\n");
+            for statement in statements {
+                dump_stmt(statement, depth + 1, out);
+                out.push('\n');
+            }
+            indent(depth, out);
+            out.push(')');
+        }
+        Stmt::If(condition, then_branch, else_branch) => {
+            out.push_str("(if\n");
+            dump_expr(condition, depth + 1, out);
+            out.push('\n');
+            dump_stmt(then_branch, depth + 1, out);
+            if let Some(else_branch) = else_branch {
+                out.push('\n');
+                dump_stmt(else_branch, depth + 1, out);
+            }
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Stmt::While(condition, body) => {
+            out.push_str("(while\n");
+            dump_expr(condition, depth + 1, out);
+            out.push('\n');
+            dump_stmt(body, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Stmt::Return(value) => {
+            out.push_str("(return");
+            if let Some(value) = value {
+                out.push('\n');
+                dump_expr(value, depth + 1, out);
+                out.push('\n');
+                indent(depth, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn dump_expr(expr: &Expr, depth: usize, out: &mut String) {
+    indent(depth, out);
+    match expr {
+        Expr::Literal(literal, _) => out.push_str(&format!("{:?}", literal)),
+        Expr::Variable(name, _) => out.push_str(name),
+        Expr::Assign(name, value, _) => {
+            out.push_str(&format!("(= {}\n", name));
+            dump_expr(value, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Expr::Unary(op, operand, _) => {
+            out.push_str(&format!("({:?}\n", op));
+            dump_expr(operand, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Expr::Binary(left, op, right, _) | Expr::Logical(left, op, right, _) => {
+            out.push_str(&format!("({:?}\n", op));
+            dump_expr(left, depth + 1, out);
+            out.push('\n');
+            dump_expr(right, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Expr::Conditional(condition, then, otherwise, _) => {
+            out.push_str("(?:\n");
+            dump_expr(condition, depth + 1, out);
+            out.push('\n');
+            dump_expr(then, depth + 1, out);
+            out.push('\n');
+            dump_expr(otherwise, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Expr::Grouping(inner, _) => {
+            out.push_str("(group\n");
+            dump_expr(inner, depth + 1, out);
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+        Expr::Call(callee, args, _) => {
+            out.push_str("(call\n");
+            dump_expr(callee, depth + 1, out);
+            for arg in args {
+                out.push('\n');
+                dump_expr(arg, depth + 1, out);
+            }
+            out.push('\n');
+            indent(depth, out);
+            out.push(')');
+        }
+    }
+}
+
+/// Binding power of a binary operator, matching `types::Precedence` (`Or`
+/// being loosest, `Call` tightest) minus the forms this frontend doesn't
+/// parse (pipes, bitwise, `**`, `\`), since those live only in the
+/// single-pass compiler's richer grammar.
+fn binary_precedence(ttype: TokenType) -> Option<u8> {
+    match ttype {
+        TokenType::Or => Some(1),
+        TokenType::And => Some(2),
+        TokenType::EqualEqual | TokenType::BangEqual => Some(3),
+        TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => {
+            Some(4)
+        }
+        TokenType::Plus | TokenType::Minus => Some(5),
+        TokenType::Star | TokenType::Slash => Some(6),
+        _ => None,
+    }
+}
+
+struct AstParser<'s> {
+    current: Token<'s>,
+    previous: Token<'s>,
+    scanner: Scanner<'s>,
+    errors: Vec<String>,
+    panic_mode: bool,
+}
+
+impl<'s> AstParser<'s> {
+    fn new(code: &'s str) -> Self {
+        let mut parser = AstParser {
+            current: Token::syntethic(""),
+            previous: Token::syntethic(""),
+            scanner: Scanner::new(code),
+            errors: Vec::new(),
+            panic_mode: false,
+        };
+        parser.advance();
+        parser
+    }
+
+    fn parse_program(&mut self) -> Ast {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::Eof) {
+            statements.push(self.declaration());
+        }
+        statements
+    }
+
+    fn advance(&mut self) {
+        self.previous = self.current;
+        loop {
+            self.current = self.scanner.scan_token();
+            if self.current.token_type != TokenType::Error {
+                break;
+            }
+            self.error_at_current(self.current.lexeme);
+        }
+    }
+
+    fn check(&self, ttype: TokenType) -> bool {
+        self.current.token_type == ttype
+    }
+
+    fn match_token(&mut self, ttype: TokenType) -> bool {
+        if !self.check(ttype) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    fn consume(&mut self, ttype: TokenType, message: &str) {
+        if self.current.token_type == ttype {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    fn declaration(&mut self) -> Stmt {
+        let stmt = if self.match_token(TokenType::Var) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+        if self.panic_mode {
+            self.synchronize();
+        }
+        stmt
+    }
+
+    fn var_declaration(&mut self) -> Stmt {
+        self.consume(TokenType::Identifier, "Expect variable name.");
+        let name = self.previous.lexeme.to_owned();
+        let init = if self.match_token(TokenType::Equal) {
+            Some(self.expression())
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+        Stmt::Var(name, init)
+    }
+
+    fn statement(&mut self) -> Stmt {
+        if self.match_token(TokenType::Print) {
+            self.print_statement()
+        } else if self.match_token(TokenType::LeftBrace) {
+            Stmt::Block(self.block())
+        } else if self.match_token(TokenType::If) {
+            self.if_statement()
+        } else if self.match_token(TokenType::While) {
+            self.while_statement()
+        } else if self.match_token(TokenType::Return) {
+            self.return_statement()
+        } else {
+            self.expression_statement()
+        }
+    }
+
+    fn print_statement(&mut self) -> Stmt {
+        let value = self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after value.");
+        Stmt::Print(value)
+    }
+
+    fn block(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            statements.push(self.declaration());
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+        statements
+    }
+
+    fn if_statement(&mut self) -> Stmt {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        let condition = self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+        let then_branch = Box::new(self.statement());
+        let else_branch = if self.match_token(TokenType::Else) {
+            Some(Box::new(self.statement()))
+        } else {
+            None
+        };
+        Stmt::If(condition, then_branch, else_branch)
+    }
+
+    fn while_statement(&mut self) -> Stmt {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        let condition = self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+        let body = Box::new(self.statement());
+        Stmt::While(condition, body)
+    }
+
+    fn return_statement(&mut self) -> Stmt {
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression())
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+        Stmt::Return(value)
+    }
+
+    fn expression_statement(&mut self) -> Stmt {
+        let expr = self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        Stmt::Expression(expr)
+    }
+
+    fn expression(&mut self) -> Expr {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Expr {
+        let expr = self.binary_expr(1);
+        if self.match_token(TokenType::Equal) {
+            let equals = self.previous.position;
+            let value = self.assignment();
+            if let Expr::Variable(name, _) = expr {
+                return Expr::Assign(name, Box::new(value), equals);
+            }
+            self.error("Invalid assignment target.");
+            return value;
+        }
+        expr
+    }
+
+    /// Precedence-climbing over [`binary_precedence`]; `And`/`Or` become
+    /// [`Expr::Logical`] so a later `optimize` pass can short-circuit them
+    /// structurally instead of treating them like any other binary operator.
+    fn binary_expr(&mut self, min_precedence: u8) -> Expr {
+        let mut left = self.conditional();
+        while let Some(precedence) = binary_precedence(self.current.token_type) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.advance();
+            let op = self.previous.token_type;
+            let position = self.previous.position;
+            let right = self.binary_expr(precedence + 1);
+            left = if matches!(op, TokenType::And | TokenType::Or) {
+                Expr::Logical(Box::new(left), op, Box::new(right), position)
+            } else {
+                Expr::Binary(Box::new(left), op, Box::new(right), position)
+            };
+        }
+        left
+    }
+
+    fn conditional(&mut self) -> Expr {
+        let expr = self.unary();
+        if self.match_token(TokenType::Question) {
+            let position = self.previous.position;
+            let then_branch = self.expression();
+            self.consume(
+                TokenType::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            );
+            let else_branch = self.conditional();
+            return Expr::Conditional(
+                Box::new(expr),
+                Box::new(then_branch),
+                Box::new(else_branch),
+                position,
+            );
+        }
+        expr
+    }
+
+    fn unary(&mut self) -> Expr {
+        if self.match_token(TokenType::Minus) || self.match_token(TokenType::Bang) {
+            let op = self.previous.token_type;
+            let position = self.previous.position;
+            let operand = self.unary();
+            return Expr::Unary(op, Box::new(operand), position);
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Expr {
+        let mut expr = self.primary();
+        while self.match_token(TokenType::LeftParen) {
+            let position = self.previous.position;
+            let mut args = Vec::new();
+            if !self.check(TokenType::RightParen) {
+                loop {
+                    args.push(self.expression());
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+            expr = Expr::Call(Box::new(expr), args, position);
+        }
+        expr
+    }
+
+    fn primary(&mut self) -> Expr {
+        let position = self.current.position;
+        if self.match_token(TokenType::False) {
+            return Expr::Literal(Literal::Bool(false), position);
+        }
+        if self.match_token(TokenType::True) {
+            return Expr::Literal(Literal::Bool(true), position);
+        }
+        if self.match_token(TokenType::Nil) {
+            return Expr::Literal(Literal::Nil, position);
+        }
+        if self.match_token(TokenType::Number) {
+            let lexeme = self.previous.lexeme;
+            return if lexeme.contains('.') {
+                Expr::Literal(Literal::Number(lexeme.parse().unwrap_or(0.0)), position)
+            } else {
+                match lexeme.parse::<i64>() {
+                    Ok(value) => Expr::Literal(Literal::Int(value), position),
+                    Err(_) => {
+                        Expr::Literal(Literal::Number(lexeme.parse().unwrap_or(0.0)), position)
+                    }
+                }
+            };
+        }
+        if self.match_token(TokenType::RString) {
+            let lexeme = self.previous.lexeme;
+            let value = lexeme[1..lexeme.chars().count() - 1].to_owned();
+            return Expr::Literal(Literal::Str(value), position);
+        }
+        if self.match_token(TokenType::Identifier) {
+            return Expr::Variable(self.previous.lexeme.to_owned(), position);
+        }
+        if self.match_token(TokenType::LeftParen) {
+            let inner = self.expression();
+            self.consume(TokenType::RightParen, "Expect ')' after expression.");
+            return Expr::Grouping(Box::new(inner), position);
+        }
+        self.error("Expect expression.");
+        Expr::Literal(Literal::Nil, position)
+    }
+
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+        while self.current.token_type != TokenType::Eof {
+            if self.previous.token_type == TokenType::Semicolon {
+                return;
+            }
+            match self.current.token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => self.advance(),
+            }
+        }
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current, message);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error_at(self.previous, message);
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        let where_ = if token.token_type == TokenType::Eof {
+            " at end".to_owned()
+        } else if token.token_type == TokenType::Error {
+            String::new()
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+        self.errors
+            .push(format!("[line {}] Error{}: {}", token.line, where_, message));
+    }
+}
@@ -0,0 +1,211 @@
+#![deny(clippy::all)]
+
+use rlox::preprocessor::{parse_search_path, preprocessor};
+use rlox::types::InterpretError;
+use rlox::vm::Vm;
+use rlox::{compile_only, dump, dump_ast, load_prelude, report_diagnostics, run_file};
+use rustyline::Editor;
+use std::{env::args, env::var as env_var, path::PathBuf, process::exit, sync::atomic::Ordering};
+
+/// Parsed command-line invocation. With no `file` and no `expr`, starts a REPL.
+#[derive(Debug, Default)]
+struct Cli {
+    file: Option<String>,
+    expr: Option<String>,
+    dump: Option<String>,
+    dump_ast: bool,
+    compile_only: bool,
+    debug: bool,
+    /// One entry per `-I` flag, each itself colon-separated; flattened and
+    /// combined with `LOX_PATH` by `search_path`.
+    include_paths: Vec<String>,
+    no_prelude: bool,
+}
+
+impl Cli {
+    /// Parses `-E/--expr <source>`, `--dump <chunk-name>`, `--dump-ast`,
+    /// `--compile-only`, `--debug`, `-I <dirs>` and `--no-prelude` out of
+    /// `args`, treating the first argument that isn't one of those flags (or
+    /// a flag's value) as the source file.
+    fn parse(args: &[String]) -> Self {
+        let mut cli = Cli::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-E" | "--expr" => {
+                    cli.expr = Some(match iter.next() {
+                        Some(value) => value.clone(),
+                        None => {
+                            eprintln!("--expr needs a <source> argument.");
+                            exit(1);
+                        }
+                    })
+                }
+                "--dump" => {
+                    cli.dump = Some(match iter.next() {
+                        Some(value) => value.clone(),
+                        None => {
+                            eprintln!("--dump needs a <chunk-name> argument.");
+                            exit(1);
+                        }
+                    })
+                }
+                "--dump-ast" => cli.dump_ast = true,
+                "--compile-only" => cli.compile_only = true,
+                "--debug" => cli.debug = true,
+                "--no-prelude" => cli.no_prelude = true,
+                "-I" => cli.include_paths.push(
+                    iter.next()
+                        .expect("-I needs a colon-separated path list")
+                        .clone(),
+                ),
+                file => cli.file = Some(file.to_owned()),
+            }
+        }
+        cli
+    }
+
+    /// The `#include`/`#module` search path: every `-I` directory, in order,
+    /// followed by every directory in `LOX_PATH` (colon-separated, same as
+    /// `$PATH`), if set.
+    fn search_path(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self
+            .include_paths
+            .iter()
+            .flat_map(|value| parse_search_path(value))
+            .collect();
+        if let Ok(lox_path) = env_var("LOX_PATH") {
+            dirs.extend(parse_search_path(&lox_path));
+        }
+        dirs
+    }
+}
+
+fn main() {
+    let args = args().skip(1).collect::<Vec<String>>();
+    let cli = Cli::parse(&args);
+    let search_path = cli.search_path();
+
+    let source = match (&cli.file, &cli.expr) {
+        (Some(_), Some(_)) => {
+            eprintln!("Pass either a file or --expr, not both.");
+            exit(1);
+        }
+        (Some(file), None) => Some(preprocessor(file, &search_path).expect("File not found")),
+        (None, Some(expr)) => Some(expr.clone()),
+        (None, None) => None,
+    };
+
+    let program = match source {
+        None => {
+            let mut vm = Vm::new(true);
+            if !cli.no_prelude {
+                load_prelude(&mut vm);
+            }
+            return repl(vm, search_path);
+        }
+        Some(program) => program,
+    };
+
+    let mut vm = Vm::new(false);
+    if cli.debug {
+        vm.set_debug();
+    }
+
+    match &cli.dump {
+        Some(chunk_name) => dump(&program, vm, chunk_name),
+        None if cli.dump_ast => dump_ast(&program),
+        None if cli.compile_only => compile_only(&program, vm),
+        None => {
+            if !cli.no_prelude {
+                load_prelude(&mut vm);
+            }
+            run_file(&program, vm)
+        }
+    }
+}
+
+pub fn repl(mut vm: Vm, search_path: Vec<PathBuf>) {
+    let interrupt = vm.interrupt_handle();
+    ctrlc::set_handler(move || interrupt.store(true, Ordering::Relaxed))
+        .expect("Error setting Ctrl-C handler.");
+
+    let mut rl = Editor::<()>::new();
+    if rl.load_history("history.txt").is_err() {}
+    loop {
+        let readline = rl.readline(">> ");
+        match readline {
+            Ok(line) => {
+                rl.add_history_entry(line.as_str());
+                if line == ":set debug" {
+                    println!("> debug flag set");
+                    vm.set_debug();
+                } else if line == ":unset debug" {
+                    println!("> debug flag unset");
+                    vm.unset_debug();
+                } else if line == ":quit" || line == ":q" {
+                    println!("> quitting");
+                    break;
+                } else if let Some(limit) = line.strip_prefix(":set stack ") {
+                    match limit.trim().parse() {
+                        Ok(stack_max) => {
+                            println!("> stack limit set to {}", stack_max);
+                            vm.set_stack_max(stack_max);
+                        }
+                        Err(_) => eprintln!("Expected a number, found \"{}\".", limit),
+                    }
+                } else if let Some(limit) = line.strip_prefix(":set frame ") {
+                    match limit.trim().parse() {
+                        Ok(frame_max) => {
+                            println!("> call stack limit set to {}", frame_max);
+                            vm.set_frame_max(frame_max);
+                        }
+                        Err(_) => eprintln!("Expected a number, found \"{}\".", limit),
+                    }
+                } else if line == ":trace" {
+                    if vm.is_debug() {
+                        vm.unset_debug();
+                        println!("> trace unset");
+                    } else {
+                        vm.set_debug();
+                        println!("> trace set");
+                    }
+                } else if let Some(expr) = line.strip_prefix(":disasm ") {
+                    match vm.disassemble(expr) {
+                        Ok(disassembly) => print!("{}", disassembly),
+                        Err(_) => eprintln!("Error while compiling."),
+                    }
+                } else if let Some(expr) = line.strip_prefix(":ast ") {
+                    for token in rlox::scanner::tokenize(expr) {
+                        println!(
+                            "{:?} '{}' (line {})",
+                            token.token_type, token.lexeme, token.line
+                        );
+                    }
+                } else if line.starts_with(":load") {
+                    let file = if let Ok(f) =
+                        preprocessor(line.trim_start_matches(":load "), &search_path)
+                    {
+                        f
+                    } else {
+                        eprintln!(
+                            "No file with name {} found.",
+                            line.trim_start_matches(":load ")
+                        );
+                        continue;
+                    };
+                    if let Err(InterpretError::Compile(diagnostics)) = vm.interpret(&file) {
+                        report_diagnostics(&file, &diagnostics);
+                    }
+                } else if let Err(InterpretError::Compile(diagnostics)) = vm.interpret(&line) {
+                    report_diagnostics(&line, &diagnostics);
+                }
+            }
+            Err(err) => {
+                println!("{:?}", err);
+                break;
+            }
+        }
+    }
+    rl.save_history("history.txt").unwrap();
+}
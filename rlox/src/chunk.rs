@@ -1,288 +1,819 @@
-use crate::{
-    gc::{Gc, GcTraceFormatter},
-    types::Value,
-};
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum OpCode {
-    Add,
-    BuildList(usize),
-    Call(usize),
-    Class(usize),
-    CloseUpvalue,
-    Closure(usize),
-    Constant(usize),
-    // Decrement,
-    DecrementGlobal(usize),
-    DecrementLocal(usize),
-    DecrementUpvalue(usize),
-    DefineGlobal(usize),
-    Div,
-    Equal,
-    False,
-    GetIndexArray,
-    GetGlobal(usize),
-    GetLocal(usize),
-    GetProperty(usize),
-    GetSuper(usize),
-    GetUpvalue(usize),
-    Greater,
-    GreaterEqual,
-    // Increment,
-    IncrementGlobal(usize),
-    IncrementLocal(usize),
-    IncrementUpvalue(usize),
-    Inherit,
-    Invoke((usize, usize)),
-    Jump(usize),
-    JumpIfFalse(usize),
-    Less,
-    LessEqual,
-    Loop(usize),
-    Method(usize),
-    Mul,
-    Negate,
-    Nil,
-    Not,
-    NotEqual,
-    Pop,
-    Print,
-    Rem,
-    Return,
-    ReturnNil,
-    SetIndexArray,
-    SetGlobal(usize),
-    SetLocal(usize),
-    SetProperty(usize),
-    SetUpvalue(usize),
-    Sub,
-    SuperInvoke((usize, usize)),
-    True,
-}
-
-#[derive(Clone, Debug, PartialEq)]
-pub struct Chunk {
-    pub code: Vec<OpCode>,
-    pub constants: Vec<Value>,
-    lines: Vec<(usize, usize)>, // repetition, line
-}
-
-impl Chunk {
-    pub fn new() -> Self {
-        Chunk {
-            code: Vec::new(),
-            constants: Vec::new(),
-            lines: Vec::new(),
-        }
-    }
-
-    #[inline]
-    pub fn write(&mut self, opcode: OpCode, line: usize) {
-        self.code.push(opcode);
-        self.add_line(line);
-    }
-
-    #[inline]
-    pub fn get_opcode(&self, index: usize) -> OpCode {
-        self.code[index]
-    }
-
-    #[inline]
-    pub fn get_constant(&self, index: usize) -> Value {
-        self.constants[index]
-    }
-
-    pub fn add_constant(&mut self, value: Value) -> usize {
-        self.constants.push(value);
-        self.constants.len() - 1
-    }
-
-    pub fn get_line(&self, index: usize) -> usize {
-        let mut ind = 0;
-        let mut i = 0;
-        let len = self.lines.len();
-        while ind <= index && i < len {
-            ind += self.lines[i].0;
-            i += 1;
-        }
-
-        self.lines[i - 1].1
-    }
-
-    fn add_line(&mut self, line: usize) {
-        let last = self.lines.len();
-        if last > 0 && self.lines[last - 1].1 == line {
-            self.lines[last - 1] = (self.lines[last - 1].0 + 1, line);
-        } else {
-            self.lines.push((1, line));
-        }
-    }
-}
-
-pub struct Disassembler<'s> {
-    pub gc: &'s Gc,
-    pub chunk: &'s Chunk,
-    pub stack: Option<&'s Vec<Value>>,
-}
-
-impl<'s> Disassembler<'s> {
-    pub fn new(gc: &'s Gc, chunk: &'s Chunk, stack: Option<&'s Vec<Value>>) -> Self {
-        Disassembler { gc, chunk, stack }
-    }
-
-    pub fn disassemble_to_string(&self, name: &str) -> String {
-        let mut content = vec![String::new()];
-        let mut length = 0;
-        for (i, op) in self.chunk.code.iter().enumerate() {
-            let line = self.disassemble_instruction_to_string(op, i);
-            length = length.max(line.len());
-            content.push(line);
-        }
-        length -= 8 + name.len();
-        let half = length / 2;
-        let begin_space = "=".repeat(half);
-        let end_space = "=".repeat(length - half);
-        content[0] = format!("{} BEGIN {} {}", begin_space, name, end_space);
-        content.push(format!("{}  END {}  {}\n\n", begin_space, name, end_space));
-        content.join("\n")
-    }
-
-    fn disassemble_instruction_to_string(&self, instruction: &OpCode, offset: usize) -> String {
-        let mut content = vec![format!("{:04} ", offset)];
-        let line = self.chunk.get_line(offset);
-        if offset > 0 && line == self.chunk.get_line(offset - 1) {
-            content.push("   | ".to_owned());
-        } else {
-            content.push(format!("{:>4} ", line));
-        }
-        let instr = match instruction {
-            OpCode::BuildList(value) => self.const_instruction_to_string("OP_BUILD_LIST", *value),
-            OpCode::Constant(value) => self.const_instruction_to_string("OP_CONSTANT", *value),
-            OpCode::DefineGlobal(value) => {
-                self.const_instruction_to_string("OP_DEFINE_GLOBAL", *value)
-            }
-            OpCode::GetGlobal(value) => self.const_instruction_to_string("OP_GET_GLOBAL", *value),
-            OpCode::SetGlobal(value) => self.const_instruction_to_string("OP_SET_GLOBAL", *value),
-            OpCode::GetLocal(value) => self.value_instruction_to_string("OP_GET_LOCAL", *value),
-            OpCode::SetLocal(value) => self.value_instruction_to_string("OP_SET_LOCAL", *value),
-            OpCode::GetUpvalue(value) => self.value_instruction_to_string("OP_GET_UPVALUE", *value),
-            OpCode::SetUpvalue(value) => self.value_instruction_to_string("OP_SET_UPVALUE", *value),
-            OpCode::GetProperty(value) => {
-                self.const_instruction_to_string("OP_GET_PROPERTY", *value)
-            }
-            OpCode::SetProperty(value) => {
-                self.const_instruction_to_string("OP_SET_PROPERTY", *value)
-            }
-            OpCode::Method(value) => self.const_instruction_to_string("OP_METHOD", *value),
-            OpCode::JumpIfFalse(value) => {
-                self.value_instruction_to_string("OP_JUMP_IF_FALSE", *value)
-            }
-            OpCode::Jump(value) => self.value_instruction_to_string("OP_JUMP", *value),
-            OpCode::Loop(value) => self.value_instruction_to_string("OP_LOOP", *value),
-            OpCode::Call(value) => format!("{:<16} {:4}", "OP_CALL", *value),
-            OpCode::Closure(value) => self.const_instruction_to_string("OP_CLOSURE", *value),
-            OpCode::Class(value) => self.const_instruction_to_string("OP_CLASS", *value),
-            OpCode::Invoke((name, args)) => {
-                self.invoke_instruction_to_string("OP_INVOKE", *name, *args)
-            }
-            OpCode::SuperInvoke((name, args)) => {
-                self.invoke_instruction_to_string("OP_SUPER_INVOKE", *name, *args)
-            }
-            OpCode::GetSuper(value) => self.const_instruction_to_string("OP_GET_SUPER", *value),
-            OpCode::IncrementGlobal(value) => {
-                self.value_instruction_to_string("OP_INCREMENT_GLOBAL", *value)
-            }
-            OpCode::IncrementLocal(value) => {
-                self.value_instruction_to_string("OP_INCREMENT_LOCAL", *value)
-            }
-            OpCode::IncrementUpvalue(value) => {
-                self.value_instruction_to_string("OP_INCREMENT_UPVALUE", *value)
-            }
-            OpCode::DecrementGlobal(value) => {
-                self.value_instruction_to_string("OP_DECREMENT_GLOBAL", *value)
-            }
-            OpCode::DecrementLocal(value) => {
-                self.value_instruction_to_string("OP_DECREMENT_LOCAL", *value)
-            }
-            OpCode::DecrementUpvalue(value) => {
-                self.value_instruction_to_string("OP_DECREMENT_UPVALUE", *value)
-            }
-
-            OpCode::Return => String::from("OP_RETURN"),
-            // OpCode::Increment => String::from("OP_INCREMENT"),
-            // OpCode::Decrement => String::from("OP_DECREMENT"),
-            OpCode::ReturnNil => String::from("OP_RETURN_NIL"),
-            OpCode::Negate => String::from("OP_NEGATE"),
-            OpCode::Add => String::from("OP_ADD"),
-            OpCode::Sub => String::from("OP_SUB"),
-            OpCode::Rem => String::from("OP_REM"),
-            OpCode::Mul => String::from("OP_MUL"),
-            OpCode::Div => String::from("OP_DIV"),
-            OpCode::True => String::from("OP_TRUE"),
-            OpCode::False => String::from("OP_FALSE"),
-            OpCode::Nil => String::from("OP_NIL"),
-            OpCode::Not => String::from("OP_NOT"),
-            OpCode::Equal => String::from("OP_EQUAL"),
-            OpCode::NotEqual => String::from("OP_NOT_EQUAL"),
-            OpCode::Greater => String::from("OP_GREATER"),
-            OpCode::GreaterEqual => String::from("OP_GREATER_EQUAL"),
-            OpCode::Less => String::from("OP_LESS"),
-            OpCode::LessEqual => String::from("OP_LESS_EQUAL"),
-            OpCode::Print => String::from("OP_PRINT"),
-            OpCode::Pop => String::from("OP_POP"),
-            OpCode::CloseUpvalue => String::from("OP_CLOSE_UPVALUE"),
-            OpCode::Inherit => String::from("OP_INHERIT"),
-            OpCode::GetIndexArray => String::from("OP_GET_INDEX_ARRAY"),
-            OpCode::SetIndexArray => String::from("OP_SET_INDEX_ARRAY"),
-        };
-        content.push(instr);
-        content.join(" ")
-    }
-
-    fn const_instruction_to_string(&self, instruction: &str, index: usize) -> String {
-        let value = self.chunk.get_constant(index);
-        format!(
-            "{:<16} {:4} {}",
-            instruction,
-            index,
-            GcTraceFormatter::new(value, self.gc)
-        )
-    }
-
-    fn value_instruction_to_string(&self, instruction: &str, index: usize) -> String {
-        format!("{:<16} {:4}", instruction, index)
-    }
-
-    fn invoke_instruction_to_string(&self, instr: &str, index: usize, args: usize) -> String {
-        let value = self.chunk.constants[index as usize];
-        format!(
-            "{:<16} {:4} ({}) {}",
-            instr,
-            index,
-            crate::gc::GcTraceFormatter::new(value, self.gc),
-            args
-        )
-    }
-
-    pub fn disassemble(&self, name: &str) {
-        println!("{}", self.disassemble_to_string(name));
-    }
-
-    pub fn disassemble_instruction(&self, instruction: &OpCode, offset: usize) {
-        self.stack();
-        println!(
-            "{}",
-            self.disassemble_instruction_to_string(instruction, offset)
-        );
-    }
-
-    fn stack(&self) {
-        if let Some(stack) = self.stack {
-            print!("Stack: ");
-            for &value in stack.iter() {
-                print!("[{}]", crate::gc::GcTraceFormatter::new(value, self.gc));
-            }
-            println!();
-        }
-    }
-}
+use crate::{
+    gc::{Gc, GcRef, GcTraceFormatter},
+    types::Value,
+};
+use std::collections::HashMap;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
+    Add,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BuildList,
+    Call,
+    Class,
+    CloseUpvalue,
+    Closure,
+    Constant,
+    DecrementGlobal,
+    DecrementIndexArray,
+    DecrementLocal,
+    DecrementProperty,
+    DecrementUpvalue,
+    DefineGlobal,
+    Div,
+    Dup,
+    Equal,
+    False,
+    GetIndexArray,
+    GetGlobal,
+    GetLocal,
+    GetProperty,
+    GetSuper,
+    GetUpvalue,
+    Greater,
+    GreaterEqual,
+    IncrementGlobal,
+    IncrementIndexArray,
+    IncrementLocal,
+    IncrementProperty,
+    IncrementUpvalue,
+    Inherit,
+    IntDiv,
+    Invoke,
+    Jump,
+    JumpIfFalse,
+    JumpIfTrue,
+    Less,
+    LessEqual,
+    Loop,
+    Method,
+    Mod,
+    Mul,
+    Negate,
+    Nil,
+    Not,
+    NotEqual,
+    Pop,
+    PopTry,
+    Pow,
+    Print,
+    PushTry,
+    Return,
+    ReturnNil,
+    SetIndexArray,
+    SetIndexArrayKeep,
+    SetGlobal,
+    SetLocal,
+    SetProperty,
+    SetPropertyKeep,
+    SetUpvalue,
+    Shl,
+    Shr,
+    Sub,
+    SuperInvoke,
+    Swap,
+    Throw,
+    True,
+}
+
+/// How many operands follow an opcode byte, and how they're encoded.
+enum OperandShape {
+    None,
+    /// A single LEB128-encoded index/count/slot.
+    Single,
+    /// Two LEB128-encoded values, e.g. a constant index and an argument count.
+    Pair,
+    /// A fixed-width 2-byte big-endian distance, used by jumps so patch-back
+    /// sites don't need to shift the rest of the chunk.
+    Jump,
+}
+
+impl OpCode {
+    const TABLE: [OpCode; 70] = [
+        OpCode::Add,
+        OpCode::BitAnd,
+        OpCode::BitOr,
+        OpCode::BitXor,
+        OpCode::BuildList,
+        OpCode::Call,
+        OpCode::Class,
+        OpCode::CloseUpvalue,
+        OpCode::Closure,
+        OpCode::Constant,
+        OpCode::DecrementGlobal,
+        OpCode::DecrementIndexArray,
+        OpCode::DecrementLocal,
+        OpCode::DecrementProperty,
+        OpCode::DecrementUpvalue,
+        OpCode::DefineGlobal,
+        OpCode::Div,
+        OpCode::Dup,
+        OpCode::Equal,
+        OpCode::False,
+        OpCode::GetIndexArray,
+        OpCode::GetGlobal,
+        OpCode::GetLocal,
+        OpCode::GetProperty,
+        OpCode::GetSuper,
+        OpCode::GetUpvalue,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::IncrementGlobal,
+        OpCode::IncrementIndexArray,
+        OpCode::IncrementLocal,
+        OpCode::IncrementProperty,
+        OpCode::IncrementUpvalue,
+        OpCode::Inherit,
+        OpCode::IntDiv,
+        OpCode::Invoke,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::JumpIfTrue,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Loop,
+        OpCode::Method,
+        OpCode::Mod,
+        OpCode::Mul,
+        OpCode::Negate,
+        OpCode::Nil,
+        OpCode::Not,
+        OpCode::NotEqual,
+        OpCode::Pop,
+        OpCode::PopTry,
+        OpCode::Pow,
+        OpCode::Print,
+        OpCode::PushTry,
+        OpCode::Return,
+        OpCode::ReturnNil,
+        OpCode::SetIndexArray,
+        OpCode::SetIndexArrayKeep,
+        OpCode::SetGlobal,
+        OpCode::SetLocal,
+        OpCode::SetProperty,
+        OpCode::SetPropertyKeep,
+        OpCode::SetUpvalue,
+        OpCode::Shl,
+        OpCode::Shr,
+        OpCode::Sub,
+        OpCode::SuperInvoke,
+        OpCode::Swap,
+        OpCode::Throw,
+        OpCode::True,
+    ];
+
+    fn from_u8(byte: u8) -> OpCode {
+        Self::TABLE[byte as usize]
+    }
+
+    fn operand_shape(&self) -> OperandShape {
+        match self {
+            OpCode::Invoke | OpCode::SuperInvoke => OperandShape::Pair,
+            OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::JumpIfTrue
+            | OpCode::Loop
+            | OpCode::PushTry => OperandShape::Jump,
+            OpCode::BuildList
+            | OpCode::Call
+            | OpCode::Class
+            | OpCode::Closure
+            | OpCode::Constant
+            | OpCode::DecrementGlobal
+            | OpCode::DecrementLocal
+            | OpCode::DecrementProperty
+            | OpCode::DecrementUpvalue
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::GetLocal
+            | OpCode::GetProperty
+            | OpCode::GetSuper
+            | OpCode::GetUpvalue
+            | OpCode::IncrementGlobal
+            | OpCode::IncrementLocal
+            | OpCode::IncrementProperty
+            | OpCode::IncrementUpvalue
+            | OpCode::Method
+            | OpCode::SetGlobal
+            | OpCode::SetLocal
+            | OpCode::SetProperty
+            | OpCode::SetPropertyKeep
+            | OpCode::SetUpvalue => OperandShape::Single,
+            _ => OperandShape::None,
+        }
+    }
+}
+
+/// Operands decoded alongside an [`OpCode`]; the shape mirrors how many
+/// values the opcode was encoded with (see [`OpCode::operand_shape`]).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operands {
+    None,
+    One(usize),
+    Two(usize, usize),
+}
+
+impl Operands {
+    pub fn one(self) -> usize {
+        match self {
+            Operands::One(value) => value,
+            _ => panic!("expected a single operand"),
+        }
+    }
+
+    pub fn two(self) -> (usize, usize) {
+        match self {
+            Operands::Two(a, b) => (a, b),
+            _ => panic!("expected a pair of operands"),
+        }
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn read_varint(buf: &[u8], offset: usize) -> (usize, usize) {
+    let mut result: u64 = 0;
+    let mut shift = 0;
+    let mut i = offset;
+    loop {
+        let byte = buf[i];
+        result |= ((byte & 0x7f) as u64) << shift;
+        i += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    (result as usize, i - offset)
+}
+
+/// Canonical, hashable form of a [`Value`] used to intern the constant pool.
+/// Runtime-only objects (closures, instances, ...) have no sensible canonical
+/// form, so they have no `ConstantKey` and always get a fresh slot.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Bool(bool),
+    Int(i64),
+    Nil,
+    Number(u64),
+    VString(GcRef<String>),
+}
+
+impl ConstantKey {
+    fn of(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(ConstantKey::Bool(b)),
+            Value::Int(n) => Some(ConstantKey::Int(n)),
+            Value::Nil => Some(ConstantKey::Nil),
+            Value::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Value::VString(s) => Some(ConstantKey::VString(s)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    lines: Vec<(usize, usize)>, // (starting byte offset of the instruction, line)
+    constant_ids: HashMap<ConstantKey, usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk {
+            code: Vec::new(),
+            constants: Vec::new(),
+            lines: Vec::new(),
+            constant_ids: HashMap::new(),
+        }
+    }
+
+    /// Writes an opcode with no operands, returning its byte offset.
+    pub fn write(&mut self, opcode: OpCode, line: usize) -> usize {
+        let offset = self.code.len();
+        self.code.push(opcode as u8);
+        self.add_line(offset, line);
+        offset
+    }
+
+    /// Writes an opcode followed by a single LEB128-encoded operand.
+    pub fn write_operand(&mut self, opcode: OpCode, operand: usize, line: usize) -> usize {
+        let offset = self.code.len();
+        self.code.push(opcode as u8);
+        write_varint(&mut self.code, operand as u64);
+        self.add_line(offset, line);
+        offset
+    }
+
+    /// Writes an opcode followed by two LEB128-encoded operands.
+    pub fn write_operands(&mut self, opcode: OpCode, a: usize, b: usize, line: usize) -> usize {
+        let offset = self.code.len();
+        self.code.push(opcode as u8);
+        write_varint(&mut self.code, a as u64);
+        write_varint(&mut self.code, b as u64);
+        self.add_line(offset, line);
+        offset
+    }
+
+    /// Writes a jump opcode with a placeholder 2-byte distance, returning the
+    /// offset of that placeholder so [`Chunk::patch_jump`] can fill it in later.
+    pub fn write_jump(&mut self, opcode: OpCode, line: usize) -> usize {
+        let offset = self.code.len();
+        self.code.push(opcode as u8);
+        self.code.push(0xff);
+        self.code.push(0xff);
+        self.add_line(offset, line);
+        offset + 1
+    }
+
+    /// Backpatches a jump placeholder emitted by [`Chunk::write_jump`] with
+    /// the byte distance to the current end of the chunk. `max_jump` lets a
+    /// caller tighten the hard `u16` encoding ceiling (see
+    /// `CompilerLimits::max_chunk_size`); it can never loosen past it.
+    pub fn patch_jump(&mut self, placeholder: usize, max_jump: usize) -> Result<(), String> {
+        let jump = self.code.len() - placeholder - 2;
+        if jump > max_jump.min(u16::MAX as usize) {
+            return Err("Too much code to jump over.".to_owned());
+        }
+        self.code[placeholder] = ((jump >> 8) & 0xff) as u8;
+        self.code[placeholder + 1] = (jump & 0xff) as u8;
+        Ok(())
+    }
+
+    /// Writes an `OP_LOOP` back to `loop_start`, measured in bytes. See
+    /// [`Chunk::patch_jump`] for `max_jump`.
+    pub fn write_loop(
+        &mut self,
+        loop_start: usize,
+        line: usize,
+        max_jump: usize,
+    ) -> Result<(), String> {
+        let offset = self.code.len();
+        self.code.push(OpCode::Loop as u8);
+        let jump = self.code.len() + 2 - loop_start;
+        if jump > max_jump.min(u16::MAX as usize) {
+            return Err("Loop body too large.".to_owned());
+        }
+        self.code.push(((jump >> 8) & 0xff) as u8);
+        self.code.push((jump & 0xff) as u8);
+        self.add_line(offset, line);
+        Ok(())
+    }
+
+    /// Decodes the instruction starting at `offset`, returning the opcode,
+    /// its operands and the offset of the next instruction.
+    #[inline]
+    pub fn decode(&self, offset: usize) -> (OpCode, Operands, usize) {
+        let opcode = OpCode::from_u8(self.code[offset]);
+        let mut pos = offset + 1;
+        let operands = match opcode.operand_shape() {
+            OperandShape::None => Operands::None,
+            OperandShape::Single => {
+                let (value, len) = read_varint(&self.code, pos);
+                pos += len;
+                Operands::One(value)
+            }
+            OperandShape::Pair => {
+                let (a, len_a) = read_varint(&self.code, pos);
+                pos += len_a;
+                let (b, len_b) = read_varint(&self.code, pos);
+                pos += len_b;
+                Operands::Two(a, b)
+            }
+            OperandShape::Jump => {
+                let value = ((self.code[pos] as usize) << 8) | self.code[pos + 1] as usize;
+                pos += 2;
+                Operands::One(value)
+            }
+        };
+        (opcode, operands, pos)
+    }
+
+    #[inline]
+    pub fn get_constant(&self, index: usize) -> Value {
+        self.constants[index]
+    }
+
+    /// Adds `value` to the constant pool, reusing an existing slot if an
+    /// equal constant was already interned. Runtime-only values (closures,
+    /// instances, ...) have no canonical key and always get a fresh slot.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        if let Some(key) = ConstantKey::of(value) {
+            if let Some(&index) = self.constant_ids.get(&key) {
+                return index;
+            }
+            let index = self.constants.len();
+            self.constants.push(value);
+            self.constant_ids.insert(key, index);
+            index
+        } else {
+            self.constants.push(value);
+            self.constants.len() - 1
+        }
+    }
+
+    pub fn get_line(&self, offset: usize) -> usize {
+        match self.lines.binary_search_by(|(start, _)| start.cmp(&offset)) {
+            Ok(i) => self.lines[i].1,
+            Err(i) => self.lines[i - 1].1,
+        }
+    }
+
+    fn add_line(&mut self, offset: usize, line: usize) {
+        if let Some(last) = self.lines.last() {
+            if last.1 == line {
+                return;
+            }
+        }
+        self.lines.push((offset, line));
+    }
+}
+
+/// An operand decoded alongside an instruction, tagged with what kind of
+/// index/slot/target it is. This is what lets a disassembler (or a golden
+/// test over [`DisassembledInstruction`]) tell a constant index apart from
+/// an identifier index instead of comparing bare numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DisassembledOperand {
+    None,
+    /// A literal value in the constant pool (numbers, strings, functions, classes).
+    ConstantIndex {
+        index: usize,
+        rendered: String,
+    },
+    /// A constant-pool slot that holds a name (global, property or method).
+    IdentifierIndex {
+        index: usize,
+        rendered: String,
+    },
+    LocalSlot(usize),
+    UpvalueSlot(usize),
+    /// A plain count, e.g. an argument count or array length.
+    Count(usize),
+    JumpTarget {
+        delta: usize,
+        absolute: usize,
+    },
+    Invoke {
+        name_index: usize,
+        rendered: String,
+        arity: usize,
+    },
+}
+
+/// A fully decoded instruction, independent of how it will be rendered —
+/// this is the structured form golden tests and tooling can compare
+/// against instead of a preformatted string.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisassembledInstruction {
+    pub offset: usize,
+    pub line: usize,
+    pub line_is_repeat: bool,
+    pub name: &'static str,
+    pub operand: DisassembledOperand,
+}
+
+pub struct Disassembler<'s> {
+    pub gc: &'s Gc,
+    pub chunk: &'s Chunk,
+    pub stack: Option<&'s Vec<Value>>,
+}
+
+impl<'s> Disassembler<'s> {
+    pub fn new(gc: &'s Gc, chunk: &'s Chunk, stack: Option<&'s Vec<Value>>) -> Self {
+        Disassembler { gc, chunk, stack }
+    }
+
+    /// Decodes every instruction in the chunk into its structured form.
+    pub fn instructions(&self) -> Vec<DisassembledInstruction> {
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while offset < self.chunk.code.len() {
+            let (opcode, operands, next) = self.chunk.decode(offset);
+            instructions.push(self.decode_instruction(opcode, operands, offset, next));
+            offset = next;
+        }
+        instructions
+    }
+
+    fn decode_instruction(
+        &self,
+        opcode: OpCode,
+        operands: Operands,
+        offset: usize,
+        next: usize,
+    ) -> DisassembledInstruction {
+        let line = self.chunk.get_line(offset);
+        let line_is_repeat = offset > 0 && line == self.chunk.get_line(offset - 1);
+        let (name, operand) = match opcode {
+            OpCode::BuildList => ("OP_BUILD_LIST", DisassembledOperand::Count(operands.one())),
+            OpCode::Constant => ("OP_CONSTANT", self.constant_operand(operands.one())),
+            OpCode::Closure => ("OP_CLOSURE", self.constant_operand(operands.one())),
+            OpCode::Class => ("OP_CLASS", self.constant_operand(operands.one())),
+            OpCode::DefineGlobal => ("OP_DEFINE_GLOBAL", self.identifier_operand(operands.one())),
+            OpCode::GetGlobal => ("OP_GET_GLOBAL", self.identifier_operand(operands.one())),
+            OpCode::SetGlobal => ("OP_SET_GLOBAL", self.identifier_operand(operands.one())),
+            OpCode::IncrementGlobal => (
+                "OP_INCREMENT_GLOBAL",
+                self.identifier_operand(operands.one()),
+            ),
+            OpCode::DecrementGlobal => (
+                "OP_DECREMENT_GLOBAL",
+                self.identifier_operand(operands.one()),
+            ),
+            OpCode::GetLocal => (
+                "OP_GET_LOCAL",
+                DisassembledOperand::LocalSlot(operands.one()),
+            ),
+            OpCode::SetLocal => (
+                "OP_SET_LOCAL",
+                DisassembledOperand::LocalSlot(operands.one()),
+            ),
+            OpCode::IncrementLocal => (
+                "OP_INCREMENT_LOCAL",
+                DisassembledOperand::LocalSlot(operands.one()),
+            ),
+            OpCode::DecrementLocal => (
+                "OP_DECREMENT_LOCAL",
+                DisassembledOperand::LocalSlot(operands.one()),
+            ),
+            OpCode::GetUpvalue => (
+                "OP_GET_UPVALUE",
+                DisassembledOperand::UpvalueSlot(operands.one()),
+            ),
+            OpCode::SetUpvalue => (
+                "OP_SET_UPVALUE",
+                DisassembledOperand::UpvalueSlot(operands.one()),
+            ),
+            OpCode::IncrementUpvalue => (
+                "OP_INCREMENT_UPVALUE",
+                DisassembledOperand::UpvalueSlot(operands.one()),
+            ),
+            OpCode::DecrementUpvalue => (
+                "OP_DECREMENT_UPVALUE",
+                DisassembledOperand::UpvalueSlot(operands.one()),
+            ),
+            OpCode::GetProperty => ("OP_GET_PROPERTY", self.identifier_operand(operands.one())),
+            OpCode::SetProperty => ("OP_SET_PROPERTY", self.identifier_operand(operands.one())),
+            OpCode::SetPropertyKeep => (
+                "OP_SET_PROPERTY_KEEP",
+                self.identifier_operand(operands.one()),
+            ),
+            OpCode::IncrementProperty => (
+                "OP_INCREMENT_PROPERTY",
+                self.identifier_operand(operands.one()),
+            ),
+            OpCode::DecrementProperty => (
+                "OP_DECREMENT_PROPERTY",
+                self.identifier_operand(operands.one()),
+            ),
+            OpCode::Method => ("OP_METHOD", self.identifier_operand(operands.one())),
+            OpCode::GetSuper => ("OP_GET_SUPER", self.identifier_operand(operands.one())),
+            OpCode::Call => ("OP_CALL", DisassembledOperand::Count(operands.one())),
+            OpCode::Jump => ("OP_JUMP", self.jump_operand(operands.one(), next, true)),
+            OpCode::JumpIfFalse => (
+                "OP_JUMP_IF_FALSE",
+                self.jump_operand(operands.one(), next, true),
+            ),
+            OpCode::JumpIfTrue => (
+                "OP_JUMP_IF_TRUE",
+                self.jump_operand(operands.one(), next, true),
+            ),
+            OpCode::Loop => ("OP_LOOP", self.jump_operand(operands.one(), next, false)),
+            OpCode::PushTry => ("OP_PUSH_TRY", self.jump_operand(operands.one(), next, true)),
+            OpCode::PopTry => ("OP_POP_TRY", DisassembledOperand::None),
+            OpCode::Throw => ("OP_THROW", DisassembledOperand::None),
+            OpCode::Invoke => {
+                let (name_index, arity) = operands.two();
+                ("OP_INVOKE", self.invoke_operand(name_index, arity))
+            }
+            OpCode::SuperInvoke => {
+                let (name_index, arity) = operands.two();
+                ("OP_SUPER_INVOKE", self.invoke_operand(name_index, arity))
+            }
+            OpCode::IncrementIndexArray => ("OP_INCREMENT_INDEX_ARRAY", DisassembledOperand::None),
+            OpCode::DecrementIndexArray => ("OP_DECREMENT_INDEX_ARRAY", DisassembledOperand::None),
+            OpCode::GetIndexArray => ("OP_GET_INDEX_ARRAY", DisassembledOperand::None),
+            OpCode::SetIndexArray => ("OP_SET_INDEX_ARRAY", DisassembledOperand::None),
+            OpCode::SetIndexArrayKeep => ("OP_SET_INDEX_ARRAY_KEEP", DisassembledOperand::None),
+            OpCode::Return => ("OP_RETURN", DisassembledOperand::None),
+            OpCode::ReturnNil => ("OP_RETURN_NIL", DisassembledOperand::None),
+            OpCode::Negate => ("OP_NEGATE", DisassembledOperand::None),
+            OpCode::Add => ("OP_ADD", DisassembledOperand::None),
+            OpCode::Sub => ("OP_SUB", DisassembledOperand::None),
+            OpCode::Mod => ("OP_MOD", DisassembledOperand::None),
+            OpCode::IntDiv => ("OP_INT_DIV", DisassembledOperand::None),
+            OpCode::Pow => ("OP_POW", DisassembledOperand::None),
+            OpCode::Shl => ("OP_SHL", DisassembledOperand::None),
+            OpCode::Shr => ("OP_SHR", DisassembledOperand::None),
+            OpCode::BitAnd => ("OP_BIT_AND", DisassembledOperand::None),
+            OpCode::BitOr => ("OP_BIT_OR", DisassembledOperand::None),
+            OpCode::BitXor => ("OP_BIT_XOR", DisassembledOperand::None),
+            OpCode::Mul => ("OP_MUL", DisassembledOperand::None),
+            OpCode::Div => ("OP_DIV", DisassembledOperand::None),
+            OpCode::True => ("OP_TRUE", DisassembledOperand::None),
+            OpCode::False => ("OP_FALSE", DisassembledOperand::None),
+            OpCode::Nil => ("OP_NIL", DisassembledOperand::None),
+            OpCode::Not => ("OP_NOT", DisassembledOperand::None),
+            OpCode::Equal => ("OP_EQUAL", DisassembledOperand::None),
+            OpCode::NotEqual => ("OP_NOT_EQUAL", DisassembledOperand::None),
+            OpCode::Greater => ("OP_GREATER", DisassembledOperand::None),
+            OpCode::GreaterEqual => ("OP_GREATER_EQUAL", DisassembledOperand::None),
+            OpCode::Less => ("OP_LESS", DisassembledOperand::None),
+            OpCode::LessEqual => ("OP_LESS_EQUAL", DisassembledOperand::None),
+            OpCode::Print => ("OP_PRINT", DisassembledOperand::None),
+            OpCode::Pop => ("OP_POP", DisassembledOperand::None),
+            OpCode::Dup => ("OP_DUP", DisassembledOperand::None),
+            OpCode::Swap => ("OP_SWAP", DisassembledOperand::None),
+            OpCode::CloseUpvalue => ("OP_CLOSE_UPVALUE", DisassembledOperand::None),
+            OpCode::Inherit => ("OP_INHERIT", DisassembledOperand::None),
+        };
+        DisassembledInstruction {
+            offset,
+            line,
+            line_is_repeat,
+            name,
+            operand,
+        }
+    }
+
+    fn constant_operand(&self, index: usize) -> DisassembledOperand {
+        DisassembledOperand::ConstantIndex {
+            index,
+            rendered: self.render_constant(index),
+        }
+    }
+
+    fn identifier_operand(&self, index: usize) -> DisassembledOperand {
+        DisassembledOperand::IdentifierIndex {
+            index,
+            rendered: self.render_constant(index),
+        }
+    }
+
+    fn jump_operand(&self, delta: usize, next: usize, forward: bool) -> DisassembledOperand {
+        let absolute = if forward { next + delta } else { next - delta };
+        DisassembledOperand::JumpTarget { delta, absolute }
+    }
+
+    fn invoke_operand(&self, name_index: usize, arity: usize) -> DisassembledOperand {
+        DisassembledOperand::Invoke {
+            name_index,
+            rendered: self.render_constant(name_index),
+            arity,
+        }
+    }
+
+    fn render_constant(&self, index: usize) -> String {
+        format!(
+            "{}",
+            GcTraceFormatter::new(self.chunk.get_constant(index), self.gc)
+        )
+    }
+
+    pub fn disassemble_to_string(&self, name: &str) -> String {
+        let mut content = vec![String::new()];
+        let mut length = 0;
+        for instr in self.instructions() {
+            let line = render_instruction(&instr);
+            length = length.max(line.len());
+            content.push(line);
+        }
+        length = length.saturating_sub(8 + name.len());
+        let half = length / 2;
+        let begin_space = "=".repeat(half);
+        let end_space = "=".repeat(length - half);
+        content[0] = format!("{} BEGIN {} {}", begin_space, name, end_space);
+        content.push(format!("{}  END {}  {}\n\n", begin_space, name, end_space));
+        content.join("\n")
+    }
+
+    pub fn disassemble(&self, name: &str) {
+        println!("{}", self.disassemble_to_string(name));
+    }
+
+    pub fn disassemble_instruction(&self, opcode: OpCode, operands: Operands, offset: usize) {
+        self.stack();
+        let (_, _, next) = self.chunk.decode(offset);
+        let instr = self.decode_instruction(opcode, operands, offset, next);
+        println!("{}", render_instruction(&instr));
+    }
+
+    fn stack(&self) {
+        if let Some(stack) = self.stack {
+            print!("Stack: ");
+            for &value in stack.iter() {
+                print!("[{}]", crate::gc::GcTraceFormatter::new(value, self.gc));
+            }
+            println!();
+        }
+    }
+}
+
+/// ANSI color codes used to style a disassembled trace when writing to a
+/// terminal. Gated behind `color_disassembly` so the structured form stays
+/// the only output when the feature is off (e.g. for golden tests).
+#[cfg(feature = "color_disassembly")]
+mod color {
+    pub const MNEMONIC: &str = "\x1b[36m"; // cyan
+    pub const OPERAND: &str = "\x1b[33m"; // yellow
+    pub const CONSTANT: &str = "\x1b[32m"; // green
+    pub const RESET: &str = "\x1b[0m";
+}
+
+fn render_instruction(instr: &DisassembledInstruction) -> String {
+    let prefix = format!("{:04} ", instr.offset);
+    let location = if instr.line_is_repeat {
+        "   | ".to_owned()
+    } else {
+        format!("{:>4} ", instr.line)
+    };
+    format!("{}{}{}", prefix, location, render_body(instr))
+}
+
+fn render_body(instr: &DisassembledInstruction) -> String {
+    let name = name_text(&format!("{:<16}", instr.name));
+    match &instr.operand {
+        DisassembledOperand::None => name_text(instr.name),
+        DisassembledOperand::ConstantIndex { index, rendered }
+        | DisassembledOperand::IdentifierIndex { index, rendered } => {
+            format!(
+                "{} {:4} {}",
+                name,
+                operand_text(*index),
+                constant_text(rendered)
+            )
+        }
+        DisassembledOperand::LocalSlot(slot)
+        | DisassembledOperand::UpvalueSlot(slot)
+        | DisassembledOperand::Count(slot) => {
+            format!("{} {:4}", name, operand_text(*slot))
+        }
+        DisassembledOperand::JumpTarget { delta, absolute } => {
+            format!(
+                "{} {:4} -> {}",
+                name,
+                operand_text(*delta),
+                operand_text(*absolute)
+            )
+        }
+        DisassembledOperand::Invoke {
+            name_index,
+            rendered,
+            arity,
+        } => format!(
+            "{} {:4} ({}) {}",
+            name,
+            operand_text(*name_index),
+            constant_text(rendered),
+            operand_text(*arity)
+        ),
+    }
+}
+
+#[cfg(not(feature = "color_disassembly"))]
+fn name_text(name: &str) -> String {
+    name.to_string()
+}
+
+#[cfg(feature = "color_disassembly")]
+fn name_text(name: &str) -> String {
+    format!("{}{}{}", color::MNEMONIC, name, color::RESET)
+}
+
+#[cfg(not(feature = "color_disassembly"))]
+fn operand_text(value: usize) -> String {
+    value.to_string()
+}
+
+#[cfg(feature = "color_disassembly")]
+fn operand_text(value: usize) -> String {
+    format!("{}{}{}", color::OPERAND, value, color::RESET)
+}
+
+#[cfg(not(feature = "color_disassembly"))]
+fn constant_text(rendered: &str) -> String {
+    rendered.to_string()
+}
+
+#[cfg(feature = "color_disassembly")]
+fn constant_text(rendered: &str) -> String {
+    format!("{}{}{}", color::CONSTANT, rendered, color::RESET)
+}
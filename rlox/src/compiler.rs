@@ -1,1054 +1,1844 @@
-use crate::{
-    chunk::{Chunk, Disassembler, OpCode},
-    gc::{Gc, GcRef},
-    object::{Function, FunctionType, FunctionUpvalue, LoxString},
-    scanner::*,
-    types::{InterpretError, Precedence, Value},
-};
-use std::{collections::HashMap, mem};
-
-type ParseFn<'s> = fn(&mut Parser<'s>, can_assign: bool) -> ();
-
-#[derive(Clone)]
-struct ParseRule<'s> {
-    prefix: Option<ParseFn<'s>>,
-    infix: Option<ParseFn<'s>>,
-    precedence: Precedence,
-}
-
-struct Parser<'s> {
-    current: Token<'s>,
-    previous: Token<'s>,
-    scanner: Scanner<'s>,
-    gc: &'s mut Gc,
-    had_error: bool,
-    panic_mode: bool,
-    parse_rules: HashMap<TokenType, ParseRule<'s>>,
-    compiler: Box<Compiler<'s>>,
-    class_compiler: Option<Box<ClassCompiler>>,
-}
-
-impl<'s> Parser<'s> {
-    fn new(code: &'s str, gc: &'s mut Gc) -> Self {
-        let mut parse_rules = HashMap::new();
-        let mut rule = |kind, prefix, infix, precedence| {
-            parse_rules.insert(
-                kind,
-                ParseRule {
-                    prefix,
-                    infix,
-                    precedence,
-                },
-            )
-        };
-        rule(
-            TokenType::LeftParen,
-            Some(Parser::grouping),
-            Some(Parser::call),
-            Precedence::Call,
-        );
-        rule(TokenType::RightParen, None, None, Precedence::None);
-        rule(TokenType::LeftBrace, None, None, Precedence::None);
-        rule(TokenType::RightBrace, None, None, Precedence::None);
-        rule(TokenType::Comma, None, None, Precedence::None);
-        rule(TokenType::Dot, None, Some(Parser::dot), Precedence::Call);
-        rule(
-            TokenType::Minus,
-            Some(Parser::unary),
-            Some(Parser::binary),
-            Precedence::Term,
-        );
-        rule(
-            TokenType::Plus,
-            None,
-            Some(Parser::binary),
-            Precedence::Term,
-        );
-        rule(
-            TokenType::Rem,
-            None,
-            Some(Parser::binary),
-            Precedence::Factor,
-        );
-        rule(TokenType::Semicolon, None, None, Precedence::None);
-        rule(
-            TokenType::Slash,
-            None,
-            Some(Parser::binary),
-            Precedence::Factor,
-        );
-        rule(
-            TokenType::Star,
-            None,
-            Some(Parser::binary),
-            Precedence::Factor,
-        );
-        rule(TokenType::Bang, Some(Parser::unary), None, Precedence::None);
-        rule(
-            TokenType::BangEqual,
-            None,
-            Some(Parser::binary),
-            Precedence::Equality,
-        );
-        rule(TokenType::Equal, None, None, Precedence::None);
-        rule(
-            TokenType::EqualEqual,
-            None,
-            Some(Parser::binary),
-            Precedence::Equality,
-        );
-        rule(
-            TokenType::Greater,
-            None,
-            Some(Parser::binary),
-            Precedence::Comparison,
-        );
-        rule(
-            TokenType::GreaterEqual,
-            None,
-            Some(Parser::binary),
-            Precedence::Comparison,
-        );
-        rule(
-            TokenType::Less,
-            None,
-            Some(Parser::binary),
-            Precedence::Comparison,
-        );
-        rule(
-            TokenType::LessEqual,
-            None,
-            Some(Parser::binary),
-            Precedence::Comparison,
-        );
-        rule(
-            TokenType::Identifier,
-            Some(Parser::variable),
-            None,
-            Precedence::None,
-        );
-        rule(
-            TokenType::RString,
-            Some(Parser::string),
-            None,
-            Precedence::None,
-        );
-        rule(
-            TokenType::Number,
-            Some(Parser::number),
-            None,
-            Precedence::None,
-        );
-        rule(TokenType::And, None, Some(Parser::and_op), Precedence::And);
-        rule(TokenType::Class, None, None, Precedence::None);
-        rule(TokenType::Else, None, None, Precedence::None);
-        rule(
-            TokenType::False,
-            Some(Parser::literal),
-            None,
-            Precedence::None,
-        );
-        rule(TokenType::For, None, None, Precedence::None);
-        rule(TokenType::Fun, None, None, Precedence::None);
-        rule(TokenType::If, None, None, Precedence::None);
-        rule(
-            TokenType::Nil,
-            Some(Parser::literal),
-            None,
-            Precedence::None,
-        );
-        rule(TokenType::Or, None, Some(Parser::or_op), Precedence::Or);
-        rule(TokenType::Print, None, None, Precedence::None);
-        rule(TokenType::Return, None, None, Precedence::None);
-        rule(
-            TokenType::Super,
-            Some(Parser::super_),
-            None,
-            Precedence::None,
-        );
-        rule(TokenType::This, Some(Parser::this), None, Precedence::None);
-        rule(
-            TokenType::True,
-            Some(Parser::literal),
-            None,
-            Precedence::None,
-        );
-        rule(TokenType::Var, None, None, Precedence::None);
-        rule(TokenType::While, None, None, Precedence::None);
-        rule(TokenType::Error, None, None, Precedence::None);
-        rule(TokenType::Eof, None, None, Precedence::None);
-
-        let compiler = Compiler::new(gc.intern("script".to_owned()), FunctionType::Script);
-
-        Parser {
-            current: Token::syntethic(""),
-            previous: Token::syntethic(""),
-            gc,
-            scanner: Scanner::new(code),
-            had_error: false,
-            panic_mode: false,
-            parse_rules,
-            compiler,
-            class_compiler: None,
-        }
-    }
-
-    fn compile(mut self) -> Result<GcRef<Function>, InterpretError> {
-        self.advance();
-        while !self.match_token(TokenType::Eof) {
-            self.declaration();
-        }
-        self.consume(TokenType::Eof, "Expect end of expression.");
-        self.emit_return();
-        if cfg!(feature = "debug_trace_execution") && !self.had_error {
-            let disassembler = Disassembler::new(self.gc, &self.compiler.function.chunk, None);
-            disassembler.disassemble("code");
-        }
-        if self.had_error {
-            Err(InterpretError::Compile)
-        } else {
-            Ok(self.gc.alloc(self.compiler.function))
-        }
-    }
-
-    fn advance(&mut self) {
-        self.previous = self.current;
-
-        loop {
-            self.current = self.scanner.scan_token();
-            if self.current.token_type != TokenType::Error {
-                break;
-            }
-
-            self.error_at_current(self.current.lexeme);
-        }
-    }
-
-    fn consume(&mut self, ttype: TokenType, message: &str) {
-        if self.current.token_type == ttype {
-            self.advance();
-        } else {
-            self.error_at_current(message);
-        }
-    }
-
-    fn parse_precedence(&mut self, precedence: Precedence) {
-        self.advance();
-        let prefix_rule = match self.get_rule(&self.previous.token_type).prefix {
-            None => {
-                self.error("Expect expression.");
-                return;
-            }
-            Some(rule) => rule,
-        };
-
-        let can_assign = precedence <= Precedence::Assignment;
-        prefix_rule(self, can_assign);
-
-        while precedence <= self.get_rule(&self.current.token_type).precedence {
-            self.advance();
-            let infix_rule = self.get_rule(&self.previous.token_type).infix.unwrap();
-            infix_rule(self, can_assign);
-        }
-
-        if can_assign && self.match_token(TokenType::Equal) {
-            self.error("Invalid assignment target.");
-        }
-    }
-
-    fn declaration(&mut self) {
-        if self.match_token(TokenType::Var) {
-            self.var_declaration();
-        } else if self.match_token(TokenType::Fun) {
-            self.fun_declaration();
-        } else if self.match_token(TokenType::Class) {
-            self.class_declaration();
-        } else {
-            self.statement();
-        }
-        if self.panic_mode {
-            self.synchronize();
-        }
-    }
-
-    fn var_declaration(&mut self) {
-        let global: usize = self.parse_variable("Expect variable name.");
-
-        if self.match_token(TokenType::Equal) {
-            self.expression();
-        } else {
-            self.emit_opcode(OpCode::Nil);
-        }
-
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after variable declaration.",
-        );
-
-        self.define_variable(global);
-    }
-
-    fn fun_declaration(&mut self) {
-        let global = self.parse_variable("Expect function name.");
-        self.mark_initialized();
-        self.function(FunctionType::Function);
-        self.define_variable(global);
-    }
-
-    fn class_declaration(&mut self) {
-        self.consume(TokenType::Identifier, "Expect class name.");
-
-        let class_name = self.previous;
-        let name_constant = self.identifier_constant(self.previous);
-        self.declare_varible();
-
-        self.emit_opcode(OpCode::Class(name_constant));
-
-        self.define_variable(name_constant);
-
-        let old_class_compiler = self.class_compiler.take();
-        let new_class_compiler = Box::new(ClassCompiler {
-            enclosing: old_class_compiler,
-            has_superclass: false,
-        });
-        self.class_compiler.replace(new_class_compiler);
-
-        if self.match_token(TokenType::LessPipe) {
-            self.consume(TokenType::Identifier, "Expect superclass name.");
-            self.variable(false);
-
-            if class_name.lexeme == self.previous.lexeme {
-                self.error("A class can't inherit from itself.");
-            }
-
-            self.begin_scope();
-            self.add_local(Token::syntethic("super"));
-            self.define_variable(0);
-
-            self.named_variable(class_name, false);
-            self.emit_opcode(OpCode::Inherit);
-            self.class_compiler.as_mut().unwrap().has_superclass = true;
-        }
-
-        self.named_variable(class_name, false);
-
-        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
-
-        while !(self.check(TokenType::RightBrace) || self.check(TokenType::Eof)) {
-            self.method();
-        }
-
-        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
-        self.emit_pop();
-
-        if self.class_compiler.as_ref().unwrap().has_superclass {
-            self.end_scope();
-        }
-
-        match self.class_compiler.take() {
-            Some(comp) => self.class_compiler = comp.enclosing,
-            None => self.class_compiler = None,
-        }
-    }
-
-    fn statement(&mut self) {
-        if self.match_token(TokenType::Print) {
-            self.print_statement();
-        } else if self.match_token(TokenType::LeftBrace) {
-            self.begin_scope();
-            self.block();
-            self.end_scope();
-        } else if self.match_token(TokenType::If) {
-            self.if_statement();
-        } else if self.match_token(TokenType::While) {
-            self.while_statement();
-        } else if self.match_token(TokenType::For) {
-            self.for_statement();
-        } else if self.match_token(TokenType::Return) {
-            self.return_statement();
-        } else {
-            self.expression_statement();
-        }
-    }
-
-    fn print_statement(&mut self) {
-        self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after value.");
-        self.emit_opcode(OpCode::Print);
-    }
-
-    fn begin_scope(&mut self) {
-        self.compiler.scope_depth += 1;
-    }
-
-    fn block(&mut self) {
-        while !(self.check(TokenType::RightBrace) || self.check(TokenType::Eof)) {
-            self.declaration();
-        }
-
-        self.consume(TokenType::RightBrace, "Expect '}' after block.");
-    }
-
-    fn end_scope(&mut self) {
-        self.compiler.scope_depth -= 1;
-        for i in (0..self.compiler.locals.len()).rev() {
-            if self.compiler.locals[i].depth > self.compiler.scope_depth {
-                if self.compiler.locals[i].is_captured {
-                    self.emit_opcode(OpCode::CloseUpvalue);
-                } else {
-                    self.emit_pop();
-                }
-                self.compiler.locals.pop();
-            }
-        }
-    }
-
-    fn if_statement(&mut self) {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after condition.");
-
-        let then = self.emit_jump(OpCode::JumpIfFalse(0));
-        self.emit_pop();
-        self.statement();
-        let else_jump = self.emit_jump(OpCode::Jump(0));
-        self.patch_jump(then);
-        self.emit_pop();
-        if self.match_token(TokenType::Else) {
-            self.statement();
-        }
-        self.patch_jump(else_jump);
-    }
-
-    fn while_statement(&mut self) {
-        let loop_start = self.start_loop();
-        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after condition.");
-
-        let exit = self.emit_jump(OpCode::JumpIfFalse(0));
-        self.emit_pop();
-        self.statement();
-        self.emit_loop(loop_start);
-        self.patch_jump(exit);
-        self.emit_pop();
-    }
-
-    fn for_statement(&mut self) {
-        self.begin_scope();
-        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
-        if self.match_token(TokenType::Semicolon) {
-        } else if self.match_token(TokenType::Var) {
-            self.var_declaration();
-        } else {
-            self.expression_statement();
-        }
-
-        let mut loop_start = self.start_loop();
-        let mut exit_jump: Option<usize> = None;
-
-        if !self.match_token(TokenType::Semicolon) {
-            self.expression();
-            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
-
-            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse(0)));
-            self.emit_pop();
-        }
-
-        if !self.match_token(TokenType::RightParen) {
-            let body_jump = self.emit_jump(OpCode::Jump(0));
-            let start = self.start_loop();
-            self.expression();
-            self.emit_pop();
-            self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
-            self.emit_loop(loop_start);
-            loop_start = start;
-            self.patch_jump(body_jump);
-        }
-
-        self.statement();
-        self.emit_loop(loop_start);
-
-        if let Some(exit) = exit_jump {
-            self.patch_jump(exit);
-            self.emit_pop();
-        }
-
-        self.end_scope();
-    }
-
-    fn return_statement(&mut self) {
-        if self.compiler.function_type == FunctionType::Script {
-            self.error("Can't return from top-level code.");
-        }
-
-        if self.match_token(TokenType::Semicolon) {
-            self.emit_return();
-        } else {
-            if self.compiler.function_type == FunctionType::Initializer {
-                self.error("Can't return a value from an initializer.");
-            }
-            self.expression();
-            self.consume(TokenType::Semicolon, "Expect ';' after return value.");
-            self.emit_opcode(OpCode::Return);
-        }
-    }
-
-    fn expression_statement(&mut self) {
-        self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
-        self.emit_pop();
-    }
-
-    fn variable(&mut self, can_assign: bool) {
-        self.named_variable(self.previous, can_assign);
-    }
-
-    fn named_variable(&mut self, token: Token, can_assign: bool) {
-        let (get_op, set_op);
-        if let Some(arg) = self.resolve_local(token) {
-            set_op = OpCode::SetLocal(arg);
-            get_op = OpCode::GetLocal(arg);
-        } else if let Some(arg) = self.resolve_upvalue(token) {
-            set_op = OpCode::SetUpvalue(arg);
-            get_op = OpCode::GetUpvalue(arg);
-        } else {
-            let arg = self.identifier_constant(token);
-            set_op = OpCode::SetGlobal(arg);
-            get_op = OpCode::GetGlobal(arg);
-        }
-
-        if can_assign && self.match_token(TokenType::Equal) {
-            self.expression();
-            self.emit_opcode(set_op);
-        } else {
-            self.emit_opcode(get_op);
-        }
-    }
-
-    fn method(&mut self) {
-        self.consume(TokenType::Identifier, "Expect method name.");
-        let constant = self.identifier_constant(self.previous);
-        let ftype = if self.previous.lexeme == "init" {
-            FunctionType::Initializer
-        } else {
-            FunctionType::Method
-        };
-
-        self.function(ftype);
-        self.emit_opcode(OpCode::Method(constant));
-    }
-
-    fn expression(&mut self) {
-        self.parse_precedence(Precedence::Assignment);
-    }
-
-    fn number(&mut self, _can_assign: bool) {
-        let value = self.previous.lexeme.parse::<f64>();
-        match value {
-            Ok(value) => self.emit_constant(Value::Number(value)),
-            Err(_) => self.error_at_current("Expect number when converting string to number."),
-        }
-    }
-
-    fn grouping(&mut self, _can_assign: bool) {
-        self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after expression.");
-    }
-
-    fn unary(&mut self, _can_assign: bool) {
-        let op_type = self.previous.token_type;
-        self.parse_precedence(Precedence::Unary);
-        match op_type {
-            TokenType::Minus => self.emit_opcode(OpCode::Negate),
-            TokenType::Bang => self.emit_opcode(OpCode::Not),
-            _ => (), // Unreachable.
-        }
-    }
-
-    fn binary(&mut self, _can_assign: bool) {
-        let op_type = self.previous.token_type;
-        let rule = self.get_rule(&op_type).clone();
-        self.parse_precedence(rule.precedence.next());
-        match op_type {
-            TokenType::Plus => self.emit_opcode(OpCode::Add),
-            TokenType::Minus => self.emit_opcode(OpCode::Sub),
-            TokenType::Rem => self.emit_opcode(OpCode::Rem),
-            TokenType::Star => self.emit_opcode(OpCode::Mul),
-            TokenType::Slash => self.emit_opcode(OpCode::Div),
-            TokenType::EqualEqual => self.emit_opcode(OpCode::Equal),
-            TokenType::BangEqual => self.emit_opcode(OpCode::NotEqual),
-            TokenType::Greater => self.emit_opcode(OpCode::Greater),
-            TokenType::GreaterEqual => self.emit_opcode(OpCode::GreaterEqual),
-            TokenType::Less => self.emit_opcode(OpCode::Less),
-            TokenType::LessEqual => self.emit_opcode(OpCode::LessEqual),
-            _ => (), // Unreachable.
-        }
-    }
-
-    fn literal(&mut self, _can_assign: bool) {
-        match self.previous.token_type {
-            TokenType::False => self.emit_opcode(OpCode::False),
-            TokenType::True => self.emit_opcode(OpCode::True),
-            TokenType::Nil => self.emit_opcode(OpCode::Nil),
-            _ => (), // Unreachable.
-        }
-    }
-
-    fn string(&mut self, _can_assign: bool) {
-        let lexeme = self.previous.lexeme;
-        let value = &lexeme[1..lexeme.chars().count() - 1];
-        let string = self.gc.intern(value.to_string());
-        self.emit_constant(Value::VString(string));
-    }
-
-    fn and_op(&mut self, _can_assign: bool) {
-        let end = self.emit_jump(OpCode::JumpIfFalse(0));
-        self.emit_pop();
-        self.parse_precedence(Precedence::And);
-        self.patch_jump(end);
-    }
-
-    fn or_op(&mut self, _can_assign: bool) {
-        let else_jump = self.emit_jump(OpCode::JumpIfFalse(0));
-        let end_jump = self.emit_jump(OpCode::Jump(0));
-        self.patch_jump(else_jump);
-        self.emit_pop();
-        self.parse_precedence(Precedence::Or);
-        self.patch_jump(end_jump);
-    }
-
-    fn call(&mut self, _can_assign: bool) {
-        let arg_count = self.argument_list();
-        self.emit_opcode(OpCode::Call(arg_count));
-    }
-
-    fn dot(&mut self, can_assign: bool) {
-        self.consume(TokenType::Identifier, "Expect property name after '.'.");
-        let name = self.identifier_constant(self.previous);
-        if can_assign && self.match_token(TokenType::Equal) {
-            self.expression();
-            self.emit_opcode(OpCode::SetProperty(name));
-        } else if self.match_token(TokenType::LeftParen) {
-            let arg_count = self.argument_list();
-            self.emit_opcode(OpCode::Invoke((name, arg_count)));
-        } else {
-            self.emit_opcode(OpCode::GetProperty(name));
-        }
-    }
-
-    fn this(&mut self, _can_assign: bool) {
-        if self.class_compiler.is_none() {
-            self.error("Can't use 'this' outside of a class.");
-            return;
-        }
-        self.variable(false);
-    }
-
-    fn super_(&mut self, _can_assign: bool) {
-        if let Some(current_class) = self.class_compiler.as_ref() {
-            if !current_class.has_superclass {
-                self.error("Can't use 'super' in a class with no superclass.");
-            }
-        } else {
-            self.error("Can't use 'super' outside of a class.");
-        }
-        self.consume(TokenType::Dot, "Expect '.' after 'super'.");
-        self.consume(TokenType::Identifier, "Expect superclass method name.");
-        let name = self.identifier_constant(self.previous);
-        self.named_variable(Token::syntethic("this"), false);
-        if self.match_token(TokenType::LeftParen) {
-            let arg_count = self.argument_list();
-            self.named_variable(Token::syntethic("super"), false);
-            self.emit_opcode(OpCode::SuperInvoke((name, arg_count)));
-        } else {
-            self.named_variable(Token::syntethic("super"), false);
-            self.emit_opcode(OpCode::GetSuper(name));
-        }
-    }
-
-    // helpers
-
-    fn match_token(&mut self, ttype: TokenType) -> bool {
-        if !self.check(ttype) {
-            false
-        } else {
-            self.advance();
-            true
-        }
-    }
-
-    #[inline]
-    fn check(&self, ttype: TokenType) -> bool {
-        self.current.token_type == ttype
-    }
-
-    fn synchronize(&mut self) {
-        self.panic_mode = false;
-        while self.current.token_type != TokenType::Eof {
-            if self.previous.token_type == TokenType::Semicolon {
-                return;
-            } else {
-                match self.current.token_type {
-                    TokenType::Class
-                    | TokenType::Fun
-                    | TokenType::Var
-                    | TokenType::For
-                    | TokenType::If
-                    | TokenType::While
-                    | TokenType::Print
-                    | TokenType::Return => return,
-                    _ => (),
-                }
-                self.advance();
-            }
-        }
-    }
-
-    fn parse_variable(&mut self, message: &str) -> usize {
-        self.consume(TokenType::Identifier, message);
-
-        self.declare_varible();
-        if self.compiler.scope_depth > 0 {
-            return 0;
-        }
-
-        self.identifier_constant(self.previous)
-    }
-
-    fn define_variable(&mut self, var: usize) {
-        if self.compiler.scope_depth > 0 {
-            self.mark_initialized();
-            return;
-        }
-        self.emit_opcode(OpCode::DefineGlobal(var))
-    }
-
-    fn declare_varible(&mut self) {
-        if self.compiler.scope_depth == 0 {
-            return;
-        }
-
-        let name = self.previous;
-        if self.compiler.is_local_defined(name) {
-            self.error("Already a variable with this name in this scope.");
-        }
-        self.add_local(name);
-    }
-
-    fn add_local(&mut self, name: Token<'s>) {
-        self.compiler.locals.push(Local {
-            name,
-            depth: -1,
-            is_captured: false,
-        });
-    }
-
-    fn identifier_constant(&mut self, token: Token) -> usize {
-        let string = self.gc.intern(token.lexeme.to_string());
-        self.make_constant(Value::VString(string))
-    }
-
-    fn mark_initialized(&mut self) {
-        if self.compiler.scope_depth == 0 {
-            return;
-        }
-        let i = self.compiler.locals.len() - 1;
-        self.compiler.locals[i].depth = self.compiler.scope_depth;
-    }
-
-    fn patch_jump(&mut self, then: usize) {
-        let offset = self.compiler.function.chunk.code.len() - then - 1;
-        let instr = self.compiler.function.chunk.code[then];
-        self.compiler.function.chunk.code[then] = match instr {
-            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(offset),
-            OpCode::Jump(_) => OpCode::Jump(offset),
-            _ => panic!("No jump instruction found"),
-        };
-    }
-
-    fn start_loop(&self) -> usize {
-        self.compiler.function.chunk.code.len()
-    }
-
-    fn code_len(&self) -> usize {
-        self.compiler.function.chunk.code.len()
-    }
-
-    fn function(&mut self, function_type: FunctionType) {
-        self.push_compiler(function_type);
-        self.begin_scope();
-        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
-
-        if !self.check(TokenType::RightParen) {
-            loop {
-                self.compiler.function.arity += 1;
-                if self.compiler.function.arity > 255 {
-                    self.error_at_current("Can't have more than 255 parameters.");
-                }
-                let constant = self.parse_variable("Expect parameter name.");
-                self.define_variable(constant);
-                if !self.match_token(TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-
-        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
-        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
-        self.block();
-        let function = self.pop_compiler();
-        let fn_id = self.gc.alloc(function);
-        let index = self.make_constant(Value::Function(fn_id));
-        self.emit_opcode(OpCode::Closure(index));
-    }
-
-    fn push_compiler(&mut self, function_type: FunctionType) {
-        let name = self.gc.intern(self.previous.lexeme.to_owned());
-        let new_compiler = Compiler::new(name, function_type);
-        let old_compiler = mem::replace(&mut self.compiler, new_compiler);
-        self.compiler.enclosing = Some(old_compiler);
-    }
-
-    fn pop_compiler(&mut self) -> Function {
-        self.emit_return();
-        match self.compiler.enclosing.take() {
-            Some(enclosing) => {
-                let compiler = mem::replace(&mut self.compiler, enclosing);
-                compiler.function
-            }
-            None => panic!("Didn't find an enclosing compiler"),
-        }
-    }
-
-    fn argument_list(&mut self) -> usize {
-        let mut arg_count = 0;
-        if !self.check(TokenType::RightParen) {
-            loop {
-                self.expression();
-                if arg_count == 255 {
-                    self.error("Can't have more than 255 arguments.");
-                }
-                arg_count += 1;
-                if !self.match_token(TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
-        arg_count
-    }
-
-    fn resolve_local(&mut self, name: Token) -> Option<usize> {
-        let mut errors: Vec<&str> = Vec::new();
-        let result = self.compiler.resolve_local(name, &mut errors);
-        while let Some(err) = errors.pop() {
-            self.error(err);
-        }
-        result
-    }
-
-    fn resolve_upvalue(&mut self, name: Token) -> Option<usize> {
-        let mut errors: Vec<&str> = Vec::new();
-        let result = self.compiler.resolve_upvalue(name, &mut errors);
-        while let Some(err) = errors.pop() {
-            self.error(err);
-        }
-        result
-    }
-
-    fn get_rule(&self, key: &TokenType) -> &ParseRule<'s> {
-        self.parse_rules.get(key).unwrap()
-    }
-
-    // chunk manipulation
-
-    fn emit_opcode(&mut self, opcode: OpCode) {
-        self.compiler
-            .function
-            .chunk
-            .write(opcode, self.previous.line);
-    }
-
-    fn emit_return(&mut self) {
-        if self.compiler.function_type == FunctionType::Initializer {
-            self.emit_opcode(OpCode::GetLocal(0));
-            self.emit_opcode(OpCode::Return);
-        } else {
-            self.emit_opcode(OpCode::ReturnNil);
-        }
-    }
-
-    fn emit_constant(&mut self, constant: Value) {
-        let index = self.make_constant(constant);
-        self.emit_opcode(OpCode::Constant(index));
-    }
-
-    fn make_constant(&mut self, constant: Value) -> usize {
-        self.compiler.function.chunk.add_constant(constant)
-    }
-
-    fn emit_jump(&mut self, jump: OpCode) -> usize {
-        self.emit_opcode(jump);
-        self.compiler.function.chunk.code.len() - 1
-    }
-
-    fn emit_loop(&mut self, start: usize) {
-        self.emit_opcode(OpCode::Loop(self.code_len() - start));
-    }
-
-    fn emit_pop(&mut self) {
-        self.emit_opcode(OpCode::Pop);
-    }
-
-    // error handling
-
-    fn error_at_current(&mut self, message: &str) {
-        self.error_at(self.current, message);
-    }
-
-    fn error(&mut self, message: &str) {
-        self.error_at(self.previous, message);
-    }
-
-    fn error_at(&mut self, token: Token, message: &str) {
-        if self.panic_mode {
-            return;
-        }
-
-        self.had_error = true;
-        self.panic_mode = true;
-
-        eprint!("[line {}] Error", token.line);
-
-        if token.token_type == TokenType::Eof {
-            eprint!(" at end");
-        } else if token.token_type == TokenType::Error {
-        } else {
-            eprint!(" at '{}'", token.lexeme);
-        }
-
-        eprintln!(": {}", message);
-    }
-}
-
-pub fn compile(code: &str, gc: &mut Gc) -> Result<GcRef<Function>, InterpretError> {
-    let parser = Parser::new(code, gc);
-    parser.compile()
-}
-
-struct Compiler<'a> {
-    enclosing: Option<Box<Compiler<'a>>>,
-    scope_depth: isize,
-    locals: Vec<Local<'a>>,
-    function: Function,
-    function_type: FunctionType,
-}
-
-impl<'a> Compiler<'a> {
-    fn new(name: GcRef<LoxString>, function_type: FunctionType) -> Box<Self> {
-        let mut compiler = Compiler {
-            enclosing: None,
-            scope_depth: 0,
-            locals: Vec::new(),
-            function: Function {
-                arity: 0,
-                chunk: Chunk::new(),
-                name,
-                upvalues: Vec::new(),
-            },
-            function_type,
-        };
-        let token = match function_type {
-            FunctionType::Method | FunctionType::Initializer => Local {
-                name: Token::syntethic("this"),
-                depth: 0,
-                is_captured: false,
-            },
-            _ => Local {
-                name: Token::syntethic(""),
-                depth: 0,
-                is_captured: false,
-            },
-        };
-        compiler.locals.push(token);
-        Box::new(compiler)
-    }
-
-    fn is_local_defined(&self, name: Token) -> bool {
-        for local in self.locals.iter().rev() {
-            if local.depth != -1 && local.depth < self.scope_depth {
-                return false;
-            }
-            if local.name.lexeme == name.lexeme {
-                return true;
-            }
-        }
-        false
-    }
-
-    fn resolve_local(&mut self, name: Token, errors: &mut Vec<&str>) -> Option<usize> {
-        for (i, local) in self.locals.iter().enumerate().rev() {
-            if name.lexeme == local.name.lexeme {
-                if local.depth == -1 {
-                    errors.push("Can't read local variable in its own initializer.");
-                }
-                return Some(i);
-            }
-        }
-        None
-    }
-
-    fn resolve_upvalue(&mut self, name: Token, errors: &mut Vec<&str>) -> Option<usize> {
-        if let Some(env) = self.enclosing.as_mut() {
-            if let Some(index) = env.resolve_local(name, errors) {
-                env.locals[index].is_captured = true;
-                return Some(self.add_upvalue(index, true));
-            } else if let Some(index) = env.resolve_upvalue(name, errors) {
-                return Some(self.add_upvalue(index, false));
-            }
-        }
-        None
-    }
-
-    fn add_upvalue(&mut self, index: usize, is_local: bool) -> usize {
-        for (i, upvalue) in self.function.upvalues.iter().enumerate() {
-            if upvalue.index == index && is_local == upvalue.is_local {
-                return i;
-            }
-        }
-        let upvalue = FunctionUpvalue { index, is_local };
-        self.function.upvalues.push(upvalue);
-        self.function.upvalues.len() - 1
-    }
-}
-
-struct Local<'a> {
-    name: Token<'a>,
-    depth: isize,
-    is_captured: bool,
-}
-
-// impl<'a> Local<'a> {
-//     fn new(name: Token<'a>, depth: isize) -> Self {
-//         Self {
-//             name,
-//             depth,
-//             is_captured: false,
-//         }
-//     }
-// }
-
-struct ClassCompiler {
-    enclosing: Option<Box<ClassCompiler>>,
-    has_superclass: bool,
-}
-
-// impl ClassCompiler {
-//     fn new(enclosing: Option<Box<ClassCompiler>>) -> Box<Self> {
-//         Box::new(Self {
-//             enclosing,
-//             has_superclass: false,
-//         })
-//     }
-// }
+use crate::{
+    chunk::{Chunk, Disassembler, OpCode},
+    gc::{Gc, GcRef},
+    object::{Function, FunctionType, FunctionUpvalue},
+    scanner::*,
+    types::{Diagnostic, InterpretError, Precedence, Severity, Value},
+};
+use std::{collections::HashMap, mem};
+
+type ParseFn<'s> = fn(&mut Parser<'s>, can_assign: bool) -> ();
+
+#[derive(Clone)]
+struct ParseRule<'s> {
+    prefix: Option<ParseFn<'s>>,
+    infix: Option<ParseFn<'s>>,
+    precedence: Precedence,
+}
+
+struct Parser<'s> {
+    current: Token<'s>,
+    previous: Token<'s>,
+    scanner: Scanner<'s>,
+    gc: &'s mut Gc,
+    /// Every error collected so far via `error_at` - see [`Diagnostic`].
+    /// Empty until the first error; `compile` returns them all at once
+    /// instead of the caller seeing only the first.
+    diagnostics: Vec<Diagnostic>,
+    panic_mode: bool,
+    parse_rules: HashMap<TokenType, ParseRule<'s>>,
+    compiler: Box<Compiler<'s>>,
+    class_compiler: Option<Box<ClassCompiler>>,
+    /// Set by [`Parser::new_repl`]. A bare top-level expression statement
+    /// emits `OpCode::Print` instead of `OpCode::Pop` so a line typed
+    /// interactively shows its result, and `return_statement` allows a
+    /// top-level `return` instead of reporting it as a compile error.
+    repl: bool,
+    limits: CompilerLimits,
+}
+
+impl<'s> Parser<'s> {
+    fn new(code: &'s str, gc: &'s mut Gc) -> Self {
+        Self::new_with_limits(code, gc, false, CompilerLimits::default())
+    }
+
+    /// Like [`Parser::new`], but compiling in REPL mode - see [`Parser::repl`].
+    fn new_repl(code: &'s str, gc: &'s mut Gc) -> Self {
+        Self::new_with_limits(code, gc, true, CompilerLimits::default())
+    }
+
+    fn new_with_limits(code: &'s str, gc: &'s mut Gc, repl: bool, limits: CompilerLimits) -> Self {
+        let mut parse_rules = HashMap::new();
+        let mut rule = |kind, prefix, infix, precedence| {
+            parse_rules.insert(
+                kind,
+                ParseRule {
+                    prefix,
+                    infix,
+                    precedence,
+                },
+            )
+        };
+        rule(
+            TokenType::LeftParen,
+            Some(Parser::grouping),
+            Some(Parser::call),
+            Precedence::Call,
+        );
+        rule(TokenType::RightParen, None, None, Precedence::None);
+        rule(
+            TokenType::LeftBrace,
+            Some(Parser::block_expr),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::RightBrace, None, None, Precedence::None);
+        rule(TokenType::Comma, None, None, Precedence::None);
+        rule(TokenType::Dot, None, Some(Parser::dot), Precedence::Call);
+        rule(
+            TokenType::Minus,
+            Some(Parser::unary),
+            Some(Parser::binary),
+            Precedence::Term,
+        );
+        rule(TokenType::MinusEqual, None, None, Precedence::None);
+        rule(
+            TokenType::Plus,
+            None,
+            Some(Parser::binary),
+            Precedence::Term,
+        );
+        rule(TokenType::PlusEqual, None, None, Precedence::None);
+        rule(
+            TokenType::Rem,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(TokenType::RemEqual, None, None, Precedence::None);
+        rule(
+            TokenType::Backslash,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::StarStar,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::Amp,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::Pipe,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::Caret,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::LessLess,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(
+            TokenType::GreaterGreater,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(TokenType::Semicolon, None, None, Precedence::None);
+        rule(
+            TokenType::PipeGreater,
+            None,
+            Some(Parser::pipe),
+            Precedence::Pipe,
+        );
+        rule(
+            TokenType::PipeColon,
+            None,
+            Some(Parser::pipe),
+            Precedence::Pipe,
+        );
+        rule(
+            TokenType::PipeQuestion,
+            None,
+            Some(Parser::pipe),
+            Precedence::Pipe,
+        );
+        rule(
+            TokenType::Question,
+            None,
+            Some(Parser::conditional),
+            Precedence::Conditional,
+        );
+        rule(TokenType::Colon, None, None, Precedence::None);
+        rule(
+            TokenType::Slash,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(TokenType::SlashEqual, None, None, Precedence::None);
+        rule(
+            TokenType::Star,
+            None,
+            Some(Parser::binary),
+            Precedence::Factor,
+        );
+        rule(TokenType::StarEqual, None, None, Precedence::None);
+        rule(TokenType::Bang, Some(Parser::unary), None, Precedence::None);
+        rule(
+            TokenType::BangEqual,
+            None,
+            Some(Parser::binary),
+            Precedence::Equality,
+        );
+        rule(TokenType::Equal, None, None, Precedence::None);
+        rule(
+            TokenType::EqualEqual,
+            None,
+            Some(Parser::binary),
+            Precedence::Equality,
+        );
+        rule(
+            TokenType::Greater,
+            None,
+            Some(Parser::binary),
+            Precedence::Comparison,
+        );
+        rule(
+            TokenType::GreaterEqual,
+            None,
+            Some(Parser::binary),
+            Precedence::Comparison,
+        );
+        rule(
+            TokenType::Less,
+            None,
+            Some(Parser::binary),
+            Precedence::Comparison,
+        );
+        rule(
+            TokenType::LessEqual,
+            None,
+            Some(Parser::binary),
+            Precedence::Comparison,
+        );
+        rule(
+            TokenType::Identifier,
+            Some(Parser::variable),
+            None,
+            Precedence::None,
+        );
+        rule(
+            TokenType::RString,
+            Some(Parser::string),
+            None,
+            Precedence::None,
+        );
+        rule(
+            TokenType::Number,
+            Some(Parser::number),
+            None,
+            Precedence::None,
+        );
+        rule(
+            TokenType::Imaginary,
+            Some(Parser::imaginary),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::And, None, Some(Parser::and_op), Precedence::And);
+        rule(TokenType::Catch, None, None, Precedence::None);
+        rule(TokenType::Class, None, None, Precedence::None);
+        rule(TokenType::Else, None, None, Precedence::None);
+        rule(
+            TokenType::False,
+            Some(Parser::literal),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::For, None, None, Precedence::None);
+        rule(TokenType::Fun, None, None, Precedence::None);
+        rule(TokenType::If, Some(Parser::if_expr), None, Precedence::None);
+        rule(
+            TokenType::Nil,
+            Some(Parser::literal),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::Or, None, Some(Parser::or_op), Precedence::Or);
+        rule(TokenType::Print, None, None, Precedence::None);
+        rule(TokenType::Return, None, None, Precedence::None);
+        rule(
+            TokenType::Super,
+            Some(Parser::super_),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::This, Some(Parser::this), None, Precedence::None);
+        rule(TokenType::Throw, None, None, Precedence::None);
+        rule(
+            TokenType::True,
+            Some(Parser::literal),
+            None,
+            Precedence::None,
+        );
+        rule(TokenType::Try, None, None, Precedence::None);
+        rule(TokenType::Var, None, None, Precedence::None);
+        rule(TokenType::While, None, None, Precedence::None);
+        rule(TokenType::Error, None, None, Precedence::None);
+        rule(TokenType::Eof, None, None, Precedence::None);
+
+        let compiler = Compiler::new(gc.intern("script".to_owned()), FunctionType::Script, 0);
+
+        Parser {
+            current: Token::syntethic(""),
+            previous: Token::syntethic(""),
+            gc,
+            scanner: Scanner::new(code),
+            diagnostics: Vec::new(),
+            panic_mode: false,
+            parse_rules,
+            compiler,
+            class_compiler: None,
+            repl,
+            limits,
+        }
+    }
+
+    fn compile(mut self) -> Result<GcRef<Function>, InterpretError> {
+        self.advance();
+        while !self.match_token(TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(TokenType::Eof, "Expect end of expression.");
+        self.emit_return();
+        if cfg!(feature = "debug_trace_execution") && self.diagnostics.is_empty() {
+            let disassembler = Disassembler::new(self.gc, &self.compiler.function.chunk, None);
+            disassembler.disassemble("code");
+        }
+        if !self.diagnostics.is_empty() {
+            Err(InterpretError::Compile(self.diagnostics))
+        } else {
+            Ok(self.gc.alloc(self.compiler.function))
+        }
+    }
+
+    fn advance(&mut self) {
+        self.previous = self.current;
+
+        loop {
+            self.current = self.scanner.scan_token();
+            if self.current.token_type != TokenType::Error {
+                break;
+            }
+
+            self.error_at_current(self.current.lexeme);
+        }
+    }
+
+    fn consume(&mut self, ttype: TokenType, message: &str) {
+        if self.current.token_type == ttype {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        self.advance();
+        let prefix_rule = match self.get_rule(&self.previous.token_type).prefix {
+            None => {
+                self.error("Expect expression.");
+                return;
+            }
+            Some(rule) => rule,
+        };
+
+        let can_assign = precedence <= Precedence::Assignment;
+        prefix_rule(self, can_assign);
+
+        while precedence <= self.get_rule(&self.current.token_type).precedence {
+            self.advance();
+            let infix_rule = self.get_rule(&self.previous.token_type).infix.unwrap();
+            infix_rule(self, can_assign);
+        }
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+    }
+
+    fn declaration(&mut self) {
+        if self.match_token(TokenType::Var) {
+            self.var_declaration();
+        } else if self.match_token(TokenType::Fun) {
+            self.fun_declaration();
+        } else if self.match_token(TokenType::Class) {
+            self.class_declaration();
+        } else {
+            self.statement();
+        }
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+
+    fn var_declaration(&mut self) {
+        let global: usize = self.parse_variable("Expect variable name.");
+
+        if self.match_token(TokenType::Equal) {
+            self.expression();
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+
+        self.define_variable(global);
+    }
+
+    fn fun_declaration(&mut self) {
+        let global = self.parse_variable("Expect function name.");
+        self.mark_initialized();
+        self.function(FunctionType::Function);
+        self.define_variable(global);
+    }
+
+    fn class_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect class name.");
+
+        let class_name = self.previous;
+        let name_constant = self.identifier_constant(self.previous);
+        self.declare_varible();
+
+        self.emit_op1(OpCode::Class, name_constant);
+
+        self.define_variable(name_constant);
+
+        let old_class_compiler = self.class_compiler.take();
+        let new_class_compiler = Box::new(ClassCompiler {
+            enclosing: old_class_compiler,
+            has_superclass: false,
+        });
+        self.class_compiler.replace(new_class_compiler);
+
+        if self.match_token(TokenType::LessPipe) {
+            self.consume(TokenType::Identifier, "Expect superclass name.");
+            self.variable(false);
+
+            if class_name.lexeme == self.previous.lexeme {
+                self.error("A class can't inherit from itself.");
+            }
+
+            self.begin_scope();
+            self.add_local(Token::syntethic("super"));
+            self.define_variable(0);
+
+            self.named_variable(class_name, false);
+            self.emit_op(OpCode::Inherit);
+            self.class_compiler.as_mut().unwrap().has_superclass = true;
+        }
+
+        self.named_variable(class_name, false);
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
+
+        while !(self.check(TokenType::RightBrace) || self.check(TokenType::Eof)) {
+            self.method();
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
+        self.emit_pop();
+
+        if self.class_compiler.as_ref().unwrap().has_superclass {
+            self.end_scope();
+        }
+
+        match self.class_compiler.take() {
+            Some(comp) => self.class_compiler = comp.enclosing,
+            None => self.class_compiler = None,
+        }
+    }
+
+    /// A leading `{` or `if` is always this statement form, never the
+    /// `block_expr`/`if_expr` prefix rules registered for those tokens -
+    /// `match_token` here consumes the token before `parse_precedence` could
+    /// ever see it in expression position. `{`/`if` elsewhere (after `=`,
+    /// inside `(...)`, etc.) reach the expression forms instead.
+    fn statement(&mut self) {
+        if self.match_token(TokenType::Print) {
+            self.print_statement();
+        } else if self.match_token(TokenType::LeftBrace) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else if self.match_token(TokenType::If) {
+            self.if_statement();
+        } else if self.match_token(TokenType::While) {
+            self.while_statement();
+        } else if self.match_token(TokenType::For) {
+            self.for_statement();
+        } else if self.match_token(TokenType::Loop) {
+            self.loop_statement();
+        } else if self.match_token(TokenType::Do) {
+            self.do_while_statement();
+        } else if self.match_token(TokenType::Return) {
+            self.return_statement();
+        } else if self.match_token(TokenType::Try) {
+            self.try_statement();
+        } else if self.match_token(TokenType::Throw) {
+            self.throw_statement();
+        } else if self.match_token(TokenType::Break) {
+            self.break_statement();
+        } else if self.match_token(TokenType::Continue) {
+            self.continue_statement();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after value.");
+        self.emit_op(OpCode::Print);
+    }
+
+    fn begin_scope(&mut self) {
+        self.compiler.scope_depth += 1;
+    }
+
+    fn block(&mut self) {
+        while !(self.check(TokenType::RightBrace) || self.check(TokenType::Eof)) {
+            self.declaration();
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+    }
+
+    fn end_scope(&mut self) {
+        self.compiler.scope_depth -= 1;
+        for i in (0..self.compiler.locals.len()).rev() {
+            if self.compiler.locals[i].depth > self.compiler.scope_depth {
+                if self.compiler.locals[i].is_captured {
+                    self.emit_op(OpCode::CloseUpvalue);
+                } else {
+                    self.emit_pop();
+                }
+                self.compiler.locals.pop();
+            }
+        }
+    }
+
+    /// Like [`Parser::end_scope`], but for a scope that leaves its block
+    /// expression's value sitting on top of the stack above its locals. Each
+    /// local is swapped below that value before being popped/closed, so it
+    /// disappears without disturbing the value now back on top.
+    fn end_scope_keep_top(&mut self) {
+        self.compiler.scope_depth -= 1;
+        for i in (0..self.compiler.locals.len()).rev() {
+            if self.compiler.locals[i].depth > self.compiler.scope_depth {
+                self.emit_op(OpCode::Swap);
+                if self.compiler.locals[i].is_captured {
+                    self.emit_op(OpCode::CloseUpvalue);
+                } else {
+                    self.emit_pop();
+                }
+                self.compiler.locals.pop();
+            }
+        }
+    }
+
+    /// `{ ... }` as an expression - the prefix rule registered for
+    /// `TokenType::LeftBrace`, reached wherever a leading `{` isn't already
+    /// claimed by `statement()` as a block statement. Compiles its
+    /// declarations exactly like `block()`, except its final entry: a
+    /// semicolon-less trailing expression is left on the stack as the
+    /// block's value instead of being popped, while a block ending on a
+    /// terminated statement (or an empty block) yields `Nil`.
+    fn block_expr(&mut self, _can_assign: bool) {
+        self.begin_scope();
+
+        let mut yields_value = false;
+        while !(self.check(TokenType::RightBrace) || self.check(TokenType::Eof)) {
+            yields_value = false;
+            match self.current.token_type {
+                TokenType::Var
+                | TokenType::Fun
+                | TokenType::Class
+                | TokenType::Print
+                | TokenType::LeftBrace
+                | TokenType::If
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Loop
+                | TokenType::Do
+                | TokenType::Return
+                | TokenType::Try
+                | TokenType::Throw
+                | TokenType::Break
+                | TokenType::Continue => self.declaration(),
+                _ => {
+                    self.expression();
+                    if self.match_token(TokenType::Semicolon) {
+                        self.emit_pop();
+                    } else if self.check(TokenType::RightBrace) {
+                        yields_value = true;
+                    } else {
+                        self.error_at_current("Expect ';' after expression.");
+                    }
+                    if self.panic_mode {
+                        self.synchronize();
+                    }
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+
+        if !yields_value {
+            self.emit_op(OpCode::Nil);
+        }
+
+        self.end_scope_keep_top();
+    }
+
+    fn if_statement(&mut self) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let then = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.statement();
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then);
+        self.emit_pop();
+        if self.match_token(TokenType::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    /// `if` as an expression - the prefix rule registered for `TokenType::If`,
+    /// mirroring `conditional`'s `?:` (each branch leaves one value, the
+    /// jumps converge on a single stack effect). Unlike `if_statement`, the
+    /// `else` branch is mandatory: without one the false case would leave
+    /// nothing on the stack, so a missing `else` is a compile error rather
+    /// than `Nil`. Each branch is itself an expression, so writing `{ ... }`
+    /// there reaches `block_expr` the same way any other expression position
+    /// would.
+    fn if_expr(&mut self, _can_assign: bool) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let then = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.expression();
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then);
+        self.emit_pop();
+        if self.match_token(TokenType::Else) {
+            self.expression();
+        } else {
+            self.error("Expect 'else' after then branch of if-expression.");
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn while_statement(&mut self) {
+        let loop_start = self.start_loop();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let exit = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.compiler.loops.push(LoopContext {
+            continue_target: loop_start,
+            forward_continue: false,
+            continue_jumps: Vec::new(),
+            scope_depth: self.compiler.scope_depth,
+            break_jumps: Vec::new(),
+        });
+        self.statement();
+        self.emit_loop(loop_start);
+        self.patch_jump(exit);
+        self.emit_pop();
+        self.patch_breaks();
+    }
+
+    fn for_statement(&mut self) {
+        self.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
+        if self.match_token(TokenType::Semicolon) {
+        } else if self.match_token(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+
+        let mut loop_start = self.start_loop();
+        let mut exit_jump: Option<usize> = None;
+
+        if !self.match_token(TokenType::Semicolon) {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_pop();
+        }
+
+        // `continue` must jump to the increment clause, not the condition -
+        // so its target is only known once we've compiled (or skipped) the
+        // increment, and the loop context is pushed only then.
+        let continue_target;
+        if !self.match_token(TokenType::RightParen) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.start_loop();
+            continue_target = increment_start;
+            self.expression();
+            self.emit_pop();
+            self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        } else {
+            continue_target = loop_start;
+        }
+
+        self.compiler.loops.push(LoopContext {
+            continue_target,
+            forward_continue: false,
+            continue_jumps: Vec::new(),
+            scope_depth: self.compiler.scope_depth,
+            break_jumps: Vec::new(),
+        });
+
+        self.statement();
+        self.emit_loop(loop_start);
+
+        if let Some(exit) = exit_jump {
+            self.patch_jump(exit);
+            self.emit_pop();
+        }
+
+        self.patch_breaks();
+        self.end_scope();
+    }
+
+    /// `loop { ... }` - an unconditional loop with no exit jump of its own;
+    /// the body runs forever unless a `break` inside it jumps out, which
+    /// `patch_breaks` wires up the same way it does for `while`/`for`.
+    fn loop_statement(&mut self) {
+        let loop_start = self.start_loop();
+        self.compiler.loops.push(LoopContext {
+            continue_target: loop_start,
+            forward_continue: false,
+            continue_jumps: Vec::new(),
+            scope_depth: self.compiler.scope_depth,
+            break_jumps: Vec::new(),
+        });
+        self.statement();
+        self.emit_loop(loop_start);
+        self.patch_breaks();
+    }
+
+    /// `do { ... } while (cond);` - like `while_statement` but with the
+    /// condition check moved after the body, so it always runs at least
+    /// once. Since the condition is compiled after the body, `continue`
+    /// can't jump straight back to it the way it does for `while`/`for`;
+    /// it emits a forward `Jump` instead, patched by `patch_continues` once
+    /// the condition is reached.
+    fn do_while_statement(&mut self) {
+        let body_start = self.start_loop();
+        self.compiler.loops.push(LoopContext {
+            continue_target: body_start,
+            forward_continue: true,
+            continue_jumps: Vec::new(),
+            scope_depth: self.compiler.scope_depth,
+            break_jumps: Vec::new(),
+        });
+        self.statement();
+        self.patch_continues();
+        self.consume(TokenType::While, "Expect 'while' after 'do' body.");
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+        self.consume(TokenType::Semicolon, "Expect ';' after 'do'/'while' statement.");
+
+        let exit = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.emit_loop(body_start);
+        self.patch_jump(exit);
+        self.emit_pop();
+        self.patch_breaks();
+    }
+
+    fn break_statement(&mut self) {
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.");
+        if self.compiler.loops.is_empty() {
+            self.error("Can't use 'break' outside of a loop.");
+            return;
+        }
+        self.emit_loop_local_cleanup();
+        let jump = self.emit_jump(OpCode::Jump);
+        self.compiler
+            .loops
+            .last_mut()
+            .expect("checked above")
+            .break_jumps
+            .push(jump);
+    }
+
+    fn continue_statement(&mut self) {
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.");
+        if self.compiler.loops.is_empty() {
+            self.error("Can't use 'continue' outside of a loop.");
+            return;
+        }
+        self.emit_loop_local_cleanup();
+        let loop_context = self.compiler.loops.last().expect("checked above");
+        if loop_context.forward_continue {
+            let jump = self.emit_jump(OpCode::Jump);
+            self.compiler
+                .loops
+                .last_mut()
+                .expect("checked above")
+                .continue_jumps
+                .push(jump);
+        } else {
+            let target = loop_context.continue_target;
+            self.emit_loop(target);
+        }
+    }
+
+    /// Emits the `Pop`/`CloseUpvalue` a `break`/`continue` needs for every
+    /// local declared since the innermost loop was entered, without removing
+    /// them from `self.compiler.locals` - the block's own `end_scope` still
+    /// does that once control reaches it normally.
+    fn emit_loop_local_cleanup(&mut self) {
+        let depth = self
+            .compiler
+            .loops
+            .last()
+            .expect("checked by caller")
+            .scope_depth;
+        for i in (0..self.compiler.locals.len()).rev() {
+            if self.compiler.locals[i].depth <= depth {
+                continue;
+            }
+            if self.compiler.locals[i].is_captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_pop();
+            }
+        }
+    }
+
+    /// Pops the innermost loop context and patches every `break` jump it
+    /// collected to the current instruction - called once a loop is done
+    /// compiling, after any exit-jump patching but before the loop
+    /// statement's own `end_scope` (if it has one) runs.
+    fn patch_breaks(&mut self) {
+        let loop_context = self
+            .compiler
+            .loops
+            .pop()
+            .expect("while_statement/for_statement always push one");
+        for break_jump in loop_context.break_jumps {
+            self.patch_jump(break_jump);
+        }
+    }
+
+    /// Patches every `continue` jump collected so far by `do_while_statement`
+    /// to the current instruction, without popping the loop context - called
+    /// once the condition it should land on is reached, before `patch_breaks`
+    /// runs at the very end of the loop.
+    fn patch_continues(&mut self) {
+        let continue_jumps = std::mem::take(
+            &mut self
+                .compiler
+                .loops
+                .last_mut()
+                .expect("do_while_statement always pushes one")
+                .continue_jumps,
+        );
+        for continue_jump in continue_jumps {
+            self.patch_jump(continue_jump);
+        }
+    }
+
+    fn return_statement(&mut self) {
+        if self.compiler.function_type == FunctionType::Script && !self.repl {
+            self.error("Can't return from top-level code.");
+        }
+
+        if self.match_token(TokenType::Semicolon) {
+            self.emit_return();
+        } else {
+            if self.compiler.function_type == FunctionType::Initializer {
+                self.error("Can't return a value from an initializer.");
+            }
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+            self.emit_op(OpCode::Return);
+        }
+    }
+
+    fn throw_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.");
+        self.emit_op(OpCode::Throw);
+    }
+
+    fn try_statement(&mut self) {
+        let push_try = self.emit_jump(OpCode::PushTry);
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.");
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        self.emit_op(OpCode::PopTry);
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        // `PushTry` jumps here on a `throw`, with the thrown value already
+        // sitting where the catch variable's local slot expects it — the
+        // same convention a call uses to hand arguments to a function.
+        self.patch_jump(push_try);
+        self.consume(TokenType::Catch, "Expect 'catch' after 'try' block.");
+        self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.");
+        self.begin_scope();
+        let exception = self.parse_variable("Expect exception variable name.");
+        self.define_variable(exception);
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after exception variable.",
+        );
+        self.consume(TokenType::LeftBrace, "Expect '{' before catch body.");
+        self.block();
+        self.end_scope();
+
+        self.patch_jump(end_jump);
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        if self.repl && self.compiler.function_type == FunctionType::Script {
+            self.emit_op(OpCode::Print);
+        } else {
+            self.emit_pop();
+        }
+    }
+
+    fn variable(&mut self, can_assign: bool) {
+        self.named_variable(self.previous, can_assign);
+    }
+
+    fn named_variable(&mut self, token: Token, can_assign: bool) {
+        let (get_op, set_op, arg);
+        if let Some(slot) = self.resolve_local(token) {
+            set_op = OpCode::SetLocal;
+            get_op = OpCode::GetLocal;
+            arg = slot;
+        } else if let Some(slot) = self.resolve_upvalue(token) {
+            set_op = OpCode::SetUpvalue;
+            get_op = OpCode::GetUpvalue;
+            arg = slot;
+        } else {
+            set_op = OpCode::SetGlobal;
+            get_op = OpCode::GetGlobal;
+            arg = self.identifier_constant(token);
+        }
+
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.expression();
+            self.emit_op1(set_op, arg);
+        } else if can_assign && self.check_compound_assign() {
+            let op = self.match_compound_assign();
+            self.emit_op1(get_op, arg);
+            self.expression();
+            self.emit_op(op);
+            self.emit_op1(set_op, arg);
+        } else {
+            self.emit_op1(get_op, arg);
+        }
+    }
+
+    /// True if the current token is one of `+=`/`-=`/`*=`/`/=`/`%=`, without
+    /// consuming it - checked before committing to the compound-assignment
+    /// branch in `named_variable`/`dot` the same way `match_token(Equal)` is
+    /// checked for plain assignment.
+    fn check_compound_assign(&self) -> bool {
+        matches!(
+            self.current.token_type,
+            TokenType::PlusEqual
+                | TokenType::MinusEqual
+                | TokenType::StarEqual
+                | TokenType::SlashEqual
+                | TokenType::RemEqual
+        )
+    }
+
+    /// Consumes the current compound-assignment operator token and returns
+    /// the arithmetic `OpCode` it desugars to - call only after
+    /// `check_compound_assign` confirms there is one.
+    fn match_compound_assign(&mut self) -> OpCode {
+        let op = match self.current.token_type {
+            TokenType::PlusEqual => OpCode::Add,
+            TokenType::MinusEqual => OpCode::Sub,
+            TokenType::StarEqual => OpCode::Mul,
+            TokenType::SlashEqual => OpCode::Div,
+            TokenType::RemEqual => OpCode::Mod,
+            _ => unreachable!("caller must check check_compound_assign first"),
+        };
+        self.advance();
+        op
+    }
+
+    fn method(&mut self) {
+        self.consume(TokenType::Identifier, "Expect method name.");
+        let constant = self.identifier_constant(self.previous);
+        let ftype = if self.previous.lexeme == "init" {
+            FunctionType::Initializer
+        } else {
+            FunctionType::Method
+        };
+
+        self.function(ftype);
+        self.emit_op1(OpCode::Method, constant);
+    }
+
+    fn expression(&mut self) {
+        self.parse_precedence(Precedence::Assignment);
+    }
+
+    fn number(&mut self, _can_assign: bool) {
+        let lexeme = self.previous.lexeme;
+        if lexeme.contains('.') {
+            match lexeme.parse::<f64>() {
+                Ok(value) => self.emit_constant(Value::Number(value)),
+                Err(_) => self.error_at_current("Expect number when converting string to number."),
+            }
+        } else if let Ok(value) = lexeme.parse::<i64>() {
+            self.emit_constant(Value::Int(value));
+        } else {
+            match lexeme.parse::<f64>() {
+                Ok(value) => self.emit_constant(Value::Number(value)),
+                Err(_) => self.error_at_current("Expect number when converting string to number."),
+            }
+        }
+    }
+
+    /// `3i`/`2.5i` — an `Imaginary` token's lexeme keeps the trailing `i`, so
+    /// it's trimmed before parsing the magnitude as the imaginary part of a
+    /// purely-imaginary `Value::Complex`.
+    fn imaginary(&mut self, _can_assign: bool) {
+        let lexeme = self.previous.lexeme.trim_end_matches('i');
+        match lexeme.parse::<f64>() {
+            Ok(value) => self.emit_constant(Value::Complex(0.0, value)),
+            Err(_) => self.error_at_current("Expect number when converting string to number."),
+        }
+    }
+
+    fn grouping(&mut self, _can_assign: bool) {
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after expression.");
+    }
+
+    fn unary(&mut self, _can_assign: bool) {
+        let op_type = self.previous.token_type;
+        self.parse_precedence(Precedence::Unary);
+        match op_type {
+            TokenType::Minus => self.emit_op(OpCode::Negate),
+            TokenType::Bang => self.emit_op(OpCode::Not),
+            _ => (), // Unreachable.
+        }
+    }
+
+    fn binary(&mut self, _can_assign: bool) {
+        let op_type = self.previous.token_type;
+        let rule = self.get_rule(&op_type).clone();
+        self.parse_precedence(rule.precedence.next());
+        match op_type {
+            TokenType::Plus => self.emit_op(OpCode::Add),
+            TokenType::Minus => self.emit_op(OpCode::Sub),
+            TokenType::Rem => self.emit_op(OpCode::Mod),
+            TokenType::Backslash => self.emit_op(OpCode::IntDiv),
+            TokenType::StarStar => self.emit_op(OpCode::Pow),
+            TokenType::Amp => self.emit_op(OpCode::BitAnd),
+            TokenType::Pipe => self.emit_op(OpCode::BitOr),
+            TokenType::Caret => self.emit_op(OpCode::BitXor),
+            TokenType::LessLess => self.emit_op(OpCode::Shl),
+            TokenType::GreaterGreater => self.emit_op(OpCode::Shr),
+            TokenType::Star => self.emit_op(OpCode::Mul),
+            TokenType::Slash => self.emit_op(OpCode::Div),
+            TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+            TokenType::BangEqual => self.emit_op(OpCode::NotEqual),
+            TokenType::Greater => self.emit_op(OpCode::Greater),
+            TokenType::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+            TokenType::Less => self.emit_op(OpCode::Less),
+            TokenType::LessEqual => self.emit_op(OpCode::LessEqual),
+            _ => (), // Unreachable.
+        }
+    }
+
+    /// `x |> f` applies `f` to `x`; `arr |: f` and `arr |? pred` desugar to
+    /// the `map`/`filter` array methods `Invoke` already runs, so a non-array
+    /// left operand surfaces the same runtime error those methods give any
+    /// other receiver. `|>` instead wants `f` as the callee with `x` as its
+    /// sole argument, the reverse of the `[x, f]` order parsing leaves on the
+    /// stack, so it swaps the two before emitting an ordinary `Call`.
+    fn pipe(&mut self, _can_assign: bool) {
+        let op_type = self.previous.token_type;
+        let rule = self.get_rule(&op_type).clone();
+        self.parse_precedence(rule.precedence.next());
+        match op_type {
+            TokenType::PipeGreater => {
+                self.emit_op(OpCode::Swap);
+                self.emit_op1(OpCode::Call, 1);
+            }
+            TokenType::PipeColon => {
+                let name = self.gc.intern("map".to_owned());
+                let name = self.make_constant(Value::VString(name));
+                self.emit_op2(OpCode::Invoke, name, 1);
+            }
+            TokenType::PipeQuestion => {
+                let name = self.gc.intern("filter".to_owned());
+                let name = self.make_constant(Value::VString(name));
+                self.emit_op2(OpCode::Invoke, name, 1);
+            }
+            _ => (), // Unreachable.
+        }
+    }
+
+    fn literal(&mut self, _can_assign: bool) {
+        match self.previous.token_type {
+            TokenType::False => self.emit_op(OpCode::False),
+            TokenType::True => self.emit_op(OpCode::True),
+            TokenType::Nil => self.emit_op(OpCode::Nil),
+            _ => (), // Unreachable.
+        }
+    }
+
+    fn string(&mut self, _can_assign: bool) {
+        let lexeme = self.previous.lexeme;
+        let value = &lexeme[1..lexeme.chars().count() - 1];
+        let string = self.gc.intern(value.to_string());
+        self.emit_constant(Value::VString(string));
+    }
+
+    fn and_op(&mut self, _can_assign: bool) {
+        let end = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end);
+    }
+
+    fn or_op(&mut self, _can_assign: bool) {
+        let end = self.emit_jump(OpCode::JumpIfTrue);
+        self.emit_pop();
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end);
+    }
+
+    fn conditional(&mut self, _can_assign: bool) {
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_pop();
+        self.parse_precedence(Precedence::Conditional);
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.",
+        );
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit_pop();
+        self.parse_precedence(Precedence::Conditional);
+        self.patch_jump(else_jump);
+    }
+
+    fn call(&mut self, _can_assign: bool) {
+        let arg_count = self.argument_list();
+        self.emit_op1(OpCode::Call, arg_count);
+    }
+
+    fn dot(&mut self, can_assign: bool) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+        let name = self.identifier_constant(self.previous);
+        if can_assign && self.match_token(TokenType::Equal) {
+            self.expression();
+            self.emit_op1(OpCode::SetProperty, name);
+        } else if can_assign && self.check_compound_assign() {
+            let op = self.match_compound_assign();
+            // The receiver is only on the stack once (from the expression
+            // before the `.`), but `GetProperty` consumes it - duplicate it
+            // first so the `SetProperty` below still has one to write to.
+            self.emit_op(OpCode::Dup);
+            self.emit_op1(OpCode::GetProperty, name);
+            self.expression();
+            self.emit_op(op);
+            self.emit_op1(OpCode::SetProperty, name);
+        } else if self.match_token(TokenType::LeftParen) {
+            let arg_count = self.argument_list();
+            self.emit_op2(OpCode::Invoke, name, arg_count);
+        } else {
+            self.emit_op1(OpCode::GetProperty, name);
+        }
+    }
+
+    fn this(&mut self, _can_assign: bool) {
+        if self.class_compiler.is_none() {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+        self.variable(false);
+    }
+
+    fn super_(&mut self, _can_assign: bool) {
+        if let Some(current_class) = self.class_compiler.as_ref() {
+            if !current_class.has_superclass {
+                self.error("Can't use 'super' in a class with no superclass.");
+            }
+        } else {
+            self.error("Can't use 'super' outside of a class.");
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.");
+        self.consume(TokenType::Identifier, "Expect superclass method name.");
+        let name = self.identifier_constant(self.previous);
+        self.named_variable(Token::syntethic("this"), false);
+        if self.match_token(TokenType::LeftParen) {
+            let arg_count = self.argument_list();
+            self.named_variable(Token::syntethic("super"), false);
+            self.emit_op2(OpCode::SuperInvoke, name, arg_count);
+        } else {
+            self.named_variable(Token::syntethic("super"), false);
+            self.emit_op1(OpCode::GetSuper, name);
+        }
+    }
+
+    // helpers
+
+    fn match_token(&mut self, ttype: TokenType) -> bool {
+        if !self.check(ttype) {
+            false
+        } else {
+            self.advance();
+            true
+        }
+    }
+
+    #[inline]
+    fn check(&self, ttype: TokenType) -> bool {
+        self.current.token_type == ttype
+    }
+
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+        while self.current.token_type != TokenType::Eof {
+            if self.previous.token_type == TokenType::Semicolon {
+                return;
+            } else {
+                match self.current.token_type {
+                    TokenType::Class
+                    | TokenType::Fun
+                    | TokenType::Var
+                    | TokenType::For
+                    | TokenType::If
+                    | TokenType::While
+                    | TokenType::Loop
+                    | TokenType::Do
+                    | TokenType::Print
+                    | TokenType::Return
+                    | TokenType::Try
+                    | TokenType::Throw => return,
+                    _ => (),
+                }
+                self.advance();
+            }
+        }
+    }
+
+    fn parse_variable(&mut self, message: &str) -> usize {
+        self.consume(TokenType::Identifier, message);
+
+        self.declare_varible();
+        if self.compiler.scope_depth > 0 {
+            return 0;
+        }
+
+        self.identifier_constant(self.previous)
+    }
+
+    fn define_variable(&mut self, var: usize) {
+        if self.compiler.scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+        self.emit_op1(OpCode::DefineGlobal, var)
+    }
+
+    fn declare_varible(&mut self) {
+        if self.compiler.scope_depth == 0 {
+            return;
+        }
+
+        let name = self.previous;
+        if self.compiler.is_local_defined(name) {
+            self.error("Already a variable with this name in this scope.");
+        }
+        self.add_local(name);
+    }
+
+    fn add_local(&mut self, name: Token<'s>) {
+        if self.compiler.locals.len() >= self.limits.max_locals {
+            self.error("Too many local variables in this function.");
+        }
+        self.compiler.locals.push(Local {
+            name,
+            depth: -1,
+            is_captured: false,
+        });
+    }
+
+    fn identifier_constant(&mut self, token: Token) -> usize {
+        let string = self.gc.intern(token.lexeme.to_string());
+        self.make_constant(Value::VString(string))
+    }
+
+    fn mark_initialized(&mut self) {
+        if self.compiler.scope_depth == 0 {
+            return;
+        }
+        let i = self.compiler.locals.len() - 1;
+        self.compiler.locals[i].depth = self.compiler.scope_depth;
+    }
+
+    fn patch_jump(&mut self, placeholder: usize) {
+        let result = self
+            .compiler
+            .function
+            .chunk
+            .patch_jump(placeholder, self.limits.max_chunk_size);
+        if let Err(message) = result {
+            self.error(&message);
+        }
+    }
+
+    fn start_loop(&self) -> usize {
+        self.compiler.function.chunk.code.len()
+    }
+
+    fn function(&mut self, function_type: FunctionType) {
+        self.push_compiler(function_type);
+        if self.compiler.depth > self.limits.max_depth {
+            // Skip the over-deep function without recursing into its body.
+            let mut braces = 0;
+            while !self.check(TokenType::Eof) {
+                if self.match_token(TokenType::LeftBrace) {
+                    braces += 1;
+                } else if self.match_token(TokenType::RightBrace) {
+                    braces -= 1;
+                    if braces == 0 {
+                        break;
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            self.pop_compiler();
+            return;
+        }
+        self.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
+
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if self.compiler.function.has_rest {
+                    self.error_at_current("Can't have parameters after a rest parameter.");
+                }
+                let total_params = self.compiler.function.arity
+                    + self.compiler.function.defaults.len()
+                    + self.compiler.function.has_rest as usize;
+                if total_params >= 255 {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+
+                if self.match_token(TokenType::DotDotDot) {
+                    let constant = self.parse_variable("Expect rest parameter name.");
+                    self.define_variable(constant);
+                    self.compiler.function.has_rest = true;
+                } else {
+                    let constant = self.parse_variable("Expect parameter name.");
+                    if self.match_token(TokenType::Equal) {
+                        let default = self.default_value();
+                        self.define_variable(constant);
+                        self.compiler.function.defaults.push(default);
+                    } else {
+                        if !self.compiler.function.defaults.is_empty() {
+                            self.error("A required parameter can't follow a default parameter.");
+                        }
+                        self.define_variable(constant);
+                        self.compiler.function.arity += 1;
+                    }
+                }
+
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
+        self.block();
+        let function = self.pop_compiler();
+        let fn_id = self.gc.alloc(function);
+        let index = self.make_constant(Value::Function(fn_id));
+        self.emit_op1(OpCode::Closure, index);
+    }
+
+    /// A parameter's `= expr` default. Kept to literal constants and folded
+    /// straight into [`Function::defaults`] at compile time rather than
+    /// compiled to bytecode, since the only thing that ever happens with it
+    /// is being pushed as-is onto the stack when a caller omits the
+    /// argument - see `Vm::call`.
+    fn default_value(&mut self) -> Value {
+        let negative = self.match_token(TokenType::Minus);
+        self.advance();
+        match self.previous.token_type {
+            TokenType::Number => {
+                let lexeme = self.previous.lexeme;
+                if lexeme.contains('.') {
+                    match lexeme.parse::<f64>() {
+                        Ok(value) => Value::Number(if negative { -value } else { value }),
+                        Err(_) => {
+                            self.error("Expect number when converting string to number.");
+                            Value::Nil
+                        }
+                    }
+                } else {
+                    match lexeme.parse::<i64>() {
+                        Ok(value) => Value::Int(if negative { -value } else { value }),
+                        Err(_) => match lexeme.parse::<f64>() {
+                            Ok(value) => Value::Number(if negative { -value } else { value }),
+                            Err(_) => {
+                                self.error("Expect number when converting string to number.");
+                                Value::Nil
+                            }
+                        },
+                    }
+                }
+            }
+            TokenType::RString => {
+                let lexeme = self.previous.lexeme;
+                let value = &lexeme[1..lexeme.chars().count() - 1];
+                Value::VString(self.gc.intern(value.to_string()))
+            }
+            TokenType::True => Value::Bool(true),
+            TokenType::False => Value::Bool(false),
+            TokenType::Nil => Value::Nil,
+            _ => {
+                self.error("Default value must be a constant literal.");
+                Value::Nil
+            }
+        }
+    }
+
+    fn push_compiler(&mut self, function_type: FunctionType) {
+        let depth = self.compiler.depth + 1;
+        if depth > self.limits.max_depth {
+            self.error("Too many nested function definitions.");
+        }
+        let name = self.gc.intern(self.previous.lexeme.to_owned());
+        let new_compiler = Compiler::new(name, function_type, depth);
+        let old_compiler = mem::replace(&mut self.compiler, new_compiler);
+        self.compiler.enclosing = Some(old_compiler);
+    }
+
+    fn pop_compiler(&mut self) -> Function {
+        self.emit_return();
+        match self.compiler.enclosing.take() {
+            Some(enclosing) => {
+                let compiler = mem::replace(&mut self.compiler, enclosing);
+                compiler.function
+            }
+            None => {
+                self.error("Internal compiler error: no enclosing compiler to return to.");
+                self.compiler.function.clone()
+            }
+        }
+    }
+
+    fn argument_list(&mut self) -> usize {
+        let mut arg_count = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression();
+                if arg_count == 255 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                arg_count += 1;
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        arg_count
+    }
+
+    fn resolve_local(&mut self, name: Token) -> Option<usize> {
+        let mut errors: Vec<&str> = Vec::new();
+        let result = self.compiler.resolve_local(name, &mut errors);
+        while let Some(err) = errors.pop() {
+            self.error(err);
+        }
+        result
+    }
+
+    fn resolve_upvalue(&mut self, name: Token) -> Option<usize> {
+        let mut errors: Vec<&str> = Vec::new();
+        let result = self
+            .compiler
+            .resolve_upvalue(name, self.limits.max_upvalues, &mut errors);
+        while let Some(err) = errors.pop() {
+            self.error(err);
+        }
+        result
+    }
+
+    fn get_rule(&self, key: &TokenType) -> &ParseRule<'s> {
+        self.parse_rules.get(key).unwrap()
+    }
+
+    // chunk manipulation
+
+    fn emit_op(&mut self, opcode: OpCode) {
+        self.compiler
+            .function
+            .chunk
+            .write(opcode, self.previous.line);
+    }
+
+    fn emit_op1(&mut self, opcode: OpCode, operand: usize) {
+        self.compiler
+            .function
+            .chunk
+            .write_operand(opcode, operand, self.previous.line);
+    }
+
+    fn emit_op2(&mut self, opcode: OpCode, a: usize, b: usize) {
+        self.compiler
+            .function
+            .chunk
+            .write_operands(opcode, a, b, self.previous.line);
+    }
+
+    fn emit_return(&mut self) {
+        if self.compiler.function_type == FunctionType::Initializer {
+            self.emit_op1(OpCode::GetLocal, 0);
+            self.emit_op(OpCode::Return);
+        } else {
+            self.emit_op(OpCode::ReturnNil);
+        }
+    }
+
+    fn emit_constant(&mut self, constant: Value) {
+        let index = self.make_constant(constant);
+        self.emit_op1(OpCode::Constant, index);
+    }
+
+    /// `Chunk::add_constant` already interns by value (and by `GcRef`
+    /// identity for `VString`), so a repeated literal or `identifier_constant`
+    /// call reuses its existing slot instead of growing the pool - nothing
+    /// further to dedup here; see
+    /// `make_constant_dedups_a_repeated_literal` in `tests/integration.rs`.
+    fn make_constant(&mut self, constant: Value) -> usize {
+        let index = self.compiler.function.chunk.add_constant(constant);
+        if index >= self.limits.max_constants {
+            self.error("Too many constants in one chunk.");
+        }
+        index
+    }
+
+    fn emit_jump(&mut self, opcode: OpCode) -> usize {
+        self.compiler
+            .function
+            .chunk
+            .write_jump(opcode, self.previous.line)
+    }
+
+    fn emit_loop(&mut self, start: usize) {
+        let result =
+            self.compiler
+                .function
+                .chunk
+                .write_loop(start, self.previous.line, self.limits.max_chunk_size);
+        if let Err(message) = result {
+            self.error(&message);
+        }
+    }
+
+    fn emit_pop(&mut self) {
+        self.emit_op(OpCode::Pop);
+    }
+
+    // error handling
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current, message);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error_at(self.previous, message);
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+
+        self.panic_mode = true;
+
+        let message = if token.token_type == TokenType::Eof {
+            format!("at end: {}", message)
+        } else if token.token_type == TokenType::Error {
+            message.to_owned()
+        } else {
+            format!("at '{}': {}", token.lexeme, message)
+        };
+
+        self.diagnostics.push(Diagnostic {
+            span: token.position,
+            message,
+            severity: Severity::Error,
+        });
+    }
+}
+
+/// Resource bounds the `Parser`/`Compiler` enforce while compiling, so
+/// embedding rlox in a server or REPL can cap how much a single untrusted
+/// compile is allowed to cost instead of trusting the input to stay small.
+/// Every limit below used to be either absent or a hard `panic!` (see
+/// `chunk.rs`'s old `patch_jump`/`write_loop` and `pop_compiler`); they now
+/// report a `Diagnostic` through the usual `error`/`error_at` path instead.
+#[derive(Clone, Copy, Debug)]
+pub struct CompilerLimits {
+    /// How deeply `fn` declarations may nest before `push_compiler` refuses
+    /// to go further - bounds the recursive-descent parser's own call stack.
+    pub max_depth: usize,
+    /// Local variables (including parameters) live per function.
+    pub max_locals: usize,
+    /// Variables a single closure may capture from enclosing scopes.
+    pub max_upvalues: usize,
+    /// Distinct constants a single function's `Chunk` may hold.
+    pub max_constants: usize,
+    /// Bytes a forward (`Jump`/`JumpIfFalse`/`JumpIfTrue`) or backward
+    /// (`Loop`) branch may span; `OperandShape::Jump` encodes the distance
+    /// in two bytes, so this can tighten but never loosen past `u16::MAX`.
+    pub max_chunk_size: usize,
+}
+
+impl Default for CompilerLimits {
+    fn default() -> Self {
+        CompilerLimits {
+            max_depth: 255,
+            max_locals: 1 << 16,
+            max_upvalues: 1 << 16,
+            max_constants: 1 << 16,
+            max_chunk_size: u16::MAX as usize,
+        }
+    }
+}
+
+pub fn compile(code: &str, gc: &mut Gc) -> Result<GcRef<Function>, InterpretError> {
+    compile_with_limits(code, gc, CompilerLimits::default())
+}
+
+/// Like [`compile`], but enforcing caller-chosen [`CompilerLimits`] instead
+/// of the defaults - for a host that wants to bound compilation of
+/// untrusted source.
+pub fn compile_with_limits(
+    code: &str,
+    gc: &mut Gc,
+    limits: CompilerLimits,
+) -> Result<GcRef<Function>, InterpretError> {
+    let parser = Parser::new_with_limits(code, gc, false, limits);
+    parser.compile()
+}
+
+/// Like [`compile`], but in REPL mode: a bare top-level expression echoes its
+/// value via `OpCode::Print` instead of being discarded, and a top-level
+/// `return` is allowed instead of reported as a compile error. Each call
+/// still allocates its own `script` [`Function`] and `Compiler`, so only
+/// state that outlives a single call - `gc`'s interned strings and, for a
+/// host loop sharing one `Vm`, its `globals` table - carries over between
+/// successive lines; `DefineGlobal` writes straight into that table, which
+/// is what makes a `var` from one line visible to the next.
+pub fn compile_repl(code: &str, gc: &mut Gc) -> Result<GcRef<Function>, InterpretError> {
+    let parser = Parser::new_repl(code, gc);
+    parser.compile()
+}
+
+struct Compiler<'a> {
+    enclosing: Option<Box<Compiler<'a>>>,
+    scope_depth: isize,
+    /// How many enclosing functions this one is nested inside; 0 for the
+    /// top-level script. Checked against [`CompilerLimits::max_depth`] in
+    /// [`Parser::push_compiler`] so a maliciously deep chain of nested `fn`
+    /// declarations reports a `Diagnostic` instead of overflowing the Rust
+    /// call stack the recursive-descent parser runs on.
+    depth: usize,
+    locals: Vec<Local<'a>>,
+    function: Function,
+    function_type: FunctionType,
+    /// Stack of enclosing `while`/`for`/`loop`/`do` loops, innermost last, so `break`/
+    /// `continue` know where to jump; empty outside any loop.
+    loops: Vec<LoopContext>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(name: GcRef<String>, function_type: FunctionType, depth: usize) -> Box<Self> {
+        let mut compiler = Compiler {
+            enclosing: None,
+            scope_depth: 0,
+            depth,
+            locals: Vec::new(),
+            function: Function {
+                arity: 0,
+                defaults: Vec::new(),
+                has_rest: false,
+                chunk: Chunk::new(),
+                name,
+                upvalues: Vec::new(),
+            },
+            function_type,
+            loops: Vec::new(),
+        };
+        let token = match function_type {
+            FunctionType::Method | FunctionType::Initializer => Local {
+                name: Token::syntethic("this"),
+                depth: 0,
+                is_captured: false,
+            },
+            _ => Local {
+                name: Token::syntethic(""),
+                depth: 0,
+                is_captured: false,
+            },
+        };
+        compiler.locals.push(token);
+        Box::new(compiler)
+    }
+
+    fn is_local_defined(&self, name: Token) -> bool {
+        for local in self.locals.iter().rev() {
+            if local.depth != -1 && local.depth < self.scope_depth {
+                return false;
+            }
+            if local.name.lexeme == name.lexeme {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn resolve_local(&mut self, name: Token, errors: &mut Vec<&str>) -> Option<usize> {
+        for (i, local) in self.locals.iter().enumerate().rev() {
+            if name.lexeme == local.name.lexeme {
+                if local.depth == -1 {
+                    errors.push("Can't read local variable in its own initializer.");
+                }
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn resolve_upvalue(
+        &mut self,
+        name: Token,
+        max_upvalues: usize,
+        errors: &mut Vec<&str>,
+    ) -> Option<usize> {
+        if let Some(env) = self.enclosing.as_mut() {
+            if let Some(index) = env.resolve_local(name, errors) {
+                env.locals[index].is_captured = true;
+                return Some(self.add_upvalue(index, true, max_upvalues, errors));
+            } else if let Some(index) = env.resolve_upvalue(name, max_upvalues, errors) {
+                return Some(self.add_upvalue(index, false, max_upvalues, errors));
+            }
+        }
+        None
+    }
+
+    fn add_upvalue(
+        &mut self,
+        index: usize,
+        is_local: bool,
+        max_upvalues: usize,
+        errors: &mut Vec<&str>,
+    ) -> usize {
+        for (i, upvalue) in self.function.upvalues.iter().enumerate() {
+            if upvalue.index == index && is_local == upvalue.is_local {
+                return i;
+            }
+        }
+        if self.function.upvalues.len() >= max_upvalues {
+            errors.push("Too many closure variables captured in this function.");
+        }
+        let upvalue = FunctionUpvalue { index, is_local };
+        self.function.upvalues.push(upvalue);
+        self.function.upvalues.len() - 1
+    }
+}
+
+struct Local<'a> {
+    name: Token<'a>,
+    depth: isize,
+    is_captured: bool,
+}
+
+/// One entry per enclosing `while`/`for`/`loop`/`do` loop currently being compiled; see
+/// `Compiler::loops`.
+struct LoopContext {
+    /// Where `continue` jumps back to via `emit_loop` - the condition for a
+    /// `while`, the increment clause (if present) for a `for`, the top of the
+    /// body for a `loop`. Unused when `forward_continue` is set.
+    continue_target: usize,
+    /// When set, `continue` can't jump backward to a known offset yet - as in
+    /// `do`/`while`, whose condition is compiled after the body - so it emits
+    /// a forward `Jump` collected in `continue_jumps` instead.
+    forward_continue: bool,
+    /// Offsets of `continue`'s `Jump` placeholders when `forward_continue` is
+    /// set, patched by `patch_continues` once the condition they should land
+    /// on is reached.
+    continue_jumps: Vec<usize>,
+    /// `scope_depth` when the loop was entered; `break`/`continue` clean up
+    /// every local declared deeper than this.
+    scope_depth: isize,
+    /// Offsets of `break`'s `Jump` placeholders, patched to the loop's exit
+    /// once it's done compiling.
+    break_jumps: Vec<usize>,
+}
+
+// impl<'a> Local<'a> {
+//     fn new(name: Token<'a>, depth: isize) -> Self {
+//         Self {
+//             name,
+//             depth,
+//             is_captured: false,
+//         }
+//     }
+// }
+
+struct ClassCompiler {
+    enclosing: Option<Box<ClassCompiler>>,
+    has_superclass: bool,
+}
+
+// impl ClassCompiler {
+//     fn new(enclosing: Option<Box<ClassCompiler>>) -> Box<Self> {
+//         Box::new(Self {
+//             enclosing,
+//             has_superclass: false,
+//         })
+//     }
+// }
@@ -1,22 +1,40 @@
 use crate::types::{Table, Value};
 use std::{
     any::{type_name, Any},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt, hash,
     marker::PhantomData,
     mem,
 };
 
+/// The two generations an object can live in. Young objects are scanned on
+/// every minor collection; once one survives `Gc::PROMOTION_THRESHOLD` of
+/// those it is promoted to `Tenured` and only revisited by a major collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generation {
+    Young,
+    Tenured,
+}
+
 pub trait GcTrace {
     fn format(&self, f: &mut fmt::Formatter, gc: &Gc) -> fmt::Result;
     fn trace(&self, gc: &mut Gc);
     fn size(&self) -> usize;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Runs once, right before `free` drops this object's slot. Override to
+    /// release a non-GC resource (an open file, a socket, ...) deterministically
+    /// at sweep time instead of relying on its own `Drop` running whenever the
+    /// `Box<dyn GcTrace>` happens to be dropped. The default no-op is correct
+    /// for every heap type that only owns other GC references.
+    fn finalize(&mut self, _gc: &mut Gc) {}
 }
 
 pub struct GcObject {
     is_marked: bool,
+    generation: Generation,
+    survived: u8,
     size: usize,
     pub object: Box<dyn GcTrace>,
 }
@@ -26,6 +44,13 @@ pub struct GcRef<T: GcTrace> {
     _marker: PhantomData<T>,
 }
 
+impl<T: GcTrace> GcRef<T> {
+    #[inline]
+    pub(crate) fn raw_index(&self) -> usize {
+        self.index
+    }
+}
+
 impl<T: GcTrace> Clone for GcRef<T> {
     #[inline]
     fn clone(&self) -> GcRef<T> {
@@ -59,6 +84,73 @@ impl hash::Hash for GcRef<String> {
     }
 }
 
+/// A handle to a heap object that does not keep it alive. Generalizes the
+/// pattern `remove_white_strings` already applies to the intern table: obtain
+/// one via `Gc::weak_ref`, then call `deref` each time you need the value —
+/// it returns `None` once the collector has reclaimed the slot.
+pub struct WeakRef<T: GcTrace> {
+    index: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: GcTrace> WeakRef<T> {
+    #[inline]
+    pub fn raw_index(&self) -> usize {
+        self.index
+    }
+
+    #[inline]
+    pub fn deref<'a>(&self, gc: &'a Gc) -> Option<&'a T>
+    where
+        T: 'static,
+    {
+        if !gc.weak_refs.contains(&self.index) {
+            return None;
+        }
+        gc.objects[self.index]
+            .as_ref()?
+            .object
+            .as_any()
+            .downcast_ref()
+    }
+}
+
+impl<T: GcTrace> Clone for WeakRef<T> {
+    #[inline]
+    fn clone(&self) -> WeakRef<T> {
+        *self
+    }
+}
+
+impl<T: GcTrace> Copy for WeakRef<T> {}
+impl<T: GcTrace> Eq for WeakRef<T> {}
+
+impl<T: GcTrace> fmt::Debug for WeakRef<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full_name = type_name::<T>();
+        full_name.split("::").last().unwrap();
+        write!(f, "weak({}: {})", self.index, full_name)
+    }
+}
+
+impl<T: GcTrace> PartialEq for WeakRef<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+/// Tracks where an incremental collection cycle currently stands. `Idle` means
+/// there is nothing to do between cycles; `Marking`/`Sweeping` mirror the two
+/// halves of `collect_garbage`, just spread across many `incremental_step` calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcPhase {
+    Idle,
+    Marking,
+    Sweeping,
+}
+
 pub struct Gc {
     bytes_allocated: usize,
     next_gc: usize,
@@ -66,11 +158,32 @@ pub struct Gc {
     pub objects: Vec<Option<GcObject>>,
     strings: HashMap<String, GcRef<String>>,
     free_slots: Vec<usize>,
+    phase: GcPhase,
+    sweep_cursor: usize,
+    // Generational bookkeeping: `nursery` lists the indices currently young
+    // (so a minor collection never has to walk the whole `objects` vector),
+    // `nursery_bytes` is the live size of those objects, `remembered_set` is
+    // every tenured index a write barrier caught pointing at a young object
+    // (minor roots beyond the VM's own roots), and `in_minor_gc` redirects
+    // `mark_object` into nursery-only tracing for the duration of one.
+    nursery: Vec<usize>,
+    nursery_bytes: usize,
+    remembered_set: HashSet<usize>,
+    nursery_grey: Vec<usize>,
+    in_minor_gc: bool,
+    // Slots with at least one outstanding `WeakRef` pointing at them, mirroring
+    // the dedicated handling `strings` already gets from `remove_white_strings`.
+    // `free` removes an index from here the moment it reclaims that slot, so a
+    // `WeakRef::deref` after that point sees the object as gone without keeping
+    // it reachable itself.
+    weak_refs: HashSet<usize>,
 }
 
 impl Gc {
     const NEXT_GC: usize = 1024 * 1024;
     const GROW_FACTOR: usize = 2;
+    const NURSERY_THRESHOLD: usize = 64 * 1024;
+    const PROMOTION_THRESHOLD: u8 = 2;
 
     #[inline]
     pub fn new() -> Self {
@@ -81,9 +194,29 @@ impl Gc {
             objects: Vec::new(),
             strings: HashMap::new(),
             free_slots: Vec::new(),
+            phase: GcPhase::Idle,
+            sweep_cursor: 0,
+            nursery: Vec::new(),
+            nursery_bytes: 0,
+            remembered_set: HashSet::new(),
+            nursery_grey: Vec::new(),
+            in_minor_gc: false,
+            weak_refs: HashSet::new(),
         }
     }
 
+    #[inline]
+    pub fn phase(&self) -> GcPhase {
+        self.phase
+    }
+
+    /// Moves an idle collector into `Marking`. The caller (the VM) is expected
+    /// to have already pushed the roots onto `grey_stack` via `mark_value`/
+    /// `mark_object` before calling this.
+    pub fn begin_cycle(&mut self) {
+        self.phase = GcPhase::Marking;
+    }
+
     #[cfg(not(feature = "debug_gc_stress"))]
     #[inline]
     pub fn should_gc(&self) -> bool {
@@ -96,17 +229,31 @@ impl Gc {
         true
     }
 
+    #[inline]
+    pub fn should_minor_gc(&self) -> bool {
+        self.nursery_bytes > Self::NURSERY_THRESHOLD
+    }
+
     pub fn alloc<T: GcTrace + 'static + fmt::Debug>(&mut self, object: T) -> GcRef<T> {
         #[cfg(feature = "debug_gc_log")]
         let repr = format!("{:?}", object);
 
+        let size = object.size() + mem::size_of::<GcObject>();
+
         let entry = GcObject {
-            is_marked: false,
-            size: object.size() + mem::size_of::<GcObject>(),
+            // An object born mid-cycle must be black already: the sweeper
+            // cannot tell it apart from one the marker simply hasn't reached
+            // yet, so leaving it white would let `sweep`/`incremental_step`
+            // reclaim a value the mutator is still holding onto.
+            is_marked: self.phase != GcPhase::Idle,
+            generation: Generation::Young,
+            survived: 0,
+            size,
             object: Box::new(object),
         };
 
-        self.bytes_allocated += entry.size;
+        self.bytes_allocated += size;
+        self.nursery_bytes += size;
 
         let index = match self.free_slots.pop() {
             Some(i) => {
@@ -118,6 +265,7 @@ impl Gc {
                 self.objects.len() - 1
             }
         };
+        self.nursery.push(index);
 
         #[cfg(feature = "debug_gc_log")]
         eprintln!(
@@ -135,6 +283,17 @@ impl Gc {
         }
     }
 
+    /// Registers `gcref`'s slot in `weak_refs` and hands back a `WeakRef`
+    /// observing it; the target stays alive only as long as something else
+    /// still roots it.
+    pub fn weak_ref<T: GcTrace>(&mut self, gcref: GcRef<T>) -> WeakRef<T> {
+        self.weak_refs.insert(gcref.index);
+        WeakRef {
+            index: gcref.index,
+            _marker: PhantomData,
+        }
+    }
+
     pub fn intern(&mut self, string: String) -> GcRef<String> {
         if let Some(&string_ref) = self.strings.get(&string) {
             string_ref
@@ -168,14 +327,24 @@ impl Gc {
     fn free(&mut self, index: usize) {
         #[cfg(feature = "debug_gc_log")]
         eprintln!("free (id:{})", index,);
-        if let Some(old) = self.objects[index].take() {
+        if let Some(mut old) = self.objects[index].take() {
+            old.object.finalize(self);
+            self.weak_refs.remove(&index);
+
             self.bytes_allocated -= old.size;
+            if old.generation == Generation::Young {
+                self.nursery_bytes = self.nursery_bytes.saturating_sub(old.size);
+            }
             self.free_slots.push(index);
         } else {
             panic!("Double free on {}", index);
         }
     }
 
+    /// Full stop-the-world collection: the *major* collection, covering both
+    /// generations, in one call. `Vm` does not call this: its major cycle
+    /// runs through `begin_cycle`/`incremental_step` once `should_gc` sees
+    /// `bytes_allocated` (young and tenured together) over `next_gc`.
     pub fn collect_garbage(&mut self) {
         #[cfg(feature = "debug_gc_log")]
         let before = self.bytes_allocated;
@@ -183,6 +352,7 @@ impl Gc {
         self.trace_references();
         self.remove_white_strings();
         self.sweep();
+        self.remembered_set.clear();
 
         self.next_gc = self.bytes_allocated * Self::GROW_FACTOR;
 
@@ -196,6 +366,70 @@ impl Gc {
         );
     }
 
+    /// Moves the collector into minor-collection mode; the caller (the VM) is
+    /// expected to mark its roots next (which will route young roots onto
+    /// `nursery_grey` and skip tenured ones) and then call `minor_collect`.
+    pub fn begin_minor_cycle(&mut self) {
+        self.in_minor_gc = true;
+    }
+
+    /// Minor collection: traces only the nursery, seeded by whatever roots
+    /// `begin_minor_cycle` + root-marking already greyed plus every tenured
+    /// object in `remembered_set` (objects a write barrier caught storing a
+    /// fresh young reference), then sweeps just the nursery. Never walks the
+    /// tenured generation, so long-lived objects aren't rescanned.
+    pub fn minor_collect(&mut self) {
+        let remembered: Vec<usize> = self.remembered_set.iter().copied().collect();
+        for index in remembered {
+            if let Some(object) = self.objects[index].take() {
+                object.object.trace(self);
+                self.objects[index] = Some(object);
+            }
+        }
+
+        while let Some(index) = self.nursery_grey.pop() {
+            if let Some(object) = self.objects[index].take() {
+                object.object.trace(self);
+                self.objects[index] = Some(object);
+            }
+        }
+
+        self.minor_sweep();
+        self.in_minor_gc = false;
+    }
+
+    fn minor_sweep(&mut self) {
+        let nursery = mem::take(&mut self.nursery);
+        let mut survivors = Vec::with_capacity(nursery.len());
+        let mut nursery_bytes = 0;
+
+        for index in nursery {
+            let marked = match self.objects[index].as_ref() {
+                Some(obj) => obj.is_marked,
+                None => continue,
+            };
+
+            if !marked {
+                self.free(index);
+                continue;
+            }
+
+            let obj = self.objects[index].as_mut().unwrap();
+            obj.is_marked = false;
+            obj.survived += 1;
+
+            if obj.survived >= Self::PROMOTION_THRESHOLD {
+                obj.generation = Generation::Tenured;
+            } else {
+                nursery_bytes += obj.size;
+                survivors.push(index);
+            }
+        }
+
+        self.nursery = survivors;
+        self.nursery_bytes = nursery_bytes;
+    }
+
     #[inline]
     pub fn mark_value(&mut self, value: Value) {
         value.trace(self);
@@ -205,6 +439,16 @@ impl Gc {
         #[cfg(feature = "debug_gc_log")]
         eprintln!("marking {:?}", object);
 
+        // During a minor collection, a tenured object is assumed already live
+        // (its own outgoing edges were traced by the last major collection, or
+        // recorded in `remembered_set` if mutated since); only young objects
+        // need to be (re)greyed, and onto the nursery's own grey stack so a
+        // minor cycle never touches the major one's bookkeeping.
+        if self.in_minor_gc {
+            self.mark_young(object.index);
+            return;
+        }
+
         if let Some(obj) = self.objects[object.index].as_mut() {
             if obj.is_marked {
                 return;
@@ -228,6 +472,16 @@ impl Gc {
         }
     }
 
+    fn mark_young(&mut self, index: usize) {
+        if let Some(obj) = self.objects[index].as_mut() {
+            if obj.generation == Generation::Tenured || obj.is_marked {
+                return;
+            }
+            obj.is_marked = true;
+            self.nursery_grey.push(index);
+        }
+    }
+
     #[inline]
     pub fn mark_table(&mut self, table: &Table) {
         for (&k, &v) in table {
@@ -269,6 +523,89 @@ impl Gc {
         let objects = &self.objects;
         strings.retain(|_k, v| objects[v.index].as_ref().unwrap().is_marked);
     }
+
+    /// Drives one bounded slice of an incremental collection cycle, doing at
+    /// most `work_budget` units of marking or sweeping work. Meant to replace
+    /// `should_gc`/`collect_garbage` in the VM's hot loop so a single GC cycle
+    /// never produces an unbounded pause: the caller is expected to call
+    /// `begin_cycle` (after marking the roots) once `should_gc()` trips while
+    /// idle, then keep calling `incremental_step` until `phase()` is `Idle`
+    /// again.
+    pub fn incremental_step(&mut self, work_budget: usize) {
+        match self.phase {
+            GcPhase::Idle => (),
+            GcPhase::Marking => {
+                for _ in 0..work_budget {
+                    match self.grey_stack.pop() {
+                        Some(index) => self.blacken_object(index),
+                        None => {
+                            self.remove_white_strings();
+                            self.phase = GcPhase::Sweeping;
+                            self.sweep_cursor = 0;
+                            break;
+                        }
+                    }
+                }
+            }
+            GcPhase::Sweeping => {
+                let mut done = 0;
+                while done < work_budget && self.sweep_cursor < self.objects.len() {
+                    let index = self.sweep_cursor;
+                    if let Some(obj) = self.objects[index].as_mut() {
+                        if obj.is_marked {
+                            obj.is_marked = false;
+                        } else {
+                            self.free(index);
+                        }
+                    }
+                    self.sweep_cursor += 1;
+                    done += 1;
+                }
+
+                if self.sweep_cursor >= self.objects.len() {
+                    self.next_gc = self.bytes_allocated * Self::GROW_FACTOR;
+                    self.phase = GcPhase::Idle;
+                    self.remembered_set.clear();
+                }
+            }
+        }
+    }
+
+    /// Write barrier: call this whenever `new_ref_index` is stored into
+    /// `holder`. It serves two collectors at once:
+    /// - tri-color (major): a black object must never hold a reference to a
+    ///   white one, so if `holder` is already marked we re-grey it, which both
+    ///   keeps it from being swept and makes sure `blacken_object` retraces it
+    ///   and marks `new_ref_index` in turn.
+    /// - generational (minor): if a tenured `holder` gains a reference to a
+    ///   young object, that edge can't be found by scanning the nursery alone,
+    ///   so remember `holder` as a minor-collection root.
+    pub fn write_barrier(&mut self, holder: usize, new_ref_index: usize) {
+        let holder_is_marked = self.objects[holder]
+            .as_ref()
+            .map(|obj| obj.is_marked)
+            .unwrap_or(false);
+
+        if holder_is_marked {
+            self.grey_stack.push(holder);
+            if let Some(new_ref) = self.objects[new_ref_index].as_mut() {
+                new_ref.is_marked = true;
+            }
+        }
+
+        let holder_tenured = self.objects[holder]
+            .as_ref()
+            .map(|obj| obj.generation == Generation::Tenured)
+            .unwrap_or(false);
+        let new_ref_young = self.objects[new_ref_index]
+            .as_ref()
+            .map(|obj| obj.generation == Generation::Young)
+            .unwrap_or(false);
+
+        if holder_tenured && new_ref_young {
+            self.remembered_set.insert(holder);
+        }
+    }
 }
 
 pub struct GcTraceFormatter<'s, T: GcTrace> {
@@ -0,0 +1,93 @@
+pub mod ast;
+pub mod chunk;
+pub mod compiler;
+pub mod gc;
+pub mod object;
+pub mod optimizer;
+pub mod preprocessor;
+pub mod scanner;
+pub mod stdlib;
+pub mod types;
+pub mod vm;
+
+use std::process::exit;
+use types::{Diagnostic, InterpretError};
+use vm::Vm;
+
+/// Small helpers written in rlox itself (see `prelude.lox`), loaded into
+/// every fresh `repl`/`run_file` session before user code runs unless
+/// `--no-prelude` was passed.
+pub const PRELUDE: &str = include_str!("prelude.lox");
+
+/// Runs the embedded `PRELUDE` in `vm` before any user code. A prelude
+/// failure means the shipped `prelude.lox` itself is broken - not something
+/// a user's program could cause - so it's reported with a message distinct
+/// from `run_file`'s usual compile/runtime exit codes instead of being
+/// folded into them.
+pub fn load_prelude(vm: &mut Vm) {
+    if vm.interpret(PRELUDE).is_err() {
+        eprintln!("Fatal: the embedded prelude failed to load. This is a bug in the build, not in your program.");
+        exit(65);
+    }
+}
+
+pub fn run_file(program: &str, mut vm: Vm) {
+    match vm.interpret(program) {
+        Err(InterpretError::Runtime) => {
+            drop(vm);
+            exit(70);
+        }
+        Err(InterpretError::Compile(diagnostics)) => {
+            report_diagnostics(program, &diagnostics);
+            drop(vm);
+            exit(65);
+        }
+        _ => (),
+    }
+}
+
+pub fn compile_only(program: &str, mut vm: Vm) {
+    if let Err(InterpretError::Compile(diagnostics)) = vm.compile_only(program) {
+        report_diagnostics(program, &diagnostics);
+        drop(vm);
+        exit(65);
+    }
+}
+
+pub fn report_diagnostics(program: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(program));
+    }
+}
+
+/// Parses `program` with [`ast::parse`], runs [`optimizer::optimize`] on the
+/// result and prints its [`ast::dump`] instead of running it - a debugging
+/// view of the two-phase frontend, independent of the `Vm`/bytecode `dump`
+/// below.
+pub fn dump_ast(program: &str) {
+    match ast::parse(program) {
+        Ok(tree) => print!("{}", ast::dump(&optimizer::optimize(tree))),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            exit(65);
+        }
+    }
+}
+
+pub fn dump(program: &str, mut vm: Vm, to_dump: &str) {
+    match vm.dump(program, to_dump) {
+        Err(InterpretError::Runtime) => {
+            println!("Error while running.");
+            drop(vm);
+            exit(70);
+        }
+        Err(InterpretError::Compile(diagnostics)) => {
+            report_diagnostics(program, &diagnostics);
+            drop(vm);
+            exit(65);
+        }
+        _ => (),
+    }
+}
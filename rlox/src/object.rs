@@ -1,10 +1,10 @@
 use crate::{
-    chunk::{Chunk, OpCode},
+    chunk::Chunk,
     gc::{Gc, GcRef, GcTrace},
     types::{Table, Value},
     vm::Vm,
 };
-use std::{any::Any, fmt, mem};
+use std::{any::Any, fmt, fs::File, mem};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct FunctionUpvalue {
@@ -21,7 +21,16 @@ impl FunctionUpvalue {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Function {
+    /// Count of required parameters - those with neither a default value nor
+    /// the trailing rest marker.
     pub arity: usize,
+    /// One default value per optional parameter (`param = expr`), in
+    /// declaration order, filled into missing trailing arguments at call
+    /// time. Only literal/constant expressions are supported.
+    pub defaults: Vec<Value>,
+    /// Whether the last parameter is a `...rest` that collects any argument
+    /// past `arity + defaults.len()` into an array.
+    pub has_rest: bool,
     pub chunk: Chunk,
     pub name: GcRef<String>,
     pub upvalues: Vec<FunctionUpvalue>,
@@ -32,6 +41,8 @@ impl Function {
     pub fn new(name: GcRef<String>) -> Self {
         Self {
             arity: 0,
+            defaults: Vec::new(),
+            has_rest: false,
             chunk: Chunk::new(),
             name,
             upvalues: Vec::new(),
@@ -47,20 +58,44 @@ pub enum FunctionType {
     Script,
 }
 
-#[derive(Clone, Copy)]
-pub struct NativeFn(pub fn(&Vm, &[Value]) -> Result<Value, String>);
+pub type NativeFnClosure = dyn Fn(&mut Vm, &[Value]) -> Result<Value, String>;
+
+/// A host-provided function exposed to Lox as a callable value. `function`
+/// is a boxed closure rather than a bare `fn` pointer so a native can
+/// capture and mutate its own state (a seeded RNG, a buffered sink, ...)
+/// across calls; `arity` is checked by the VM before the call, with `None`
+/// meaning variadic.
+pub struct NativeFn {
+    pub name: GcRef<String>,
+    pub arity: Option<usize>,
+    pub function: Box<NativeFnClosure>,
+}
+
+impl NativeFn {
+    #[inline]
+    pub fn new(name: GcRef<String>, arity: Option<usize>, function: Box<NativeFnClosure>) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+        }
+    }
+}
 
 impl fmt::Debug for NativeFn {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<fn>")
+        write!(f, "<native fn>")
     }
 }
 
 impl PartialEq for NativeFn {
     #[inline]
-    fn eq(&self, _: &Self) -> bool {
-        false
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(
+            self.function.as_ref() as *const NativeFnClosure as *const (),
+            other.function.as_ref() as *const NativeFnClosure as *const (),
+        )
     }
 }
 
@@ -100,6 +135,10 @@ impl Closure {
 pub struct Class {
     pub name: GcRef<String>,
     pub methods: Table,
+    /// Set by `OpCode::Inherit` alongside the existing flattened copy of the
+    /// superclass's methods, so `instanceof` and method lookup can walk the
+    /// chain explicitly instead of relying only on that copy.
+    pub superclass: Option<GcRef<Class>>,
 }
 
 impl Class {
@@ -108,6 +147,7 @@ impl Class {
         Self {
             name,
             methods: Table::new(),
+            superclass: None,
         }
     }
 }
@@ -141,6 +181,43 @@ impl BoundMethod {
     }
 }
 
+/// A `target` (`Closure`, `NativeFn`, or `BoundMethod`) with `bound_args`
+/// already captured ahead of whatever arguments the call site supplies, as
+/// produced by `bind`. `call_value` splices `bound_args` onto the stack
+/// before `target`'s own arguments and re-checks arity against the total.
+#[derive(Debug)]
+pub struct PartialFn {
+    pub target: Value,
+    pub bound_args: Vec<Value>,
+}
+
+impl PartialFn {
+    #[inline]
+    pub fn new(target: Value, bound_args: Vec<Value>) -> Self {
+        Self { target, bound_args }
+    }
+}
+
+/// An open OS file backing the `openFile`/`readFile`/`closeFile` natives.
+/// `file` is `None` once the handle has been closed, either explicitly or by
+/// the collector's `finalize` call, so a handle can outlive the underlying
+/// descriptor without anyone accessing it.
+#[derive(Debug)]
+pub struct FileHandle {
+    pub path: GcRef<String>,
+    pub file: Option<File>,
+}
+
+impl FileHandle {
+    #[inline]
+    pub fn new(path: GcRef<String>, file: File) -> Self {
+        Self {
+            path,
+            file: Some(file),
+        }
+    }
+}
+
 impl GcTrace for String {
     #[inline]
     fn format(&self, f: &mut fmt::Formatter<'_>, _gc: &Gc) -> fmt::Result {
@@ -181,7 +258,8 @@ impl GcTrace for Function {
     fn size(&self) -> usize {
         mem::size_of::<Function>()
             + self.upvalues.capacity() * mem::size_of::<FunctionUpvalue>()
-            + self.chunk.code.capacity() * mem::size_of::<OpCode>()
+            + self.defaults.capacity() * mem::size_of::<Value>()
+            + self.chunk.code.capacity()
             + self.chunk.constants.capacity() * mem::size_of::<Value>()
             + self.chunk.constants.capacity() * mem::size_of::<usize>()
     }
@@ -192,6 +270,37 @@ impl GcTrace for Function {
         for &constant in &self.chunk.constants {
             gc.mark_value(constant);
         }
+        for &default in &self.defaults {
+            gc.mark_value(default);
+        }
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl GcTrace for NativeFn {
+    #[inline]
+    fn format(&self, f: &mut fmt::Formatter<'_>, gc: &Gc) -> fmt::Result {
+        let name = gc.deref(self.name);
+        write!(f, "<native fn {}>", name)
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        mem::size_of::<NativeFn>()
+    }
+
+    #[inline]
+    fn trace(&self, gc: &mut Gc) {
+        gc.mark_object(self.name);
     }
 
     #[inline]
@@ -280,6 +389,9 @@ impl GcTrace for Class {
     fn trace(&self, gc: &mut Gc) {
         gc.mark_object(self.name);
         gc.mark_table(&self.methods);
+        if let Some(superclass) = self.superclass {
+            gc.mark_object(superclass);
+        }
     }
 
     #[inline]
@@ -323,6 +435,124 @@ impl GcTrace for Instance {
     }
 }
 
+impl GcTrace for PartialFn {
+    #[inline]
+    fn format(&self, f: &mut fmt::Formatter<'_>, gc: &Gc) -> fmt::Result {
+        write!(f, "<bound ")?;
+        self.target.format(f, gc)?;
+        write!(f, ">")
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        mem::size_of::<PartialFn>() + self.bound_args.capacity() * mem::size_of::<Value>()
+    }
+
+    #[inline]
+    fn trace(&self, gc: &mut Gc) {
+        gc.mark_value(self.target);
+        for &arg in &self.bound_args {
+            gc.mark_value(arg);
+        }
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A lazy sequence that produces values one at a time through `next` instead
+/// of materializing an intermediate array. `Range`/`Array` are the two leaf
+/// sources (a numeric `start..limit` stepped by `step`, or an adapter pulling
+/// from an existing array); `Map`/`Filter`/`Take`/`Skip` each wrap another
+/// `Iter` by `GcRef`, so combinators compose into a chain that's only walked
+/// as far as something (`next`, `collect`, a `for` loop) actually pulls.
+#[derive(Clone, Debug)]
+pub enum Iter {
+    Range {
+        current: f64,
+        limit: f64,
+        step: f64,
+    },
+    Array {
+        array: GcRef<Vec<Value>>,
+        index: usize,
+    },
+    Map {
+        inner: GcRef<Iter>,
+        callback: Value,
+    },
+    Filter {
+        inner: GcRef<Iter>,
+        callback: Value,
+    },
+    Take {
+        inner: GcRef<Iter>,
+        remaining: usize,
+    },
+    Skip {
+        inner: GcRef<Iter>,
+        remaining: usize,
+    },
+}
+
+impl Iter {
+    #[inline]
+    pub fn range(current: f64, limit: f64, step: f64) -> Self {
+        Self::Range {
+            current,
+            limit,
+            step,
+        }
+    }
+
+    #[inline]
+    pub fn array(array: GcRef<Vec<Value>>) -> Self {
+        Self::Array { array, index: 0 }
+    }
+}
+
+impl GcTrace for Iter {
+    #[inline]
+    fn format(&self, f: &mut fmt::Formatter<'_>, _gc: &Gc) -> fmt::Result {
+        write!(f, "<iterator>")
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        mem::size_of::<Iter>()
+    }
+
+    #[inline]
+    fn trace(&self, gc: &mut Gc) {
+        match self {
+            Iter::Range { .. } => (),
+            Iter::Array { array, .. } => gc.mark_object(*array),
+            Iter::Map { inner, callback } | Iter::Filter { inner, callback } => {
+                gc.mark_object(*inner);
+                gc.mark_value(*callback);
+            }
+            Iter::Take { inner, .. } | Iter::Skip { inner, .. } => gc.mark_object(*inner),
+        }
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
 impl GcTrace for BoundMethod {
     #[inline]
     fn format(&self, f: &mut fmt::Formatter<'_>, gc: &Gc) -> fmt::Result {
@@ -352,6 +582,39 @@ impl GcTrace for BoundMethod {
     }
 }
 
+impl GcTrace for FileHandle {
+    #[inline]
+    fn format(&self, f: &mut fmt::Formatter<'_>, gc: &Gc) -> fmt::Result {
+        let path = gc.deref(self.path);
+        write!(f, "<file {}>", path)
+    }
+
+    #[inline]
+    fn size(&self) -> usize {
+        mem::size_of::<FileHandle>()
+    }
+
+    #[inline]
+    fn trace(&self, gc: &mut Gc) {
+        gc.mark_object(self.path);
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    #[inline]
+    fn finalize(&mut self, _gc: &mut Gc) {
+        self.file = None;
+    }
+}
+
 impl GcTrace for Vec<Value> {
     #[inline]
     fn format(&self, f: &mut fmt::Formatter<'_>, gc: &Gc) -> fmt::Result {
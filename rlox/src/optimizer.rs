@@ -0,0 +1,191 @@
+//! A compile-time constant-folding pass over [`crate::ast::Ast`], run before
+//! a hypothetical lowering-to-bytecode step would see the tree. Folds
+//! bottom-up so a deeply nested literal expression collapses to a single
+//! [`Literal`] node, and drops the dead branch of an `if` whose condition is
+//! already a literal. Only covers the literal/operator combinations this
+//! AST actually parses (see `ast.rs`'s own scope note) - it does not attempt
+//! the full numeric-coercion matrix (`Int`/`Number`/`Rational`/`Complex`
+//! cross-folding, string-plus-number concatenation, ...) the real VM
+//! supports, since folding those here without the VM's exact promotion
+//! rules alongside would risk silently changing a program's behavior.
+use crate::ast::{Ast, Expr, Literal, Stmt};
+use crate::scanner::TokenType;
+
+pub fn optimize(ast: Ast) -> Ast {
+    ast.into_iter().map(optimize_stmt).collect()
+}
+
+fn optimize_stmt(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Expression(expr) => Stmt::Expression(optimize_expr(expr)),
+        Stmt::Print(expr) => Stmt::Print(optimize_expr(expr)),
+        Stmt::Var(name, init) => Stmt::Var(name, init.map(optimize_expr)),
+        Stmt::Block(statements) => {
+            Stmt::Block(statements.into_iter().map(optimize_stmt).collect())
+        }
+        Stmt::If(condition, then_branch, else_branch) => {
+            let condition = optimize_expr(condition);
+            let then_branch = Box::new(optimize_stmt(*then_branch));
+            let else_branch = else_branch.map(|branch| Box::new(optimize_stmt(*branch)));
+            match as_bool(&condition) {
+                Some(true) => *then_branch,
+                Some(false) => *else_branch.unwrap_or_else(|| Box::new(Stmt::Block(Vec::new()))),
+                None => Stmt::If(condition, then_branch, else_branch),
+            }
+        }
+        Stmt::While(condition, body) => {
+            Stmt::While(optimize_expr(condition), Box::new(optimize_stmt(*body)))
+        }
+        Stmt::Return(value) => Stmt::Return(value.map(optimize_expr)),
+    }
+}
+
+fn as_bool(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Literal(Literal::Bool(value), _) => Some(*value),
+        _ => None,
+    }
+}
+
+fn optimize_expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::Literal(_, _) | Expr::Variable(_, _) => expr,
+        Expr::Assign(name, value, position) => {
+            Expr::Assign(name, Box::new(optimize_expr(*value)), position)
+        }
+        Expr::Grouping(inner, position) => {
+            let inner = optimize_expr(*inner);
+            // A parenthesized literal carries no runtime meaning once the
+            // AST is the thing being folded, so drop the wrapper too.
+            if matches!(inner, Expr::Literal(_, _)) {
+                inner
+            } else {
+                Expr::Grouping(Box::new(inner), position)
+            }
+        }
+        Expr::Unary(op, operand, position) => {
+            let operand = optimize_expr(*operand);
+            match fold_unary(op, &operand) {
+                Some(literal) => Expr::Literal(literal, position),
+                None => Expr::Unary(op, Box::new(operand), position),
+            }
+        }
+        Expr::Binary(left, op, right, position) => {
+            let left = optimize_expr(*left);
+            let right = optimize_expr(*right);
+            match fold_binary(&left, op, &right) {
+                Some(literal) => Expr::Literal(literal, position),
+                None => Expr::Binary(Box::new(left), op, Box::new(right), position),
+            }
+        }
+        Expr::Logical(left, op, right, position) => {
+            let left = optimize_expr(*left);
+            let right = optimize_expr(*right);
+            match (op, as_bool(&left)) {
+                (TokenType::And, Some(true)) => right,
+                (TokenType::And, Some(false)) => Expr::Literal(Literal::Bool(false), position),
+                (TokenType::Or, Some(true)) => Expr::Literal(Literal::Bool(true), position),
+                (TokenType::Or, Some(false)) => right,
+                _ => Expr::Logical(Box::new(left), op, Box::new(right), position),
+            }
+        }
+        Expr::Conditional(condition, then_branch, else_branch, position) => {
+            let condition = optimize_expr(*condition);
+            let then_branch = optimize_expr(*then_branch);
+            let else_branch = optimize_expr(*else_branch);
+            match as_bool(&condition) {
+                Some(true) => then_branch,
+                Some(false) => else_branch,
+                None => Expr::Conditional(
+                    Box::new(condition),
+                    Box::new(then_branch),
+                    Box::new(else_branch),
+                    position,
+                ),
+            }
+        }
+        Expr::Call(callee, args, position) => {
+            let callee = optimize_expr(*callee);
+            let args = args.into_iter().map(optimize_expr).collect();
+            Expr::Call(Box::new(callee), args, position)
+        }
+    }
+}
+
+fn fold_unary(op: TokenType, operand: &Expr) -> Option<Literal> {
+    match (op, operand) {
+        (TokenType::Minus, Expr::Literal(Literal::Int(value), _)) => {
+            Some(Literal::Int(-value))
+        }
+        (TokenType::Minus, Expr::Literal(Literal::Number(value), _)) => {
+            Some(Literal::Number(-value))
+        }
+        (TokenType::Bang, Expr::Literal(Literal::Bool(value), _)) => {
+            Some(Literal::Bool(!value))
+        }
+        (TokenType::Bang, Expr::Literal(Literal::Nil, _)) => Some(Literal::Bool(true)),
+        _ => None,
+    }
+}
+
+fn fold_binary(left: &Expr, op: TokenType, right: &Expr) -> Option<Literal> {
+    use Literal::*;
+    match (left, right) {
+        (Expr::Literal(Int(a), _), Expr::Literal(Int(b), _)) => fold_int(*a, op, *b),
+        (Expr::Literal(Number(a), _), Expr::Literal(Number(b), _)) => fold_number(*a, op, *b),
+        (Expr::Literal(Bool(a), _), Expr::Literal(Bool(b), _)) => fold_bool(*a, op, *b),
+        (Expr::Literal(Str(a), _), Expr::Literal(Str(b), _)) => fold_str(a, op, b),
+        _ => None,
+    }
+}
+
+fn fold_int(a: i64, op: TokenType, b: i64) -> Option<Literal> {
+    match op {
+        TokenType::Plus => Some(Literal::Int(a + b)),
+        TokenType::Minus => Some(Literal::Int(a - b)),
+        TokenType::Star => Some(Literal::Int(a * b)),
+        // The VM's `Div` gives a `Number` quotient even for two `Int`s, so
+        // fold the same way; a zero divisor is left for the VM to evaluate.
+        TokenType::Slash if b != 0 => Some(Literal::Number(a as f64 / b as f64)),
+        TokenType::EqualEqual => Some(Literal::Bool(a == b)),
+        TokenType::BangEqual => Some(Literal::Bool(a != b)),
+        TokenType::Less => Some(Literal::Bool(a < b)),
+        TokenType::LessEqual => Some(Literal::Bool(a <= b)),
+        TokenType::Greater => Some(Literal::Bool(a > b)),
+        TokenType::GreaterEqual => Some(Literal::Bool(a >= b)),
+        _ => None,
+    }
+}
+
+fn fold_number(a: f64, op: TokenType, b: f64) -> Option<Literal> {
+    match op {
+        TokenType::Plus => Some(Literal::Number(a + b)),
+        TokenType::Minus => Some(Literal::Number(a - b)),
+        TokenType::Star => Some(Literal::Number(a * b)),
+        TokenType::Slash if b != 0.0 => Some(Literal::Number(a / b)),
+        TokenType::EqualEqual => Some(Literal::Bool(a == b)),
+        TokenType::BangEqual => Some(Literal::Bool(a != b)),
+        TokenType::Less => Some(Literal::Bool(a < b)),
+        TokenType::LessEqual => Some(Literal::Bool(a <= b)),
+        TokenType::Greater => Some(Literal::Bool(a > b)),
+        TokenType::GreaterEqual => Some(Literal::Bool(a >= b)),
+        _ => None,
+    }
+}
+
+fn fold_bool(a: bool, op: TokenType, b: bool) -> Option<Literal> {
+    match op {
+        TokenType::EqualEqual => Some(Literal::Bool(a == b)),
+        TokenType::BangEqual => Some(Literal::Bool(a != b)),
+        _ => None,
+    }
+}
+
+fn fold_str(a: &str, op: TokenType, b: &str) -> Option<Literal> {
+    match op {
+        TokenType::Plus => Some(Literal::Str(format!("{}{}", a, b))),
+        TokenType::EqualEqual => Some(Literal::Bool(a == b)),
+        TokenType::BangEqual => Some(Literal::Bool(a != b)),
+        _ => None,
+    }
+}
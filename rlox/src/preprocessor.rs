@@ -1,61 +1,233 @@
-// Preprocessor for rlox
-
-use std::{fs, io::Result};
-
-pub fn preprocessor(filename: &str) -> Result<String> {
-    let mut program = fs::read_to_string(filename)?;
-    let mut imported = vec![filename.to_string()];
-    include_resolver(&mut program, &mut imported);
-    // println!("{}", code);
-    Ok(program)
-}
-
-/*
-#include statements
-
-- it handles #include statements for importing other rlox programs into the current file
-- it scans the beginning of the program looking for #include
-| statements and recursively adding the content of the included program
-- #include statements must go at the beginning of the program, no #include are allowed
-| after the first non-#include line
-- syntax: #include {program_name}
-- program_name must include file extension
-- program_name can possibly contain a local path to another file (not yet implemented)
-- the whole line is substituted by the content of {program_name} after having
-| preprocessed it
-*/
-
-use lazy_static::lazy_static;
-use regex::Regex;
-
-const INCLUDE_HEADER: &str = "#include";
-lazy_static! {
-    static ref INCLUDE_PATH: Regex = Regex::new(r"#include ([a-zA-Z_]\w*.lox)").unwrap();
-}
-
-fn include_resolver(code: &mut String, imported: &mut Vec<String>) {
-    let mut temp = code.split('\n').map(String::from).collect::<Vec<String>>();
-    for line in temp.iter_mut() {
-        if line.starts_with(INCLUDE_HEADER) {
-            let matches = INCLUDE_PATH.captures(line);
-            if let Some(mat) = matches {
-                let file = mat[1].to_string();
-                if !imported.contains(&file) {
-                    imported.push(file.clone());
-                    let mut import_file = fs::read_to_string(file).expect("File not found");
-                    include_resolver(&mut import_file, imported);
-                    *line = import_file;
-                } else {
-                    *line = String::new();
-                }
-            } else {
-                panic!("INCLUDE Error: expected filename, found {}", line);
-            }
-        } else if line.is_empty() {
-            continue;
-        } else {
-            break;
-        }
-    }
-    *code = temp.join("\n");
-}
+// Preprocessor for rlox
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Reads `filename` and resolves every `#include`/`#module` directive it (and
+/// anything it includes) contains. `search_path` is tried, in order, for any
+/// include that isn't found relative to the including file's own directory -
+/// see `resolve`.
+pub fn preprocessor(filename: &str, search_path: &[PathBuf]) -> Result<String, String> {
+    let path = PathBuf::from(filename);
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", filename, e))?;
+    let mut program = fs::read_to_string(&canonical).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut imported = HashSet::new();
+    imported.insert(canonical.clone());
+    include_resolver(&mut program, &mut imported, &canonical, search_path)?;
+    // println!("{}", program);
+    Ok(program)
+}
+
+/*
+#include and #module statements
+
+- #include handles importing another rlox program's content into the current
+| file; #include? does the same but silently skips a missing file instead of
+| raising an error
+- #module registers the same import under a namespace: `#module alias name.lox`
+| loads `name.lox` and rewrites its top-level `fun`/`class`/`var` names (and
+| every reference to them within the module) to `alias_name`, then rewrites
+| `alias.name` anywhere later in the file into that same mangled identifier,
+| so the module's members are reached as `alias.thing`
+- #include/#module statements must go at the beginning of the program, no
+| #include/#module are allowed after the first non-directive line
+- syntax: #include {program_name} / #include? {program_name} / #module {alias} {program_name}
+- program_name must include file extension and may contain a path (absolute,
+| relative to the directory of the file doing the including, or found in one
+| of the `-I`/`LOX_PATH` search directories)
+- the whole line is substituted by the content of {program_name} after having
+| preprocessed it
+*/
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const INCLUDE_HEADER: &str = "#include";
+const MODULE_HEADER: &str = "#module";
+
+lazy_static! {
+    static ref INCLUDE_PATH: Regex = Regex::new(r"^#include\??\s+([\w./\\-]+\.lox)\s*$").unwrap();
+    static ref MODULE_PATH: Regex =
+        Regex::new(r"^#module\s+(\w+)\s+([\w./\\-]+\.lox)\s*$").unwrap();
+}
+
+/// Splits a colon-separated `-I`/`LOX_PATH` value into its component
+/// directories, dropping empty segments (e.g. a trailing `:`).
+pub fn parse_search_path(value: &str) -> Vec<PathBuf> {
+    value
+        .split(':')
+        .filter(|segment| !segment.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Finds `target` on disk: relative to the directory containing `base`
+/// first, unless `target` is already absolute, then falling back to each
+/// directory in `search_path` in order. Errors listing every location tried
+/// if none of them has it.
+fn resolve(base: &Path, target: &str, search_path: &[PathBuf]) -> Result<PathBuf, String> {
+    let target_path = Path::new(target);
+    if target_path.is_absolute() {
+        return Ok(target_path.to_path_buf());
+    }
+
+    let relative = base
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(target_path);
+    if relative.exists() {
+        return Ok(relative);
+    }
+
+    for dir in search_path {
+        let candidate = dir.join(target_path);
+        if candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+
+    let mut tried = vec![relative.display().to_string()];
+    tried.extend(
+        search_path
+            .iter()
+            .map(|dir| dir.join(target_path).display().to_string()),
+    );
+    Err(format!(
+        "couldn't find \"{}\" (tried {})",
+        target,
+        tried.join(", ")
+    ))
+}
+
+/// Reads and preprocesses `path`, returning `Ok(None)` if it was already
+/// imported (so the including line is simply dropped) and an `Err` describing
+/// the failure to resolve or read it.
+fn load(
+    base: &Path,
+    target: &str,
+    imported: &mut HashSet<PathBuf>,
+    search_path: &[PathBuf],
+) -> Result<Option<String>, String> {
+    let resolved = resolve(base, target, search_path)?;
+    let canonical = resolved
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", resolved.display(), e))?;
+    if imported.contains(&canonical) {
+        return Ok(None);
+    }
+    let mut contents =
+        fs::read_to_string(&canonical).map_err(|e| format!("{}: {}", canonical.display(), e))?;
+    imported.insert(canonical.clone());
+    include_resolver(&mut contents, imported, &canonical, search_path)?;
+    Ok(Some(contents))
+}
+
+/// Renames every top-level `fun`/`class`/`var` name declared in `contents`
+/// (and every reference to it within `contents`) to `alias_name`, so the
+/// module can be reached from the including file as `alias.name`.
+fn mangle_module(contents: &str, alias: &str) -> String {
+    let mut names = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim_start();
+        let rest = trimmed
+            .strip_prefix("fun ")
+            .or_else(|| trimmed.strip_prefix("class "))
+            .or_else(|| trimmed.strip_prefix("var "));
+        if let Some(rest) = rest {
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            if !name.is_empty() {
+                names.push(name);
+            }
+        }
+    }
+
+    let mut mangled = contents.to_string();
+    for name in &names {
+        let pattern = Regex::new(&format!(r"\b{}\b", regex::escape(name))).unwrap();
+        mangled = pattern
+            .replace_all(&mangled, format!("{}_{}", alias, name).as_str())
+            .into_owned();
+    }
+    mangled
+}
+
+fn include_resolver(
+    code: &mut String,
+    imported: &mut HashSet<PathBuf>,
+    base: &Path,
+    search_path: &[PathBuf],
+) -> Result<(), String> {
+    let lines = code.split('\n').collect::<Vec<&str>>();
+    let mut out = Vec::with_capacity(lines.len());
+    let mut past_directives = false;
+    let mut aliases = Vec::new();
+
+    for (number, line) in lines.iter().enumerate() {
+        if past_directives {
+            out.push((*line).to_string());
+            continue;
+        }
+
+        if line.is_empty() {
+            out.push(String::new());
+        } else if line.starts_with(MODULE_HEADER) {
+            let caps = MODULE_PATH.captures(line).ok_or_else(|| {
+                format!(
+                    "MODULE Error in {}:{}: expected `#module alias name.lox`, found \"{}\"",
+                    base.display(),
+                    number + 1,
+                    line
+                )
+            })?;
+            let alias = caps[1].to_string();
+            let rendered = load(base, &caps[2], imported, search_path)
+                .map_err(|e| format!("MODULE Error in {}:{}: {}", base.display(), number + 1, e))?;
+            out.push(
+                rendered.map_or_else(String::new, |contents| mangle_module(&contents, &alias)),
+            );
+            aliases.push(alias);
+        } else if line.starts_with(INCLUDE_HEADER) {
+            let optional = line.starts_with("#include?");
+            let caps = INCLUDE_PATH.captures(line).ok_or_else(|| {
+                format!(
+                    "INCLUDE Error in {}:{}: expected filename, found \"{}\"",
+                    base.display(),
+                    number + 1,
+                    line
+                )
+            })?;
+            match load(base, &caps[1], imported, search_path) {
+                Ok(rendered) => out.push(rendered.unwrap_or_default()),
+                Err(_) if optional => out.push(String::new()),
+                Err(e) => {
+                    return Err(format!(
+                        "INCLUDE Error in {}:{}: {}",
+                        base.display(),
+                        number + 1,
+                        e
+                    ))
+                }
+            }
+        } else {
+            past_directives = true;
+            out.push((*line).to_string());
+        }
+    }
+
+    *code = out.join("\n");
+    for alias in &aliases {
+        let pattern = Regex::new(&format!(r"\b{}\.(\w+)", regex::escape(alias))).unwrap();
+        *code = pattern
+            .replace_all(code, format!("{}_$1", alias).as_str())
+            .into_owned();
+    }
+    Ok(())
+}
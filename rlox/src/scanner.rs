@@ -5,6 +5,8 @@ pub struct Scanner<'s> {
     start: usize,
     current: usize,
     line: usize,
+    col: usize,
+    start_col: usize,
 }
 
 impl<'s> Scanner<'s> {
@@ -14,12 +16,17 @@ impl<'s> Scanner<'s> {
             start: 0,
             current: 0,
             line: 1,
+            col: 1,
+            start_col: 1,
         }
     }
 
     pub fn scan_token(&mut self) -> Token<'s> {
-        self.skip_withespaces();
+        if let Some(err) = self.skip_withespaces() {
+            return err;
+        }
         self.start = self.current;
+        self.start_col = self.col;
 
         if self.at_end() {
             self.make_token(Eof)
@@ -35,7 +42,13 @@ impl<'s> Scanner<'s> {
                 '}' => self.make_token(RightBrace),
                 ';' => self.make_token(Semicolon),
                 ',' => self.make_token(Comma),
-                '.' => self.make_token(Dot),
+                '.' => {
+                    if self.match_char('.') && self.match_char('.') {
+                        self.make_token(DotDotDot)
+                    } else {
+                        self.make_token(Dot)
+                    }
+                }
                 '-' => {
                     if self.match_char('=') {
                         self.make_token(MinusEqual)
@@ -64,6 +77,8 @@ impl<'s> Scanner<'s> {
                 '*' => {
                     if self.match_char('=') {
                         self.make_token(StarEqual)
+                    } else if self.match_char('*') {
+                        self.make_token(StarStar)
                     } else {
                         self.make_token(Star)
                     }
@@ -87,6 +102,8 @@ impl<'s> Scanner<'s> {
                         self.make_token(LessEqual)
                     } else if self.match_char('|') {
                         self.make_token(LessPipe)
+                    } else if self.match_char('<') {
+                        self.make_token(LessLess)
                     } else {
                         self.make_token(Less)
                     }
@@ -94,11 +111,35 @@ impl<'s> Scanner<'s> {
                 '>' => {
                     if self.match_char('=') {
                         self.make_token(GreaterEqual)
+                    } else if self.match_char('>') {
+                        self.make_token(GreaterGreater)
                     } else {
                         self.make_token(Greater)
                     }
                 }
-                '%' => self.make_token(Rem),
+                '%' => {
+                    if self.match_char('=') {
+                        self.make_token(RemEqual)
+                    } else {
+                        self.make_token(Rem)
+                    }
+                }
+                '&' => self.make_token(Amp),
+                '|' => {
+                    if self.match_char('>') {
+                        self.make_token(PipeGreater)
+                    } else if self.match_char(':') {
+                        self.make_token(PipeColon)
+                    } else if self.match_char('?') {
+                        self.make_token(PipeQuestion)
+                    } else {
+                        self.make_token(Pipe)
+                    }
+                }
+                '^' => self.make_token(Caret),
+                '\\' => self.make_token(Backslash),
+                '?' => self.make_token(Question),
+                ':' => self.make_token(Colon),
                 '"' => self.string(),
                 '0'..='9' => self.number(),
                 'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
@@ -112,23 +153,42 @@ impl<'s> Scanner<'s> {
         self.source.chars().count() == self.current
     }
 
+    /// The `Position` spanning whatever has been consumed so far for the
+    /// token currently being built, i.e. `start..current`.
+    #[inline]
+    fn current_position(&self) -> Position {
+        Position {
+            line: self.line,
+            col: self.start_col,
+            start_byte: self.start,
+            len: self.current - self.start,
+        }
+    }
+
     #[inline]
     fn make_token(&self, token_type: TokenType) -> Token<'s> {
         let lexeme = &self.source[self.start..self.current];
-        Token::new(token_type, lexeme, self.line)
+        Token::new(token_type, lexeme, self.line, self.current_position())
     }
 
     #[inline]
     fn error_token(&self, message: &'s str) -> Token<'s> {
-        Token::new(Error, message, self.line)
+        Token::new(Error, message, self.line, self.current_position())
     }
 
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source
+        let ch = self
+            .source
             .chars()
             .nth(self.current - 1)
-            .expect("Error advancing on character")
+            .expect("Error advancing on character");
+        if ch == '\n' {
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        ch
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -136,11 +196,12 @@ impl<'s> Scanner<'s> {
             false
         } else {
             self.current += 1;
+            self.col += 1;
             true
         }
     }
 
-    fn skip_withespaces(&mut self) {
+    fn skip_withespaces(&mut self) -> Option<Token<'s>> {
         loop {
             match self.peek() {
                 ' ' | '\t' | '\r' => {
@@ -155,13 +216,52 @@ impl<'s> Scanner<'s> {
                         while self.peek() != '\n' && !self.at_end() {
                             self.advance();
                         }
+                    } else if self.peek_next() == '*' {
+                        self.advance();
+                        self.advance();
+                        if self.skip_block_comment().is_none() {
+                            self.start = self.current;
+                            self.start_col = self.col;
+                            return Some(self.error_token("Unterminated block comment."));
+                        }
                     } else {
-                        return;
+                        return None;
                     }
                 }
-                _ => return,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Consumes a `/* ... */` block comment, the opening `/*` already
+    /// consumed by the caller, tracking nesting depth so `/* outer /* inner
+    /// */ still comment */` is fully consumed in one go.
+    fn skip_block_comment(&mut self) -> Option<()> {
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.at_end() {
+                return None;
+            }
+
+            if self.peek() == '\n' {
+                self.line += 1;
+            }
+
+            if self.peek() == '/' && self.peek_next() == '*' {
+                depth += 1;
+                self.advance();
+                self.advance();
+            } else if self.peek() == '*' && self.peek_next() == '/' {
+                depth -= 1;
+                self.advance();
+                self.advance();
+            } else {
+                self.advance();
             }
         }
+
+        Some(())
     }
 
     #[inline]
@@ -203,7 +303,12 @@ impl<'s> Scanner<'s> {
             }
         }
 
-        self.make_token(Number)
+        if self.peek() == 'i' {
+            self.advance();
+            self.make_token(Imaginary)
+        } else {
+            self.make_token(Number)
+        }
     }
 
     fn identifier(&mut self) -> Token<'s> {
@@ -227,7 +332,25 @@ impl<'s> Scanner<'s> {
             .expect("Error advancing on character")
         {
             'a' => self.check_keyword(1, 2, "nd", And),
-            'c' => self.check_keyword(1, 4, "lass", Class),
+            'b' => self.check_keyword(1, 4, "reak", Break),
+            'c' => {
+                if self.current - self.start > 1 {
+                    match self
+                        .source
+                        .chars()
+                        .nth(self.start + 1)
+                        .expect("Error advancing on character")
+                    {
+                        'a' => self.check_keyword(2, 3, "tch", Catch),
+                        'l' => self.check_keyword(2, 3, "ass", Class),
+                        'o' => self.check_keyword(2, 6, "ntinue", Continue),
+                        _ => Identifier,
+                    }
+                } else {
+                    Identifier
+                }
+            }
+            'd' => self.check_keyword(1, 1, "o", Do),
             'e' => self.check_keyword(1, 3, "lse", Else),
             'f' => {
                 if self.current - self.start > 1 {
@@ -247,6 +370,7 @@ impl<'s> Scanner<'s> {
                 }
             }
             'i' => self.check_keyword(1, 1, "f", If),
+            'l' => self.check_keyword(1, 3, "oop", Loop),
             'n' => self.check_keyword(1, 2, "il", Nil),
             'o' => self.check_keyword(1, 1, "r", Or),
             'p' => self.check_keyword(1, 4, "rint", Print),
@@ -260,8 +384,38 @@ impl<'s> Scanner<'s> {
                         .nth(self.start + 1)
                         .expect("Error advancing on character")
                     {
-                        'h' => self.check_keyword(2, 2, "is", This),
-                        'r' => self.check_keyword(2, 2, "ue", True),
+                        'h' => {
+                            if self.current - self.start > 2 {
+                                match self
+                                    .source
+                                    .chars()
+                                    .nth(self.start + 2)
+                                    .expect("Error advancing on character")
+                                {
+                                    'i' => self.check_keyword(2, 2, "is", This),
+                                    'r' => self.check_keyword(2, 3, "row", Throw),
+                                    _ => Identifier,
+                                }
+                            } else {
+                                Identifier
+                            }
+                        }
+                        'r' => {
+                            if self.current - self.start > 2 {
+                                match self
+                                    .source
+                                    .chars()
+                                    .nth(self.start + 2)
+                                    .expect("Error advancing on character")
+                                {
+                                    'u' => self.check_keyword(2, 2, "ue", True),
+                                    'y' => self.check_keyword(2, 1, "y", Try),
+                                    _ => Identifier,
+                                }
+                            } else {
+                                Identifier
+                            }
+                        }
                         _ => Identifier,
                     }
                 } else {
@@ -291,19 +445,50 @@ impl<'s> Scanner<'s> {
     }
 }
 
+/// Scans `source` to completion, returning every token including the
+/// trailing `Eof`. This crate's compiler is single-pass - it has no separate
+/// AST - so this is the closest thing to a "parsed structure" there is to
+/// inspect; used by the REPL's `:ast` command.
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = Vec::new();
+    loop {
+        let token = scanner.scan_token();
+        let done = token.token_type == Eof;
+        tokens.push(token);
+        if done {
+            break;
+        }
+    }
+    tokens
+}
+
+/// A (line, column) location with the byte span it covers, so a later
+/// diagnostics layer can render a caret-underlined source snippet for any
+/// token without re-scanning the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+    pub start_byte: usize,
+    pub len: usize,
+}
+
 #[derive(Clone, Copy)]
 pub struct Token<'a> {
     pub token_type: TokenType,
     pub lexeme: &'a str,
     pub line: usize,
+    pub position: Position,
 }
 
 impl<'a> Token<'a> {
-    pub fn new(token_type: TokenType, lexeme: &'a str, line: usize) -> Self {
+    pub fn new(token_type: TokenType, lexeme: &'a str, line: usize, position: Position) -> Self {
         Self {
             token_type,
             lexeme,
             line,
+            position,
         }
     }
 
@@ -312,6 +497,12 @@ impl<'a> Token<'a> {
             token_type: Error,
             lexeme,
             line: 0,
+            position: Position {
+                line: 0,
+                col: 0,
+                start_byte: 0,
+                len: lexeme.len(),
+            },
         }
     }
 }
@@ -321,12 +512,18 @@ use self::TokenType::*;
 #[derive(Clone, PartialEq, Debug, Eq, Hash, Copy)]
 pub enum TokenType {
     // Single-character tokens.
+    Amp,
+    Backslash,
+    Caret,
+    Colon,
     Comma,
     Dot,
     LeftBrace,
     LeftBracket,
     LeftParen,
     Minus,
+    Pipe,
+    Question,
     Rem,
     Plus,
     RightBrace,
@@ -338,38 +535,54 @@ pub enum TokenType {
     // One or two character tokens.
     Bang,
     BangEqual,
+    DotDotDot,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
     LessPipe,
     MinusEqual,
     MinusMinus,
+    PipeColon,
+    PipeGreater,
+    PipeQuestion,
     PlusEqual,
     PlusPlus,
+    RemEqual,
     SlashEqual,
     StarEqual,
+    StarStar,
     // Literals.
     Identifier,
+    Imaginary,
     Number,
     RString,
     // Keywords.
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
+    Do,
     Else,
     False,
     For,
     Fun,
     If,
+    Loop,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
     // Signal tokens
@@ -0,0 +1,333 @@
+//! Native standard library, organized into named modules instead of being
+//! hand-wired one native at a time in `Vm::new`. A [`Module`] groups related
+//! natives under a name; `Vm::load_module` installs each one as a global
+//! named `{module}_{native}`, the same flattening the `#module` preprocessor
+//! directive uses to reach a source module's members as `alias.thing` (see
+//! `preprocessor::mangle_module`). Adding a module to the language surface
+//! is then a matter of building one and handing it to `Vm::load_module`,
+//! without touching `Vm::new` itself.
+
+use crate::{object::FileHandle, types::Value, vm::Vm};
+use std::{env, fs, io, io::Read as _, process};
+
+/// A named group of natives, installed together by `Vm::load_module`.
+pub struct Module {
+    pub name: &'static str,
+    pub natives: Vec<(
+        &'static str,
+        Option<usize>,
+        fn(&mut Vm, &[Value]) -> Result<Value, String>,
+    )>,
+}
+
+impl Module {
+    fn new(
+        name: &'static str,
+        natives: Vec<(
+            &'static str,
+            Option<usize>,
+            fn(&mut Vm, &[Value]) -> Result<Value, String>,
+        )>,
+    ) -> Self {
+        Self { name, natives }
+    }
+}
+
+/// `math_sqrt`, `math_pow`, `math_abs`, `math_floor`, `math_ceil`,
+/// `math_square`, `math_float`, `math_sin`, `math_cos`, `math_log`,
+/// `math_rational`.
+pub fn math_module() -> Module {
+    Module::new(
+        "math",
+        vec![
+            ("sqrt", Some(1), sqrt),
+            ("pow", Some(2), pow),
+            ("abs", Some(1), abs),
+            ("floor", Some(1), floor),
+            ("ceil", Some(1), ceil),
+            ("square", Some(1), square),
+            ("float", Some(1), float),
+            ("sin", Some(1), sin),
+            ("cos", Some(1), cos),
+            ("log", Some(1), log),
+            ("rational", Some(2), rational),
+        ],
+    )
+}
+
+/// `iter_map`, `iter_filter`, `iter_reduce`, `iter_range`, all operating on
+/// `Value::Array`; `map`/`filter`/`reduce` call back into the Lox callback
+/// they're given via `Vm::call_callback`.
+pub fn iter_module() -> Module {
+    Module::new(
+        "iter",
+        vec![
+            ("map", Some(2), map),
+            ("filter", Some(2), filter),
+            ("reduce", Some(3), reduce),
+            ("range", Some(2), range),
+        ],
+    )
+}
+
+/// `sys_args`, `sys_exit`, `sys_read`.
+pub fn sys_module() -> Module {
+    Module::new(
+        "sys",
+        vec![
+            ("args", Some(0), args),
+            ("exit", Some(1), exit),
+            ("read", Some(0), read),
+        ],
+    )
+}
+
+/// `io_openFile`, `io_readFile`, `io_closeFile` — the file natives that used
+/// to be wired directly into `Vm::new`.
+pub fn io_module() -> Module {
+    Module::new(
+        "io",
+        vec![
+            ("openFile", Some(2), open_file),
+            ("readFile", Some(1), read_file),
+            ("closeFile", Some(1), close_file),
+        ],
+    )
+}
+
+// math
+
+fn sqrt(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.sqrt())),
+        _ => Err("sqrt needs a numeric argument.".to_owned()),
+    }
+}
+
+fn pow(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match (args[0], args[1]) {
+        (Value::Number(n), Value::Number(e)) => Ok(Value::Number(n.powf(e))),
+        _ => Err("pow needs two numeric arguments.".to_owned()),
+    }
+}
+
+fn abs(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.abs())),
+        _ => Err("abs needs a numeric argument.".to_owned()),
+    }
+}
+
+fn floor(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.floor())),
+        _ => Err("floor needs a numeric argument.".to_owned()),
+    }
+}
+
+fn ceil(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.ceil())),
+        _ => Err("ceil needs a numeric argument.".to_owned()),
+    }
+}
+
+fn square(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n * n)),
+        _ => Err("square needs a numeric argument.".to_owned()),
+    }
+}
+
+fn float(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::VString(s) => vm
+            .gc
+            .deref(s)
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| "couldn't read number from string".to_owned()),
+        _ => Err("float needs a string argument.".to_owned()),
+    }
+}
+
+fn sin(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.sin())),
+        _ => Err("sin needs a numeric argument.".to_owned()),
+    }
+}
+
+fn cos(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.cos())),
+        _ => Err("cos needs a numeric argument.".to_owned()),
+    }
+}
+
+fn log(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => Ok(Value::Number(n.ln())),
+        _ => Err("log needs a numeric argument.".to_owned()),
+    }
+}
+
+fn rational(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match (args[0], args[1]) {
+        (Value::Int(n), Value::Int(d)) => Value::rational(n, d),
+        _ => Err("rational needs two integer arguments.".to_owned()),
+    }
+}
+
+// iter
+
+fn map(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (array, callback) = match (args[0], args[1]) {
+        (Value::Array(array), callback) => (array, callback),
+        _ => return Err("map needs an array and a callback.".to_owned()),
+    };
+    let items = vm.gc.deref(array).clone();
+    let mut result = Vec::with_capacity(items.len());
+    for item in items {
+        result.push(vm.call_callback(callback, &[item])?);
+    }
+    let result = vm.alloc(result);
+    Ok(Value::Array(result))
+}
+
+fn filter(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (array, callback) = match (args[0], args[1]) {
+        (Value::Array(array), callback) => (array, callback),
+        _ => return Err("filter needs an array and a callback.".to_owned()),
+    };
+    let items = vm.gc.deref(array).clone();
+    let mut result = Vec::new();
+    for item in items {
+        if !vm.call_callback(callback, &[item])?.is_false() {
+            result.push(item);
+        }
+    }
+    let result = vm.alloc(result);
+    Ok(Value::Array(result))
+}
+
+fn reduce(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (array, callback, mut accumulator) = match (args[0], args[1], args[2]) {
+        (Value::Array(array), callback, initial) => (array, callback, initial),
+        _ => return Err("reduce needs an array, a callback and an initial value.".to_owned()),
+    };
+    let items = vm.gc.deref(array).clone();
+    for item in items {
+        accumulator = vm.call_callback(callback, &[accumulator, item])?;
+    }
+    Ok(accumulator)
+}
+
+fn range(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (start, end) = match (args[0], args[1]) {
+        (Value::Number(start), Value::Number(end)) => (start, end),
+        _ => return Err("range needs two numbers.".to_owned()),
+    };
+    let mut items = Vec::new();
+    let mut n = start;
+    while n < end {
+        items.push(Value::Number(n));
+        n += 1.0;
+    }
+    let array = vm.alloc(items);
+    Ok(Value::Array(array))
+}
+
+// sys
+
+fn args(vm: &mut Vm, _args: &[Value]) -> Result<Value, String> {
+    let items = env::args()
+        .map(|arg| Value::VString(vm.intern(arg)))
+        .collect::<Vec<_>>();
+    let array = vm.alloc(items);
+    Ok(Value::Array(array))
+}
+
+fn exit(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Number(n) => process::exit(n as i32),
+        _ => Err("exit needs a numeric exit code.".to_owned()),
+    }
+}
+
+fn read(vm: &mut Vm, _args: &[Value]) -> Result<Value, String> {
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Value::VString(vm.intern(line)))
+}
+
+// io
+
+fn open_file(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (path, mode) = match (args[0], args[1]) {
+        (Value::VString(path), Value::VString(mode)) => (path, mode),
+        _ => return Err("openFile needs a path and a mode, both strings".to_owned()),
+    };
+
+    let path_str = vm.gc.deref(path).clone();
+    let mode_str = vm.gc.deref(mode).clone();
+
+    let file = match mode_str.as_str() {
+        "r" => fs::File::open(&path_str),
+        "w" => fs::File::create(&path_str),
+        "a" => fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path_str),
+        other => {
+            return Err(format!(
+                "openFile mode must be \"r\", \"w\" or \"a\", found \"{}\"",
+                other
+            ))
+        }
+    };
+
+    match file {
+        Ok(file) => {
+            let handle = vm.alloc(FileHandle::new(path, file));
+            Ok(Value::File(handle))
+        }
+        Err(e) => Err(format!("couldn't open \"{}\": {}", path_str, e)),
+    }
+}
+
+fn read_file(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let handle = match args[0] {
+        Value::File(handle) => handle,
+        _ => return Err("readFile needs a file".to_owned()),
+    };
+
+    let mut contents = String::new();
+    {
+        let handle = vm.gc.deref_mut(handle);
+        let file = handle.file.as_mut().ok_or("file is closed")?;
+        file.read_to_string(&mut contents)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let contents = vm.intern(contents);
+    Ok(Value::VString(contents))
+}
+
+fn close_file(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let handle = match args[0] {
+        Value::File(handle) => handle,
+        _ => return Err("closeFile needs a file".to_owned()),
+    };
+
+    vm.gc.deref_mut(handle).file = None;
+    Ok(Value::Nil)
+}
@@ -11,11 +11,23 @@ pub enum Value {
     BoundMethod(GcRef<BoundMethod>),
     Class(GcRef<Class>),
     Closure(GcRef<Closure>),
+    /// A real/imaginary `f64` pair; always stored as given, no reduction.
+    Complex(f64, f64),
+    File(GcRef<FileHandle>),
     Function(GcRef<Function>),
     Instance(GcRef<Instance>),
-    NativeFn(NativeFn),
+    Int(i64),
+    /// A lazy `range`/array-adapter sequence pulled via `next`; see [`Iter`].
+    Iterator(GcRef<Iter>),
+    NativeFn(GcRef<NativeFn>),
     Nil,
     Number(f64),
+    PartialFn(GcRef<PartialFn>),
+    /// A reduced `i64` numerator/denominator pair — always built through
+    /// [`Value::rational`], which divides by the gcd and keeps the
+    /// denominator positive, so every `Value::Rational` in play is already
+    /// in lowest terms.
+    Rational(i64, i64),
     VString(GcRef<String>),
 }
 
@@ -35,18 +47,62 @@ impl Value {
             Value::BoundMethod(_) => "bound method",
             Value::Class(_) => "class",
             Value::Closure(_) => "closure",
+            Value::Complex(_, _) => "complex",
+            Value::File(_) => "file",
             Value::Function(_) => "function",
             Value::Instance(_) => "instance",
+            Value::Int(_) => "int",
+            Value::Iterator(_) => "iterator",
             Value::NativeFn(_) => "native function",
             Value::Nil => "nil",
             Value::Number(_) => "number",
+            Value::PartialFn(_) => "bound function",
+            Value::Rational(_, _) => "rational",
             Value::VString(_) => "string",
         }
     }
 
+    /// Builds a reduced `Value::Rational`, dividing `n`/`d` by their gcd and
+    /// normalizing the sign so the denominator is always positive. Errors on
+    /// a zero denominator instead of producing a value that would divide by
+    /// zero the moment it's used.
+    pub fn rational(n: i64, d: i64) -> Result<Value, String> {
+        if d == 0 {
+            return Err("Rational denominator cannot be zero.".to_owned());
+        }
+        let (n, d) = if d < 0 { (-n, -d) } else { (n, d) };
+        let divisor = gcd(n.unsigned_abs(), d.unsigned_abs()).max(1) as i64;
+        Ok(Value::Rational(n / divisor, d / divisor))
+    }
+
     // pub fn is_number(&self) -> bool {
     //     matches!(self, Value::Number(_))
     // }
+
+    /// The GC slot this value points into, if it wraps a heap object at all.
+    /// Used to drive `Gc::write_barrier` at the handful of call sites that
+    /// store a fresh `Value` into an already-allocated `Table`/array/instance.
+    pub(crate) fn gc_index(&self) -> Option<usize> {
+        match self {
+            Value::Array(value) => Some(value.raw_index()),
+            Value::BoundMethod(value) => Some(value.raw_index()),
+            Value::Class(value) => Some(value.raw_index()),
+            Value::Closure(value) => Some(value.raw_index()),
+            Value::File(value) => Some(value.raw_index()),
+            Value::Function(value) => Some(value.raw_index()),
+            Value::Instance(value) => Some(value.raw_index()),
+            Value::Iterator(value) => Some(value.raw_index()),
+            Value::NativeFn(value) => Some(value.raw_index()),
+            Value::PartialFn(value) => Some(value.raw_index()),
+            Value::VString(value) => Some(value.raw_index()),
+            Value::Bool(_)
+            | Value::Complex(_, _)
+            | Value::Int(_)
+            | Value::Nil
+            | Value::Number(_)
+            | Value::Rational(_, _) => None,
+        }
+    }
 }
 
 impl GcTrace for Value {
@@ -57,11 +113,18 @@ impl GcTrace for Value {
             Value::BoundMethod(value) => gc.deref(*value).format(f, gc),
             Value::Class(value) => gc.deref(*value).format(f, gc),
             Value::Closure(value) => gc.deref(*value).format(f, gc),
+            Value::Complex(re, im) if *im < 0.0 => write!(f, "{}-{}i", re, -im),
+            Value::Complex(re, im) => write!(f, "{}+{}i", re, im),
+            Value::File(value) => gc.deref(*value).format(f, gc),
             Value::Function(value) => gc.deref(*value).format(f, gc),
             Value::Instance(value) => gc.deref(*value).format(f, gc),
-            Value::NativeFn(_) => write!(f, "<native fn>"),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Iterator(value) => gc.deref(*value).format(f, gc),
+            Value::NativeFn(value) => gc.deref(*value).format(f, gc),
             Value::Nil => write!(f, "nil"),
             Value::Number(value) => write!(f, "{}", value),
+            Value::PartialFn(value) => gc.deref(*value).format(f, gc),
+            Value::Rational(n, d) => write!(f, "{}/{}", n, d),
             Value::VString(value) => gc.deref(*value).format(f, gc),
         }
     }
@@ -74,11 +137,15 @@ impl GcTrace for Value {
     fn trace(&self, gc: &mut Gc) {
         match self {
             Value::Closure(value) => gc.mark_object(*value),
+            Value::File(value) => gc.mark_object(*value),
             Value::Function(value) => gc.mark_object(*value),
+            Value::NativeFn(value) => gc.mark_object(*value),
             Value::VString(value) => gc.mark_object(*value),
             Value::BoundMethod(value) => gc.mark_object(*value),
             Value::Class(value) => gc.mark_object(*value),
             Value::Instance(value) => gc.mark_object(*value),
+            Value::Iterator(value) => gc.mark_object(*value),
+            Value::PartialFn(value) => gc.mark_object(*value),
             _ => (),
         }
     }
@@ -94,24 +161,62 @@ impl GcTrace for Value {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum InterpretError {
-    Compile,
+    /// Every error the parser accumulated before giving up, in source order -
+    /// see [`Diagnostic`].
+    Compile(Vec<Diagnostic>),
     Runtime,
 }
 
+/// One compile error collected by the `Parser` instead of being printed the
+/// moment it's found, so a caller can report every error from a single
+/// compile in one pass rather than just the first.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub span: crate::scanner::Position,
+    pub message: String,
+    pub severity: Severity,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+}
+
+impl Diagnostic {
+    /// Renders `[line N] message`, followed by the offending source line
+    /// with a `^`-caret underlining `self.span`.
+    pub fn render(&self, source: &str) -> String {
+        let line_text = source.lines().nth(self.span.line.saturating_sub(1));
+        let mut out = format!("[line {}] Error: {}", self.span.line, self.message);
+        if let Some(line_text) = line_text {
+            let caret_col = self.span.col.saturating_sub(1);
+            out.push_str(&format!(
+                "\n  {}\n  {}{}",
+                line_text,
+                " ".repeat(caret_col),
+                "^".repeat(self.span.len.max(1))
+            ));
+        }
+        out
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug, PartialOrd)]
 pub enum Precedence {
     None,
-    Assignment, // =
-    Or,         // or
-    And,        // and
-    Equality,   // == !=
-    Comparison, // < > <= >=
-    Term,       // + -
-    Factor,     // * /
-    Unary,      // ! -
-    Call,       // . ()
+    Assignment,  // =
+    Pipe,        // |> |: |?
+    Conditional, // ?:
+    Or,          // or
+    And,         // and
+    Equality,    // == !=
+    Comparison,  // < > <= >=
+    Term,        // + -
+    Factor,      // * /
+    Unary,       // ! -
+    Call,        // . ()
     Primary,
 }
 
@@ -119,7 +224,9 @@ impl Precedence {
     pub fn next(&self) -> Self {
         match self {
             Precedence::None => Precedence::Assignment,
-            Precedence::Assignment => Precedence::Or,
+            Precedence::Assignment => Precedence::Pipe,
+            Precedence::Pipe => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Or,
             Precedence::Or => Precedence::And,
             Precedence::And => Precedence::Equality,
             Precedence::Equality => Precedence::Comparison,
@@ -134,3 +241,11 @@ impl Precedence {
 }
 
 pub type Table = HashMap<GcRef<String>, Value>;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -1,1443 +1,2801 @@
-use crate::{
-    chunk::{Chunk, Disassembler, OpCode},
-    compiler,
-    gc::{Gc, GcRef, GcTrace, GcTraceFormatter},
-    object::*,
-    types::{InterpretError, Table, Value},
-};
-use cpu_time::ProcessTime;
-use std::{fmt, fs, process};
-
-pub struct Vm {
-    debug: bool,
-    repl: bool,
-    gc: Gc,
-    stack: Vec<Value>,
-    globals: Table,
-    frames: Vec<CallFrame>,
-    open_upvalues: Vec<GcRef<Upvalue>>,
-    start_time: ProcessTime,
-    init_string: GcRef<String>,
-}
-
-impl Vm {
-    // public interface
-
-    pub fn new(repl: bool) -> Self {
-        let mut gc = Gc::new();
-        let init_string = gc.intern("init".to_owned());
-
-        let mut vm = Vm {
-            debug: false,
-            repl,
-            gc,
-            stack: Vec::new(),
-            globals: Table::new(),
-            frames: Vec::new(),
-            open_upvalues: Vec::new(),
-            start_time: ProcessTime::now(),
-            init_string,
-        };
-
-        // native function definition
-        vm.define_native("clock", NativeFn(clock));
-        vm.define_native("panic", NativeFn(lox_panic));
-        // vm.define_native("sqrt", NativeFn(sqrt));
-        // vm.define_native("pow", NativeFn(pow));
-        // vm.define_native("square", NativeFn(square));
-        // vm.define_native("abs", NativeFn(abs));
-        vm.define_native("min", NativeFn(min));
-        vm.define_native("max", NativeFn(max));
-        // vm.define_native("floor", NativeFn(floor));
-        // vm.define_native("ceil", NativeFn(ceil));
-        vm.define_native("isBool", NativeFn(is_bool));
-        vm.define_native("isClass", NativeFn(is_class));
-        vm.define_native("isClosure", NativeFn(is_closure));
-        vm.define_native("isFunction", NativeFn(is_function));
-        vm.define_native("isInstance", NativeFn(is_instance));
-        vm.define_native("isNil", NativeFn(is_nil));
-        vm.define_native("isNumber", NativeFn(is_number));
-        vm.define_native("isString", NativeFn(is_string));
-        vm.define_native("instanceof", NativeFn(instance_of));
-        // vm.define_native("float", NativeFn(float));
-
-        vm
-    }
-
-    pub fn interpret(&mut self, code: &str) -> Result<(), InterpretError> {
-        let function = compiler::compile(code, &mut self.gc)?;
-        self.push(Value::Function(function))?;
-        let closure = self.alloc(Closure::new(function));
-        self.frames.push(CallFrame::new(closure, 0));
-        self.run()
-    }
-
-    pub fn dump(&mut self, code: &str, file: &str) -> Result<(), InterpretError> {
-        let function = compiler::compile(code, &mut self.gc)?;
-        let function = self.gc.deref(function);
-        let name = &self.gc.deref(function.name);
-        let disassembler = Disassembler::new(&self.gc, &function.chunk, Some(&self.stack));
-        let mut content = vec![disassembler.disassemble_to_string(name)];
-        for gcref in self.gc.objects.iter().rev().flatten() {
-            if let Some(fun) = gcref.object.as_any().downcast_ref::<Function>() {
-                if fun.name != function.name {
-                    let name = &self.gc.deref(fun.name);
-                    let disassembler = Disassembler::new(&self.gc, &fun.chunk, Some(&self.stack));
-                    content.push(disassembler.disassemble_to_string(name));
-                }
-            }
-        }
-        fs::write(file, content.join("")).expect("Couldn't write to file.");
-        Ok(())
-    }
-
-    #[inline]
-    pub fn set_debug(&mut self) {
-        self.debug = true;
-    }
-
-    #[inline]
-    pub fn unset_debug(&mut self) {
-        self.debug = false;
-    }
-
-    // stack manipulation
-
-    fn pop(&mut self) -> Value {
-        if let Some(value) = self.stack.pop() {
-            value
-        } else {
-            eprintln!("Error: popping a value from empty stack.");
-            process::exit(65);
-        }
-    }
-
-    fn pop_number(&mut self, msg: &str) -> Result<f64, InterpretError> {
-        if let Value::Number(n) = self.pop() {
-            Ok(n)
-        } else {
-            Err(self.runtime_error(&format!("Error: no number found on stack {}.", msg)))
-        }
-    }
-
-    fn push(&mut self, value: Value) -> Result<(), InterpretError> {
-        if self.stack.capacity() == isize::MAX as usize {
-            Err(self.runtime_error("Stack full."))
-        } else {
-            self.stack.push(value);
-            Ok(())
-        }
-    }
-
-    fn push_number(&mut self, n: f64) -> Result<(), InterpretError> {
-        self.push(Value::Number(n))
-    }
-
-    #[inline]
-    fn peek(&self, index: usize) -> Value {
-        self.stack[self.stack.len() - 1 - index]
-    }
-
-    // main function
-
-    fn run(&mut self) -> Result<(), InterpretError> {
-        loop {
-            let instruction = self.current_chunk().get_opcode(self.current_frame().ip);
-            if self.debug || cfg!(feature = "debug_trace_execution") {
-                let disassembler =
-                    Disassembler::new(&self.gc, self.current_chunk(), Some(&self.stack));
-                disassembler.disassemble_instruction(&instruction, self.current_frame().ip);
-                println!();
-            }
-
-            self.current_frame_mut().ip += 1;
-
-            match instruction {
-                OpCode::Add => match (self.pop(), self.pop()) {
-                    (Value::Number(b), Value::Number(a)) => self.push(Value::Number(a + b))?,
-                    (Value::VString(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let new = format!("{}{}", a, b);
-                        let string = self.intern(new);
-                        self.push(Value::VString(string))?
-                    }
-                    (Value::VString(b), Value::Number(a)) => {
-                        let b = self.gc.deref(b);
-                        let new = format!("{}{}", a, b);
-                        let string = self.intern(new);
-                        self.push(Value::VString(string))?
-                    }
-                    (Value::Number(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let new = format!("{}{}", a, b);
-                        let string = self.intern(new);
-                        self.push(Value::VString(string))?
-                    }
-                    (Value::Array(b), Value::Array(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let mut c = a.clone();
-                        c.extend(b);
-                        let c = self.alloc(c);
-                        self.push(Value::Array(c))?
-                    }
-                    _ => {
-                        return Err(self.runtime_error(
-                            "Arguments must be both numbers or at least one string.",
-                        ))
-                    }
-                },
-                OpCode::BuildList(length) => {
-                    let mut vec: Vec<Value> = Vec::new();
-                    for _ in 0..length {
-                        vec.push(self.pop());
-                    }
-                    vec.reverse();
-                    let vec = self.gc.alloc(vec);
-                    self.push(Value::Array(vec))?
-                }
-                OpCode::Call(arg_count) => self.call_value(self.peek(arg_count), arg_count)?,
-                OpCode::Class(value) => {
-                    if let Value::VString(name) = self.current_chunk().constants[value] {
-                        let class = Class::new(name);
-                        let class = self.alloc(class);
-                        self.push(Value::Class(class))?
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::CloseUpvalue => {
-                    self.close_upvalue(self.stack.len() - 1);
-                    self.pop();
-                }
-                OpCode::Closure(index) => match self.current_chunk().get_constant(index) {
-                    Value::Function(function) => {
-                        let upvalue_count = self.gc.deref(function).upvalues.len();
-                        let mut closure = Closure::new(function);
-
-                        for i in 0..upvalue_count {
-                            let upvalue = self.gc.deref(function).upvalues[i];
-                            let value = if upvalue.is_local {
-                                self.capture_upvalue(self.current_frame().slot + upvalue.index)
-                            } else {
-                                self.current_closure().upvalues[upvalue.index]
-                            };
-                            closure.upvalues.push(value);
-                        }
-                        let closure = self.alloc(closure);
-                        self.push(Value::Closure(closure))?
-                    }
-                    _ => return Err(self.runtime_error("Error: no function found.")),
-                },
-                OpCode::Constant(index) => self.push(self.current_chunk().get_constant(index))?,
-                OpCode::DecrementGlobal(index) => {
-                    if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
-                        match self.globals.get(&string_ref) {
-                            Some(&value) => {
-                                if let Value::Number(v) = value {
-                                    let val = Value::Number(v - 1.0);
-                                    self.push(val)?;
-                                    if self.globals.insert(string_ref, val).is_none() {
-                                        self.globals.remove(&string_ref);
-                                        return Err(self.runtime_error(&format!(
-                                            "Undefined variable '{}'.",
-                                            self.gc.deref(string_ref)
-                                        )));
-                                    }
-                                } else {
-                                    return Err(self.runtime_error(
-                                        "Only numeric variables can be incremented.",
-                                    ));
-                                }
-                            }
-                            None => {
-                                return Err(self.runtime_error(&format!(
-                                    "Undefined variable '{}'.",
-                                    self.gc.deref(string_ref)
-                                )))
-                            }
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::DecrementLocal(slot) => {
-                    let index = slot + self.current_frame().slot;
-                    if let Value::Number(value) = self.stack[index] {
-                        let value = Value::Number(value - 1.0);
-                        self.stack[index] = value;
-                        self.push(value)?;
-                    } else {
-                        return Err(self.runtime_error("Only number can be incremented."));
-                    }
-                }
-                OpCode::DecrementUpvalue(slot) => {
-                    let upvalue = self.current_closure().upvalues[slot];
-                    let value = {
-                        let upvalue = self.gc.deref(upvalue);
-                        let temp = if let Some(value) = upvalue.closed {
-                            value
-                        } else {
-                            self.stack[upvalue.location]
-                        };
-
-                        if let Value::Number(val) = temp {
-                            Value::Number(val - 1.0)
-                        } else {
-                            return Err(self.runtime_error("Only numbers can be incremented."));
-                        }
-                    };
-                    let mut upvalue = self.gc.deref_mut(upvalue);
-                    if upvalue.closed.is_none() {
-                        self.stack[upvalue.location] = value;
-                    } else {
-                        upvalue.closed = Some(value);
-                    }
-                    self.push(value)?;
-                }
-                OpCode::DefineGlobal(index) => {
-                    if let Value::VString(string_ref) = self.current_chunk().constants[index] {
-                        self.globals.insert(string_ref, self.peek(0));
-                        self.pop();
-                    } else {
-                        return Err(self.runtime_error(
-                            "Error: Invalid identifier found for definition on stack.",
-                        ));
-                    }
-                }
-                OpCode::Div => self.bin_arith_op(|x, y| x / y, "when dividing")?,
-                OpCode::Equal => self.bin_bool_op(|x, y| x == y)?,
-                OpCode::False => {
-                    self.push(Value::Bool(false))?;
-                }
-                OpCode::GetIndexArray => {
-                    let index = self.pop_number("for indexing an array.")?;
-                    if index.fract() != 0.0 {
-                        return Err(
-                            self.runtime_error("Can't index an array with a fractional number.")
-                        );
-                    }
-                    if let Value::Array(array) = self.pop() {
-                        let array = self.gc.deref(array);
-                        let value = array[index as usize];
-                        self.push(value)?
-                    } else {
-                        return Err(
-                            self.runtime_error("No array found on stack when indexing an array.")
-                        );
-                    }
-                }
-                OpCode::GetGlobal(index) => {
-                    if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
-                        match self.globals.get(&string_ref) {
-                            Some(&value) => self.push(value)?,
-                            None => {
-                                return Err(self.runtime_error(&format!(
-                                    "Undefined variable '{}'.",
-                                    self.gc.deref(string_ref)
-                                )))
-                            }
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::GetLocal(slot) => {
-                    self.push(self.stack[slot + self.current_frame().slot])?;
-                }
-                OpCode::GetProperty(slot) => {
-                    if let Value::Instance(instance) = self.peek(0) {
-                        let instance = self.gc.deref(instance);
-                        if let Value::VString(name) = self.current_chunk().get_constant(slot) {
-                            let value = instance.fields.get(&name);
-                            if let Some(&value) = value {
-                                self.pop();
-                                self.push(value)?
-                            } else {
-                                let class = instance.class;
-                                self.bind_method(class, name)?;
-                            }
-                        } else {
-                            return Err(self.runtime_error(
-                                "Error: Invalid identifier found for usage on stack.",
-                            ));
-                        }
-                    } else {
-                        return Err(self.runtime_error("Only instances have properties."));
-                    }
-                }
-                OpCode::GetSuper(slot) => {
-                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
-                        if let Value::Class(superclass) = self.pop() {
-                            self.bind_method(superclass, name)?
-                        } else {
-                            return Err(self.runtime_error("No superclass found on the stack"));
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::GetUpvalue(slot) => {
-                    let value = {
-                        let upvalue = self.current_closure().upvalues[slot];
-                        let upvalue = self.gc.deref(upvalue);
-                        if let Some(value) = upvalue.closed {
-                            value
-                        } else {
-                            self.stack[upvalue.location]
-                        }
-                    };
-                    self.push(value)?
-                }
-                OpCode::Greater => match (self.pop(), self.pop()) {
-                    (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a > b))?,
-                    (Value::VString(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let result = Value::Bool(a > b);
-                        self.push(result)?
-                    }
-                    _ => {
-                        return Err(
-                            self.runtime_error("Arguments must be of same type and comparable.")
-                        )
-                    }
-                },
-                OpCode::GreaterEqual => match (self.pop(), self.pop()) {
-                    (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a >= b))?,
-                    (Value::VString(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let result = Value::Bool(a >= b);
-                        self.push(result)?
-                    }
-                    _ => {
-                        return Err(
-                            self.runtime_error("Arguments must be of same type and comparable.")
-                        )
-                    }
-                },
-                OpCode::IncrementGlobal(index) => {
-                    if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
-                        match self.globals.get(&string_ref) {
-                            Some(&value) => {
-                                if let Value::Number(v) = value {
-                                    let val = Value::Number(v + 1.0);
-                                    self.push(val)?;
-                                    if self.globals.insert(string_ref, val).is_none() {
-                                        self.globals.remove(&string_ref);
-                                        return Err(self.runtime_error(&format!(
-                                            "Undefined variable '{}'.",
-                                            self.gc.deref(string_ref)
-                                        )));
-                                    }
-                                } else {
-                                    return Err(self.runtime_error(
-                                        "Only numeric variables can be incremented.",
-                                    ));
-                                }
-                            }
-                            None => {
-                                return Err(self.runtime_error(&format!(
-                                    "Undefined variable '{}'.",
-                                    self.gc.deref(string_ref)
-                                )))
-                            }
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::IncrementLocal(slot) => {
-                    let index = slot + self.current_frame().slot;
-                    if let Value::Number(value) = self.stack[index] {
-                        let value = Value::Number(value + 1.0);
-                        self.stack[index] = value;
-                        self.push(value)?;
-                    } else {
-                        return Err(self.runtime_error("Only number can be incremented."));
-                    }
-                }
-                OpCode::IncrementUpvalue(slot) => {
-                    let upvalue = self.current_closure().upvalues[slot];
-                    let value = {
-                        let upvalue = self.gc.deref(upvalue);
-                        let temp = if let Some(value) = upvalue.closed {
-                            value
-                        } else {
-                            self.stack[upvalue.location]
-                        };
-
-                        if let Value::Number(val) = temp {
-                            Value::Number(val + 1.0)
-                        } else {
-                            return Err(self.runtime_error("Only numbers can be incremented."));
-                        }
-                    };
-                    let mut upvalue = self.gc.deref_mut(upvalue);
-                    if upvalue.closed.is_none() {
-                        self.stack[upvalue.location] = value;
-                    } else {
-                        upvalue.closed = Some(value);
-                    }
-                    self.push(value)?;
-                }
-                OpCode::Inherit => {
-                    let pair = (self.peek(0), self.peek(1));
-                    if let (Value::Class(class), Value::Class(superclass)) = pair {
-                        let superclass = self.gc.deref(superclass);
-                        let methods = superclass.methods.clone();
-                        let class = self.gc.deref_mut(class);
-                        class.methods = methods;
-                        self.pop();
-                    } else {
-                        return Err(self.runtime_error("Superclass must be a class."));
-                    }
-                }
-                OpCode::Invoke((name, count)) => {
-                    if let Value::VString(name) = self.current_chunk().get_constant(name) {
-                        self.invoke(name, count)?
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::Jump(offset) => {
-                    self.current_frame_mut().ip += offset;
-                }
-                OpCode::JumpIfFalse(offset) => {
-                    if self.peek(0).is_false() {
-                        self.current_frame_mut().ip += offset;
-                    }
-                }
-                OpCode::Less => match (self.pop(), self.pop()) {
-                    (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a < b))?,
-                    (Value::VString(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let result = Value::Bool(a < b);
-                        self.push(result)?
-                    }
-                    _ => {
-                        return Err(
-                            self.runtime_error("Arguments must be of same type and comparable.")
-                        )
-                    }
-                },
-                OpCode::LessEqual => match (self.pop(), self.pop()) {
-                    (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a <= b))?,
-                    (Value::VString(b), Value::VString(a)) => {
-                        let a = self.gc.deref(a);
-                        let b = self.gc.deref(b);
-                        let result = Value::Bool(a <= b);
-                        self.push(result)?
-                    }
-                    _ => {
-                        return Err(
-                            self.runtime_error("Arguments must be of same type and comparable.")
-                        )
-                    }
-                },
-                OpCode::Loop(offset) => {
-                    self.current_frame_mut().ip -= offset + 1;
-                }
-                OpCode::Method(slot) => {
-                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
-                        self.define_method(name)?
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::Rem => {
-                    let (b, a) = (
-                        self.pop_number("as divisor in rem")?,
-                        self.pop_number("as dividend in rem")?,
-                    );
-                    if b.fract() == 0.0 && a.fract() == 0.0 {
-                        let a = a as usize;
-                        let b = b as usize;
-                        let rem = a % b;
-                        self.push(Value::Number(rem as f64))?
-                    }
-                }
-                OpCode::Mul => self.bin_arith_op(|x, y| x * y, "when multiplying")?,
-                OpCode::Negate => {
-                    let n = self.pop_number("to negate")?;
-                    self.push(Value::Number(-n))?
-                }
-                OpCode::Nil => self.push(Value::Nil)?,
-                OpCode::Not => {
-                    let value = self.pop().is_false();
-                    self.push(Value::Bool(value))?
-                }
-                OpCode::NotEqual => self.bin_bool_op(|x, y| x != y)?,
-                OpCode::Pop => {
-                    self.pop();
-                }
-                OpCode::Print => {
-                    let value = self.pop();
-                    if self.repl {
-                        println!(">  {}", GcTraceFormatter::new(value, &self.gc));
-                    } else {
-                        println!("{}", GcTraceFormatter::new(value, &self.gc));
-                    }
-                }
-                OpCode::Return => {
-                    let frame = self.frames.pop().unwrap();
-                    let result = self.pop();
-                    self.close_upvalue(frame.slot);
-                    if self.frames.is_empty() {
-                        return Ok(());
-                    } else {
-                        self.stack.truncate(frame.slot);
-                        self.push(result)?
-                    }
-                }
-                OpCode::ReturnNil => {
-                    let frame = self.frames.pop().unwrap();
-                    self.close_upvalue(frame.slot);
-                    if self.frames.is_empty() {
-                        return Ok(());
-                    } else {
-                        self.stack.truncate(frame.slot);
-                        self.push(Value::Nil)?
-                    }
-                }
-                OpCode::SetIndexArray => {
-                    let value = self.pop();
-                    let index = self.pop_number("for indexing an array")?;
-                    if let Value::Array(arrayref) = self.pop() {
-                        let array = self.gc.deref_mut(arrayref);
-                        array[index as usize] = value;
-                        self.push(Value::Array(arrayref))?
-                    } else {
-                        return Err(self.runtime_error("No array found on stack when indexing."));
-                    }
-                }
-                OpCode::SetGlobal(index) => {
-                    if let Value::VString(string_ref) = self.current_chunk().constants[index] {
-                        if self.globals.insert(string_ref, self.peek(0)).is_none() {
-                            self.globals.remove(&string_ref);
-                            return Err(self.runtime_error(&format!(
-                                "Undefined variable '{}'.",
-                                self.gc.deref(string_ref)
-                            )));
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::SetLocal(slot) => {
-                    let index = slot + self.current_frame().slot;
-                    self.stack[index] = self.peek(0);
-                }
-                OpCode::SetProperty(slot) => {
-                    if let Value::Instance(instance) = self.peek(1) {
-                        if let Value::VString(name) = self.current_chunk().get_constant(slot) {
-                            let value = self.pop();
-                            let instance = self.gc.deref_mut(instance);
-                            instance.fields.insert(name, value);
-                            self.pop();
-                            self.push(value)?
-                        } else {
-                            return Err(self.runtime_error(
-                                "Error: Invalid identifier found for usage on stack.",
-                            ));
-                        }
-                    } else {
-                        return Err(self.runtime_error("Only instances have fields."));
-                    }
-                }
-                OpCode::SetUpvalue(slot) => {
-                    let upvalue = self.current_closure().upvalues[slot];
-                    let value = self.peek(0);
-                    let mut upvalue = self.gc.deref_mut(upvalue);
-                    if upvalue.closed.is_none() {
-                        self.stack[upvalue.location] = value;
-                    } else {
-                        upvalue.closed = Some(value);
-                    }
-                }
-                OpCode::Sub => self.bin_arith_op(|x, y| x - y, "when subtracting")?,
-                OpCode::SuperInvoke((name, count)) => {
-                    if let Value::VString(name) = self.current_chunk().get_constant(name) {
-                        if let Value::Class(class) = self.pop() {
-                            self.invoke_from_class(class, name, count)?
-                        } else {
-                            return Err(self.runtime_error("No class found on the stack."));
-                        }
-                    } else {
-                        return Err(self
-                            .runtime_error("Error: Invalid identifier found for usage on stack."));
-                    }
-                }
-                OpCode::True => self.push(Value::Bool(true))?,
-            }
-        }
-    }
-
-    // helpers for binary operations
-
-    fn bin_arith_op(&mut self, f: fn(f64, f64) -> f64, msg: &str) -> Result<(), InterpretError> {
-        let (b, a) = (
-            self.pop_number(&format!("as second term {}", msg))?,
-            self.pop_number(&format!("as second term {}", msg))?,
-        );
-        self.push_number(f(a, b))
-    }
-
-    fn bin_bool_op(&mut self, f: fn(Value, Value) -> bool) -> Result<(), InterpretError> {
-        let (b, a) = (self.pop(), self.pop());
-        self.push(Value::Bool(f(a, b)))
-    }
-
-    // error functions
-
-    fn runtime_error(&mut self, message: &str) -> InterpretError {
-        eprintln!("{}", message);
-
-        for frame in self.frames.iter().rev() {
-            let closure = self.gc.deref(frame.closure);
-            let function = self.gc.deref(closure.function);
-            let name = self.gc.deref(function.name);
-            let name = if name.is_empty() { "<script>" } else { &name };
-            let line = function.chunk.get_line(frame.ip - 1);
-            eprintln!("[line {}] in {}", line, name);
-        }
-
-        self.stack.clear();
-        InterpretError::Runtime
-    }
-
-    // current pointers
-
-    #[inline]
-    fn current_frame(&self) -> &CallFrame {
-        self.frames.last().unwrap()
-    }
-
-    #[inline]
-    fn current_closure(&self) -> &Closure {
-        let closure = self.current_frame().closure;
-        self.gc.deref(closure)
-    }
-
-    #[inline]
-    fn current_frame_mut(&mut self) -> &mut CallFrame {
-        self.frames.last_mut().unwrap()
-    }
-
-    #[inline]
-    fn current_chunk(&self) -> &Chunk {
-        let function = self.gc.deref(self.current_closure().function);
-        &function.chunk
-    }
-
-    // helpers for calling a function
-
-    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), InterpretError> {
-        match callee {
-            Value::NativeFn(fun) => {
-                let left = self.stack.len() - arg_count;
-                let result = match fun.0(self, &self.stack[left..]) {
-                    Ok(res) => res,
-                    Err(e) => return Err(self.runtime_error(&e)),
-                };
-                self.stack.truncate(left - 1);
-                self.push(result)
-            }
-            Value::Closure(fun) => self.call(fun, arg_count),
-            Value::Class(cls) => {
-                let instance = Instance::new(cls);
-                let instance = self.alloc(instance);
-                let index = self.stack.len() - arg_count - 1;
-                self.stack[index] = Value::Instance(instance);
-
-                match self.gc.deref(cls).methods.get(&self.init_string) {
-                    Some(&method) => {
-                        if let Value::Closure(method) = method {
-                            self.call(method, arg_count)
-                        } else {
-                            Err(self.runtime_error("Initializer is not closure"))
-                        }
-                    }
-                    None => {
-                        if arg_count != 0 {
-                            let msg = format!("Expected 0 arguments but got {}.", arg_count);
-                            Err(self.runtime_error(&msg))
-                        } else {
-                            Ok(())
-                        }
-                    }
-                }
-            }
-            Value::BoundMethod(met) => {
-                let bound_method = self.gc.deref(met);
-                let method = bound_method.method;
-                let receiver = bound_method.receiver;
-                let index = self.stack.len() - 1 - arg_count;
-                self.stack[index] = receiver;
-                self.call(method, arg_count)
-            }
-            _ => Err(self.runtime_error("Can only call functions and classes.")),
-        }
-    }
-
-    fn call(&mut self, callee: GcRef<Closure>, arg_count: usize) -> Result<(), InterpretError> {
-        let closure = self.gc.deref(callee);
-        let function = self.gc.deref(closure.function);
-        if function.arity != arg_count {
-            let msg = format!(
-                "Expected {} arguments but got {}.",
-                function.arity, arg_count
-            );
-            Err(self.runtime_error(&msg))
-        } else {
-            let frame = CallFrame::new(callee, self.stack.len() - arg_count - 1);
-            self.frames.push(frame);
-            Ok(())
-        }
-    }
-
-    #[inline]
-    fn define_native(&mut self, name: &str, function: NativeFn) {
-        let name = self.intern(name.to_owned());
-        self.globals.insert(name, Value::NativeFn(function));
-    }
-
-    fn capture_upvalue(&mut self, index: usize) -> GcRef<Upvalue> {
-        for &upvalue in &self.open_upvalues {
-            if self.gc.deref(upvalue).location == index {
-                return upvalue;
-            }
-        }
-        let upvalue = Upvalue::new(index);
-        let upvalue = self.alloc(upvalue);
-        self.open_upvalues.push(upvalue);
-        upvalue
-    }
-
-    fn close_upvalue(&mut self, last: usize) {
-        let mut i = 0;
-        while i != self.open_upvalues.len() {
-            let upvalue = self.open_upvalues[i];
-            let upvalue = self.gc.deref_mut(upvalue);
-            if upvalue.location >= last {
-                self.open_upvalues.remove(i);
-                upvalue.closed = Some(self.stack[upvalue.location]);
-            } else {
-                i += 1;
-            }
-        }
-    }
-
-    fn define_method(&mut self, name: GcRef<String>) -> Result<(), InterpretError> {
-        let method = self.peek(0);
-        if let Value::Class(class) = self.peek(1) {
-            let class = self.gc.deref_mut(class);
-            class.methods.insert(name, method);
-            self.pop();
-            Ok(())
-        } else {
-            Err(self.runtime_error("Cannot define a method on non class."))
-        }
-    }
-
-    fn bind_method(
-        &mut self,
-        class: GcRef<Class>,
-        name: GcRef<String>,
-    ) -> Result<(), InterpretError> {
-        let class = self.gc.deref(class);
-        if let Some(method) = class.methods.get(&name) {
-            let receiver = self.peek(0);
-            let method = match method {
-                Value::Closure(cl) => cl,
-                _ => return Err(self.runtime_error("No method found")),
-            };
-            let bound = BoundMethod::new(receiver, *method);
-            let bound = self.alloc(bound);
-            self.pop();
-            self.push(Value::BoundMethod(bound))
-        } else {
-            let name = &self.gc.deref(name);
-            let message = format!("Undefined property '{}'.", name);
-            Err(self.runtime_error(&message))
-        }
-    }
-
-    fn invoke(&mut self, name: GcRef<String>, arg_count: usize) -> Result<(), InterpretError> {
-        let receiver = self.peek(arg_count);
-        let method_name = self.gc.deref(name).clone();
-        if method_name == "copy" {
-            if arg_count != 0 {
-                Err(self.runtime_error("Copy requires only one argument."))
-            } else {
-                let to_push = match receiver {
-                    Value::Array(value) => {
-                        let new = self.gc.deref(value).clone();
-                        let new = self.alloc(new);
-                        Value::Array(new)
-                    }
-                    Value::Instance(value) => {
-                        let new = self.gc.deref(value).clone();
-                        let new = self.alloc(new);
-                        Value::Instance(new)
-                    }
-                    _ => {
-                        return Err(self.runtime_error(&format!(
-                            "Function copy is not defined for {}",
-                            receiver.type_of()
-                        )))
-                    }
-                };
-                self.pop();
-                self.push(to_push)
-            }
-        } else if let Value::VString(string) = receiver {
-            match &*method_name {
-                "isAlpha" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("isAlpha requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push(Value::Bool(
-                            self.gc.deref(string).chars().all(char::is_alphabetic),
-                        ))
-                    }
-                }
-                "isAlphaNumeric" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("isAlphaNumeric requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push(Value::Bool(
-                            self.gc.deref(string).chars().all(char::is_alphanumeric),
-                        ))
-                    }
-                }
-                "isDigit" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("isDigit requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push(Value::Bool(
-                            self.gc.deref(string).chars().all(char::is_numeric),
-                        ))
-                    }
-                }
-
-                "float" => {
-                    if arg_count == 1 {
-                        let top = self.pop();
-                        if let Value::VString(string) = top {
-                            match self.gc.deref(string).parse() {
-                                Ok(n) => {
-                                    self.pop();
-                                    self.push_number(n)
-                                }
-                                _ => Err(self.runtime_error("couldn't read number from string")),
-                            }
-                        } else {
-                            Err(self.runtime_error(&format!(
-                                "float needs a number as an argument, found {}",
-                                top.type_of()
-                            )))
-                        }
-                    } else {
-                        Err(self.runtime_error("float needs one argument"))
-                    }
-                }
-                "length" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("length requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push_number(self.gc.deref(string).len() as f64)
-                    }
-                }
-                "ord" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("ord requires no arguments."))
-                    } else if self.gc.deref(string).chars().count() == 1 {
-                        let c = self.gc.deref(string).chars().next().unwrap();
-                        self.push_number((c as u32) as f64)
-                    } else {
-                        Err(self.runtime_error("ord can be called on one-char strings only."))
-                    }
-                }
-                _ => {
-                    Err(self
-                        .runtime_error(&format!("String doesn't have {} as method.", method_name)))
-                }
-            }
-        } else if let Value::Instance(instance) = receiver {
-            let instance = self.gc.deref(instance);
-            if let Some(&value) = instance.fields.get(&name) {
-                let pos = self.stack.len() - 1 - arg_count;
-                self.stack[pos] = value;
-                self.call_value(value, arg_count)
-            } else {
-                let class = instance.class;
-                self.invoke_from_class(class, name, arg_count)
-            }
-        } else if let Value::Array(array) = receiver {
-            match &*method_name {
-                "all" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("all requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push(Value::Bool(
-                            !self.gc.deref(array).iter().any(|&x| x.is_false()),
-                        ))
-                    }
-                }
-                "any" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("any requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push(Value::Bool(
-                            self.gc.deref(array).iter().any(|&x| !x.is_false()),
-                        ))
-                    }
-                }
-                "extend" => {
-                    if arg_count != 1 {
-                        Err(self.runtime_error("extend requires only one argument."))
-                    } else if let Value::Array(array_ref) = self.pop() {
-                        let mut new_array = self.gc.deref(array_ref).clone();
-                        self.gc.deref_mut(array).append(&mut new_array);
-                        self.pop();
-                        self.push(Value::Nil)
-                    } else {
-                        Err(self.runtime_error("extend needs an array as argument"))
-                    }
-                }
-                "length" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("length requires no arguments."))
-                    } else {
-                        self.pop();
-                        self.push_number(self.gc.deref(array).len() as f64)
-                    }
-                }
-                "pop" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("pop requires no arguments."))
-                    } else if let Some(value) = self.gc.deref_mut(array).pop() {
-                        self.pop();
-                        self.push(value)
-                    } else {
-                        Err(self.runtime_error("No element in array when popping from it."))
-                    }
-                }
-                "push" => {
-                    if arg_count == 0 {
-                        Err(self.runtime_error("No arguments given to function push."))
-                    } else {
-                        let mut temp = Vec::new();
-                        for _ in 0..arg_count {
-                            temp.push(self.pop());
-                        }
-                        temp.reverse();
-                        self.gc.deref_mut(array).append(&mut temp);
-                        self.pop();
-                        self.push(Value::Nil)
-                    }
-                }
-                "reverse" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("reverse requires only one argument."))
-                    } else {
-                        self.gc.deref_mut(array).reverse();
-                        self.pop();
-                        self.push(Value::Nil)
-                    }
-                }
-                "sort" => {
-                    let array = self.gc.deref_mut(array);
-                    if array.iter().all(|&x| matches!(x, Value::Number(_))) {
-                        array.sort_by(|a, b| {
-                            if let (Value::Number(a), Value::Number(b)) = (a, b) {
-                                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less)
-                            } else {
-                                panic!();
-                            }
-                        });
-                        self.pop();
-                        self.push(Value::Nil)
-                    } else {
-                        Err(self.runtime_error("Cannot sort an array with not-number elements"))
-                    }
-                }
-                _ => {
-                    Err(self
-                        .runtime_error(&format!("Array doesn't have {} as method.", method_name)))
-                }
-            }
-        } else if let Value::Number(n) = receiver {
-            match &*method_name {
-                "abs" => match arg_count {
-                    0 => {
-                        if let Value::Number(n) = self.pop() {
-                            self.push_number(n.abs())
-                        } else {
-                            Err(self.runtime_error("abs needs numeric argument."))
-                        }
-                    }
-                    _ => Err(self.runtime_error("abs expects only one argument.")),
-                },
-                "ceil" => match arg_count {
-                    0 => {
-                        if let Value::Number(n) = self.pop() {
-                            self.push_number(n.ceil())
-                        } else {
-                            Err(self.runtime_error("ceil needs numeric argument."))
-                        }
-                    }
-                    _ => Err(self.runtime_error("ceil needs one argument.")),
-                },
-                "chr" => {
-                    if arg_count != 0 {
-                        Err(self.runtime_error("chr requires no arguments."))
-                    } else {
-                        self.pop();
-                        let n = if n.fract() == 0.0 {
-                            n as u32
-                        } else {
-                            return Err(self.runtime_error("chr needs an integer argument."));
-                        };
-                        let s = match char::from_u32(n) {
-                            Some(c) => self.intern(c.to_string()),
-                            None => {
-                                return Err(self.runtime_error("chr couldn't read number to char"))
-                            }
-                        };
-                        self.push(Value::VString(s))
-                    }
-                }
-                "floor" => {
-                    if arg_count == 0 {
-                        self.pop();
-                        self.push_number(n.floor())
-                    } else {
-                        Err(self.runtime_error("floor needs one argument."))
-                    }
-                }
-                "pow" => {
-                    if arg_count == 1 {
-                        if let Value::Number(n1) = self.pop() {
-                            self.push_number(n.powf(n1))
-                        } else {
-                            Err(self.runtime_error("sqrt needs numeric argument"))
-                        }
-                    } else {
-                        Err(self.runtime_error("sqrt expects only one argument"))
-                    }
-                }
-                "sqrt" => {
-                    if arg_count == 0 {
-                        self.pop();
-                        self.push_number(n.sqrt())
-                    } else {
-                        Err(self.runtime_error("sqrt expects only one argument"))
-                    }
-                }
-                "square" => {
-                    if arg_count == 0 {
-                        self.pop();
-                        self.push_number(n * n)
-                    } else {
-                        Err(self.runtime_error("square expects only one argument"))
-                    }
-                }
-                _ => {
-                    Err(self
-                        .runtime_error(&format!("Float doesn't have {} as method.", method_name)))
-                }
-            }
-        } else if method_name == "toString" {
-            if arg_count != 0 {
-                Err(self.runtime_error("toString requires no arguments"))
-            } else {
-                let string = format!("{}", GcTraceFormatter::new(receiver, &self.gc));
-                let string = self.alloc(string);
-                self.pop();
-                self.push(Value::VString(string))
-            }
-        } else {
-            Err(self.runtime_error("Only instances have methods."))
-        }
-    }
-
-    fn invoke_from_class(
-        &mut self,
-        class: GcRef<Class>,
-        name: GcRef<String>,
-        count: usize,
-    ) -> Result<(), InterpretError> {
-        let class = self.gc.deref(class);
-        if let Some(&method) = class.methods.get(&name) {
-            if let Value::Closure(closure) = method {
-                self.call(closure, count)
-            } else {
-                Err(self.runtime_error("Got method that is not closure!"))
-            }
-        } else if self.gc.deref(name) == "toString" {
-            if count != 0 {
-                Err(self.runtime_error("toString requires no arguments"))
-            } else {
-                let name = class.name;
-                self.pop();
-                self.push(Value::VString(name))
-            }
-        } else {
-            let name = &self.gc.deref(name);
-            let message = format!("Undefined property '{}'.", name);
-            Err(self.runtime_error(&message))
-        }
-    }
-
-    // garbage collection helpers
-
-    fn collect_garbage(&mut self) {
-        if self.gc.should_gc() {
-            #[cfg(feature = "debug_gc_log")]
-            eprintln!("\n-- gc start");
-            self.mark_roots();
-            self.gc.collect_garbage();
-            #[cfg(feature = "debug_gc_log")]
-            eprintln!("-- gc end\n");
-        }
-    }
-
-    #[inline]
-    fn alloc<T: GcTrace + 'static + fmt::Debug>(&mut self, object: T) -> GcRef<T> {
-        self.collect_garbage();
-        self.gc.alloc(object)
-    }
-
-    #[inline]
-    fn intern(&mut self, string: String) -> GcRef<String> {
-        self.collect_garbage();
-        self.gc.intern(string)
-    }
-
-    fn mark_roots(&mut self) {
-        for &value in &self.stack {
-            self.gc.mark_value(value);
-        }
-
-        for frame in &self.frames {
-            self.gc.mark_object(frame.closure);
-        }
-
-        for &upvalue in &self.open_upvalues {
-            self.gc.mark_object(upvalue);
-        }
-
-        self.gc.mark_table(&self.globals);
-        self.gc.mark_object(self.init_string);
-    }
-}
-
-struct CallFrame {
-    closure: GcRef<Closure>,
-    ip: usize,
-    slot: usize,
-}
-
-impl CallFrame {
-    fn new(closure: GcRef<Closure>, slot: usize) -> Self {
-        CallFrame {
-            closure,
-            ip: 0,
-            slot,
-        }
-    }
-}
-
-// native functions
-
-fn clock(vm: &Vm, _args: &[Value]) -> Result<Value, String> {
-    let time = vm.start_time.elapsed().as_secs_f64();
-    Ok(Value::Number(time))
-}
-
-fn instance_of(vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        2 => {
-            if let (Value::Instance(instance), Value::Class(class)) = (args[0], args[1]) {
-                let class_ref = vm.gc.deref(instance).class;
-                Ok(Value::Bool(class_ref == class))
-            } else {
-                Err(format!(
-                    "instanceof needs an instance and a class, found {} {}",
-                    args[0].type_of(),
-                    args[1].type_of()
-                ))
-            }
-        }
-        _ => Err("instanceof needs two arguments".to_owned()),
-    }
-}
-
-fn is_bool(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Bool(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isBool needs one argument".to_owned()),
-    }
-}
-
-fn is_class(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Class(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isClass needs one argument".to_owned()),
-    }
-}
-
-fn is_closure(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Closure(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isClosure needs one argument".to_owned()),
-    }
-}
-
-fn is_function(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Function(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isFunction needs one argument".to_owned()),
-    }
-}
-
-fn is_instance(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Instance(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isInstance needs one argument".to_owned()),
-    }
-}
-
-fn is_nil(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Nil = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isNil needs one argument".to_owned()),
-    }
-}
-
-fn is_number(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::Number(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isNumber needs one argument".to_owned()),
-    }
-}
-
-fn is_string(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        1 => {
-            if let Value::VString(_) = args[0] {
-                Ok(Value::Bool(true))
-            } else {
-                Ok(Value::Bool(false))
-            }
-        }
-        _ => Err("isString needs one argument".to_owned()),
-    }
-}
-
-fn lox_panic(vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    let mut terms: Vec<String> = vec![];
-
-    for &arg in args.iter() {
-        let formatter = GcTraceFormatter::new(arg, &vm.gc);
-        let term = format!("{}", formatter);
-        terms.push(term);
-    }
-
-    panic!("panic: {}", terms.join(", "))
-}
-
-fn max(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        0 | 1 => Err("max expects more than 1 argument".to_owned()),
-        _ => {
-            let mut max = -f64::INFINITY;
-            for &arg in args.iter() {
-                if let Value::Number(n) = arg {
-                    max = max.max(n);
-                } else {
-                    return Err("max needs numeric argument".to_owned());
-                }
-            }
-            Ok(Value::Number(max))
-        }
-    }
-}
-
-fn min(_vm: &Vm, args: &[Value]) -> Result<Value, String> {
-    match args.len() {
-        0 | 1 => Err("min expects more than 1 argument".to_owned()),
-        _ => {
-            let mut min = f64::INFINITY;
-            for &arg in args.iter() {
-                if let Value::Number(n) = arg {
-                    min = min.min(n);
-                } else {
-                    return Err("min needs numeric argument".to_owned());
-                }
-            }
-            Ok(Value::Number(min))
-        }
-    }
-}
+use crate::{
+    chunk::{Chunk, Disassembler, OpCode, Operands},
+    compiler,
+    gc::{Gc, GcPhase, GcRef, GcTrace, GcTraceFormatter},
+    object::*,
+    stdlib::{self, Module},
+    types::{InterpretError, Table, Value},
+};
+use cpu_time::ProcessTime;
+use std::{
+    fmt, fs, process,
+    sync::{atomic::AtomicBool, atomic::Ordering, Arc},
+};
+
+/// Pops `a`/`b` for a binary numeric op and evaluates `$int_body`/`$float_body`
+/// against the four `(Value::Int | Value::Number, Value::Int | Value::Number)`
+/// combinations, promoting to `f64` as soon as either side is a `Number` and
+/// staying in `i64` only when both sides are `Int` — mirrors the `Int`/
+/// `Number` coercion `OpCode::Add` already does by hand alongside its string
+/// concatenation arms. Each body
+/// must evaluate to the `Value` to push; `$err_msg` is used when either
+/// operand is not an `Int` or `Number`.
+macro_rules! numeric_binop {
+    ($self:ident, |$a:ident, $b:ident| $int_body:expr, |$a2:ident, $b2:ident| $float_body:expr, $err_msg:expr) => {{
+        match ($self.pop(), $self.pop()) {
+            (Value::Int($b), Value::Int($a)) => {
+                let result = $int_body;
+                $self.push(result)?
+            }
+            (Value::Int($b2), Value::Number($a2)) => {
+                let $b2 = $b2 as f64;
+                let result = Value::Number($float_body);
+                $self.push(result)?
+            }
+            (Value::Number($b2), Value::Int($a2)) => {
+                let $a2 = $a2 as f64;
+                let result = Value::Number($float_body);
+                $self.push(result)?
+            }
+            (Value::Number($b2), Value::Number($a2)) => {
+                let result = Value::Number($float_body);
+                $self.push(result)?
+            }
+            _ => return Err($self.runtime_error($err_msg)),
+        }
+    }};
+}
+
+pub struct Vm {
+    debug: bool,
+    repl: bool,
+    pub(crate) gc: Gc,
+    stack: Vec<Value>,
+    globals: Table,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<GcRef<Upvalue>>,
+    start_time: ProcessTime,
+    init_string: GcRef<String>,
+    /// Set by [`Vm::throw`] when it unwound to a handler rather than falling
+    /// off the top of [`Vm::frames`]; [`Vm::run`] checks this right after an
+    /// instruction errors to decide whether to keep looping or propagate.
+    caught_exception: bool,
+    /// Maximum length of [`Vm::stack`] before `push` reports a "Stack
+    /// overflow." runtime error instead of growing further.
+    stack_max: usize,
+    /// Maximum depth of [`Vm::frames`] before calling a function reports a
+    /// "Call stack overflow." runtime error instead of recursing further.
+    frame_max: usize,
+    /// Set from outside the VM (e.g. a Ctrl-C handler in the REPL) to abort
+    /// the running script. Checked on every backward [`OpCode::Loop`], so a
+    /// spinning `while`/`for` loop can be interrupted without aborting the
+    /// process; see [`Vm::interrupt_handle`].
+    interrupt: Arc<AtomicBool>,
+}
+
+/// Default [`Vm::stack_max`]: generous for normal programs, small enough to
+/// fail a runaway recursive script well before the host starves for memory.
+const DEFAULT_STACK_MAX: usize = 256 * 1024;
+
+/// Default [`Vm::frame_max`]: matches the `clox`/jlox convention of a few
+/// thousand call frames, scaled up since `CallFrame` here is fairly small.
+const DEFAULT_FRAME_MAX: usize = 16 * 1024;
+
+impl Vm {
+    // public interface
+
+    pub fn new(repl: bool) -> Self {
+        let mut gc = Gc::new();
+        let init_string = gc.intern("init".to_owned());
+
+        let mut vm = Vm {
+            debug: false,
+            repl,
+            gc,
+            stack: Vec::new(),
+            globals: Table::new(),
+            frames: Vec::new(),
+            open_upvalues: Vec::new(),
+            start_time: ProcessTime::now(),
+            init_string,
+            caught_exception: false,
+            stack_max: DEFAULT_STACK_MAX,
+            frame_max: DEFAULT_FRAME_MAX,
+            interrupt: Arc::new(AtomicBool::new(false)),
+        };
+
+        // native function definition
+        vm.define_native("clock", Some(0), clock);
+        vm.define_native("panic", None, lox_panic);
+        vm.define_native("raise", Some(1), lox_raise);
+        vm.define_native("min", None, min);
+        vm.define_native("max", None, max);
+        vm.define_native("range", None, range);
+        vm.define_native("isBool", Some(1), is_bool);
+        vm.define_native("isClass", Some(1), is_class);
+        vm.define_native("isClosure", Some(1), is_closure);
+        vm.define_native("isFunction", Some(1), is_function);
+        vm.define_native("isInstance", Some(1), is_instance);
+        vm.define_native("isNil", Some(1), is_nil);
+        vm.define_native("isNumber", Some(1), is_number);
+        vm.define_native("isString", Some(1), is_string);
+        vm.define_native("instanceof", Some(2), instance_of);
+
+        // standard library: each module's natives are installed as globals
+        // named `{module}_{native}`, the same flattening the `#module`
+        // preprocessor directive uses to reach a source module's members as
+        // `alias.thing` (see preprocessor::mangle_module).
+        vm.load_module(stdlib::math_module());
+        vm.load_module(stdlib::iter_module());
+        vm.load_module(stdlib::sys_module());
+        vm.load_module(stdlib::io_module());
+
+        vm
+    }
+
+    pub fn interpret(&mut self, code: &str) -> Result<(), InterpretError> {
+        let function = if self.repl {
+            compiler::compile_repl(code, &mut self.gc)?
+        } else {
+            compiler::compile(code, &mut self.gc)?
+        };
+        self.push(Value::Function(function))?;
+        let closure = self.alloc(Closure::new(function));
+        self.frames.push(CallFrame::new(closure, 0));
+        self.run()
+    }
+
+    /// Runs only the compiler stage, discarding the resulting chunk instead of
+    /// handing it to the VM, so `--compile-only` can report compile errors
+    /// without any runtime effects.
+    pub fn compile_only(&mut self, code: &str) -> Result<(), InterpretError> {
+        compiler::compile(code, &mut self.gc)?;
+        Ok(())
+    }
+
+    pub fn dump(&mut self, code: &str, file: &str) -> Result<(), InterpretError> {
+        let function = compiler::compile(code, &mut self.gc)?;
+        let function = self.gc.deref(function);
+        let name = &self.gc.deref(function.name);
+        let disassembler = Disassembler::new(&self.gc, &function.chunk, Some(&self.stack));
+        let mut content = vec![disassembler.disassemble_to_string(name)];
+        for gcref in self.gc.objects.iter().rev().flatten() {
+            if let Some(fun) = gcref.object.as_any().downcast_ref::<Function>() {
+                if fun.name != function.name {
+                    let name = &self.gc.deref(fun.name);
+                    let disassembler = Disassembler::new(&self.gc, &fun.chunk, Some(&self.stack));
+                    content.push(disassembler.disassemble_to_string(name));
+                }
+            }
+        }
+        fs::write(file, content.join("")).expect("Couldn't write to file.");
+        Ok(())
+    }
+
+    /// Compiles `code` and returns its chunk's disassembly without running
+    /// it, the way [`Vm::dump`] does for a whole file - but returned as a
+    /// `String` instead of written out, so the REPL's `:disasm` command can
+    /// print it straight to the terminal for a single line.
+    pub fn disassemble(&mut self, code: &str) -> Result<String, InterpretError> {
+        let function = compiler::compile(code, &mut self.gc)?;
+        let function = self.gc.deref(function);
+        let name = &self.gc.deref(function.name);
+        let disassembler = Disassembler::new(&self.gc, &function.chunk, Some(&self.stack));
+        Ok(disassembler.disassemble_to_string(name))
+    }
+
+    #[inline]
+    pub fn set_debug(&mut self) {
+        self.debug = true;
+    }
+
+    #[inline]
+    pub fn unset_debug(&mut self) {
+        self.debug = false;
+    }
+
+    /// Whether execution tracing (`debug`) is currently on; backs the REPL's
+    /// `:trace` toggle, which flips the same flag `:set debug`/`:unset debug`
+    /// do under a name matching this crate's `debug_trace_execution` feature.
+    #[inline]
+    pub fn is_debug(&self) -> bool {
+        self.debug
+    }
+
+    #[inline]
+    pub fn set_stack_max(&mut self, stack_max: usize) {
+        self.stack_max = stack_max;
+    }
+
+    #[inline]
+    pub fn set_frame_max(&mut self, frame_max: usize) {
+        self.frame_max = frame_max;
+    }
+
+    /// Hands out a clone of the interrupt flag; a host can set it (e.g. from
+    /// a Ctrl-C signal handler) to abort the script currently running in
+    /// [`Vm::run`] with an "Interrupted." runtime error.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    // stack manipulation
+
+    fn pop(&mut self) -> Value {
+        if let Some(value) = self.stack.pop() {
+            value
+        } else {
+            eprintln!("Error: popping a value from empty stack.");
+            process::exit(65);
+        }
+    }
+
+    fn pop_number(&mut self, msg: &str) -> Result<f64, InterpretError> {
+        if let Value::Number(n) = self.pop() {
+            Ok(n)
+        } else {
+            Err(self.runtime_error(&format!("Error: no number found on stack {}.", msg)))
+        }
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), InterpretError> {
+        if self.stack.len() >= self.stack_max {
+            Err(self.runtime_error("Stack overflow."))
+        } else {
+            self.stack.push(value);
+            Ok(())
+        }
+    }
+
+    fn push_number(&mut self, n: f64) -> Result<(), InterpretError> {
+        self.push(Value::Number(n))
+    }
+
+    /// Pops the two operands for a bitwise/shift op. Since `Value::Int` is a
+    /// first-class variant here rather than `Number` alone, an operand either
+    /// already is an integer or it isn't — there's no `fract() == 0.0` float
+    /// coercion to perform on the way in.
+    fn pop_ints(&mut self, msg: &str) -> Result<(i64, i64), InterpretError> {
+        match (self.pop(), self.pop()) {
+            (Value::Int(b), Value::Int(a)) => Ok((a, b)),
+            _ => Err(self.runtime_error(&format!("Operands must be integers {}.", msg))),
+        }
+    }
+
+    #[inline]
+    fn peek(&self, index: usize) -> Value {
+        self.stack[self.stack.len() - 1 - index]
+    }
+
+    #[inline]
+    fn swap_top(&mut self) {
+        let len = self.stack.len();
+        self.stack.swap(len - 1, len - 2);
+    }
+
+    // main function
+
+    fn run(&mut self) -> Result<(), InterpretError> {
+        self.run_until(0)
+    }
+
+    /// Executes instructions until [`Vm::frames`] unwinds back down to
+    /// `depth` entries. [`Vm::run`] calls this with `depth` 0 to drive the
+    /// whole script; [`Vm::call_callback`] calls it with the frame depth
+    /// captured just before pushing a callback's frame, so control returns
+    /// to the native that invoked the callback as soon as it (and anything
+    /// it calls) has returned, instead of running the rest of the program.
+    fn run_until(&mut self, depth: usize) -> Result<(), InterpretError> {
+        while self.frames.len() > depth {
+            let ip = self.current_frame().ip;
+            let (opcode, operands, next_ip) = self.current_chunk().decode(ip);
+            if self.debug || cfg!(feature = "debug_trace_execution") {
+                let disassembler =
+                    Disassembler::new(&self.gc, self.current_chunk(), Some(&self.stack));
+                disassembler.disassemble_instruction(opcode, operands, ip);
+                println!();
+            }
+
+            self.current_frame_mut().ip = next_ip;
+
+            match self.execute_instruction(opcode, operands) {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                // `caught_exception` means `throw` already rewound `ip`/`stack`
+                // to a handler; swallow the Err here and keep looping instead
+                // of tearing down the interpreter.
+                Err(_) if self.caught_exception => self.caught_exception = false,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `callee` (a closure, bound method, class, or native passed
+    /// around as a plain [`Value`]) with `args` and hands back its result,
+    /// the way a native like `iter_map`/`iter_filter`/`iter_reduce` calls
+    /// back into Lox code. Mirrors the stack/frame setup `OpCode::Call`
+    /// does, then drives execution with [`Vm::run_until`] instead of
+    /// returning to the bytecode loop.
+    pub(crate) fn call_callback(&mut self, callee: Value, args: &[Value]) -> Result<Value, String> {
+        let depth = self.frames.len();
+        self.push(callee)
+            .map_err(|_| "Stack overflow in callback.".to_owned())?;
+        for &arg in args {
+            self.push(arg)
+                .map_err(|_| "Stack overflow in callback.".to_owned())?;
+        }
+        self.call_value(callee, args.len())
+            .map_err(|_| "callback raised an error".to_owned())?;
+        self.run_until(depth)
+            .map_err(|_| "callback raised an error".to_owned())?;
+        Ok(self.pop())
+    }
+
+    /// Runs a single decoded instruction. Returns `Ok(true)` once the script's
+    /// outermost frame returns (signalling `run` to stop), `Ok(false)` to keep
+    /// looping, and `Err` on a runtime error `throw` couldn't find a handler for.
+    fn execute_instruction(
+        &mut self,
+        opcode: OpCode,
+        operands: Operands,
+    ) -> Result<bool, InterpretError> {
+        match opcode {
+            OpCode::Add => match (self.pop(), self.pop()) {
+                (Value::Number(b), Value::Number(a)) => self.push(Value::Number(a + b))?,
+                (Value::Int(b), Value::Int(a)) => self.push(Value::Int(a + b))?,
+                (Value::Int(b), Value::Number(a)) => self.push(Value::Number(a + b as f64))?,
+                (Value::Number(b), Value::Int(a)) => self.push(Value::Number(a as f64 + b))?,
+                (Value::VString(b), Value::VString(a)) => {
+                    let a = self.gc.deref(a);
+                    let b = self.gc.deref(b);
+                    let new = format!("{}{}", a, b);
+                    let string = self.intern(new);
+                    self.push(Value::VString(string))?
+                }
+                (Value::VString(b), Value::Number(a)) => {
+                    let b = self.gc.deref(b);
+                    let new = format!("{}{}", a, b);
+                    let string = self.intern(new);
+                    self.push(Value::VString(string))?
+                }
+                (Value::Number(b), Value::VString(a)) => {
+                    let a = self.gc.deref(a);
+                    let new = format!("{}{}", a, b);
+                    let string = self.intern(new);
+                    self.push(Value::VString(string))?
+                }
+                (Value::VString(b), Value::Int(a)) => {
+                    let b = self.gc.deref(b);
+                    let new = format!("{}{}", a, b);
+                    let string = self.intern(new);
+                    self.push(Value::VString(string))?
+                }
+                (Value::Int(b), Value::VString(a)) => {
+                    let a = self.gc.deref(a);
+                    let new = format!("{}{}", a, b);
+                    let string = self.intern(new);
+                    self.push(Value::VString(string))?
+                }
+                (Value::Array(b), Value::Array(a)) => {
+                    let a = self.gc.deref(a);
+                    let b = self.gc.deref(b);
+                    let mut c = a.clone();
+                    c.extend(b);
+                    let c = self.alloc(c);
+                    self.push(Value::Array(c))?
+                }
+                (b, a)
+                    if matches!(b, Value::Rational(_, _) | Value::Complex(_, _))
+                        || matches!(a, Value::Rational(_, _) | Value::Complex(_, _)) =>
+                {
+                    self.push(a)?;
+                    self.push(b)?;
+                    self.tower_binop(
+                        |a, b| Value::Int(a + b),
+                        |an, ad, bn, bd| Value::rational(an * bd + bn * ad, ad * bd),
+                        |a, b| a + b,
+                        |(are, aim), (bre, bim)| (are + bre, aim + bim),
+                        "when adding",
+                    )?
+                }
+                _ => {
+                    return Err(self
+                        .runtime_error("Arguments must be both numbers or at least one string."))
+                }
+            },
+            OpCode::BuildList => {
+                let length = operands.one();
+                let mut vec: Vec<Value> = Vec::new();
+                for _ in 0..length {
+                    vec.push(self.pop());
+                }
+                vec.reverse();
+                let vec = self.gc.alloc(vec);
+                self.push(Value::Array(vec))?
+            }
+            OpCode::Call => {
+                let arg_count = operands.one();
+                self.call_value(self.peek(arg_count), arg_count)?
+            }
+            OpCode::Class => {
+                let value = operands.one();
+                if let Value::VString(name) = self.current_chunk().constants[value] {
+                    let class = Class::new(name);
+                    let class = self.alloc(class);
+                    self.push(Value::Class(class))?
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::CloseUpvalue => {
+                self.close_upvalue(self.stack.len() - 1);
+                self.pop();
+            }
+            OpCode::Closure => match self.current_chunk().get_constant(operands.one()) {
+                Value::Function(function) => {
+                    let upvalue_count = self.gc.deref(function).upvalues.len();
+                    let mut closure = Closure::new(function);
+
+                    for i in 0..upvalue_count {
+                        let upvalue = self.gc.deref(function).upvalues[i];
+                        let value = if upvalue.is_local {
+                            self.capture_upvalue(self.current_frame().slot + upvalue.index)
+                        } else {
+                            self.current_closure().upvalues[upvalue.index]
+                        };
+                        closure.upvalues.push(value);
+                    }
+                    let closure = self.alloc(closure);
+                    self.push(Value::Closure(closure))?
+                }
+                _ => return Err(self.runtime_error("Error: no function found.")),
+            },
+            OpCode::Constant => self.push(self.current_chunk().get_constant(operands.one()))?,
+            OpCode::DecrementGlobal => {
+                let index = operands.one();
+                if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
+                    match self.globals.get(&string_ref) {
+                        Some(&value) => {
+                            if let Value::Number(v) = value {
+                                let val = Value::Number(v - 1.0);
+                                self.push(val)?;
+                                if self.globals.insert(string_ref, val).is_none() {
+                                    self.globals.remove(&string_ref);
+                                    return Err(self.runtime_error(&format!(
+                                        "Undefined variable '{}'.",
+                                        self.gc.deref(string_ref)
+                                    )));
+                                }
+                            } else {
+                                return Err(self
+                                    .runtime_error("Only numeric variables can be incremented."));
+                            }
+                        }
+                        None => {
+                            return Err(self.runtime_error(&format!(
+                                "Undefined variable '{}'.",
+                                self.gc.deref(string_ref)
+                            )))
+                        }
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::DecrementLocal => {
+                let slot = operands.one();
+                let index = slot + self.current_frame().slot;
+                if let Value::Number(value) = self.stack[index] {
+                    let value = Value::Number(value - 1.0);
+                    self.stack[index] = value;
+                    self.push(value)?;
+                } else {
+                    return Err(self.runtime_error("Only number can be incremented."));
+                }
+            }
+            OpCode::DecrementUpvalue => {
+                let slot = operands.one();
+                let upvalue = self.current_closure().upvalues[slot];
+                let value = {
+                    let upvalue = self.gc.deref(upvalue);
+                    let temp = if let Some(value) = upvalue.closed {
+                        value
+                    } else {
+                        self.stack[upvalue.location]
+                    };
+
+                    if let Value::Number(val) = temp {
+                        Value::Number(val - 1.0)
+                    } else {
+                        return Err(self.runtime_error("Only numbers can be incremented."));
+                    }
+                };
+                let mut upvalue = self.gc.deref_mut(upvalue);
+                if upvalue.closed.is_none() {
+                    self.stack[upvalue.location] = value;
+                } else {
+                    upvalue.closed = Some(value);
+                }
+                self.push(value)?;
+            }
+            OpCode::DecrementIndexArray => {
+                let index = self.pop_number("for indexing an array")?;
+                if let Value::Array(arrayref) = self.pop() {
+                    let array = self.gc.deref_mut(arrayref);
+                    if let Value::Number(value) = array[index as usize] {
+                        let value = Value::Number(value - 1.0);
+                        array[index as usize] = value;
+                        self.push(value)?;
+                    } else {
+                        return Err(self.runtime_error("Only numbers can be decremented."));
+                    }
+                } else {
+                    return Err(self.runtime_error("No array found on stack when indexing."));
+                }
+            }
+            OpCode::DecrementProperty => {
+                let slot = operands.one();
+                if let Value::Instance(instance) = self.pop() {
+                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                        let instance = self.gc.deref_mut(instance);
+                        let value = match instance.fields.get(&name) {
+                            Some(Value::Number(v)) => Value::Number(v - 1.0),
+                            Some(_) => {
+                                return Err(self.runtime_error("Only numbers can be decremented."))
+                            }
+                            None => {
+                                return Err(self.runtime_error(&format!(
+                                    "Undefined property '{}'.",
+                                    self.gc.deref(name)
+                                )))
+                            }
+                        };
+                        instance.fields.insert(name, value);
+                        self.push(value)?;
+                    } else {
+                        return Err(self
+                            .runtime_error("Error: Invalid identifier found for usage on stack."));
+                    }
+                } else {
+                    return Err(self.runtime_error("Only instances have fields."));
+                }
+            }
+            OpCode::DefineGlobal => {
+                let index = operands.one();
+                if let Value::VString(string_ref) = self.current_chunk().constants[index] {
+                    self.globals.insert(string_ref, self.peek(0));
+                    self.pop();
+                } else {
+                    return Err(self.runtime_error(
+                        "Error: Invalid identifier found for definition on stack.",
+                    ));
+                }
+            }
+            OpCode::Div => self.tower_binop(
+                |a, b| Value::Number(a as f64 / b as f64),
+                |an, ad, bn, bd| Value::rational(an * bd, ad * bn),
+                |a, b| a / b,
+                |(are, aim), (bre, bim)| {
+                    let denom = bre * bre + bim * bim;
+                    (
+                        (are * bre + aim * bim) / denom,
+                        (aim * bre - are * bim) / denom,
+                    )
+                },
+                "when dividing",
+            )?,
+            OpCode::Dup => {
+                let value = self.peek(0);
+                self.push(value)?;
+            }
+            OpCode::Equal => self.bin_bool_op(|x, y| x == y)?,
+            OpCode::False => {
+                self.push(Value::Bool(false))?;
+            }
+            OpCode::GetIndexArray => {
+                let index = match self.pop() {
+                    Value::Int(index) => index,
+                    Value::Number(index) if index.fract() == 0.0 => index as i64,
+                    Value::Number(_) => {
+                        return Err(
+                            self.runtime_error("Can't index an array with a fractional number.")
+                        )
+                    }
+                    _ => return Err(self.runtime_error("Can only index an array with an integer.")),
+                };
+                if let Value::Array(array) = self.pop() {
+                    let array = self.gc.deref(array);
+                    let value = array[index as usize];
+                    self.push(value)?
+                } else {
+                    return Err(
+                        self.runtime_error("No array found on stack when indexing an array.")
+                    );
+                }
+            }
+            OpCode::GetGlobal => {
+                let index = operands.one();
+                if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
+                    match self.globals.get(&string_ref) {
+                        Some(&value) => self.push(value)?,
+                        None => {
+                            return Err(self.runtime_error(&format!(
+                                "Undefined variable '{}'.",
+                                self.gc.deref(string_ref)
+                            )))
+                        }
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::GetLocal => {
+                let slot = operands.one();
+                self.push(self.stack[slot + self.current_frame().slot])?;
+            }
+            OpCode::GetProperty => {
+                let slot = operands.one();
+                if let Value::Instance(instance) = self.peek(0) {
+                    let instance = self.gc.deref(instance);
+                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                        let value = instance.fields.get(&name);
+                        if let Some(&value) = value {
+                            self.pop();
+                            self.push(value)?
+                        } else {
+                            let class = instance.class;
+                            self.bind_method(class, name)?;
+                        }
+                    } else {
+                        return Err(self
+                            .runtime_error("Error: Invalid identifier found for usage on stack."));
+                    }
+                } else {
+                    return Err(self.runtime_error("Only instances have properties."));
+                }
+            }
+            OpCode::GetSuper => {
+                let slot = operands.one();
+                if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                    if let Value::Class(superclass) = self.pop() {
+                        self.bind_method(superclass, name)?
+                    } else {
+                        return Err(self.runtime_error("No superclass found on the stack"));
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::GetUpvalue => {
+                let slot = operands.one();
+                let value = {
+                    let upvalue = self.current_closure().upvalues[slot];
+                    let upvalue = self.gc.deref(upvalue);
+                    if let Some(value) = upvalue.closed {
+                        value
+                    } else {
+                        self.stack[upvalue.location]
+                    }
+                };
+                self.push(value)?
+            }
+            OpCode::Greater => {
+                let (b, a) = (self.pop(), self.pop());
+                let ordering = self.val_cmp(a, b)?;
+                self.push(Value::Bool(ordering == std::cmp::Ordering::Greater))?
+            }
+            OpCode::GreaterEqual => {
+                let (b, a) = (self.pop(), self.pop());
+                let ordering = self.val_cmp(a, b)?;
+                self.push(Value::Bool(ordering != std::cmp::Ordering::Less))?
+            }
+            OpCode::IncrementGlobal => {
+                let index = operands.one();
+                if let Value::VString(string_ref) = self.current_chunk().get_constant(index) {
+                    match self.globals.get(&string_ref) {
+                        Some(&value) => {
+                            if let Value::Number(v) = value {
+                                let val = Value::Number(v + 1.0);
+                                self.push(val)?;
+                                if self.globals.insert(string_ref, val).is_none() {
+                                    self.globals.remove(&string_ref);
+                                    return Err(self.runtime_error(&format!(
+                                        "Undefined variable '{}'.",
+                                        self.gc.deref(string_ref)
+                                    )));
+                                }
+                            } else {
+                                return Err(self
+                                    .runtime_error("Only numeric variables can be incremented."));
+                            }
+                        }
+                        None => {
+                            return Err(self.runtime_error(&format!(
+                                "Undefined variable '{}'.",
+                                self.gc.deref(string_ref)
+                            )))
+                        }
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::IncrementLocal => {
+                let slot = operands.one();
+                let index = slot + self.current_frame().slot;
+                if let Value::Number(value) = self.stack[index] {
+                    let value = Value::Number(value + 1.0);
+                    self.stack[index] = value;
+                    self.push(value)?;
+                } else {
+                    return Err(self.runtime_error("Only number can be incremented."));
+                }
+            }
+            OpCode::IncrementUpvalue => {
+                let slot = operands.one();
+                let upvalue = self.current_closure().upvalues[slot];
+                let value = {
+                    let upvalue = self.gc.deref(upvalue);
+                    let temp = if let Some(value) = upvalue.closed {
+                        value
+                    } else {
+                        self.stack[upvalue.location]
+                    };
+
+                    if let Value::Number(val) = temp {
+                        Value::Number(val + 1.0)
+                    } else {
+                        return Err(self.runtime_error("Only numbers can be incremented."));
+                    }
+                };
+                let mut upvalue = self.gc.deref_mut(upvalue);
+                if upvalue.closed.is_none() {
+                    self.stack[upvalue.location] = value;
+                } else {
+                    upvalue.closed = Some(value);
+                }
+                self.push(value)?;
+            }
+            OpCode::IncrementIndexArray => {
+                let index = self.pop_number("for indexing an array")?;
+                if let Value::Array(arrayref) = self.pop() {
+                    let array = self.gc.deref_mut(arrayref);
+                    if let Value::Number(value) = array[index as usize] {
+                        let value = Value::Number(value + 1.0);
+                        array[index as usize] = value;
+                        self.push(value)?;
+                    } else {
+                        return Err(self.runtime_error("Only numbers can be incremented."));
+                    }
+                } else {
+                    return Err(self.runtime_error("No array found on stack when indexing."));
+                }
+            }
+            OpCode::IncrementProperty => {
+                let slot = operands.one();
+                if let Value::Instance(instance) = self.pop() {
+                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                        let instance = self.gc.deref_mut(instance);
+                        let value = match instance.fields.get(&name) {
+                            Some(Value::Number(v)) => Value::Number(v + 1.0),
+                            Some(_) => {
+                                return Err(self.runtime_error("Only numbers can be incremented."))
+                            }
+                            None => {
+                                return Err(self.runtime_error(&format!(
+                                    "Undefined property '{}'.",
+                                    self.gc.deref(name)
+                                )))
+                            }
+                        };
+                        instance.fields.insert(name, value);
+                        self.push(value)?;
+                    } else {
+                        return Err(self
+                            .runtime_error("Error: Invalid identifier found for usage on stack."));
+                    }
+                } else {
+                    return Err(self.runtime_error("Only instances have fields."));
+                }
+            }
+            OpCode::Inherit => {
+                let pair = (self.peek(0), self.peek(1));
+                if let (Value::Class(class), Value::Class(superclass_ref)) = pair {
+                    let methods = self.gc.deref(superclass_ref).methods.clone();
+                    let method_values: Vec<Value> = methods.values().copied().collect();
+                    let class_mut = self.gc.deref_mut(class);
+                    class_mut.methods = methods;
+                    class_mut.superclass = Some(superclass_ref);
+                    for method in method_values {
+                        self.write_barrier(class, method);
+                    }
+                    self.write_barrier(class, Value::Class(superclass_ref));
+                    self.pop();
+                } else {
+                    return Err(self.runtime_error("Superclass must be a class."));
+                }
+            }
+            OpCode::Invoke => {
+                let (name, count) = operands.two();
+                if let Value::VString(name) = self.current_chunk().get_constant(name) {
+                    self.invoke(name, count)?
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::Jump => {
+                self.current_frame_mut().ip += operands.one();
+            }
+            OpCode::JumpIfFalse => {
+                if self.peek(0).is_false() {
+                    self.current_frame_mut().ip += operands.one();
+                }
+            }
+            OpCode::JumpIfTrue => {
+                if !self.peek(0).is_false() {
+                    self.current_frame_mut().ip += operands.one();
+                }
+            }
+            OpCode::Less => {
+                let (b, a) = (self.pop(), self.pop());
+                let ordering = self.val_cmp(a, b)?;
+                self.push(Value::Bool(ordering == std::cmp::Ordering::Less))?
+            }
+            OpCode::LessEqual => {
+                let (b, a) = (self.pop(), self.pop());
+                let ordering = self.val_cmp(a, b)?;
+                self.push(Value::Bool(ordering != std::cmp::Ordering::Greater))?
+            }
+            OpCode::Loop => {
+                if self.interrupt.swap(false, Ordering::Relaxed) {
+                    return Err(self.runtime_error("Interrupted."));
+                }
+                self.current_frame_mut().ip -= operands.one();
+            }
+            OpCode::Method => {
+                let slot = operands.one();
+                if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                    self.define_method(name)?
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::Mod => numeric_binop!(
+                self,
+                |a, b| {
+                    if b == 0 {
+                        return Err(self.runtime_error("Can't take the remainder by zero."));
+                    }
+                    if a == i64::MIN && b == -1 {
+                        Value::Int(0)
+                    } else {
+                        Value::Int(a % b)
+                    }
+                },
+                |a, b| a % b,
+                "Operands must be numbers when taking a remainder."
+            ),
+            OpCode::IntDiv => numeric_binop!(
+                self,
+                |a, b| {
+                    if b == 0 {
+                        return Err(self.runtime_error("Can't divide by zero."));
+                    }
+                    if a == i64::MIN && b == -1 {
+                        Value::Number((a as f64) / (b as f64))
+                    } else {
+                        Value::Int(a / b)
+                    }
+                },
+                |a, b| (a / b).trunc(),
+                "Operands must be numbers when dividing."
+            ),
+            OpCode::Pow => numeric_binop!(
+                self,
+                |a, b| match u32::try_from(b).ok().and_then(|b| a.checked_pow(b)) {
+                    Some(result) => Value::Int(result),
+                    None => Value::Number((a as f64).powf(b as f64)),
+                },
+                |a, b| a.powf(b),
+                "Operands must be numbers when raising to a power."
+            ),
+            OpCode::Mul => self.tower_binop(
+                |a, b| Value::Int(a * b),
+                |an, ad, bn, bd| Value::rational(an * bn, ad * bd),
+                |a, b| a * b,
+                |(are, aim), (bre, bim)| (are * bre - aim * bim, are * bim + aim * bre),
+                "when multiplying",
+            )?,
+            OpCode::Negate => match self.pop() {
+                Value::Number(n) => self.push(Value::Number(-n))?,
+                Value::Int(n) => self.push(Value::Int(-n))?,
+                Value::Rational(n, d) => self.push(Value::Rational(-n, d))?,
+                Value::Complex(re, im) => self.push(Value::Complex(-re, -im))?,
+                _ => return Err(self.runtime_error("no number found on stack to negate.")),
+            },
+            OpCode::Nil => self.push(Value::Nil)?,
+            OpCode::Not => {
+                let value = self.pop().is_false();
+                self.push(Value::Bool(value))?
+            }
+            OpCode::NotEqual => self.bin_bool_op(|x, y| x != y)?,
+            OpCode::Pop => {
+                self.pop();
+            }
+            OpCode::Print => {
+                let value = self.pop();
+                if self.repl {
+                    println!(">  {}", GcTraceFormatter::new(value, &self.gc));
+                } else {
+                    println!("{}", GcTraceFormatter::new(value, &self.gc));
+                }
+            }
+            OpCode::Return => {
+                let frame = self.frames.pop().unwrap();
+                let result = self.pop();
+                self.close_upvalue(frame.slot);
+                if self.frames.is_empty() {
+                    return Ok(true);
+                } else {
+                    self.stack.truncate(frame.slot);
+                    self.push(result)?
+                }
+            }
+            OpCode::ReturnNil => {
+                let frame = self.frames.pop().unwrap();
+                self.close_upvalue(frame.slot);
+                if self.frames.is_empty() {
+                    return Ok(true);
+                } else {
+                    self.stack.truncate(frame.slot);
+                    self.push(Value::Nil)?
+                }
+            }
+            OpCode::SetIndexArray => {
+                let value = self.pop();
+                let index = self.pop_number("for indexing an array")?;
+                if let Value::Array(arrayref) = self.pop() {
+                    let array = self.gc.deref_mut(arrayref);
+                    array[index as usize] = value;
+                    self.write_barrier(arrayref, value);
+                    self.push(Value::Array(arrayref))?
+                } else {
+                    return Err(self.runtime_error("No array found on stack when indexing."));
+                }
+            }
+            OpCode::SetIndexArrayKeep => {
+                let value = self.pop();
+                let index = self.pop_number("for indexing an array")?;
+                if let Value::Array(arrayref) = self.pop() {
+                    let array = self.gc.deref_mut(arrayref);
+                    array[index as usize] = value;
+                    self.write_barrier(arrayref, value);
+                    self.push(value)?
+                } else {
+                    return Err(self.runtime_error("No array found on stack when indexing."));
+                }
+            }
+            OpCode::SetGlobal => {
+                let index = operands.one();
+                if let Value::VString(string_ref) = self.current_chunk().constants[index] {
+                    if self.globals.insert(string_ref, self.peek(0)).is_none() {
+                        self.globals.remove(&string_ref);
+                        return Err(self.runtime_error(&format!(
+                            "Undefined variable '{}'.",
+                            self.gc.deref(string_ref)
+                        )));
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::SetLocal => {
+                let slot = operands.one();
+                let index = slot + self.current_frame().slot;
+                self.stack[index] = self.peek(0);
+            }
+            OpCode::SetProperty => {
+                let slot = operands.one();
+                if let Value::Instance(instance) = self.peek(1) {
+                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                        let value = self.pop();
+                        let instance_ref = self.gc.deref_mut(instance);
+                        instance_ref.fields.insert(name, value);
+                        self.write_barrier(instance, value);
+                        self.pop();
+                        self.push(value)?
+                    } else {
+                        return Err(self
+                            .runtime_error("Error: Invalid identifier found for usage on stack."));
+                    }
+                } else {
+                    return Err(self.runtime_error("Only instances have fields."));
+                }
+            }
+            OpCode::SetPropertyKeep => {
+                let slot = operands.one();
+                if let Value::Instance(instance) = self.peek(1) {
+                    if let Value::VString(name) = self.current_chunk().get_constant(slot) {
+                        let value = self.pop();
+                        let instance_ref = self.gc.deref_mut(instance);
+                        instance_ref.fields.insert(name, value);
+                        self.write_barrier(instance, value);
+                        self.pop();
+                        self.push(value)?
+                    } else {
+                        return Err(self
+                            .runtime_error("Error: Invalid identifier found for usage on stack."));
+                    }
+                } else {
+                    return Err(self.runtime_error("Only instances have fields."));
+                }
+            }
+            OpCode::SetUpvalue => {
+                let slot = operands.one();
+                let upvalue = self.current_closure().upvalues[slot];
+                let value = self.peek(0);
+                let mut upvalue = self.gc.deref_mut(upvalue);
+                if upvalue.closed.is_none() {
+                    self.stack[upvalue.location] = value;
+                } else {
+                    upvalue.closed = Some(value);
+                }
+            }
+            OpCode::Sub => self.tower_binop(
+                |a, b| Value::Int(a - b),
+                |an, ad, bn, bd| Value::rational(an * bd - bn * ad, ad * bd),
+                |a, b| a - b,
+                |(are, aim), (bre, bim)| (are - bre, aim - bim),
+                "when subtracting",
+            )?,
+            OpCode::SuperInvoke => {
+                let (name, count) = operands.two();
+                if let Value::VString(name) = self.current_chunk().get_constant(name) {
+                    if let Value::Class(class) = self.pop() {
+                        self.invoke_from_class(class, name, count)?
+                    } else {
+                        return Err(self.runtime_error("No class found on the stack."));
+                    }
+                } else {
+                    return Err(
+                        self.runtime_error("Error: Invalid identifier found for usage on stack.")
+                    );
+                }
+            }
+            OpCode::Swap => self.swap_top(),
+            OpCode::True => self.push(Value::Bool(true))?,
+            OpCode::PushTry => {
+                let catch_ip = self.current_frame().ip + operands.one();
+                let stack_len = self.stack.len();
+                self.current_frame_mut().try_frames.push(TryFrame {
+                    catch_ip,
+                    stack_len,
+                });
+            }
+            OpCode::PopTry => {
+                self.current_frame_mut().try_frames.pop();
+            }
+            OpCode::Throw => {
+                let value = self.pop();
+                return Err(self.throw(value));
+            }
+            OpCode::BitAnd => {
+                let (a, b) = self.pop_ints("for bitwise and")?;
+                self.push(Value::Int(a & b))?
+            }
+            OpCode::BitOr => {
+                let (a, b) = self.pop_ints("for bitwise or")?;
+                self.push(Value::Int(a | b))?
+            }
+            OpCode::BitXor => {
+                let (a, b) = self.pop_ints("for bitwise xor")?;
+                self.push(Value::Int(a ^ b))?
+            }
+            OpCode::Shl => {
+                let (a, b) = self.pop_ints("for left shift")?;
+                match u32::try_from(b).ok().filter(|&b| b < 64) {
+                    Some(b) => self.push(Value::Int(a << b))?,
+                    None => {
+                        return Err(self.runtime_error("Shift amount must be between 0 and 63."))
+                    }
+                }
+            }
+            OpCode::Shr => {
+                let (a, b) = self.pop_ints("for right shift")?;
+                match u32::try_from(b).ok().filter(|&b| b < 64) {
+                    Some(b) => self.push(Value::Int(a >> b))?,
+                    None => {
+                        return Err(self.runtime_error("Shift amount must be between 0 and 63."))
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // helpers for binary operations
+
+    /// Pops `a`/`b` and evaluates the matching op across the
+    /// `Int -> Rational -> Number -> Complex` numeric tower: both `Int`
+    /// computes via `int_op` (`i64` arithmetic, but an `f64` quotient for
+    /// `Div`), both `Rational`
+    /// computes in exact `i64` arithmetic (reduced via `Value::rational`),
+    /// either side a plain `Number` (or `Int` mixed with `Rational`/`Number`)
+    /// promotes the pair to `f64`, and either side a `Complex` promotes the
+    /// pair (treating a real number, integer, or rational as having a zero
+    /// imaginary part) and computes in `f64` pairs. Used by `Sub`/`Mul`/`Div`,
+    /// and by `Add` for `Rational`/`Complex` operands,
+    /// so they share one coercion path instead of each hand-rolling the
+    /// type combinations.
+    fn tower_binop(
+        &mut self,
+        int_op: fn(i64, i64) -> Value,
+        rational_op: fn(i64, i64, i64, i64) -> Result<Value, String>,
+        float_op: fn(f64, f64) -> f64,
+        complex_op: fn((f64, f64), (f64, f64)) -> (f64, f64),
+        msg: &str,
+    ) -> Result<(), InterpretError> {
+        let to_complex = |re: f64| (re, 0.0);
+        match (self.pop(), self.pop()) {
+            (Value::Int(b), Value::Int(a)) => self.push(int_op(a, b)),
+            (Value::Rational(bn, bd), Value::Rational(an, ad)) => {
+                match rational_op(an, ad, bn, bd) {
+                    Ok(value) => self.push(value),
+                    Err(e) => Err(self.runtime_error(&e)),
+                }
+            }
+            (Value::Rational(n, d), Value::Int(a)) => {
+                self.push_number(float_op(a as f64, n as f64 / d as f64))
+            }
+            (Value::Int(b), Value::Rational(n, d)) => {
+                self.push_number(float_op(n as f64 / d as f64, b as f64))
+            }
+            (Value::Rational(n, d), Value::Number(a)) => {
+                self.push_number(float_op(a, n as f64 / d as f64))
+            }
+            (Value::Number(b), Value::Rational(n, d)) => {
+                self.push_number(float_op(n as f64 / d as f64, b))
+            }
+            (Value::Int(b), Value::Number(a)) => self.push_number(float_op(a, b as f64)),
+            (Value::Number(b), Value::Int(a)) => self.push_number(float_op(a as f64, b)),
+            (Value::Number(b), Value::Number(a)) => self.push_number(float_op(a, b)),
+            (Value::Complex(bre, bim), Value::Rational(an, ad)) => {
+                let (re, im) = complex_op(to_complex(an as f64 / ad as f64), (bre, bim));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Rational(bn, bd), Value::Complex(are, aim)) => {
+                let (re, im) = complex_op((are, aim), to_complex(bn as f64 / bd as f64));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Complex(bre, bim), Value::Int(a)) => {
+                let (re, im) = complex_op(to_complex(a as f64), (bre, bim));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Int(b), Value::Complex(are, aim)) => {
+                let (re, im) = complex_op((are, aim), to_complex(b as f64));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Complex(bre, bim), Value::Number(a)) => {
+                let (re, im) = complex_op(to_complex(a), (bre, bim));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Number(b), Value::Complex(are, aim)) => {
+                let (re, im) = complex_op((are, aim), to_complex(b));
+                self.push(Value::Complex(re, im))
+            }
+            (Value::Complex(bre, bim), Value::Complex(are, aim)) => {
+                let (re, im) = complex_op((are, aim), (bre, bim));
+                self.push(Value::Complex(re, im))
+            }
+            _ => Err(self.runtime_error(&format!("Operands must be numeric {}.", msg))),
+        }
+    }
+
+    fn bin_bool_op(&mut self, f: fn(Value, Value) -> bool) -> Result<(), InterpretError> {
+        let (b, a) = (self.pop(), self.pop());
+        self.push(Value::Bool(f(a, b)))
+    }
+
+    // error functions
+
+    /// A total order over the value pairs `Less`/`LessEqual`/`Greater`/
+    /// `GreaterEqual`/array `sort` all need: numbers (mixing `Int` and
+    /// `Number` the same way `numeric_binop!` does) via `f64::total_cmp`,
+    /// which — unlike `partial_cmp` — gives `NaN` a defined place instead of
+    /// `None`, and interned strings via `gc.deref`. Anything else is a
+    /// runtime error rather than a silent `Ordering::Less`.
+    /// Pulls the next value out of a lazy `Value::Iterator`, or `Nil` once
+    /// it's exhausted, advancing the iterator's own state in place so the
+    /// next call continues from where this one left off. `Map`/`Filter`
+    /// recurse into their `inner` iterator and run the stored callback
+    /// through `call_callback`; `Take`/`Skip` recurse too, tracking their own
+    /// `remaining` count. Shared by the `"next"` method and `"collect"`'s
+    /// drain loop.
+    fn advance_iterator(&mut self, iter: GcRef<Iter>) -> Result<Value, InterpretError> {
+        match self.gc.deref(iter).clone() {
+            Iter::Range {
+                current,
+                limit,
+                step,
+            } => {
+                let exhausted =
+                    (step >= 0.0 && current >= limit) || (step < 0.0 && current <= limit);
+                if exhausted {
+                    Ok(Value::Nil)
+                } else {
+                    *self.gc.deref_mut(iter) = Iter::Range {
+                        current: current + step,
+                        limit,
+                        step,
+                    };
+                    Ok(Value::Number(current))
+                }
+            }
+            Iter::Array { array, index } => {
+                if index >= self.gc.deref(array).len() {
+                    Ok(Value::Nil)
+                } else {
+                    let value = self.gc.deref(array)[index];
+                    *self.gc.deref_mut(iter) = Iter::Array {
+                        array,
+                        index: index + 1,
+                    };
+                    Ok(value)
+                }
+            }
+            Iter::Map { inner, callback } => match self.advance_iterator(inner)? {
+                Value::Nil => Ok(Value::Nil),
+                value => self
+                    .call_callback(callback, &[value])
+                    .map_err(|e| self.runtime_error(&e)),
+            },
+            Iter::Filter { inner, callback } => loop {
+                match self.advance_iterator(inner)? {
+                    Value::Nil => break Ok(Value::Nil),
+                    value => {
+                        let keep = self
+                            .call_callback(callback, &[value])
+                            .map_err(|e| self.runtime_error(&e))?;
+                        if !keep.is_false() {
+                            break Ok(value);
+                        }
+                    }
+                }
+            },
+            Iter::Take { inner, remaining } => {
+                if remaining == 0 {
+                    Ok(Value::Nil)
+                } else {
+                    match self.advance_iterator(inner)? {
+                        Value::Nil => Ok(Value::Nil),
+                        value => {
+                            *self.gc.deref_mut(iter) = Iter::Take {
+                                inner,
+                                remaining: remaining - 1,
+                            };
+                            Ok(value)
+                        }
+                    }
+                }
+            }
+            Iter::Skip {
+                inner,
+                mut remaining,
+            } => {
+                while remaining > 0 {
+                    if self.advance_iterator(inner)? == Value::Nil {
+                        *self.gc.deref_mut(iter) = Iter::Skip {
+                            inner,
+                            remaining: 0,
+                        };
+                        return Ok(Value::Nil);
+                    }
+                    remaining -= 1;
+                }
+                *self.gc.deref_mut(iter) = Iter::Skip {
+                    inner,
+                    remaining: 0,
+                };
+                self.advance_iterator(inner)
+            }
+        }
+    }
+
+    fn val_cmp(&mut self, a: Value, b: Value) -> Result<std::cmp::Ordering, InterpretError> {
+        match (a, b) {
+            (Value::Int(a), Value::Int(b)) => Ok(a.cmp(&b)),
+            (Value::Int(a), Value::Number(b)) => Ok((a as f64).total_cmp(&b)),
+            (Value::Number(a), Value::Int(b)) => Ok(a.total_cmp(&(b as f64))),
+            (Value::Number(a), Value::Number(b)) => Ok(a.total_cmp(&b)),
+            (Value::VString(a), Value::VString(b)) => {
+                let a = self.gc.deref(a);
+                let b = self.gc.deref(b);
+                Ok(a.cmp(b))
+            }
+            _ => Err(self.runtime_error("Arguments must be of same type and comparable.")),
+        }
+    }
+
+    fn runtime_error(&mut self, message: &str) -> InterpretError {
+        let value = self.intern(message.to_owned());
+        self.throw(Value::VString(value))
+    }
+
+    /// Unwinds the stack looking for a `try`/`catch` to resume at, same as a
+    /// Lox-level `throw` would. If one is found, `value` lands on top of the
+    /// stack at the `catch` and `Vm::run` keeps going; otherwise this reports
+    /// the exception as a fatal runtime error, same as the old `runtime_error`.
+    fn throw(&mut self, value: Value) -> InterpretError {
+        if self.unwind_to_handler(value) {
+            self.caught_exception = true;
+            return InterpretError::Runtime;
+        }
+
+        match value {
+            Value::VString(string) => eprintln!("{}", self.gc.deref(string)),
+            _ => eprintln!(
+                "Uncaught exception: {}",
+                GcTraceFormatter::new(value, &self.gc)
+            ),
+        }
+
+        for frame in self.frames.iter().rev() {
+            let closure = self.gc.deref(frame.closure);
+            let function = self.gc.deref(closure.function);
+            let name = self.gc.deref(function.name);
+            let name = if name.is_empty() { "<script>" } else { &name };
+            let line = function.chunk.get_line(frame.ip - 1);
+            eprintln!("[line {}] in {}", line, name);
+        }
+
+        self.stack.clear();
+        InterpretError::Runtime
+    }
+
+    /// Pops call frames (closing their upvalues as a normal return would)
+    /// until one has a pending `try`, then truncates the stack back to where
+    /// that `try` began and pushes `value` for the `catch` body to bind.
+    /// Returns whether a handler was found anywhere in `self.frames`. Popping
+    /// frames one at a time here plays the same role a stored `frame_depth`
+    /// on `TryFrame` would: either way the net effect is a handler in an
+    /// outer call frame catching a throw raised several calls deeper - see
+    /// `a_handler_catches_a_throw_several_call_frames_deeper` in
+    /// `tests/integration.rs`.
+    fn unwind_to_handler(&mut self, value: Value) -> bool {
+        loop {
+            if let Some(try_frame) = self.frames.last_mut().and_then(|f| f.try_frames.pop()) {
+                self.close_upvalue(try_frame.stack_len);
+                self.stack.truncate(try_frame.stack_len);
+                self.stack.push(value);
+                self.current_frame_mut().ip = try_frame.catch_ip;
+                return true;
+            }
+
+            if self.frames.len() <= 1 {
+                return false;
+            }
+
+            let frame = self.frames.pop().unwrap();
+            self.close_upvalue(frame.slot);
+        }
+    }
+
+    // current pointers
+
+    #[inline]
+    fn current_frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    #[inline]
+    fn current_closure(&self) -> &Closure {
+        let closure = self.current_frame().closure;
+        self.gc.deref(closure)
+    }
+
+    #[inline]
+    fn current_frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    #[inline]
+    fn current_chunk(&self) -> &Chunk {
+        let function = self.gc.deref(self.current_closure().function);
+        &function.chunk
+    }
+
+    // helpers for calling a function
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), InterpretError> {
+        match callee {
+            Value::NativeFn(fun) => {
+                if let Some(arity) = self.gc.deref(fun).arity {
+                    if arity != arg_count {
+                        let msg = format!("Expected {} arguments but got {}.", arity, arg_count);
+                        return Err(self.runtime_error(&msg));
+                    }
+                }
+
+                let left = self.stack.len() - arg_count;
+                let args: Vec<Value> = self.stack[left..].to_vec();
+
+                // SAFETY: `function` points into the `Box` owned by the GC-allocated
+                // `NativeFn`; nothing frees or moves that allocation while this call
+                // runs (it stays reachable through `self.globals` for the duration),
+                // so calling through the raw pointer lets the closure take `&mut Vm`
+                // without also holding `self.gc`'s borrow open across the call.
+                let function: *const NativeFnClosure = self.gc.deref(fun).function.as_ref();
+                let result = match unsafe { (*function)(self, &args) } {
+                    Ok(res) => res,
+                    Err(e) => return Err(self.runtime_error(&e)),
+                };
+                self.stack.truncate(left - 1);
+                self.push(result)
+            }
+            Value::Closure(fun) => self.call(fun, arg_count),
+            Value::Class(cls) => {
+                let instance = Instance::new(cls);
+                let instance = self.alloc(instance);
+                let index = self.stack.len() - arg_count - 1;
+                self.stack[index] = Value::Instance(instance);
+
+                match self.gc.deref(cls).methods.get(&self.init_string) {
+                    Some(&method) => {
+                        if let Value::Closure(method) = method {
+                            self.call(method, arg_count)
+                        } else {
+                            Err(self.runtime_error("Initializer is not closure"))
+                        }
+                    }
+                    None => {
+                        if arg_count != 0 {
+                            let msg = format!("Expected 0 arguments but got {}.", arg_count);
+                            Err(self.runtime_error(&msg))
+                        } else {
+                            Ok(())
+                        }
+                    }
+                }
+            }
+            Value::BoundMethod(met) => {
+                let bound_method = self.gc.deref(met);
+                let method = bound_method.method;
+                let receiver = bound_method.receiver;
+                let index = self.stack.len() - 1 - arg_count;
+                self.stack[index] = receiver;
+                self.call(method, arg_count)
+            }
+            Value::PartialFn(partial) => {
+                let partial = self.gc.deref(partial);
+                let target = partial.target;
+                let bound_args = partial.bound_args.clone();
+                let bound_count = bound_args.len();
+                let index = self.stack.len() - arg_count - 1;
+                self.stack[index] = target;
+                for (offset, arg) in bound_args.into_iter().enumerate() {
+                    self.stack.insert(index + 1 + offset, arg);
+                }
+                self.call_value(target, arg_count + bound_count)
+            }
+            _ => Err(self.runtime_error("Can only call functions and classes.")),
+        }
+    }
+
+    fn call(&mut self, callee: GcRef<Closure>, arg_count: usize) -> Result<(), InterpretError> {
+        let closure = self.gc.deref(callee);
+        let function = self.gc.deref(closure.function);
+        let min_arity = function.arity;
+        let optional_count = function.defaults.len();
+        let has_rest = function.has_rest;
+        let max_fixed = min_arity + optional_count;
+        let fill_defaults: Vec<Value> = if arg_count < max_fixed {
+            function.defaults[arg_count.saturating_sub(min_arity)..].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        if arg_count < min_arity {
+            let msg = if optional_count > 0 || has_rest {
+                format!(
+                    "Expected at least {} arguments but got {}.",
+                    min_arity, arg_count
+                )
+            } else {
+                format!("Expected {} arguments but got {}.", min_arity, arg_count)
+            };
+            return Err(self.runtime_error(&msg));
+        }
+        if !has_rest && arg_count > max_fixed {
+            let msg = if optional_count > 0 {
+                format!(
+                    "Expected between {} and {} arguments but got {}.",
+                    min_arity, max_fixed, arg_count
+                )
+            } else {
+                format!("Expected {} arguments but got {}.", min_arity, arg_count)
+            };
+            return Err(self.runtime_error(&msg));
+        }
+        if self.frames.len() >= self.frame_max {
+            return Err(self.runtime_error("Call stack overflow."));
+        }
+
+        // Pad any optional parameters the caller didn't supply with their
+        // compiled default values, so the callee always finds every fixed
+        // parameter slot populated.
+        for default in fill_defaults {
+            self.push(default)?;
+        }
+
+        // Collect anything past the fixed parameters into the rest parameter's array.
+        if has_rest {
+            let rest_count = arg_count.saturating_sub(max_fixed);
+            let rest_start = self.stack.len() - rest_count;
+            let rest = self.stack.split_off(rest_start);
+            let rest = self.alloc(rest);
+            self.push(Value::Array(rest))?;
+        }
+
+        let total_params = max_fixed + has_rest as usize;
+        let frame = CallFrame::new(callee, self.stack.len() - total_params - 1);
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    #[inline]
+    fn define_native(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        function: impl Fn(&mut Vm, &[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let name = self.intern(name.to_owned());
+        let native = self.alloc(NativeFn::new(name, arity, Box::new(function)));
+        self.globals.insert(name, Value::NativeFn(native));
+    }
+
+    /// Installs every native in `module` as a global named
+    /// `{module.name}_{native name}`. Lets an embedder extend the language
+    /// surface with a new [`stdlib::Module`] without editing [`Vm::new`].
+    pub fn load_module(&mut self, module: Module) {
+        for (name, arity, function) in module.natives {
+            self.define_native(&format!("{}_{}", module.name, name), arity, function);
+        }
+    }
+
+    fn capture_upvalue(&mut self, index: usize) -> GcRef<Upvalue> {
+        for &upvalue in &self.open_upvalues {
+            if self.gc.deref(upvalue).location == index {
+                return upvalue;
+            }
+        }
+        let upvalue = Upvalue::new(index);
+        let upvalue = self.alloc(upvalue);
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    fn close_upvalue(&mut self, last: usize) {
+        let mut i = 0;
+        while i != self.open_upvalues.len() {
+            let upvalue = self.open_upvalues[i];
+            let upvalue = self.gc.deref_mut(upvalue);
+            if upvalue.location >= last {
+                self.open_upvalues.remove(i);
+                upvalue.closed = Some(self.stack[upvalue.location]);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn define_method(&mut self, name: GcRef<String>) -> Result<(), InterpretError> {
+        let method = self.peek(0);
+        if let Value::Class(class) = self.peek(1) {
+            let class = self.gc.deref_mut(class);
+            class.methods.insert(name, method);
+            self.pop();
+            Ok(())
+        } else {
+            Err(self.runtime_error("Cannot define a method on non class."))
+        }
+    }
+
+    fn bind_method(
+        &mut self,
+        class: GcRef<Class>,
+        name: GcRef<String>,
+    ) -> Result<(), InterpretError> {
+        let class = self.gc.deref(class);
+        if let Some(method) = class.methods.get(&name) {
+            let receiver = self.peek(0);
+            let method = match method {
+                Value::Closure(cl) => cl,
+                _ => return Err(self.runtime_error("No method found")),
+            };
+            let bound = BoundMethod::new(receiver, *method);
+            let bound = self.alloc(bound);
+            self.pop();
+            self.push(Value::BoundMethod(bound))
+        } else {
+            let name = &self.gc.deref(name);
+            let message = format!("Undefined property '{}'.", name);
+            Err(self.runtime_error(&message))
+        }
+    }
+
+    fn invoke(&mut self, name: GcRef<String>, arg_count: usize) -> Result<(), InterpretError> {
+        let receiver = self.peek(arg_count);
+        let method_name = self.gc.deref(name).clone();
+        if method_name == "copy" {
+            if arg_count != 0 {
+                Err(self.runtime_error("Copy requires only one argument."))
+            } else {
+                let to_push = match receiver {
+                    Value::Array(value) => {
+                        let new = self.gc.deref(value).clone();
+                        let new = self.alloc(new);
+                        Value::Array(new)
+                    }
+                    Value::Instance(value) => {
+                        let new = self.gc.deref(value).clone();
+                        let new = self.alloc(new);
+                        Value::Instance(new)
+                    }
+                    _ => {
+                        return Err(self.runtime_error(&format!(
+                            "Function copy is not defined for {}",
+                            receiver.type_of()
+                        )))
+                    }
+                };
+                self.pop();
+                self.push(to_push)
+            }
+        } else if method_name == "bind" {
+            match receiver {
+                Value::Closure(_) | Value::NativeFn(_) | Value::BoundMethod(_) => {
+                    let mut bound_args = Vec::with_capacity(arg_count);
+                    for _ in 0..arg_count {
+                        bound_args.push(self.pop());
+                    }
+                    bound_args.reverse();
+                    self.pop();
+                    let partial = self.alloc(PartialFn::new(receiver, bound_args));
+                    self.push(Value::PartialFn(partial))
+                }
+                _ => Err(self.runtime_error(&format!(
+                    "Function bind is not defined for {}",
+                    receiver.type_of()
+                ))),
+            }
+        } else if let Value::VString(string) = receiver {
+            match &*method_name {
+                "isAlpha" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("isAlpha requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Bool(
+                            self.gc.deref(string).chars().all(char::is_alphabetic),
+                        ))
+                    }
+                }
+                "isAlphaNumeric" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("isAlphaNumeric requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Bool(
+                            self.gc.deref(string).chars().all(char::is_alphanumeric),
+                        ))
+                    }
+                }
+                "isDigit" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("isDigit requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Bool(
+                            self.gc.deref(string).chars().all(char::is_numeric),
+                        ))
+                    }
+                }
+
+                "float" => {
+                    if arg_count == 1 {
+                        let top = self.pop();
+                        if let Value::VString(string) = top {
+                            match self.gc.deref(string).parse() {
+                                Ok(n) => {
+                                    self.pop();
+                                    self.push_number(n)
+                                }
+                                _ => Err(self.runtime_error("couldn't read number from string")),
+                            }
+                        } else {
+                            Err(self.runtime_error(&format!(
+                                "float needs a number as an argument, found {}",
+                                top.type_of()
+                            )))
+                        }
+                    } else {
+                        Err(self.runtime_error("float needs one argument"))
+                    }
+                }
+                "length" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("length requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push_number(self.gc.deref(string).len() as f64)
+                    }
+                }
+                "ord" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("ord requires no arguments."))
+                    } else if self.gc.deref(string).chars().count() == 1 {
+                        let c = self.gc.deref(string).chars().next().unwrap();
+                        self.push_number((c as u32) as f64)
+                    } else {
+                        Err(self.runtime_error("ord can be called on one-char strings only."))
+                    }
+                }
+                "len" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("len requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push_number(self.gc.deref(string).chars().count() as f64)
+                    }
+                }
+                "upper" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("upper requires no arguments."))
+                    } else {
+                        let upper = self.gc.deref(string).to_uppercase();
+                        let upper = self.intern(upper);
+                        self.pop();
+                        self.push(Value::VString(upper))
+                    }
+                }
+                "lower" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("lower requires no arguments."))
+                    } else {
+                        let lower = self.gc.deref(string).to_lowercase();
+                        let lower = self.intern(lower);
+                        self.pop();
+                        self.push(Value::VString(lower))
+                    }
+                }
+                "trim" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("trim requires no arguments."))
+                    } else {
+                        let trimmed = self.gc.deref(string).trim().to_owned();
+                        let trimmed = self.intern(trimmed);
+                        self.pop();
+                        self.push(Value::VString(trimmed))
+                    }
+                }
+                "split" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("split requires one argument."))
+                    } else if let Value::VString(sep) = self.pop() {
+                        let sep = self.gc.deref(sep).clone();
+                        let parts: Vec<String> = if sep.is_empty() {
+                            self.gc.deref(string).chars().map(String::from).collect()
+                        } else {
+                            self.gc
+                                .deref(string)
+                                .split(sep.as_str())
+                                .map(String::from)
+                                .collect()
+                        };
+                        let parts = parts
+                            .into_iter()
+                            .map(|part| Value::VString(self.intern(part)))
+                            .collect::<Vec<_>>();
+                        let array = self.alloc(parts);
+                        self.pop();
+                        self.push(Value::Array(array))
+                    } else {
+                        Err(self.runtime_error("split needs a string separator as argument."))
+                    }
+                }
+                "contains" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("contains requires one argument."))
+                    } else if let Value::VString(needle) = self.pop() {
+                        let contains = self
+                            .gc
+                            .deref(string)
+                            .contains(self.gc.deref(needle).as_str());
+                        self.pop();
+                        self.push(Value::Bool(contains))
+                    } else {
+                        Err(self.runtime_error("contains needs a string argument."))
+                    }
+                }
+                "startsWith" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("startsWith requires one argument."))
+                    } else if let Value::VString(prefix) = self.pop() {
+                        let result = self
+                            .gc
+                            .deref(string)
+                            .starts_with(self.gc.deref(prefix).as_str());
+                        self.pop();
+                        self.push(Value::Bool(result))
+                    } else {
+                        Err(self.runtime_error("startsWith needs a string argument."))
+                    }
+                }
+                "endsWith" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("endsWith requires one argument."))
+                    } else if let Value::VString(suffix) = self.pop() {
+                        let result = self
+                            .gc
+                            .deref(string)
+                            .ends_with(self.gc.deref(suffix).as_str());
+                        self.pop();
+                        self.push(Value::Bool(result))
+                    } else {
+                        Err(self.runtime_error("endsWith needs a string argument."))
+                    }
+                }
+                "charAt" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("charAt requires one argument."))
+                    } else if let Value::Number(index) = self.pop() {
+                        if index.fract() != 0.0 || index < 0.0 {
+                            return Err(self.runtime_error("charAt needs a non-negative integer."));
+                        }
+                        match self.gc.deref(string).chars().nth(index as usize) {
+                            Some(c) => {
+                                let c = self.intern(c.to_string());
+                                self.pop();
+                                self.push(Value::VString(c))
+                            }
+                            None => Err(self.runtime_error("charAt index out of range.")),
+                        }
+                    } else {
+                        Err(self.runtime_error("charAt needs a numeric argument."))
+                    }
+                }
+                "codeAt" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("codeAt requires one argument."))
+                    } else if let Value::Number(index) = self.pop() {
+                        if index.fract() != 0.0 || index < 0.0 {
+                            return Err(self.runtime_error("codeAt needs a non-negative integer."));
+                        }
+                        match self.gc.deref(string).chars().nth(index as usize) {
+                            Some(c) => {
+                                self.pop();
+                                self.push_number((c as u32) as f64)
+                            }
+                            None => Err(self.runtime_error("codeAt index out of range.")),
+                        }
+                    } else {
+                        Err(self.runtime_error("codeAt needs a numeric argument."))
+                    }
+                }
+                "replace" => {
+                    if arg_count != 2 {
+                        Err(self.runtime_error("replace requires two arguments."))
+                    } else {
+                        let to = self.pop();
+                        let from = self.pop();
+                        if let (Value::VString(from), Value::VString(to)) = (from, to) {
+                            let replaced = self
+                                .gc
+                                .deref(string)
+                                .replace(self.gc.deref(from).as_str(), self.gc.deref(to).as_str());
+                            let replaced = self.intern(replaced);
+                            self.pop();
+                            self.push(Value::VString(replaced))
+                        } else {
+                            Err(self.runtime_error("replace needs two string arguments."))
+                        }
+                    }
+                }
+                "repeat" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("repeat requires one argument."))
+                    } else if let Value::Number(n) = self.pop() {
+                        if n.fract() != 0.0 || n < 0.0 {
+                            return Err(self.runtime_error("repeat needs a non-negative integer."));
+                        }
+                        let repeated = self.gc.deref(string).repeat(n as usize);
+                        let repeated = self.intern(repeated);
+                        self.pop();
+                        self.push(Value::VString(repeated))
+                    } else {
+                        Err(self.runtime_error("repeat needs a numeric argument."))
+                    }
+                }
+                _ => {
+                    Err(self
+                        .runtime_error(&format!("String doesn't have {} as method.", method_name)))
+                }
+            }
+        } else if let Value::Instance(instance) = receiver {
+            let instance = self.gc.deref(instance);
+            if let Some(&value) = instance.fields.get(&name) {
+                let pos = self.stack.len() - 1 - arg_count;
+                self.stack[pos] = value;
+                self.call_value(value, arg_count)
+            } else {
+                let class = instance.class;
+                self.invoke_from_class(class, name, arg_count)
+            }
+        } else if let Value::Array(array) = receiver {
+            match &*method_name {
+                "all" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("all requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Bool(
+                            !self.gc.deref(array).iter().any(|&x| x.is_false()),
+                        ))
+                    }
+                }
+                "any" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("any requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Bool(
+                            self.gc.deref(array).iter().any(|&x| !x.is_false()),
+                        ))
+                    }
+                }
+                "extend" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("extend requires only one argument."))
+                    } else if let Value::Array(array_ref) = self.pop() {
+                        let mut new_array = self.gc.deref(array_ref).clone();
+                        self.gc.deref_mut(array).append(&mut new_array);
+                        self.pop();
+                        self.push(Value::Nil)
+                    } else {
+                        Err(self.runtime_error("extend needs an array as argument"))
+                    }
+                }
+                "length" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("length requires no arguments."))
+                    } else {
+                        self.pop();
+                        self.push_number(self.gc.deref(array).len() as f64)
+                    }
+                }
+                "pop" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("pop requires no arguments."))
+                    } else if let Some(value) = self.gc.deref_mut(array).pop() {
+                        self.pop();
+                        self.push(value)
+                    } else {
+                        Err(self.runtime_error("No element in array when popping from it."))
+                    }
+                }
+                "push" => {
+                    if arg_count == 0 {
+                        Err(self.runtime_error("No arguments given to function push."))
+                    } else {
+                        let mut temp = Vec::new();
+                        for _ in 0..arg_count {
+                            temp.push(self.pop());
+                        }
+                        temp.reverse();
+                        self.gc.deref_mut(array).append(&mut temp);
+                        self.pop();
+                        self.push(Value::Nil)
+                    }
+                }
+                "reverse" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("reverse requires only one argument."))
+                    } else {
+                        self.gc.deref_mut(array).reverse();
+                        self.pop();
+                        self.push(Value::Nil)
+                    }
+                }
+                "sort" if arg_count == 0 => {
+                    let mut items = self.gc.deref(array).clone();
+                    let mut error = None;
+                    items.sort_by(|&a, &b| match self.val_cmp(a, b) {
+                        Ok(ordering) => ordering,
+                        Err(e) => {
+                            error.get_or_insert(e);
+                            std::cmp::Ordering::Equal
+                        }
+                    });
+                    match error {
+                        Some(e) => Err(e),
+                        None => {
+                            *self.gc.deref_mut(array) = items;
+                            self.pop();
+                            self.push(Value::Nil)
+                        }
+                    }
+                }
+                // `sort` with a comparator: calls it pairwise, expecting a
+                // number whose sign orders the pair the way `Ordering` would.
+                "sort" if arg_count == 1 => {
+                    let comparator = self.pop();
+                    let mut items = self.gc.deref(array).clone();
+                    let mut error = None;
+                    items.sort_by(|&a, &b| {
+                        if error.is_some() {
+                            return std::cmp::Ordering::Equal;
+                        }
+                        match self.call_callback(comparator, &[a, b]) {
+                            Ok(Value::Number(n)) => {
+                                n.partial_cmp(&0.0).unwrap_or(std::cmp::Ordering::Equal)
+                            }
+                            Ok(Value::Int(n)) => n.cmp(&0),
+                            Ok(_) => {
+                                error.get_or_insert("comparator must return a number.".to_owned());
+                                std::cmp::Ordering::Equal
+                            }
+                            Err(e) => {
+                                error.get_or_insert(e);
+                                std::cmp::Ordering::Equal
+                            }
+                        }
+                    });
+                    match error {
+                        Some(e) => Err(self.runtime_error(&e)),
+                        None => {
+                            *self.gc.deref_mut(array) = items;
+                            self.pop();
+                            self.push(Value::Nil)
+                        }
+                    }
+                }
+                "sort" => Err(self.runtime_error("sort takes zero or one arguments.")),
+                "dedup" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("dedup requires no arguments."))
+                    } else {
+                        let mut items = self.gc.deref(array).clone();
+                        items.dedup();
+                        *self.gc.deref_mut(array) = items;
+                        self.pop();
+                        self.push(Value::Nil)
+                    }
+                }
+                "iter" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("iter requires no arguments."))
+                    } else {
+                        let iter = self.alloc(Iter::array(array));
+                        self.pop();
+                        self.push(Value::Iterator(iter))
+                    }
+                }
+                "map" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("map requires one argument."))
+                    } else {
+                        let callback = self.pop();
+                        self.pop();
+                        let items = self.gc.deref(array).clone();
+                        let mut result = Vec::with_capacity(items.len());
+                        let mut error = None;
+                        for item in items {
+                            match self.call_callback(callback, &[item]) {
+                                Ok(value) => result.push(value),
+                                Err(message) => {
+                                    error = Some(message);
+                                    break;
+                                }
+                            }
+                        }
+                        match error {
+                            Some(message) => Err(self.runtime_error(&message)),
+                            None => {
+                                let result = self.alloc(result);
+                                self.push(Value::Array(result))
+                            }
+                        }
+                    }
+                }
+                "filter" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("filter requires one argument."))
+                    } else {
+                        let callback = self.pop();
+                        self.pop();
+                        let items = self.gc.deref(array).clone();
+                        let mut result = Vec::new();
+                        let mut error = None;
+                        for item in items {
+                            match self.call_callback(callback, &[item]) {
+                                Ok(value) => {
+                                    if !value.is_false() {
+                                        result.push(item);
+                                    }
+                                }
+                                Err(message) => {
+                                    error = Some(message);
+                                    break;
+                                }
+                            }
+                        }
+                        match error {
+                            Some(message) => Err(self.runtime_error(&message)),
+                            None => {
+                                let result = self.alloc(result);
+                                self.push(Value::Array(result))
+                            }
+                        }
+                    }
+                }
+                "foldl" => {
+                    if arg_count != 2 {
+                        Err(self.runtime_error("foldl requires two arguments."))
+                    } else {
+                        let callback = self.pop();
+                        let mut accumulator = self.pop();
+                        self.pop();
+                        let items = self.gc.deref(array).clone();
+                        let mut error = None;
+                        for item in items {
+                            match self.call_callback(callback, &[accumulator, item]) {
+                                Ok(value) => accumulator = value,
+                                Err(message) => {
+                                    error = Some(message);
+                                    break;
+                                }
+                            }
+                        }
+                        match error {
+                            Some(message) => Err(self.runtime_error(&message)),
+                            None => self.push(accumulator),
+                        }
+                    }
+                }
+                _ => {
+                    Err(self
+                        .runtime_error(&format!("Array doesn't have {} as method.", method_name)))
+                }
+            }
+        } else if let Value::Number(n) = receiver {
+            match &*method_name {
+                "abs" => match arg_count {
+                    0 => {
+                        if let Value::Number(n) = self.pop() {
+                            self.push_number(n.abs())
+                        } else {
+                            Err(self.runtime_error("abs needs numeric argument."))
+                        }
+                    }
+                    _ => Err(self.runtime_error("abs expects only one argument.")),
+                },
+                "ceil" => match arg_count {
+                    0 => {
+                        if let Value::Number(n) = self.pop() {
+                            self.push_number(n.ceil())
+                        } else {
+                            Err(self.runtime_error("ceil needs numeric argument."))
+                        }
+                    }
+                    _ => Err(self.runtime_error("ceil needs one argument.")),
+                },
+                "chr" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("chr requires no arguments."))
+                    } else {
+                        self.pop();
+                        let n = if n.fract() == 0.0 {
+                            n as u32
+                        } else {
+                            return Err(self.runtime_error("chr needs an integer argument."));
+                        };
+                        let s = match char::from_u32(n) {
+                            Some(c) => self.intern(c.to_string()),
+                            None => {
+                                return Err(self.runtime_error("chr couldn't read number to char"))
+                            }
+                        };
+                        self.push(Value::VString(s))
+                    }
+                }
+                "floor" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        self.push_number(n.floor())
+                    } else {
+                        Err(self.runtime_error("floor needs one argument."))
+                    }
+                }
+                "pow" => {
+                    if arg_count == 1 {
+                        if let Value::Number(n1) = self.pop() {
+                            self.push_number(n.powf(n1))
+                        } else {
+                            Err(self.runtime_error("sqrt needs numeric argument"))
+                        }
+                    } else {
+                        Err(self.runtime_error("sqrt expects only one argument"))
+                    }
+                }
+                "sqrt" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        self.push_number(n.sqrt())
+                    } else {
+                        Err(self.runtime_error("sqrt expects only one argument"))
+                    }
+                }
+                "square" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        self.push_number(n * n)
+                    } else {
+                        Err(self.runtime_error("square expects only one argument"))
+                    }
+                }
+                _ => {
+                    Err(self
+                        .runtime_error(&format!("Float doesn't have {} as method.", method_name)))
+                }
+            }
+        } else if let Value::Complex(re, im) = receiver {
+            match &*method_name {
+                "abs" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("abs expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push_number(re.hypot(im))
+                    }
+                }
+                "arg" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("arg expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push_number(im.atan2(re))
+                    }
+                }
+                "conj" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("conj expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Complex(re, -im))
+                    }
+                }
+                "im" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("im expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push_number(im)
+                    }
+                }
+                "re" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("re expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push_number(re)
+                    }
+                }
+                // `z.pow(n)` via the principal branch: lift to polar form
+                // `r*e^(i*theta)`, scale, and convert back.
+                "pow" => {
+                    if arg_count == 1 {
+                        if let Value::Number(n) = self.pop() {
+                            let r = re.hypot(im).powf(n);
+                            let theta = im.atan2(re) * n;
+                            self.pop();
+                            self.push(Value::Complex(r * theta.cos(), r * theta.sin()))
+                        } else {
+                            Err(self.runtime_error("pow needs a numeric argument"))
+                        }
+                    } else {
+                        Err(self.runtime_error("pow expects only one argument"))
+                    }
+                }
+                // The principal square root: same polar lift as `pow`, halving
+                // the angle instead of scaling it by an arbitrary exponent.
+                "sqrt" => {
+                    if arg_count == 0 {
+                        let r = re.hypot(im).sqrt();
+                        let theta = im.atan2(re) / 2.0;
+                        self.pop();
+                        self.push(Value::Complex(r * theta.cos(), r * theta.sin()))
+                    } else {
+                        Err(self.runtime_error("sqrt expects only one argument"))
+                    }
+                }
+                "square" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        self.push(Value::Complex(re * re - im * im, 2.0 * re * im))
+                    } else {
+                        Err(self.runtime_error("square expects only one argument"))
+                    }
+                }
+                _ => {
+                    Err(self
+                        .runtime_error(&format!("Complex doesn't have {} as method.", method_name)))
+                }
+            }
+        } else if let Value::Rational(n, d) = receiver {
+            match &*method_name {
+                "abs" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("abs expects only one argument."))
+                    } else {
+                        self.pop();
+                        match Value::rational(n.abs(), d) {
+                            Ok(value) => self.push(value),
+                            Err(e) => Err(self.runtime_error(&e)),
+                        }
+                    }
+                }
+                "den" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("den expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Int(d))
+                    }
+                }
+                "num" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("num expects only one argument."))
+                    } else {
+                        self.pop();
+                        self.push(Value::Int(n))
+                    }
+                }
+                "pow" => {
+                    if arg_count == 1 {
+                        if let Value::Int(exponent) = self.pop() {
+                            match u32::try_from(exponent)
+                                .ok()
+                                .and_then(|e| n.checked_pow(e).zip(d.checked_pow(e)))
+                                .map(|(n, d)| Value::rational(n, d))
+                            {
+                                Some(Ok(value)) => {
+                                    self.pop();
+                                    self.push(value)
+                                }
+                                Some(Err(e)) => Err(self.runtime_error(&e)),
+                                None => Err(self.runtime_error(
+                                    "pow only supports non-negative integer exponents that don't overflow.",
+                                )),
+                            }
+                        } else {
+                            Err(self.runtime_error("pow needs an integer argument"))
+                        }
+                    } else {
+                        Err(self.runtime_error("pow expects only one argument"))
+                    }
+                }
+                "sqrt" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        self.push_number((n as f64 / d as f64).sqrt())
+                    } else {
+                        Err(self.runtime_error("sqrt expects only one argument"))
+                    }
+                }
+                "square" => {
+                    if arg_count == 0 {
+                        self.pop();
+                        match Value::rational(n * n, d * d) {
+                            Ok(value) => self.push(value),
+                            Err(e) => Err(self.runtime_error(&e)),
+                        }
+                    } else {
+                        Err(self.runtime_error("square expects only one argument"))
+                    }
+                }
+                _ => Err(self
+                    .runtime_error(&format!("Rational doesn't have {} as method.", method_name))),
+            }
+        } else if let Value::Iterator(iter) = receiver {
+            match &*method_name {
+                "next" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("next requires no arguments."))
+                    } else {
+                        let value = self.advance_iterator(iter)?;
+                        self.pop();
+                        self.push(value)
+                    }
+                }
+                "collect" => {
+                    if arg_count != 0 {
+                        Err(self.runtime_error("collect requires no arguments."))
+                    } else {
+                        let mut items = Vec::new();
+                        loop {
+                            match self.advance_iterator(iter)? {
+                                Value::Nil => break,
+                                value => items.push(value),
+                            }
+                        }
+                        let array = self.alloc(items);
+                        self.pop();
+                        self.push(Value::Array(array))
+                    }
+                }
+                "map" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("map requires one argument."))
+                    } else {
+                        let callback = self.pop();
+                        self.pop();
+                        let new_iter = self.alloc(Iter::Map {
+                            inner: iter,
+                            callback,
+                        });
+                        self.push(Value::Iterator(new_iter))
+                    }
+                }
+                "filter" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error("filter requires one argument."))
+                    } else {
+                        let callback = self.pop();
+                        self.pop();
+                        let new_iter = self.alloc(Iter::Filter {
+                            inner: iter,
+                            callback,
+                        });
+                        self.push(Value::Iterator(new_iter))
+                    }
+                }
+                "take" | "skip" => {
+                    if arg_count != 1 {
+                        Err(self.runtime_error(&format!("{} requires one argument.", method_name)))
+                    } else if let Value::Number(n) = self.pop() {
+                        if n < 0.0 {
+                            return Err(self.runtime_error(&format!(
+                                "{} needs a non-negative count.",
+                                method_name
+                            )));
+                        }
+                        let remaining = n as usize;
+                        let new_state = if method_name == "take" {
+                            Iter::Take {
+                                inner: iter,
+                                remaining,
+                            }
+                        } else {
+                            Iter::Skip {
+                                inner: iter,
+                                remaining,
+                            }
+                        };
+                        let new_iter = self.alloc(new_state);
+                        self.pop();
+                        self.push(Value::Iterator(new_iter))
+                    } else {
+                        Err(self
+                            .runtime_error(&format!("{} needs a numeric argument.", method_name)))
+                    }
+                }
+                _ => Err(self
+                    .runtime_error(&format!("Iterator doesn't have {} as method.", method_name))),
+            }
+        } else if method_name == "toString" {
+            if arg_count != 0 {
+                Err(self.runtime_error("toString requires no arguments"))
+            } else {
+                let string = format!("{}", GcTraceFormatter::new(receiver, &self.gc));
+                let string = self.alloc(string);
+                self.pop();
+                self.push(Value::VString(string))
+            }
+        } else {
+            Err(self.runtime_error("Only instances have methods."))
+        }
+    }
+
+    fn invoke_from_class(
+        &mut self,
+        class: GcRef<Class>,
+        name: GcRef<String>,
+        count: usize,
+    ) -> Result<(), InterpretError> {
+        let mut ancestor = Some(class);
+        let mut found = None;
+        while let Some(current) = ancestor {
+            let current = self.gc.deref(current);
+            if let Some(&method) = current.methods.get(&name) {
+                found = Some(method);
+                break;
+            }
+            ancestor = current.superclass;
+        }
+        if let Some(method) = found {
+            if let Value::Closure(closure) = method {
+                self.call(closure, count)
+            } else {
+                Err(self.runtime_error("Got method that is not closure!"))
+            }
+        } else if self.gc.deref(name) == "toString" {
+            if count != 0 {
+                Err(self.runtime_error("toString requires no arguments"))
+            } else {
+                let name = self.gc.deref(class).name;
+                self.pop();
+                self.push(Value::VString(name))
+            }
+        } else {
+            let name = &self.gc.deref(name);
+            let message = format!("Undefined property '{}'.", name);
+            Err(self.runtime_error(&message))
+        }
+    }
+
+    // garbage collection helpers
+
+    // Caps how much marking/sweeping work a single `incremental_step` call
+    // may do, so one GC "tick" never produces a stop-the-world pause.
+    const GC_WORK_BUDGET: usize = 256;
+
+    fn collect_garbage(&mut self) {
+        // Minor collection: cheap enough (nursery-sized) to always run to
+        // completion in one go, rather than spreading it across calls like
+        // the major collection below.
+        if self.gc.should_minor_gc() {
+            #[cfg(feature = "debug_gc_log")]
+            eprintln!("\n-- minor gc start");
+            self.gc.begin_minor_cycle();
+            self.mark_roots();
+            self.gc.minor_collect();
+            #[cfg(feature = "debug_gc_log")]
+            eprintln!("-- minor gc end\n");
+        }
+
+        if self.gc.phase() == GcPhase::Idle {
+            if !self.gc.should_gc() {
+                return;
+            }
+            #[cfg(feature = "debug_gc_log")]
+            eprintln!("\n-- gc start");
+            self.mark_roots();
+            self.gc.begin_cycle();
+        }
+
+        self.gc.incremental_step(Self::GC_WORK_BUDGET);
+
+        #[cfg(feature = "debug_gc_log")]
+        if self.gc.phase() == GcPhase::Idle {
+            eprintln!("-- gc end\n");
+        }
+    }
+
+    #[inline]
+    pub(crate) fn alloc<T: GcTrace + 'static + fmt::Debug>(&mut self, object: T) -> GcRef<T> {
+        self.collect_garbage();
+        self.gc.alloc(object)
+    }
+
+    /// Re-greys `holder` when `value` is a heap reference being stored into it,
+    /// so a holder the marker already blackened doesn't end up pointing at a
+    /// white (about-to-be-swept) object mid-cycle.
+    #[inline]
+    fn write_barrier<T: GcTrace>(&mut self, holder: GcRef<T>, value: Value) {
+        if let Some(new_ref_index) = value.gc_index() {
+            self.gc.write_barrier(holder.raw_index(), new_ref_index);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn intern(&mut self, string: String) -> GcRef<String> {
+        self.collect_garbage();
+        self.gc.intern(string)
+    }
+
+    fn mark_roots(&mut self) {
+        for &value in &self.stack {
+            self.gc.mark_value(value);
+        }
+
+        for frame in &self.frames {
+            self.gc.mark_object(frame.closure);
+        }
+
+        for &upvalue in &self.open_upvalues {
+            self.gc.mark_object(upvalue);
+        }
+
+        self.gc.mark_table(&self.globals);
+        self.gc.mark_object(self.init_string);
+    }
+}
+
+struct CallFrame {
+    closure: GcRef<Closure>,
+    ip: usize,
+    slot: usize,
+    try_frames: Vec<TryFrame>,
+}
+
+impl CallFrame {
+    fn new(closure: GcRef<Closure>, slot: usize) -> Self {
+        CallFrame {
+            closure,
+            ip: 0,
+            slot,
+            try_frames: Vec::new(),
+        }
+    }
+}
+
+/// A `try` block this frame is currently inside, recorded by `OpCode::PushTry`
+/// so a later `throw` can unwind straight back to its `catch`.
+struct TryFrame {
+    /// Instruction to resume at, pointing at the start of the `catch` body.
+    catch_ip: usize,
+    /// `Vm::stack` height to truncate back to before pushing the thrown value,
+    /// discarding anything the `try` body left behind.
+    stack_len: usize,
+}
+
+// native functions
+
+fn clock(vm: &mut Vm, _args: &[Value]) -> Result<Value, String> {
+    let time = vm.start_time.elapsed().as_secs_f64();
+    Ok(Value::Number(time))
+}
+
+fn instance_of(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    if let (Value::Instance(instance), Value::Class(class)) = (args[0], args[1]) {
+        let mut ancestor = Some(vm.gc.deref(instance).class);
+        let mut found = false;
+        while let Some(current) = ancestor {
+            if current == class {
+                found = true;
+                break;
+            }
+            ancestor = vm.gc.deref(current).superclass;
+        }
+        Ok(Value::Bool(found))
+    } else {
+        Err(format!(
+            "instanceof needs an instance and a class, found {} {}",
+            args[0].type_of(),
+            args[1].type_of()
+        ))
+    }
+}
+
+fn is_bool(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Bool(_))))
+}
+
+fn is_class(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Class(_))))
+}
+
+fn is_closure(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Closure(_))))
+}
+
+fn is_function(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Function(_))))
+}
+
+fn is_instance(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Instance(_))))
+}
+
+fn is_nil(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Nil)))
+}
+
+fn is_number(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::Number(_))))
+}
+
+fn is_string(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(matches!(args[0], Value::VString(_))))
+}
+
+fn lox_panic(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let mut terms: Vec<String> = vec![];
+
+    for &arg in args.iter() {
+        let formatter = GcTraceFormatter::new(arg, &vm.gc);
+        let term = format!("{}", formatter);
+        terms.push(term);
+    }
+
+    panic!("panic: {}", terms.join(", "))
+}
+
+/// Native counterpart to the `throw` statement, for raising an exception
+/// from expression position. Unlike `throw`, which hands the raw value to
+/// [`Vm::throw`] directly, a native can only report failure as a `String`
+/// (see [`Vm::call_value`]), so the value reaches any `catch` block as its
+/// formatted string rather than the original value.
+fn lox_raise(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    Err(format!("{}", GcTraceFormatter::new(args[0], &vm.gc)))
+}
+
+fn max(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args.len() {
+        0 | 1 => Err("max expects more than 1 argument".to_owned()),
+        _ => {
+            let mut max = -f64::INFINITY;
+            for &arg in args.iter() {
+                if let Value::Number(n) = arg {
+                    max = max.max(n);
+                } else {
+                    return Err("max needs numeric argument".to_owned());
+                }
+            }
+            Ok(Value::Number(max))
+        }
+    }
+}
+
+fn min(_vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    match args.len() {
+        0 | 1 => Err("min expects more than 1 argument".to_owned()),
+        _ => {
+            let mut min = f64::INFINITY;
+            for &arg in args.iter() {
+                if let Value::Number(n) = arg {
+                    min = min.min(n);
+                } else {
+                    return Err("min needs numeric argument".to_owned());
+                }
+            }
+            Ok(Value::Number(min))
+        }
+    }
+}
+
+/// Builds a lazy `Iter::Range` instead of `iter_range`'s materialized array:
+/// `range(stop)` counts up from 0, `range(start, stop)` sets both ends, and
+/// `range(start, stop, step)` also sets the stride. Nothing is computed until
+/// something pulls from it via `next`/`collect`/the `|:`/`|?` pipe operators.
+fn range(vm: &mut Vm, args: &[Value]) -> Result<Value, String> {
+    let (start, limit, step) = match args {
+        [Value::Number(limit)] => (0.0, *limit, 1.0),
+        [Value::Number(start), Value::Number(limit)] => (*start, *limit, 1.0),
+        [Value::Number(start), Value::Number(limit), Value::Number(step)] => {
+            (*start, *limit, *step)
+        }
+        _ => return Err("range needs 1 to 3 numeric arguments.".to_owned()),
+    };
+    let iter = vm.alloc(Iter::range(start, limit, step));
+    Ok(Value::Iterator(iter))
+}